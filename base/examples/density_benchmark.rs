@@ -0,0 +1,66 @@
+//! Compares the generation of chunks with the interpolated density field to
+//! the generation with the exact noise of every unit.
+//!
+//! Run it with `cargo run --release --example density_benchmark` from the
+//! `base` directory.
+
+extern crate base;
+
+use base::gen::WorldGenerator;
+use base::gen::world::pass::{ClimatePass, DensityPass, ExactDensityPass, GenerationPass,
+                             ProtoChunk};
+use base::math::AxialPoint;
+use base::world::ChunkIndex;
+use std::time::{Duration, Instant};
+
+/// Number of chunks generated in both directions.
+const CHUNKS: i32 = 8;
+
+fn main() {
+    let gen = WorldGenerator::with_seed(42);
+
+    println!("Filling {} chunks per run", CHUNKS * CHUNKS);
+    let (exact, exact_chunks) = fill_chunks(&gen, &ExactDensityPass);
+    let (interpolated, interpolated_chunks) = fill_chunks(&gen, &DensityPass);
+    println!("{:>13}: {:8.2} ms per chunk", "exact", exact);
+    println!("{:>13}: {:8.2} ms per chunk ({:.1}x faster)",
+             "interpolated",
+             interpolated,
+             exact / interpolated);
+
+    let mut units = 0;
+    let mut different = 0;
+    for (a, b) in exact_chunks.iter().zip(&interpolated_chunks) {
+        for (a, b) in a.columns().iter().zip(b.columns()) {
+            units += a.filled.len();
+            different += a.filled.iter().zip(&b.filled).filter(|&(a, b)| a != b).count();
+        }
+    }
+    println!("{:.3}% of the units differ", different as f64 * 100.0 / units as f64);
+}
+
+/// Determines the climate of all chunks and fills them with terrain by
+/// running the given pass. Returns the average time of the pass per chunk in
+/// milliseconds and the filled chunks.
+fn fill_chunks(gen: &WorldGenerator, pass: &GenerationPass) -> (f64, Vec<ProtoChunk>) {
+    let mut chunks = Vec::new();
+    for q in 0..CHUNKS {
+        for r in 0..CHUNKS {
+            let mut chunk = ProtoChunk::new(ChunkIndex(AxialPoint::new(q, r)),
+                                            0,
+                                            gen.config().world_height);
+            ClimatePass.apply(gen, &mut chunk);
+            chunks.push(chunk);
+        }
+    }
+
+    let start = Instant::now();
+    for chunk in &mut chunks {
+        pass.apply(gen, chunk);
+    }
+    (millis(start.elapsed()) / (CHUNKS * CHUNKS) as f64, chunks)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
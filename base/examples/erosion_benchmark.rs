@@ -0,0 +1,55 @@
+//! Measures how much time the erosion adds to the generation of a chunk.
+//!
+//! Run it with `cargo run --release --example erosion_benchmark` from the
+//! `base` directory.
+
+extern crate base;
+
+use base::gen::WorldGenerator;
+use base::gen::world::config::WorldGenConfig;
+use base::gen::world::erosion::{ErosionConfig, HydraulicErosion, ThermalErosion};
+use base::math::AxialPoint;
+use base::world::{ChunkIndex, ChunkProvider};
+use std::time::{Duration, Instant};
+
+/// Number of chunks generated in both directions. The chunks cover several
+/// erosion regions, so the cost of eroding a region is spread over all chunks
+/// in it like in the game.
+const CHUNKS: i32 = 8;
+
+fn main() {
+    let configs = [("none", ErosionConfig::default()),
+                   ("thermal",
+                    ErosionConfig { hydraulic: None, thermal: Some(ThermalErosion::default()) }),
+                   ("hydraulic",
+                    ErosionConfig { hydraulic: Some(HydraulicErosion::default()), thermal: None }),
+                   ("both", ErosionConfig::enabled())];
+
+    println!("Generating {} chunks per run", CHUNKS * CHUNKS);
+    let mut baseline = None;
+    for &(name, config) in &configs {
+        let per_chunk = millis_per_chunk(config);
+        let base = baseline.unwrap_or(per_chunk);
+        baseline = Some(base);
+        println!("{:>10}: {:8.2} ms per chunk ({:+.2} ms)", name, per_chunk, per_chunk - base);
+    }
+}
+
+/// Generates all chunks with a fresh generator and returns the average time
+/// per chunk in milliseconds.
+fn millis_per_chunk(config: ErosionConfig) -> f64 {
+    let config = WorldGenConfig { erosion: config, ..WorldGenConfig::default() };
+    let gen = WorldGenerator::with_seed(42).with_config(config);
+
+    let start = Instant::now();
+    for q in 0..CHUNKS {
+        for r in 0..CHUNKS {
+            gen.load_chunk(ChunkIndex(AxialPoint::new(q, r)));
+        }
+    }
+    millis(start.elapsed()) / (CHUNKS * CHUNKS) as f64
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
@@ -0,0 +1,64 @@
+//! Helpers for reading the TOML data files (materials, biomes, ...).
+
+use toml::{Parser, Table, Value};
+
+/// Parses TOML data. On failure, all messages of the parser are returned
+/// together with their line and column.
+pub fn parse(src: &str) -> Result<Table, String> {
+    let mut parser = Parser::new(src);
+    match parser.parse() {
+        Some(table) => Ok(table),
+        None => {
+            let messages: Vec<_> = parser.errors
+                .iter()
+                .map(|e| {
+                    let (line, col) = parser.to_linecol(e.lo);
+                    format!("{}:{}: {}", line + 1, col + 1, e.desc)
+                })
+                .collect();
+            Err(messages.join(", "))
+        }
+    }
+}
+
+/// Returns the entries of an array of tables (`[[key]]`). A missing array is
+/// treated as empty, `None` is returned if the value isn't an array.
+pub fn entries<'a>(table: &'a Table, key: &str) -> Option<&'a [Value]> {
+    const NO_ENTRIES: &'static [Value] = &[];
+    match table.get(key) {
+        Some(&Value::Array(ref entries)) => Some(&entries[..]),
+        Some(_) => None,
+        None => Some(NO_ENTRIES),
+    }
+}
+
+/// Reads an integer which fits into a `u8`.
+pub fn byte(value: &Value) -> Option<u8> {
+    value.as_integer().and_then(|i| if i >= 0 && i <= u8::max_value() as i64 {
+        Some(i as u8)
+    } else {
+        None
+    })
+}
+
+/// Reads an integer which fits into a `u16`.
+pub fn short(value: &Value) -> Option<u16> {
+    value.as_integer().and_then(|i| if i >= 0 && i <= u16::max_value() as i64 {
+        Some(i as u16)
+    } else {
+        None
+    })
+}
+
+/// Reads a number. Integers are accepted as well, so that `1` doesn't have to
+/// be written as `1.0`.
+pub fn float(value: &Value) -> Option<f32> {
+    value.as_float().or_else(|| value.as_integer().map(|i| i as f64)).map(|f| f as f32)
+}
+
+/// Reads an array of exactly `len` numbers.
+pub fn float_array(value: &Value, len: usize) -> Option<Vec<f32>> {
+    value.as_slice()
+        .and_then(|values| values.iter().map(float).collect::<Option<Vec<_>>>())
+        .and_then(|values| if values.len() == len { Some(values) } else { None })
+}
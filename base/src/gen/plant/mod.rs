@@ -1,9 +1,36 @@
+pub mod preset;
+pub mod registry;
 pub mod tree;
 
-use self::tree::{PlantType, TreeGen};
+use self::preset::Preset;
+use self::tree::TreeGen;
 use prop::Plant;
 use rand::Rng;
 
+/// The kinds of plant generators a species can be grown by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, RustcEncodable, RustcDecodable)]
+pub enum GeneratorKind {
+    /// Trees and tree-like plants, see `tree`.
+    Tree,
+}
+
+impl GeneratorKind {
+    /// Returns the name used for this generator in data files.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            GeneratorKind::Tree => "tree",
+        }
+    }
+
+    /// Returns the generator with the given name, see `name()`.
+    pub fn from_name(name: &str) -> Option<GeneratorKind> {
+        match name {
+            "tree" => Some(GeneratorKind::Tree),
+            _ => None,
+        }
+    }
+}
+
 /// Plant generation entry point.
 ///
 /// This struct will randomly generate a plant using a more specific plant
@@ -19,7 +46,10 @@ impl PlantGenerator {
         }
     }
 
-    pub fn new(plant_type: PlantType) -> Self {
-        PlantGenerator::Tree(TreeGen::new(plant_type))
+    /// Creates a generator of the given kind with the given parameters.
+    pub fn new(kind: GeneratorKind, preset: Preset) -> Self {
+        match kind {
+            GeneratorKind::Tree => PlantGenerator::Tree(TreeGen::new(preset)),
+        }
     }
 }
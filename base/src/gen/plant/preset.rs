@@ -0,0 +1,386 @@
+//! Parameters of the tree generator and how they are read from data files.
+//!
+//! Every plant species has a `Preset` which is part of its `[[species]]`
+//! table in `base/data/plants.toml`. Most parameters are ranges written as
+//! `[min, max]`: every generated plant samples its own value from them. The
+//! height dependence of the branch length is a piecewise linear
+//! `HeightCurve`:
+//!
+//! ```text
+//! [species.preset]
+//! trunk_diameter = [0.4, 0.6]
+//! trunk_color = [[0.4, 0.45], [0.3, 0.35], [0.2, 0.25]]
+//! branch_segment_count = [3, 3]
+//! height_curve = [[0.0, 1.0], [8.0, 0.1]]
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use toml::{Table, Value};
+use data::{float, float_array, short};
+
+/// Parameters for the tree generator. All lengths are measured in world
+/// units.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Preset {
+    /// Diameter of the first branch we create (the trunk).
+    pub trunk_diameter: (f32, f32),
+    /// Trunk height. Note that branches going upward can increase plant height
+    /// beyond this.
+    pub trunk_height: (f32, f32),
+    /// Trunk diameter at `trunk_height`. Should be smaller than
+    /// `trunk_diameter`.
+    pub trunk_diameter_top: (f32, f32),
+    /// Trunk height (relative to `trunk_height`) at which we start creating
+    /// branches.
+    pub min_branch_height: (f32, f32),
+    /// How many branches will the tree have
+    pub branch_chance: f32,
+    /// Range of subbranch diameters as a factor of the parent branch.
+    pub branch_diameter_factor: (f32, f32),
+    /// Range of subbranch angles in degrees.
+    pub branch_angle_deg: (f32, f32),
+    /// Factor by which to reduce segment diameter between consecutive points,
+    /// sampled per branch.
+    pub branch_diam_reduction: (f32, f32),
+    /// Factor determining branch length depending on the branch diameter.
+    pub branch_segment_length: (f32, f32),
+    /// Factor determining branch length depending on the branch diameter for
+    /// branches starting with recursion depth 2
+    pub branch_segment_length2: (f32, f32),
+    /// Range of angles to use for rotation of new segments.
+    /// The higher the angle, the more "twisted" branches appear.
+    pub branch_segment_angle: (f32, f32),
+    /// Range of segment counts for branches (both inclusive).
+    ///
+    /// Together with `branch_segment_length`, this defines the overall branch
+    /// length.
+    pub branch_segment_count: (u32, u32),
+    /// The color of the trunk, as ranges of the RGB channels (in `0..1`).
+    pub trunk_color: ((f32, f32), (f32, f32), (f32, f32)),
+    /// The color of the leafs, as ranges of the RGB channels (in `0..1`).
+    pub leaf_color: ((f32, f32), (f32, f32), (f32, f32)),
+    /// At which recursion depth should the color switch.
+    /// x > 3 basically means only trunk_color will be used (because plants are
+    /// generated with a max depth of 3). The actual trunk though will always
+    /// get trunk_color, even if leaf_depth is 0.
+    pub leaf_depth: u16,
+    /// Factor for the branch length depending on the height at which the
+    /// branch starts. For conifer trees the branches become smaller with
+    /// height.
+    pub height_curve: HeightCurve,
+}
+
+impl Preset {
+    /// Checks whether trees can be generated with these parameters.
+    pub fn validate(&self) -> Result<(), PresetError> {
+        let ranges = [("trunk_diameter", self.trunk_diameter),
+                      ("trunk_height", self.trunk_height),
+                      ("trunk_diameter_top", self.trunk_diameter_top),
+                      ("min_branch_height", self.min_branch_height),
+                      ("branch_diameter_factor", self.branch_diameter_factor),
+                      ("branch_angle_deg", self.branch_angle_deg),
+                      ("branch_diam_reduction", self.branch_diam_reduction),
+                      ("branch_segment_length", self.branch_segment_length),
+                      ("branch_segment_length2", self.branch_segment_length2),
+                      ("branch_segment_angle", self.branch_segment_angle),
+                      ("trunk_color", self.trunk_color.0),
+                      ("trunk_color", self.trunk_color.1),
+                      ("trunk_color", self.trunk_color.2),
+                      ("leaf_color", self.leaf_color.0),
+                      ("leaf_color", self.leaf_color.1),
+                      ("leaf_color", self.leaf_color.2)];
+        for &(field, (min, max)) in &ranges {
+            if !min.is_finite() || !max.is_finite() || min < 0.0 {
+                return Err(PresetError::OutOfBounds(field));
+            }
+            if min > max {
+                return Err(PresetError::InvertedRange(field));
+            }
+        }
+
+        let colors = [self.trunk_color.0,
+                      self.trunk_color.1,
+                      self.trunk_color.2,
+                      self.leaf_color.0,
+                      self.leaf_color.1,
+                      self.leaf_color.2];
+        let count = self.branch_segment_count;
+        if self.trunk_diameter.0 <= 0.0 {
+            Err(PresetError::OutOfBounds("trunk_diameter"))
+        } else if self.trunk_height.0 <= 0.0 {
+            Err(PresetError::OutOfBounds("trunk_height"))
+        } else if self.min_branch_height.1 > 1.0 {
+            Err(PresetError::OutOfBounds("min_branch_height"))
+        } else if !self.branch_chance.is_finite() || self.branch_chance < 0.0 {
+            Err(PresetError::OutOfBounds("branch_chance"))
+        } else if count.0 > count.1 {
+            Err(PresetError::InvertedRange("branch_segment_count"))
+        } else if count.0 == 0 {
+            // Every branch needs at least one segment
+            Err(PresetError::OutOfBounds("branch_segment_count"))
+        } else if colors[..3].iter().any(|c| c.1 > 1.0) {
+            Err(PresetError::OutOfBounds("trunk_color"))
+        } else if colors[3..].iter().any(|c| c.1 > 1.0) {
+            Err(PresetError::OutOfBounds("leaf_color"))
+        } else {
+            self.height_curve.validate()
+        }
+    }
+
+    /// Reads a preset from the given TOML table. Fields which aren't given
+    /// are taken from `base`; without a base, all fields except the height
+    /// curve are required. The preset is validated.
+    pub fn from_toml(table: &Table, base: Option<&Preset>) -> Result<Self, PresetError> {
+        let field = |name: &'static str| {
+            table.get(name).ok_or(PresetError::InvalidField(name))
+        };
+        macro_rules! read {
+            ($name:ident, $read:expr) => {
+                match (field(stringify!($name)), base) {
+                    (Ok(value), _) => {
+                        try!($read(value).ok_or(PresetError::InvalidField(stringify!($name))))
+                    }
+                    (Err(_), Some(base)) => base.$name.clone(),
+                    (Err(e), None) => return Err(e),
+                }
+            }
+        }
+
+        let height_curve = match (table.get("height_curve"), base) {
+            (Some(value), _) => {
+                try!(parse_curve(value).ok_or(PresetError::InvalidField("height_curve")))
+            }
+            (None, Some(base)) => base.height_curve.clone(),
+            (None, None) => HeightCurve::default(),
+        };
+
+        let preset = Preset {
+            trunk_diameter: read!(trunk_diameter, range),
+            trunk_height: read!(trunk_height, range),
+            trunk_diameter_top: read!(trunk_diameter_top, range),
+            min_branch_height: read!(min_branch_height, range),
+            branch_chance: read!(branch_chance, float),
+            branch_diameter_factor: read!(branch_diameter_factor, range),
+            branch_angle_deg: read!(branch_angle_deg, range),
+            branch_diam_reduction: read!(branch_diam_reduction, range),
+            branch_segment_length: read!(branch_segment_length, range),
+            branch_segment_length2: read!(branch_segment_length2, range),
+            branch_segment_angle: read!(branch_segment_angle, range),
+            branch_segment_count: read!(branch_segment_count, count_range),
+            trunk_color: read!(trunk_color, color),
+            leaf_color: read!(leaf_color, color),
+            leaf_depth: read!(leaf_depth, short),
+            height_curve: height_curve,
+        };
+        try!(preset.validate());
+        Ok(preset)
+    }
+}
+
+/// A piecewise linear function of the height. Between two points, the value
+/// is interpolated linearly; below the first and above the last point, the
+/// value of the nearest point is used. A curve without any points is `1.0`
+/// everywhere.
+#[derive(Clone, Debug, Default, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct HeightCurve {
+    /// `(height, value)` pairs, sorted by height.
+    points: Vec<(f32, f32)>,
+}
+
+impl HeightCurve {
+    /// Creates a curve through the given `(height, value)` points. The heights
+    /// have to increase strictly and all values have to be positive.
+    pub fn new(points: Vec<(f32, f32)>) -> Result<Self, PresetError> {
+        let curve = HeightCurve { points: points };
+        try!(curve.validate());
+        Ok(curve)
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    /// Returns the value of the curve at the given height.
+    pub fn at(&self, height: f32) -> f32 {
+        let next = match self.points.iter().position(|&(h, _)| h > height) {
+            Some(0) => return self.points[0].1,
+            Some(next) => next,
+            None => return self.points.last().map_or(1.0, |&(_, value)| value),
+        };
+
+        let (h0, v0) = self.points[next - 1];
+        let (h1, v1) = self.points[next];
+        v0 + (v1 - v0) * (height - h0) / (h1 - h0)
+    }
+
+    fn validate(&self) -> Result<(), PresetError> {
+        let increasing = self.points.windows(2).all(|w| w[0].0 < w[1].0);
+        let valid = self.points
+            .iter()
+            .all(|&(height, value)| height.is_finite() && value.is_finite() && value > 0.0);
+        if increasing && valid {
+            Ok(())
+        } else {
+            Err(PresetError::OutOfBounds("height_curve"))
+        }
+    }
+}
+
+/// The error type for invalid presets. Contains the name of the invalid
+/// field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresetError {
+    /// A field is missing or has the wrong type.
+    InvalidField(&'static str),
+    /// The minimum of a range is larger than its maximum.
+    InvertedRange(&'static str),
+    /// The value of a field is impossible, e.g. a negative length.
+    OutOfBounds(&'static str),
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PresetError::InvalidField(field) |
+            PresetError::InvertedRange(field) |
+            PresetError::OutOfBounds(field) => write!(f, "{} '{}'", self.description(), field),
+        }
+    }
+}
+
+impl Error for PresetError {
+    fn description(&self) -> &str {
+        match *self {
+            PresetError::InvalidField(_) => "missing or invalid field",
+            PresetError::InvertedRange(_) => "minimum is larger than maximum of range",
+            PresetError::OutOfBounds(_) => "impossible value of field",
+        }
+    }
+}
+
+/// Reads a range of numbers written as `[min, max]`.
+fn range(value: &Value) -> Option<(f32, f32)> {
+    float_array(value, 2).map(|v| (v[0], v[1]))
+}
+
+/// Reads a range of integers written as `[min, max]`.
+fn count_range(value: &Value) -> Option<(u32, u32)> {
+    let bounds: Option<Vec<_>> = match value.as_slice() {
+        Some(values) if values.len() == 2 => values.iter().map(count).collect(),
+        _ => None,
+    };
+    bounds.map(|b| (b[0], b[1]))
+}
+
+/// Reads an integer which fits into a `u32`.
+fn count(value: &Value) -> Option<u32> {
+    value.as_integer().and_then(|i| if i >= 0 && i <= u32::max_value() as i64 {
+        Some(i as u32)
+    } else {
+        None
+    })
+}
+
+/// Reads the ranges of the three channels of a color.
+fn color(value: &Value) -> Option<((f32, f32), (f32, f32), (f32, f32))> {
+    let channels: Option<Vec<_>> = match value.as_slice() {
+        Some(values) if values.len() == 3 => values.iter().map(range).collect(),
+        _ => None,
+    };
+    channels.map(|c| (c[0], c[1], c[2]))
+}
+
+/// Reads a height curve written as list of `[height, value]` points.
+fn parse_curve(value: &Value) -> Option<HeightCurve> {
+    let points: Option<Vec<_>> = value.as_slice()
+        .and_then(|points| points.iter().map(range).collect());
+    points.map(|points| HeightCurve { points: points })
+}
+
+#[cfg(test)]
+fn test_preset() -> Preset {
+    Preset {
+        trunk_diameter: (0.4, 0.6),
+        trunk_height: (5.0, 6.0),
+        trunk_diameter_top: (0.3, 0.5),
+        min_branch_height: (0.4, 0.5),
+        branch_chance: 6.0,
+        branch_diameter_factor: (0.7, 0.85),
+        branch_angle_deg: (80.0, 100.0),
+        branch_diam_reduction: (0.6, 0.7),
+        branch_segment_length: (0.15, 0.2),
+        branch_segment_length2: (0.5, 0.75),
+        branch_segment_angle: (3.0, 5.0),
+        branch_segment_count: (3, 3),
+        trunk_color: ((0.4, 0.4), (0.3, 0.3), (0.2, 0.2)),
+        leaf_color: ((0.2, 0.21), (0.45, 0.46), (0.2, 0.21)),
+        leaf_depth: 1,
+        height_curve: HeightCurve::default(),
+    }
+}
+
+#[test]
+fn height_curve_interpolates() {
+    assert_eq!(HeightCurve::default().at(3.0), 1.0);
+
+    let curve = HeightCurve::new(vec![(1.0, 2.0), (3.0, 1.0), (4.0, 5.0)]).unwrap();
+    assert_eq!(curve.at(-10.0), 2.0);
+    assert_eq!(curve.at(1.0), 2.0);
+    assert_eq!(curve.at(2.0), 1.5);
+    assert_eq!(curve.at(3.5), 3.0);
+    assert_eq!(curve.at(4.0), 5.0);
+    assert_eq!(curve.at(100.0), 5.0);
+
+    assert!(HeightCurve::new(vec![(1.0, 2.0), (1.0, 3.0)]).is_err());
+    assert!(HeightCurve::new(vec![(1.0, 2.0), (2.0, 0.0)]).is_err());
+}
+
+#[test]
+fn presets_are_read_from_toml() {
+    use data;
+
+    let src = r#"
+        trunk_diameter = [0.4, 0.6]
+        trunk_height = [5, 6]
+        trunk_diameter_top = [0.3, 0.5]
+        min_branch_height = [0.4, 0.5]
+        branch_chance = 6
+        branch_diameter_factor = [0.7, 0.85]
+        branch_angle_deg = [80, 100]
+        branch_diam_reduction = [0.6, 0.7]
+        branch_segment_length = [0.15, 0.2]
+        branch_segment_length2 = [0.5, 0.75]
+        branch_segment_angle = [3, 5]
+        branch_segment_count = [3, 3]
+        trunk_color = [[0.4, 0.4], [0.3, 0.3], [0.2, 0.2]]
+        leaf_color = [[0.2, 0.21], [0.45, 0.46], [0.2, 0.21]]
+        leaf_depth = 1
+    "#;
+    let preset = Preset::from_toml(&data::parse(src).unwrap(), None).unwrap();
+    assert_eq!(preset, test_preset());
+
+    // Missing fields are taken from the base
+    let table = data::parse("height_curve = [[0.0, 1.0], [8.0, 0.5]]").unwrap();
+    assert_eq!(Preset::from_toml(&table, None),
+               Err(PresetError::InvalidField("trunk_diameter")));
+    let changed = Preset::from_toml(&table, Some(&preset)).unwrap();
+    assert_eq!(changed.trunk_diameter, preset.trunk_diameter);
+    assert_eq!(changed.height_curve.at(4.0), 0.75);
+
+    let invalid = |src: &str| {
+        Preset::from_toml(&data::parse(src).unwrap(), Some(&preset)).unwrap_err()
+    };
+    assert_eq!(invalid("trunk_height = [6, 5]"),
+               PresetError::InvertedRange("trunk_height"));
+    assert_eq!(invalid("trunk_height = [0, 5]"),
+               PresetError::OutOfBounds("trunk_height"));
+    assert_eq!(invalid("branch_segment_count = [0, 2]"),
+               PresetError::OutOfBounds("branch_segment_count"));
+    assert_eq!(invalid("leaf_color = [[0, 1], [0, 2], [0, 1]]"),
+               PresetError::OutOfBounds("leaf_color"));
+    assert_eq!(invalid("height_curve = [[0, 1], [8, -1]]"),
+               PresetError::OutOfBounds("height_curve"));
+    assert_eq!(invalid("trunk_diameter = 0.5"),
+               PresetError::InvalidField("trunk_diameter"));
+}
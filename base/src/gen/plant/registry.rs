@@ -0,0 +1,506 @@
+//! Plant species and the registry describing them.
+//!
+//! Props only store the `PlantId` of their plant: the stable id of its
+//! species and the index of its variant. Every world generates a number of
+//! differently shaped variants of every species from its seed and the
+//! `Preset` of the species, so the same id always refers to the same plant.
+//! The built-in species are defined in `base/data/plants.toml` and are always
+//! available through `PlantRegistry::builtin()`. Data files refer to species
+//! by their unique name.
+
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::slice;
+use toml::Value;
+use data::{self, short};
+use gen::seeded_rng;
+use gen::world::decoration::Placement;
+use prop::Plant;
+use super::{GeneratorKind, PlantGenerator};
+use super::preset::{Preset, PresetError};
+
+/// The built-in plant species in TOML format.
+const BUILTIN_PLANTS: &'static str = include_str!("../../../data/plants.toml");
+
+/// The number of variants of species which don't specify it.
+pub const DEFAULT_VARIANTS: u16 = 5;
+
+/// The stable numeric id of a plant species. Ids are stored in saved chunks,
+/// so the id of a species must never change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct SpeciesId(pub u16);
+
+/// Identifies a single generated plant: a variant (in the range
+/// `0..variants`) of a species.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct PlantId {
+    pub species: SpeciesId,
+    pub variant: u16,
+}
+
+/// The description of a plant species.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Species {
+    pub id: SpeciesId,
+    /// The unique name used to refer to the species in data files.
+    pub name: String,
+    /// The generator of the plants. Any number of species can share one.
+    pub generator: GeneratorKind,
+    /// The number of differently generated plants of this species.
+    pub variants: u16,
+    /// Where the species grows in the world.
+    pub placement: Placement,
+    /// The parameters of the generated plants.
+    pub preset: Preset,
+}
+
+/// Maps species ids to their descriptions.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct PlantRegistry {
+    /// Sorted by the species id.
+    species: Vec<Species>,
+}
+
+impl PlantRegistry {
+    /// Creates a registry without any species.
+    pub fn new() -> Self {
+        PlantRegistry { species: Vec::new() }
+    }
+
+    /// Creates a registry with all built-in species.
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN_PLANTS).expect("built-in plant species are invalid")
+    }
+
+    /// Creates a registry with the species described by the given TOML data,
+    /// see `merge_toml()`.
+    pub fn from_toml(src: &str) -> Result<Self, PlantError> {
+        let mut registry = Self::new();
+        try!(registry.merge_toml(src));
+        Ok(registry)
+    }
+
+    /// Creates a registry with all built-in species and merges the species
+    /// from the given file into it, if it exists.
+    pub fn load(path: &Path) -> Result<Self, Box<Error>> {
+        let mut registry = Self::builtin();
+        if path.exists() {
+            let mut src = String::new();
+            try!(try!(File::open(path)).read_to_string(&mut src));
+            try!(registry.merge_toml(&src));
+        }
+        Ok(registry)
+    }
+
+    /// Adds the species described by the given TOML data. Every species is a
+    /// `[[species]]` table with an `id`, a `name`, the name of its
+    /// `generator`, optionally the number of `variants`, a `placement` table
+    /// (see `Placement`) and a `preset` table (see the `preset` module).
+    ///
+    /// Species with the id of an already registered species replace it. In
+    /// this case, the generator, the number of variants and all fields of the
+    /// placement and the preset which aren't given keep their old value. If
+    /// there is an error, the registry isn't changed at all.
+    pub fn merge_toml(&mut self, src: &str) -> Result<(), PlantError> {
+        let root = try!(data::parse(src).map_err(PlantError::Parse));
+        let entries = try!(data::entries(&root, "species")
+            .ok_or(PlantError::invalid("", "species")));
+
+        let mut merged = self.clone();
+        for entry in entries {
+            let species = try!(merged.parse_species(entry));
+            try!(merged.register(species));
+        }
+        *self = merged;
+        Ok(())
+    }
+
+    /// Adds the species to the registry, replacing the species with the same
+    /// id, if there is one.
+    pub fn register(&mut self, species: Species) -> Result<(), PlantError> {
+        if species.name.is_empty() {
+            return Err(PlantError::invalid("", "name"));
+        }
+        if species.variants == 0 {
+            return Err(PlantError::invalid(&species.name, "variants"));
+        }
+        if let Err(field) = species.placement.validate() {
+            return Err(PlantError::invalid(&species.name, placement_field(field)));
+        }
+        try!(species.preset.validate().map_err(|e| PlantError::preset(&species.name, e)));
+        if self.by_name(&species.name).map_or(false, |other| other.id != species.id) {
+            return Err(PlantError::DuplicateName(species.name));
+        }
+
+        match self.species.binary_search_by_key(&species.id, |s| s.id) {
+            Ok(index) => self.species[index] = species,
+            Err(index) => self.species.insert(index, species),
+        }
+        Ok(())
+    }
+
+    /// Checks the invariants `register()` guarantees. Registries received
+    /// over the network should be validated before they are used.
+    pub fn validate(&self) -> Result<(), PlantError> {
+        let mut checked = Self::new();
+        for species in &self.species {
+            if checked.get(species.id).is_some() {
+                return Err(PlantError::DuplicateId(species.id));
+            }
+            try!(checked.register(species.clone()));
+        }
+        Ok(())
+    }
+
+    /// Returns the species with the given id, if it is registered.
+    pub fn get(&self, id: SpeciesId) -> Option<&Species> {
+        self.species.binary_search_by_key(&id, |s| s.id).ok().map(|index| &self.species[index])
+    }
+
+    /// Returns the species with the given name, if it is registered.
+    pub fn by_name(&self, name: &str) -> Option<&Species> {
+        self.iter().find(|s| s.name == name)
+    }
+
+    /// Returns an iterator over all species, ordered by id.
+    pub fn iter(&self) -> slice::Iter<Species> {
+        self.species.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.species.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.species.is_empty()
+    }
+
+    /// Generates all variants of the given species. Every variant is
+    /// generated with its own random number generator, so a variant doesn't
+    /// change when other species or variants are added.
+    pub fn generate(&self, seed: u64, species: &[SpeciesId]) -> PlantList {
+        let mut plants = HashMap::new();
+        for species in self.iter().filter(|s| species.contains(&s.id)) {
+            for variant in 0..species.variants {
+                let mut rng = seeded_rng(seed, "PLANT_VARIANT", (species.id.0, variant));
+                let id = PlantId {
+                    species: species.id,
+                    variant: variant,
+                };
+                let generator = PlantGenerator::new(species.generator, species.preset.clone());
+                plants.insert(id, generator.generate(&mut rng));
+            }
+        }
+        PlantList { plants: plants }
+    }
+
+    /// Parses a single `[[species]]` table.
+    fn parse_species(&self, entry: &Value) -> Result<Species, PlantError> {
+        let table = match entry.as_table() {
+            Some(table) => table,
+            None => return Err(PlantError::invalid("", "species")),
+        };
+        let name = match table.get("name").and_then(|v| v.as_str()) {
+            Some(name) => name,
+            None => return Err(PlantError::invalid("", "name")),
+        };
+        let id = match table.get("id").and_then(short) {
+            Some(id) => SpeciesId(id),
+            None => return Err(PlantError::invalid(name, "id")),
+        };
+
+        let old = self.get(id);
+        let generator = match (table.get("generator"), old) {
+            (Some(v), _) => {
+                let kind = try!(v.as_str().ok_or(PlantError::invalid(name, "generator")));
+                try!(GeneratorKind::from_name(kind)
+                    .ok_or(PlantError::UnknownGenerator(kind.to_string())))
+            }
+            (None, Some(old)) => old.generator,
+            (None, None) => return Err(PlantError::invalid(name, "generator")),
+        };
+        let variants = match table.get("variants") {
+            Some(v) => try!(short(v).ok_or(PlantError::invalid(name, "variants"))),
+            None => old.map_or(DEFAULT_VARIANTS, |s| s.variants),
+        };
+        let preset = match (table.get("preset"), old) {
+            (Some(&Value::Table(ref preset)), _) => {
+                try!(Preset::from_toml(preset, old.map(|s| &s.preset))
+                    .map_err(|e| PlantError::preset(name, e)))
+            }
+            (None, Some(old)) => old.preset.clone(),
+            _ => return Err(PlantError::invalid(name, "preset")),
+        };
+        let placement = match (table.get("placement"), old) {
+            (Some(&Value::Table(ref placement)), _) => {
+                try!(Placement::from_toml(placement, old.map(|s| &s.placement))
+                    .map_err(|field| PlantError::invalid(name, placement_field(field))))
+            }
+            (None, Some(old)) => old.placement,
+            _ => return Err(PlantError::invalid(name, "placement")),
+        };
+
+        Ok(Species {
+            id: id,
+            name: name.to_string(),
+            generator: generator,
+            variants: variants,
+            placement: placement,
+            preset: preset,
+        })
+    }
+}
+
+/// Returns the name of the given field of a `placement` table for errors.
+fn placement_field(field: &'static str) -> &'static str {
+    match field {
+        "layer" => "placement.layer",
+        "spacing" => "placement.spacing",
+        "density" => "placement.density",
+        "cluster_frequency" => "placement.cluster_frequency",
+        "max_slope" => "placement.max_slope",
+        "min_altitude" => "placement.min_altitude",
+        "max_altitude" => "placement.max_altitude",
+        _ => "placement",
+    }
+}
+
+impl Default for PlantRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// The generated plants of a world, see `PlantRegistry::generate()`.
+#[derive(Clone, Debug, Default)]
+pub struct PlantList {
+    plants: HashMap<PlantId, Plant>,
+}
+
+impl PlantList {
+    /// Returns the plant with the given id, if it was generated.
+    pub fn get(&self, id: PlantId) -> Option<&Plant> {
+        self.plants.get(&id)
+    }
+
+    /// Returns an iterator over all plants in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<PlantId, Plant> {
+        self.plants.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.plants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plants.is_empty()
+    }
+}
+
+/// The error type for loading plant species.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlantError {
+    /// The data isn't valid TOML. Contains the messages of the parser.
+    Parse(String),
+    /// A field of a species is missing or has the wrong type.
+    InvalidField {
+        species: String,
+        field: &'static str,
+    },
+    /// There is no plant generator with the given name.
+    UnknownGenerator(String),
+    /// Two species with different ids have the same name.
+    DuplicateName(String),
+    /// Two species have the same id.
+    DuplicateId(SpeciesId),
+    /// The preset of a species is invalid.
+    InvalidPreset {
+        species: String,
+        error: PresetError,
+    },
+}
+
+impl PlantError {
+    fn invalid(species: &str, field: &'static str) -> Self {
+        PlantError::InvalidField {
+            species: species.to_string(),
+            field: field,
+        }
+    }
+
+    fn preset(species: &str, error: PresetError) -> Self {
+        PlantError::InvalidPreset {
+            species: species.to_string(),
+            error: error,
+        }
+    }
+}
+
+impl fmt::Display for PlantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlantError::Parse(ref msg) => write!(f, "{}: {}", self.description(), msg),
+            PlantError::InvalidField { ref species, field } => {
+                write!(f, "{} '{}' of species '{}'", self.description(), field, species)
+            }
+            PlantError::UnknownGenerator(ref name) |
+            PlantError::DuplicateName(ref name) => write!(f, "{} ('{}')", self.description(), name),
+            PlantError::DuplicateId(id) => write!(f, "{} ({})", self.description(), id.0),
+            PlantError::InvalidPreset { ref species, error } => {
+                write!(f, "{} of species '{}'", error, species)
+            }
+        }
+    }
+}
+
+impl Error for PlantError {
+    fn description(&self) -> &str {
+        match *self {
+            PlantError::Parse(_) => "invalid TOML",
+            PlantError::InvalidField { .. } => "missing or invalid field",
+            PlantError::UnknownGenerator(_) => "unknown plant generator",
+            PlantError::DuplicateName(_) => "species name is used twice",
+            PlantError::DuplicateId(_) => "species id is used twice",
+            PlantError::InvalidPreset { .. } => "invalid preset",
+        }
+    }
+}
+
+#[test]
+fn builtin_species() {
+    let registry = PlantRegistry::builtin();
+    assert_eq!(registry.len(), 8);
+    assert_eq!(registry.validate(), Ok(()));
+    for species in registry.iter() {
+        assert_eq!(registry.by_name(&species.name), Some(species));
+        assert_eq!(species.generator, GeneratorKind::Tree);
+        assert_eq!(species.variants, DEFAULT_VARIANTS);
+    }
+
+    // The ids are stored in saved chunks
+    assert_eq!(registry.by_name("withered_tree").unwrap().id, SpeciesId(1));
+    assert_eq!(registry.by_name("flower").unwrap().id, SpeciesId(8));
+    assert!(registry.by_name("palm").is_none());
+}
+
+#[test]
+fn merge_species() {
+    use gen::world::decoration::PlantLayer;
+
+    let mut registry = PlantRegistry::builtin();
+    registry.merge_toml(r#"
+        [[species]]
+        id = 7
+        name = "oak_tree"
+        variants = 12
+
+        [species.preset]
+        leaf_depth = 2
+
+        [species.placement]
+        max_altitude = 50
+
+        [[species]]
+        id = 6
+        name = "conifer"
+
+        # A new species sharing the generator of the others
+        [[species]]
+        id = 20
+        name = "birch"
+        generator = "tree"
+
+        [species.placement]
+        layer = "canopy"
+        spacing = 2.0
+        density = 0.8
+        cluster_frequency = 0.05
+        max_slope = 3
+        min_altitude = 10
+
+        [species.preset]
+        trunk_diameter = [0.2, 0.3]
+        trunk_height = [6.0, 9.0]
+        trunk_diameter_top = [0.1, 0.2]
+        min_branch_height = [0.3, 0.5]
+        branch_chance = 2.0
+        branch_diameter_factor = [0.4, 0.6]
+        branch_angle_deg = [40.0, 60.0]
+        branch_diam_reduction = [0.7, 0.8]
+        branch_segment_length = [11.25, 11.26]
+        branch_segment_length2 = [11.25, 11.26]
+        branch_segment_angle = [5.0, 10.0]
+        branch_segment_count = [1, 3]
+        trunk_color = [[0.9, 0.95], [0.9, 0.95], [0.85, 0.9]]
+        leaf_color = [[0.3, 0.4], [0.6, 0.7], [0.1, 0.2]]
+        leaf_depth = 1
+    "#)
+        .unwrap();
+
+    assert_eq!(registry.len(), 9);
+    assert_eq!(registry.validate(), Ok(()));
+    assert_eq!(registry.get(SpeciesId(7)).unwrap().variants, 12);
+    assert_eq!(registry.get(SpeciesId(6)).unwrap().variants, DEFAULT_VARIANTS);
+    let oak = registry.get(SpeciesId(7)).unwrap();
+    let builtin = PlantRegistry::builtin();
+    let builtin_oak = builtin.get(SpeciesId(7)).unwrap();
+    assert_eq!(oak.preset.leaf_depth, 2);
+    assert_eq!(oak.preset.trunk_diameter, builtin_oak.preset.trunk_diameter);
+    assert_eq!(oak.placement.altitude, (builtin_oak.placement.altitude.0, 50));
+    assert_eq!(oak.placement.spacing, builtin_oak.placement.spacing);
+
+    let birch = registry.by_name("birch").unwrap();
+    assert_eq!(birch.id, SpeciesId(20));
+    assert_eq!(birch.generator, GeneratorKind::Tree);
+    assert_eq!(birch.placement.layer, PlantLayer::Canopy);
+    assert_eq!(birch.placement.altitude, (10, u16::max_value()));
+
+    let invalid = |src: &str| PlantRegistry::builtin().merge_toml(src).unwrap_err();
+    assert_eq!(invalid("[[species]]\nid = 20\nname = \"palm\""),
+               PlantError::invalid("palm", "generator"));
+    assert_eq!(invalid("[[species]]\nid = 20\nname = \"palm\"\ngenerator = \"palm\""),
+               PlantError::UnknownGenerator("palm".into()));
+    assert_eq!(invalid("[[species]]\nid = 7\nname = \"shrub\""),
+               PlantError::DuplicateName("shrub".into()));
+    assert_eq!(invalid("[[species]]\nid = 20\nname = \"palm\"\ngenerator = \"tree\""),
+               PlantError::invalid("palm", "preset"));
+    assert_eq!(invalid("[[species]]\nid = 7\nname = \"oak_tree\"\n[species.preset]\n\
+                        trunk_height = [6.0, 5.0]"),
+               PlantError::preset("oak_tree", PresetError::InvertedRange("trunk_height")));
+    assert_eq!(invalid("[[species]]\nid = 7\nname = \"oak_tree\"\n[species.placement]\n\
+                        density = 2.0"),
+               PlantError::invalid("oak_tree", "placement.density"));
+    assert_eq!(invalid("[[species]]\nid = 2\nname = \"shrub\"\nvariants = 0"),
+               PlantError::invalid("shrub", "variants"));
+    assert_eq!(invalid("[[species]]\nid = -1\nname = \"shrub\""),
+               PlantError::invalid("shrub", "id"));
+}
+
+#[test]
+fn variants_are_stable() {
+    let registry = PlantRegistry::builtin();
+    let shrub = registry.by_name("shrub").unwrap().id;
+    let oak = registry.by_name("oak_tree").unwrap().id;
+    let flower = registry.by_name("flower").unwrap().id;
+    let plants = registry.generate(3, &[shrub, oak]);
+    assert_eq!(plants.len(), 2 * DEFAULT_VARIANTS as usize);
+    let id = PlantId {
+        species: oak,
+        variant: 2,
+    };
+    assert!(plants.get(PlantId { variant: DEFAULT_VARIANTS, ..id }).is_none());
+
+    // More variants and other species don't change the existing variants
+    let mut more = registry.clone();
+    more.merge_toml("[[species]]\nid = 7\nname = \"oak_tree\"\nvariants = 9").unwrap();
+    let others = more.generate(3, &[oak, flower]);
+    assert_eq!(others.len(), 9 + DEFAULT_VARIANTS as usize);
+    assert_eq!(format!("{:?}", others.get(id)), format!("{:?}", plants.get(id)));
+}
@@ -1,144 +1,961 @@
-use world::GroundMaterial;
-use gen::plant::tree::PlantType;
+//! Biomes and the registry describing them.
+//!
+//! Pillars only store the `BiomeId` of their biome. Everything else (the
+//! climate in which a biome is generated, its ground layers, plants, weather
+//! and terrain) is looked up in a `BiomeRegistry`, which is loaded from TOML.
+//! The built-in biomes are defined in `base/data/biomes.toml` and are always
+//! available through `BiomeRegistry::builtin()`.
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Biome {
-    GrassLand,
-    Desert,
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::slice;
+use toml::Value;
+use data::{self, byte, float, float_array};
+use gen::plant::registry::{PlantRegistry, SpeciesId};
+use world::{MaterialId, MaterialRegistry, materials};
+
+/// The built-in biomes in TOML format.
+const BUILTIN_BIOMES: &'static str = include_str!("../../../data/biomes.toml");
+
+/// The stable numeric id of a biome. Ids are stored in saved chunks, so the
+/// id of a biome must never change. The id `255` is reserved and never used
+/// by any biome.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BiomeId(pub u8);
+
+impl BiomeId {
+    pub fn is_reserved(&self) -> bool {
+        self.0 == u8::max_value()
+    }
+}
+
+impl Default for BiomeId {
+    fn default() -> Self {
+        biomes::DEBUG
+    }
+}
+
+/// The ids of the built-in biomes.
+pub mod biomes {
+    use super::BiomeId;
+
+    pub const GRASS_LAND: BiomeId = BiomeId(0);
+    pub const DESERT: BiomeId = BiomeId(1);
+    pub const SNOW: BiomeId = BiomeId(2);
+    pub const FOREST: BiomeId = BiomeId(3);
+    pub const RAIN_FOREST: BiomeId = BiomeId(4);
+    pub const SAVANNA: BiomeId = BiomeId(5);
+    pub const STONE: BiomeId = BiomeId(6);
+    pub const DEBUG: BiomeId = BiomeId(7);
+}
+
+/// The ranges of temperature and humidity (both in `0..1`) in which a biome
+/// is generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Climate {
+    pub temperature: (f32, f32),
+    pub humidity: (f32, f32),
+}
+
+/// The materials of the terrain layers, see `Strata`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GroundLayers {
+    pub surface: MaterialId,
+    pub subsoil: MaterialId,
+    pub rock: MaterialId,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeatherKind {
+    Rain,
     Snow,
-    Forest,
-    RainForest,
-    Savanna,
-    Stone,
-    Debug,
+    Pollen,
 }
 
-impl Default for Biome {
-    fn default() -> Biome {
-        Biome::Debug
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeatherStrength {
+    Weak,
+    Medium,
+    Heavy,
+}
+
+/// The weather of a biome.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeatherChances {
+    pub kind: WeatherKind,
+    /// The probabilities (in percent) of the weather strengths whenever the
+    /// weather changes.
+    pub weak: f32,
+    pub medium: f32,
+    pub heavy: f32,
+}
+
+impl WeatherChances {
+    /// Returns the strength of the weather for a random number in the range
+    /// `0..100` or `None` if there is no weather.
+    pub fn strength(&self, chance: f32) -> Option<WeatherStrength> {
+        if chance < self.weak {
+            Some(WeatherStrength::Weak)
+        } else if chance < self.weak + self.medium {
+            Some(WeatherStrength::Medium)
+        } else if chance < self.weak + self.medium + self.heavy {
+            Some(WeatherStrength::Heavy)
+        } else {
+            None
+        }
     }
 }
 
+/// A plant species growing in a biome.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlantChance {
+    pub species: SpeciesId,
+    /// The probability of the species is proportional to its weight.
+    pub weight: f32,
+}
+
+/// The description of a biome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Biome {
+    pub id: BiomeId,
+    /// A unique name, used to refer to the biome in data files.
+    pub name: String,
+    /// Biomes without a climate are never generated.
+    pub climate: Option<Climate>,
+    pub ground: GroundLayers,
+    /// Factor for the steepness of the terrain, which mainly depends on the
+    /// temperature.
+    pub steepness: f32,
+    /// Plants grow where the cluster noise of their species (in the range
+    /// -1..1) exceeds this value, see `decoration`.
+    pub plant_threshold: f32,
+    pub plants: Vec<PlantChance>,
+    pub weather: Option<WeatherChances>,
+}
+
 impl Biome {
-    pub fn material(&self) -> GroundMaterial {
-        match *self {
-            Biome::GrassLand => GroundMaterial::Grass,
-            Biome::Desert => GroundMaterial::Sand,
-            Biome::Snow => GroundMaterial::Snow,
-            Biome::Forest => GroundMaterial::Mulch,
-            Biome::RainForest => GroundMaterial::JungleGrass,
-            Biome::Savanna => GroundMaterial::Dirt,
-            Biome::Stone => GroundMaterial::Stone,
-            Biome::Debug => GroundMaterial::Debug,
+    /// Creates a biome with the given id and name, which is never generated
+    /// and has no plants and weather.
+    pub fn new(id: BiomeId, name: &str) -> Self {
+        Biome {
+            id: id,
+            name: name.to_string(),
+            climate: None,
+            ground: GroundLayers {
+                surface: materials::DIRT,
+                subsoil: materials::DIRT,
+                rock: materials::STONE,
+            },
+            steepness: 1.0,
+            plant_threshold: 1.0,
+            plants: Vec::new(),
+            weather: None,
         }
     }
 
-    pub fn plant_threshold(&self) -> f32 {
-        0.05 +
-        match *self {
-            Biome::GrassLand => 0.3,
-            Biome::Desert => 0.46,
-            Biome::Snow => 0.35,
-            Biome::Forest => 0.25,
-            Biome::RainForest => 0.21,
-            Biome::Savanna => 0.375,
-            Biome::Stone => 0.45,
-            Biome::Debug => 1.0,
+    /// The biome which is used for unknown ids. Its ground is drawn as debug
+    /// material.
+    fn fallback() -> Self {
+        Biome {
+            ground: GroundLayers {
+                surface: materials::DEBUG,
+                subsoil: materials::DEBUG,
+                rock: materials::DEBUG,
+            },
+            ..Biome::new(BiomeId(u8::max_value()), "unknown")
         }
     }
 
-    pub fn from_climate(temperature: f32, humidity: f32) -> Biome {
-        match (temperature, humidity) {
-            (0.0...0.2, 0.0...0.2) => Biome::Stone,
-            (0.0...0.2, 0.2...0.4) => Biome::Snow,
-            (0.0...0.2, 0.4...1.0) => Biome::Snow,
-            (0.2...0.4, 0.0...0.2) => Biome::GrassLand,
-            (0.2...0.4, 0.2...0.4) => Biome::GrassLand,
-            (0.2...0.4, 0.4...1.0) => Biome::Forest,
-            (0.4...1.0, 0.0...0.2) => Biome::Desert,
-            (0.4...1.0, 0.2...0.4) => Biome::Savanna,
-            (0.4...1.0, 0.4...1.0) => Biome::RainForest,
-            _ => Biome::Debug,
-        }
+    /// Chooses a plant species with a probability proportional to its
+    /// weight. `r` is a random number in the range 0..1.
+    pub fn pick_plant(&self, r: f32) -> Option<SpeciesId> {
+        self.pick_plant_where(r, |_| true)
     }
 
-    pub fn plant_distribution(&self) -> &'static [PlantType] {
-        match *self {
-            Biome::GrassLand => {
-                static PLANTS: &'static [PlantType] = &[PlantType::WitheredTree,
-                                                        PlantType::OakTree,
-                                                        PlantType::OakTree,
-                                                        PlantType::ClumpOfGrass,
-                                                        PlantType::ClumpOfGrass,
-                                                        PlantType::Flower,
-                                                        PlantType::Flower];
-                PLANTS
+    /// Like `pick_plant()`, but only chooses from the plant species for
+    /// which `filter` returns `true`.
+    pub fn pick_plant_where<F>(&self, r: f32, filter: F) -> Option<SpeciesId>
+        where F: Fn(SpeciesId) -> bool
+    {
+        let plants: Vec<_> = self.plants.iter().filter(|p| filter(p.species)).collect();
+        let total: f32 = plants.iter().map(|p| p.weight).sum();
+        let mut sum = 0.0;
+        for plant in &plants {
+            sum += plant.weight;
+            if r * total < sum {
+                return Some(plant.species);
             }
-            Biome::Desert => {
-                static PLANTS: &'static [PlantType] = &[PlantType::Cactus];
-                PLANTS
+        }
+        plants.iter().rev().find(|p| p.weight > 0.0).map(|p| p.species)
+    }
+}
+
+/// Maps biome ids to their descriptions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiomeRegistry {
+    /// Indexed by the biome id.
+    biomes: Vec<Option<Biome>>,
+    fallback: Biome,
+}
+
+impl BiomeRegistry {
+    /// Creates a registry without any biomes.
+    pub fn new() -> Self {
+        BiomeRegistry {
+            biomes: Vec::new(),
+            fallback: Biome::fallback(),
+        }
+    }
+
+    /// Creates a registry with all built-in biomes, which use the built-in
+    /// materials and plant species.
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN_BIOMES,
+                        &MaterialRegistry::builtin(),
+                        &PlantRegistry::builtin())
+            .expect("built-in biomes are invalid")
+    }
+
+    /// Creates a registry with the biomes described by the given TOML data,
+    /// see `merge_toml()`.
+    pub fn from_toml(src: &str,
+                     materials: &MaterialRegistry,
+                     plants: &PlantRegistry)
+                     -> Result<Self, BiomeError> {
+        let mut registry = Self::new();
+        try!(registry.merge_toml(src, materials, plants));
+        Ok(registry)
+    }
+
+    /// Creates a registry with all built-in biomes and merges the biomes
+    /// from the given file into it, if it exists.
+    pub fn load(path: &Path,
+                materials: &MaterialRegistry,
+                plants: &PlantRegistry)
+                -> Result<Self, Box<Error>> {
+        let mut registry = try!(Self::from_toml(BUILTIN_BIOMES, materials, plants));
+        if path.exists() {
+            let mut src = String::new();
+            try!(try!(File::open(path)).read_to_string(&mut src));
+            try!(registry.merge_toml(&src, materials, plants));
+        }
+        Ok(registry)
+    }
+
+    /// Adds the biomes described by the given TOML data. Every biome is a
+    /// `[[biome]]` table with at least an `id` and a `name` (see
+    /// `base/data/biomes.toml` for all fields). Materials and plant species
+    /// are referred to by their name in the given registries.
+    ///
+    /// Biomes with the id of an already registered biome replace it. In this
+    /// case, all fields which aren't given keep their old value, otherwise
+    /// they get a default value. If there is an error, the registry isn't
+    /// changed at all.
+    pub fn merge_toml(&mut self,
+                      src: &str,
+                      materials: &MaterialRegistry,
+                      plants: &PlantRegistry)
+                      -> Result<(), BiomeError> {
+        let root = try!(data::parse(src).map_err(BiomeError::Parse));
+        let entries = try!(data::entries(&root, "biome").ok_or(BiomeError::invalid("", "biome")));
+
+        let mut merged = self.clone();
+        for entry in entries {
+            let biome = try!(merged.parse_biome(entry, materials, plants));
+            try!(merged.register(biome));
+        }
+        *self = merged;
+        Ok(())
+    }
+
+    /// Adds the biome to the registry, replacing the biome with the same id,
+    /// if there is one.
+    pub fn register(&mut self, biome: Biome) -> Result<(), BiomeError> {
+        if biome.id.is_reserved() {
+            return Err(BiomeError::ReservedId(biome.name));
+        }
+        if self.by_name(&biome.name).map_or(false, |other| other.id != biome.id) {
+            return Err(BiomeError::DuplicateName(biome.name));
+        }
+
+        let index = biome.id.0 as usize;
+        if self.biomes.len() <= index {
+            self.biomes.resize(index + 1, None);
+        }
+        self.biomes[index] = Some(biome);
+        Ok(())
+    }
+
+    /// Returns the biome with the given id, if it is registered.
+    pub fn get(&self, id: BiomeId) -> Option<&Biome> {
+        self.biomes.get(id.0 as usize).and_then(|b| b.as_ref())
+    }
+
+    /// Returns the biome with the given id. For unknown ids, a placeholder
+    /// biome with debug ground is returned.
+    pub fn biome(&self, id: BiomeId) -> &Biome {
+        self.get(id).unwrap_or(&self.fallback)
+    }
+
+    /// Returns the biome with the given name.
+    pub fn by_name(&self, name: &str) -> Option<&Biome> {
+        self.iter().find(|b| b.name == name)
+    }
+
+    /// Returns an iterator over all biomes, ordered by id.
+    pub fn iter(&self) -> Biomes {
+        Biomes { inner: self.biomes.iter() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the ids of all plant species growing in any biome, sorted.
+    pub fn plant_species(&self) -> Vec<SpeciesId> {
+        let mut species: Vec<_> =
+            self.iter().flat_map(|b| b.plants.iter().map(|p| p.species)).collect();
+        species.sort();
+        species.dedup();
+        species
+    }
+
+    /// Returns the biome for the given climate. Temperature and humidity
+    /// should be in the range 0..1, other values are clamped.
+    pub fn from_climate(&self, temperature: f32, humidity: f32) -> BiomeId {
+        let sharp = BiomeTransition {
+            temperature: 0.0,
+            humidity: 0.0,
+        };
+        self.blend_from_climate(temperature, humidity, &sharp).dominant()
+    }
+
+    /// Returns the weights of all biomes contributing to the given climate.
+    ///
+    /// Within the transition widths around the borders of the climate ranges
+    /// the neighbouring biomes are blended smoothly. Temperature and humidity
+    /// are clamped to the range 0..1. If no biome covers the climate, the
+    /// nearest one is used.
+    pub fn blend_from_climate(&self,
+                              temperature: f32,
+                              humidity: f32,
+                              transition: &BiomeTransition)
+                              -> BiomeBlend {
+        let (temperature, humidity) = (clamp(temperature), clamp(humidity));
+
+        let mut weights = Vec::new();
+        let mut total = 0.0;
+        for biome in self.iter() {
+            if let Some(climate) = biome.climate {
+                let weight =
+                    range_weight(temperature, climate.temperature, transition.temperature) *
+                    range_weight(humidity, climate.humidity, transition.humidity);
+                if weight > 0.0 {
+                    weights.push((biome.id, weight));
+                    total += weight;
+                }
             }
-            Biome::Snow => {
-                static PLANTS: &'static [PlantType] = &[PlantType::Conifer];
-                PLANTS
+        }
+
+        if total <= 0.0 {
+            let distance = |climate: &Climate| {
+                let dt = range_distance(temperature, climate.temperature);
+                let dh = range_distance(humidity, climate.humidity);
+                dt * dt + dh * dh
+            };
+            let nearest = self.iter()
+                .filter_map(|b| b.climate.map(|c| (b.id, distance(&c))))
+                .fold(None, |nearest: Option<(BiomeId, f32)>, (id, d)| match nearest {
+                    Some((_, min)) if min <= d => nearest,
+                    _ => Some((id, d)),
+                })
+                .map_or(biomes::DEBUG, |(id, _)| id);
+            return BiomeBlend::single(nearest);
+        }
+
+        for &mut (_, ref mut weight) in &mut weights {
+            *weight /= total;
+        }
+        // Sorting is stable, so equal weights keep the order of the ids
+        weights.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+        BiomeBlend { weights: weights }
+    }
+
+    /// Parses a single `[[biome]]` table. Fields which aren't given are taken
+    /// from the registered biome with the same id, if there is one.
+    fn parse_biome(&self,
+                   entry: &Value,
+                   materials: &MaterialRegistry,
+                   plants: &PlantRegistry)
+                   -> Result<Biome, BiomeError> {
+        let table = match entry.as_table() {
+            Some(table) => table,
+            None => return Err(BiomeError::invalid("", "biome")),
+        };
+        let name = match table.get("name").and_then(|v| v.as_str()) {
+            Some(name) => name,
+            None => return Err(BiomeError::invalid("", "name")),
+        };
+        let id = match table.get("id").and_then(byte) {
+            Some(id) => BiomeId(id),
+            None => return Err(BiomeError::invalid(name, "id")),
+        };
+
+        let mut biome = self.get(id).cloned().unwrap_or_else(|| Biome::new(id, name));
+        biome.name = name.to_string();
+
+        if let Some(v) = table.get("steepness") {
+            biome.steepness = try!(float(v).ok_or(BiomeError::invalid(name, "steepness")));
+        }
+        if let Some(v) = table.get("plant_threshold") {
+            biome.plant_threshold = try!(float(v)
+                .ok_or(BiomeError::invalid(name, "plant_threshold")));
+        }
+        if let Some(v) = table.get("climate") {
+            biome.climate = Some(try!(parse_climate(v, biome.climate, name)));
+        }
+        if let Some(v) = table.get("ground") {
+            biome.ground = try!(parse_ground(v, biome.ground, name, materials));
+        }
+        if let Some(v) = table.get("weather") {
+            biome.weather = Some(try!(parse_weather(v, biome.weather, name)));
+        }
+        if let Some(v) = table.get("plants") {
+            biome.plants = try!(parse_plants(v, name, plants));
+        }
+
+        Ok(biome)
+    }
+}
+
+impl Default for BiomeRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Iterator over all biomes of a `BiomeRegistry`.
+pub struct Biomes<'a> {
+    inner: slice::Iter<'a, Option<Biome>>,
+}
+
+impl<'a> Iterator for Biomes<'a> {
+    type Item = &'a Biome;
+
+    fn next(&mut self) -> Option<&'a Biome> {
+        while let Some(entry) = self.inner.next() {
+            if let Some(ref biome) = *entry {
+                return Some(biome);
             }
-            Biome::Forest => {
-                static PLANTS: &'static [PlantType] = &[PlantType::WitheredTree,
-                                                        PlantType::Conifer,
-                                                        PlantType::OakTree,
-                                                        PlantType::OakTree,
-                                                        PlantType::OakTree,
-                                                        PlantType::Conifer,
-                                                        PlantType::ClumpOfGrass,
-                                                        PlantType::Conifer,
-                                                        PlantType::Flower,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub];
-                PLANTS
+        }
+        None
+    }
+}
+
+/// The widths of the transitions between biomes, in units of the climate
+/// values. A width of 0 results in sharp borders.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct BiomeTransition {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+impl Default for BiomeTransition {
+    fn default() -> Self {
+        BiomeTransition {
+            temperature: 0.04,
+            humidity: 0.04,
+        }
+    }
+}
+
+/// The weights of the biomes at a position, see
+/// `BiomeRegistry::blend_from_climate()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiomeBlend {
+    /// Sorted by descending weight, the weights sum up to 1.
+    weights: Vec<(BiomeId, f32)>,
+}
+
+impl BiomeBlend {
+    /// Creates a blend which consists of a single biome.
+    pub fn single(biome: BiomeId) -> Self {
+        BiomeBlend { weights: vec![(biome, 1.0)] }
+    }
+
+    /// Returns all contributing biomes with their weight, the biome with the
+    /// largest weight first.
+    pub fn weights(&self) -> &[(BiomeId, f32)] {
+        &self.weights
+    }
+
+    /// Returns the biome with the largest weight.
+    pub fn dominant(&self) -> BiomeId {
+        self.weights[0].0
+    }
+
+    /// Calculates the weighted average of a value depending on the biome.
+    pub fn blend<F>(&self, mut f: F) -> f32
+        where F: FnMut(BiomeId) -> f32
+    {
+        self.weights.iter().map(|&(biome, weight)| weight * f(biome)).sum()
+    }
+
+    /// Picks a biome with a probability equal to its weight. `r` is a random
+    /// number in the range 0..1.
+    pub fn pick(&self, r: f32) -> BiomeId {
+        let mut sum = 0.0;
+        for &(biome, weight) in &self.weights {
+            sum += weight;
+            if r < sum {
+                return biome;
             }
-            Biome::RainForest => {
-                static PLANTS: &'static [PlantType] = &[PlantType::JungleTree,
-                                                        PlantType::JungleTree,
-                                                        PlantType::JungleTree,
-                                                        PlantType::JungleTree,
-                                                        PlantType::OakTree,
-                                                        PlantType::ClumpOfGrass,
-                                                        PlantType::ClumpOfGrass,
-                                                        PlantType::OakTree,
-                                                        PlantType::Shrub,
-                                                        PlantType::WitheredTree];
-                PLANTS
+        }
+        self.weights[self.weights.len() - 1].0
+    }
+}
+
+/// The error type for loading biomes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BiomeError {
+    /// The data isn't valid TOML. Contains the messages of the parser.
+    Parse(String),
+    /// A field of a biome is missing or has the wrong type.
+    InvalidField {
+        biome: String,
+        field: &'static str,
+    },
+    /// A ground layer uses a material which isn't registered.
+    UnknownMaterial {
+        biome: String,
+        material: String,
+    },
+    /// A plant species doesn't exist.
+    UnknownPlant {
+        biome: String,
+        plant: String,
+    },
+    /// A biome uses the reserved id `255`.
+    ReservedId(String),
+    /// Two biomes with different ids have the same name.
+    DuplicateName(String),
+}
+
+impl BiomeError {
+    fn invalid(biome: &str, field: &'static str) -> Self {
+        BiomeError::InvalidField {
+            biome: biome.to_string(),
+            field: field,
+        }
+    }
+}
+
+impl fmt::Display for BiomeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BiomeError::Parse(ref msg) => write!(f, "{}: {}", self.description(), msg),
+            BiomeError::InvalidField { ref biome, field } => {
+                write!(f, "{} '{}' of biome '{}'", self.description(), field, biome)
             }
-            Biome::Savanna => {
-                static PLANTS: &'static [PlantType] = &[PlantType::OakTree,
-                                                        PlantType::ClumpOfGrass,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub,
-                                                        PlantType::Shrub];
-                PLANTS
+            BiomeError::UnknownMaterial { ref biome, material: ref name } |
+            BiomeError::UnknownPlant { ref biome, plant: ref name } => {
+                write!(f, "{} '{}' in biome '{}'", self.description(), name, biome)
             }
-            Biome::Stone => {
-                static PLANTS: &'static [PlantType] = &[PlantType::Conifer,
-                                                        PlantType::Conifer,
-                                                        PlantType::Conifer,
-                                                        PlantType::Conifer,
-                                                        PlantType::OakTree,
-                                                        PlantType::Conifer,
-                                                        PlantType::Conifer,
-                                                        PlantType::Conifer];
-                PLANTS
+            BiomeError::ReservedId(ref name) |
+            BiomeError::DuplicateName(ref name) => write!(f, "{} ('{}')", self.description(), name),
+        }
+    }
+}
+
+impl Error for BiomeError {
+    fn description(&self) -> &str {
+        match *self {
+            BiomeError::Parse(_) => "invalid TOML",
+            BiomeError::InvalidField { .. } => "missing or invalid field",
+            BiomeError::UnknownMaterial { .. } => "unknown material",
+            BiomeError::UnknownPlant { .. } => "unknown plant species",
+            BiomeError::ReservedId(_) => "biome uses the reserved id 255",
+            BiomeError::DuplicateName(_) => "biome name is used twice",
+        }
+    }
+}
+
+fn parse_climate(value: &Value,
+                 climate: Option<Climate>,
+                 name: &str)
+                 -> Result<Climate, BiomeError> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Err(BiomeError::invalid(name, "climate")),
+    };
+    let mut climate = climate.unwrap_or(Climate {
+        temperature: (0.0, 1.0),
+        humidity: (0.0, 1.0),
+    });
+
+    let range = |key: &str, field| {
+        let invalid = BiomeError::invalid(name, field);
+        let range = try!(table.get(key).and_then(|v| float_array(v, 2)).ok_or(invalid.clone()));
+        if range[0] > range[1] {
+            return Err(invalid);
+        }
+        Ok((range[0], range[1]))
+    };
+    if table.contains_key("temperature") {
+        climate.temperature = try!(range("temperature", "climate.temperature"));
+    }
+    if table.contains_key("humidity") {
+        climate.humidity = try!(range("humidity", "climate.humidity"));
+    }
+
+    Ok(climate)
+}
+
+fn parse_ground(value: &Value,
+                mut ground: GroundLayers,
+                name: &str,
+                materials: &MaterialRegistry)
+                -> Result<GroundLayers, BiomeError> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Err(BiomeError::invalid(name, "ground")),
+    };
+
+    let layers = [("surface", "ground.surface"),
+                  ("subsoil", "ground.subsoil"),
+                  ("rock", "ground.rock")];
+    for &(key, field) in &layers {
+        let material = match table.get(key) {
+            Some(v) => try!(v.as_str().ok_or(BiomeError::invalid(name, field))),
+            None => continue,
+        };
+        let id = match materials.by_name(material) {
+            Some(material) => material.id,
+            None => {
+                return Err(BiomeError::UnknownMaterial {
+                    biome: name.to_string(),
+                    material: material.to_string(),
+                })
             }
-            Biome::Debug => {
-                static PLANTS: &'static [PlantType] = &[PlantType::ClumpOfGrass];
-                PLANTS
+        };
+        match key {
+            "surface" => ground.surface = id,
+            "subsoil" => ground.subsoil = id,
+            _ => ground.rock = id,
+        }
+    }
+
+    Ok(ground)
+}
+
+fn parse_weather(value: &Value,
+                 weather: Option<WeatherChances>,
+                 name: &str)
+                 -> Result<WeatherChances, BiomeError> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Err(BiomeError::invalid(name, "weather")),
+    };
+
+    let kind = match table.get("kind").map(|v| v.as_str()) {
+        Some(Some("rain")) => WeatherKind::Rain,
+        Some(Some("snow")) => WeatherKind::Snow,
+        Some(Some("pollen")) => WeatherKind::Pollen,
+        None if weather.is_some() => weather.unwrap().kind,
+        _ => return Err(BiomeError::invalid(name, "weather.kind")),
+    };
+    let mut weather = weather.unwrap_or(WeatherChances {
+        kind: kind,
+        weak: 0.0,
+        medium: 0.0,
+        heavy: 0.0,
+    });
+    weather.kind = kind;
+
+    if let Some(v) = table.get("weak") {
+        weather.weak = try!(float(v).ok_or(BiomeError::invalid(name, "weather.weak")));
+    }
+    if let Some(v) = table.get("medium") {
+        weather.medium = try!(float(v).ok_or(BiomeError::invalid(name, "weather.medium")));
+    }
+    if let Some(v) = table.get("heavy") {
+        weather.heavy = try!(float(v).ok_or(BiomeError::invalid(name, "weather.heavy")));
+    }
+
+    Ok(weather)
+}
+
+fn parse_plants(value: &Value,
+                name: &str,
+                registry: &PlantRegistry)
+                -> Result<Vec<PlantChance>, BiomeError> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Err(BiomeError::invalid(name, "plants")),
+    };
+
+    let mut plants = Vec::new();
+    for (species, weight) in table {
+        let id = match registry.by_name(species) {
+            Some(species) => species.id,
+            None => {
+                return Err(BiomeError::UnknownPlant {
+                    biome: name.to_string(),
+                    plant: species.clone(),
+                })
             }
+        };
+        let weight = match float(weight) {
+            Some(weight) if weight >= 0.0 => weight,
+            _ => return Err(BiomeError::invalid(name, "plants")),
+        };
+        plants.push(PlantChance {
+            species: id,
+            weight: weight,
+        });
+    }
+
+    Ok(plants)
+}
+
+fn clamp(x: f32) -> f32 {
+    // `max` returns 0 for NaN
+    x.max(0.0).min(1.0)
+}
+
+/// Returns the weight of the given range for the value `x`. The range is
+/// treated as unbounded if it ends at 0 or 1.
+fn range_weight(x: f32, range: (f32, f32), width: f32) -> f32 {
+    // The share of `x` which lies above the given border
+    let above = |border: f32| {
+        if width > 0.0 {
+            let t = clamp((x - border) / width + 0.5);
+            t * t * (3.0 - 2.0 * t)
+        } else if x > border {
+            1.0
+        } else {
+            0.0
         }
+    };
+    let low = if range.0 <= 0.0 { 1.0 } else { above(range.0) };
+    let high = if range.1 >= 1.0 { 0.0 } else { above(range.1) };
+    (low - high).max(0.0)
+}
+
+/// Returns how far `x` lies outside of the range.
+fn range_distance(x: f32, range: (f32, f32)) -> f32 {
+    (range.0 - x).max(x - range.1).max(0.0)
+}
+
+#[test]
+fn builtin_biomes() {
+    let registry = BiomeRegistry::builtin();
+    let names = ["grass_land", "desert", "snow", "forest", "rain_forest", "savanna", "stone",
+                 "debug"];
+    assert_eq!(registry.len(), names.len());
+    for (i, &name) in names.iter().enumerate() {
+        assert_eq!(registry.get(BiomeId(i as u8)).unwrap().name, name);
     }
+
+    let savanna = registry.get(biomes::SAVANNA).unwrap();
+    assert_eq!(savanna.ground.surface, materials::DIRT);
+    assert_eq!(savanna.weather.unwrap().kind, WeatherKind::Pollen);
+    assert!(savanna.plants.contains(&PlantChance {
+        species: PlantRegistry::builtin().by_name("shrub").unwrap().id,
+        weight: 10.0,
+    }));
+    assert_eq!(registry.get(biomes::RAIN_FOREST).unwrap().ground.surface,
+               materials::JUNGLE_GRASS);
+    assert_eq!(registry.get(biomes::DEBUG).unwrap().climate, None);
+    let species: Vec<_> = PlantRegistry::builtin().iter().map(|s| s.id).collect();
+    assert_eq!(registry.plant_species(), species);
+
+    assert_eq!(registry.get(BiomeId(100)), None);
+    assert_eq!(registry.biome(BiomeId(100)).ground.surface, materials::DEBUG);
+}
+
+#[test]
+fn merge_biomes() {
+    let plants = PlantRegistry::builtin();
+    let mut registry = BiomeRegistry::builtin();
+    registry.merge_toml(r#"
+        [[biome]]
+        id = 2
+        name = "snow"
+        [biome.ground]
+        subsoil = "stone"
+
+        [[biome]]
+        id = 20
+        name = "swamp"
+        [biome.climate]
+        temperature = [0.3, 0.5]
+        humidity = [0.8, 1.0]
+        [biome.plants]
+        shrub = 1
+        flower = 3
+    "#,
+                      &MaterialRegistry::builtin(),
+                      &plants)
+        .unwrap();
+
+    let snow = registry.get(biomes::SNOW).unwrap();
+    assert_eq!(snow.ground.subsoil, materials::STONE);
+    assert_eq!(snow.ground.surface, materials::SNOW);
+    assert_eq!(snow.weather.unwrap().kind, WeatherKind::Snow);
+
+    let swamp = registry.by_name("swamp").unwrap();
+    assert_eq!(swamp.plants.len(), 2);
+    assert_eq!(swamp.pick_plant(0.0), Some(plants.by_name("flower").unwrap().id));
+    assert_eq!(swamp.pick_plant(0.99), Some(plants.by_name("shrub").unwrap().id));
+    assert_eq!(swamp.weather, None);
+
+    // The new biome overlaps with others, the weights are normalized
+    let blend = registry.blend_from_climate(0.45, 0.9, &BiomeTransition::default());
+    assert_eq!(blend.weights(), &[(biomes::RAIN_FOREST, 0.5), (BiomeId(20), 0.5)]);
+}
+
+#[test]
+fn reject_invalid_biomes() {
+    let materials = MaterialRegistry::builtin();
+    let plants = PlantRegistry::builtin();
+    let invalid = |src: &str| {
+        BiomeRegistry::builtin().merge_toml(src, &materials, &plants).unwrap_err()
+    };
+
+    match invalid("[[biome]\nid = 3") {
+        BiomeError::Parse(_) => {}
+        e => panic!("unexpected error {:?}", e),
+    }
+    assert_eq!(invalid("[[biome]]\nid = 30"), BiomeError::invalid("", "name"));
+    assert_eq!(invalid("[[biome]]\nid = 30\nname = \"a\"\nsteepness = \"steep\""),
+               BiomeError::invalid("a", "steepness"));
+    assert_eq!(invalid("[[biome]]\nid = 30\nname = \"a\"\n[biome.climate]\nhumidity = [0.5, 0.2]"),
+               BiomeError::invalid("a", "climate.humidity"));
+    assert_eq!(invalid("[[biome]]\nid = 30\nname = \"a\"\n[biome.weather]\nweak = 3"),
+               BiomeError::invalid("a", "weather.kind"));
+    assert_eq!(invalid("[[biome]]\nid = 30\nname = \"a\"\n[biome.ground]\nrock = \"cheese\""),
+               BiomeError::UnknownMaterial {
+                   biome: "a".into(),
+                   material: "cheese".into(),
+               });
+    assert_eq!(invalid("[[biome]]\nid = 30\nname = \"a\"\n[biome.plants]\npalm = 1"),
+               BiomeError::UnknownPlant {
+                   biome: "a".into(),
+                   plant: "palm".into(),
+               });
+    assert_eq!(invalid("[[biome]]\nid = 255\nname = \"a\""),
+               BiomeError::ReservedId("a".into()));
+    assert_eq!(invalid("[[biome]]\nid = 30\nname = \"desert\""),
+               BiomeError::DuplicateName("desert".into()));
+}
+
+#[test]
+fn weather_strength() {
+    let weather = WeatherChances {
+        kind: WeatherKind::Rain,
+        weak: 5.0,
+        medium: 3.0,
+        heavy: 2.0,
+    };
+    assert_eq!(weather.strength(0.0), Some(WeatherStrength::Weak));
+    assert_eq!(weather.strength(6.0), Some(WeatherStrength::Medium));
+    assert_eq!(weather.strength(9.5), Some(WeatherStrength::Heavy));
+    assert_eq!(weather.strength(10.0), None);
+}
+
+#[test]
+fn climate_is_clamped() {
+    let registry = BiomeRegistry::builtin();
+    assert_eq!(registry.from_climate(0.1, 0.1), biomes::STONE);
+    assert_eq!(registry.from_climate(0.3, 0.5), biomes::FOREST);
+    assert_eq!(registry.from_climate(0.2, 0.4), biomes::SNOW);
+    assert_eq!(registry.from_climate(-0.3, -5.0), biomes::STONE);
+    assert_eq!(registry.from_climate(1.2, 0.3), biomes::SAVANNA);
+    assert_eq!(registry.from_climate(0.5, 7.0), biomes::RAIN_FOREST);
+
+    let transition = BiomeTransition::default();
+    for &t in &[-1.0, 0.0, 0.5, 1.0, 3.0, ::std::f32::NAN, ::std::f32::INFINITY] {
+        for &h in &[-1.0, 0.0, 0.5, 1.0, 3.0, ::std::f32::NAN, ::std::f32::NEG_INFINITY] {
+            assert!(registry.from_climate(t, h) != biomes::DEBUG);
+            let blend = registry.blend_from_climate(t, h, &transition);
+            assert!(blend.weights().iter().all(|&(b, _)| b != biomes::DEBUG));
+        }
+    }
+
+    // Climates which aren't covered by any biome use the nearest one
+    let mut registry = BiomeRegistry::new();
+    let mut cold = Biome::new(BiomeId(0), "cold");
+    cold.climate = Some(Climate {
+        temperature: (0.0, 0.3),
+        humidity: (0.0, 1.0),
+    });
+    let mut hot = Biome::new(BiomeId(1), "hot");
+    hot.climate = Some(Climate {
+        temperature: (0.8, 1.0),
+        humidity: (0.0, 1.0),
+    });
+    registry.register(cold).unwrap();
+    registry.register(hot).unwrap();
+    assert_eq!(registry.from_climate(0.5, 0.5), BiomeId(0));
+    assert_eq!(registry.from_climate(0.6, 0.5), BiomeId(1));
+}
+
+#[test]
+fn blend_biomes_at_borders() {
+    let registry = BiomeRegistry::builtin();
+    let transition = BiomeTransition {
+        temperature: 0.1,
+        humidity: 0.05,
+    };
+
+    // Away from the borders, there is only one biome
+    let blend = registry.blend_from_climate(0.1, 0.7, &transition);
+    assert_eq!(blend.weights(), &[(biomes::SNOW, 1.0)]);
+    let blend = registry.blend_from_climate(0.3, 0.1, &transition);
+    assert_eq!(blend.weights(), &[(biomes::GRASS_LAND, 1.0)]);
+
+    // Right on a border both biomes have the same weight
+    let blend = registry.blend_from_climate(0.4, 0.1, &transition);
+    assert_eq!(blend.weights(), &[(biomes::GRASS_LAND, 0.5), (biomes::DESERT, 0.5)]);
+
+    // The weights always sum up to 1 and change smoothly
+    let value = |b: BiomeId| registry.biome(b).plant_threshold;
+    let mut prev = registry.blend_from_climate(0.0, 0.15, &transition).blend(&value);
+    for i in 1..1001 {
+        let t = i as f32 / 1000.0;
+        let blend = registry.blend_from_climate(t, 0.15 + t / 5.0, &transition);
+        let sum = blend.blend(|_| 1.0);
+        assert!((sum - 1.0).abs() < 1e-5);
+        let current = blend.blend(&value);
+        assert!((current - prev).abs() < 0.01, "jump at {}", t);
+        prev = current;
+    }
+
+    // Without a transition, the borders are sharp
+    let sharp = BiomeTransition {
+        temperature: 0.0,
+        humidity: 0.0,
+    };
+    let blend = registry.blend_from_climate(0.41, 0.399, &sharp);
+    assert_eq!(blend.weights(), &[(biomes::SAVANNA, 1.0)]);
+}
+
+#[test]
+fn pick_biomes_by_weight() {
+    let blend = BiomeBlend { weights: vec![(biomes::FOREST, 0.75), (biomes::SNOW, 0.25)] };
+    assert_eq!(blend.dominant(), biomes::FOREST);
+    assert_eq!(blend.pick(0.0), biomes::FOREST);
+    assert_eq!(blend.pick(0.7), biomes::FOREST);
+    assert_eq!(blend.pick(0.8), biomes::SNOW);
+    assert_eq!(blend.pick(1.0), biomes::SNOW);
 }
@@ -0,0 +1,474 @@
+//! Tunable parameters of the terrain generation.
+//!
+//! Together with the seed, a `WorldGenConfig` completely determines the
+//! generated terrain. It's stored in the save directory of a world and sent
+//! to the clients, so that every world keeps its own character.
+//!
+//! New worlds can be configured with a TOML file, which may set any of the
+//! fields of `WorldGenConfig`; missing fields keep their default value. The
+//! widths of the biome transitions are given as `[temperature, humidity]`,
+//! the water parameters are set directly and every erosion stage is enabled
+//! by its table:
+//!
+//! ```text
+//! world_height = 256
+//! sea_level = 80
+//! land_noise_scale = [0.02, 0.02, 0.05]
+//! biome_scale = 2.0
+//! biome_transition = [0.04, 0.04]
+//! river_threshold = 30
+//! min_lake_depth = 2
+//!
+//! [erosion.hydraulic]
+//! droplets_per_pillar = 0.5
+//!
+//! [erosion.thermal]
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use toml::{Table, Value};
+use data;
+use super::biome::BiomeTransition;
+use super::erosion::{ErosionConfig, HydraulicErosion, ThermalErosion};
+use super::water::WaterConfig;
+
+/// Parameters which determine the shape of the generated terrain.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct WorldGenConfig {
+    /// The height (in units) up to which terrain is generated.
+    pub world_height: u16,
+    /// All columns with their surface below this height (in units) are
+    /// filled with water up to it.
+    pub sea_level: u16,
+    /// Scaling of the 3D "fill noise" in x, y and z direction. Smaller values
+    /// result in larger hills and caves.
+    pub land_noise_scale: (f32, f32, f32),
+    /// Minimum threshold of the fill noise, which prevents the threshold from
+    /// reaching 0. Higher values result in more caves.
+    pub min_thresh: f32,
+    /// The relative height (in the range 0..1) at which the threshold is at
+    /// half of its value, which is the average height of the terrain.
+    pub thresh_mid: f32,
+    /// Coefficients `(a, b, c)` of the polynomial `a·t² + b·t + c` which
+    /// maps the temperature `t` to the steepness of the terrain.
+    pub steepness: (f32, f32, f32),
+    /// Frequency of the noise of temperature and humidity.
+    pub climate_frequency: f32,
+    /// Size of the biomes relative to the default size. Unlike the climate
+    /// frequency, this also scales the noise which makes the borders between
+    /// biomes frayed.
+    pub biome_scale: f32,
+    /// The widths of the transitions between biomes.
+    pub biome_transition: BiomeTransition,
+    /// Parameters for the generation of sea, lakes and rivers.
+    pub water: WaterConfig,
+    /// Parameters of the erosion, which is disabled by default.
+    pub erosion: ErosionConfig,
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        WorldGenConfig {
+            world_height: 256,
+            sea_level: 100,
+            land_noise_scale: (0.03, 0.03, 0.05),
+            min_thresh: 0.6,
+            thresh_mid: 0.5,
+            // Approximates the steepness values 10, 20, 60 and 200 for the
+            // temperatures 0.0, 0.2, 0.4 and 1.0
+            steepness: (120.0, 72.0, 3.5),
+            climate_frequency: 0.0015,
+            biome_scale: 1.0,
+            biome_transition: BiomeTransition::default(),
+            water: WaterConfig::default(),
+            erosion: ErosionConfig::default(),
+        }
+    }
+}
+
+impl WorldGenConfig {
+    /// Checks whether a world can be generated with these parameters.
+    /// Configurations read from files or received over the network should be
+    /// validated before they are used.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let scale = self.land_noise_scale;
+        let floats = [scale.0,
+                      scale.1,
+                      scale.2,
+                      self.min_thresh,
+                      self.thresh_mid,
+                      self.steepness.0,
+                      self.steepness.1,
+                      self.steepness.2,
+                      self.climate_frequency,
+                      self.biome_scale,
+                      self.biome_transition.temperature,
+                      self.biome_transition.humidity];
+
+        if self.world_height < 2 {
+            Err("world height has to be at least 2")
+        } else if self.sea_level >= self.world_height {
+            Err("sea level has to be below the world height")
+        } else if floats.iter().any(|f| !f.is_finite()) {
+            Err("parameters have to be finite numbers")
+        } else if self.min_thresh < 0.0 {
+            Err("minimum threshold must not be negative")
+        } else if self.biome_scale <= 0.0 {
+            Err("biome scale has to be positive")
+        } else if self.biome_transition.temperature < 0.0 || self.biome_transition.humidity < 0.0 {
+            Err("biome transition widths must not be negative")
+        } else if self.water.river_threshold == 0 {
+            Err("river threshold has to be positive")
+        } else {
+            self.validate_erosion()
+        }
+    }
+
+    /// Checks the parameters of the enabled erosion stages.
+    fn validate_erosion(&self) -> Result<(), &'static str> {
+        if let Some(hydraulic) = self.erosion.hydraulic {
+            let factors = [hydraulic.erosion_rate,
+                           hydraulic.deposition_rate,
+                           hydraulic.evaporation];
+            let amounts = [hydraulic.droplets_per_pillar, hydraulic.capacity, hydraulic.min_slope];
+            if factors.iter().chain(&amounts).any(|f| !f.is_finite()) {
+                return Err("erosion parameters have to be finite numbers");
+            }
+            if factors.iter().any(|&f| f < 0.0 || f > 1.0) {
+                return Err("hydraulic erosion rates have to be in the range 0..1");
+            }
+            if amounts.iter().any(|&f| f < 0.0) {
+                return Err("hydraulic erosion parameters must not be negative");
+            }
+        }
+        if let Some(thermal) = self.erosion.thermal {
+            if !thermal.talus.is_finite() || !thermal.rate.is_finite() {
+                return Err("erosion parameters have to be finite numbers");
+            }
+            if thermal.talus < 0.0 {
+                return Err("thermal erosion talus must not be negative");
+            }
+            if thermal.rate < 0.0 || thermal.rate > 1.0 {
+                return Err("thermal erosion rate has to be in the range 0..1");
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a configuration from the given TOML data, see the module
+    /// documentation.
+    pub fn from_toml(src: &str) -> Result<Self, ConfigError> {
+        let table = try!(data::parse(src).map_err(ConfigError::Parse));
+        let mut config = WorldGenConfig::default();
+
+        if let Some(v) = try!(field(&table, "world_height", height)) {
+            config.world_height = v;
+        }
+        if let Some(v) = try!(field(&table, "sea_level", height)) {
+            config.sea_level = v;
+        }
+        if let Some(v) = try!(field(&table, "land_noise_scale", triple)) {
+            config.land_noise_scale = v;
+        }
+        if let Some(v) = try!(field(&table, "min_thresh", data::float)) {
+            config.min_thresh = v;
+        }
+        if let Some(v) = try!(field(&table, "thresh_mid", data::float)) {
+            config.thresh_mid = v;
+        }
+        if let Some(v) = try!(field(&table, "steepness", triple)) {
+            config.steepness = v;
+        }
+        if let Some(v) = try!(field(&table, "climate_frequency", data::float)) {
+            config.climate_frequency = v;
+        }
+        if let Some(v) = try!(field(&table, "biome_scale", data::float)) {
+            config.biome_scale = v;
+        }
+        if let Some(v) = try!(field(&table, "biome_transition", pair)) {
+            config.biome_transition = BiomeTransition {
+                temperature: v.0,
+                humidity: v.1,
+            };
+        }
+        if let Some(v) = try!(field(&table, "river_threshold", count)) {
+            config.water.river_threshold = v;
+        }
+        if let Some(v) = try!(field(&table, "min_lake_depth", height)) {
+            config.water.min_lake_depth = v;
+        }
+        if let Some(erosion) = try!(subtable(&table, "erosion")) {
+            config.erosion = try!(erosion_from_toml(erosion));
+        }
+
+        try!(config.validate().map_err(ConfigError::Invalid));
+        Ok(config)
+    }
+
+    /// Loads the configuration from the given file. If the file doesn't
+    /// exist, the default configuration is returned.
+    pub fn load(path: &Path) -> Result<Self, Box<Error>> {
+        if !path.exists() {
+            return Ok(WorldGenConfig::default());
+        }
+
+        let mut src = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut src));
+        Ok(try!(WorldGenConfig::from_toml(&src)))
+    }
+
+    /// Returns the steepness of the terrain at the given temperature, which
+    /// is clamped to the range 0..1.
+    pub fn steepness_at(&self, temperature: f32) -> f32 {
+        let t = temperature.max(0.0).min(1.0);
+        let (a, b, c) = self.steepness;
+        a * t * t + b * t + c
+    }
+}
+
+/// The error type for loading a configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The data isn't valid TOML. Contains the messages of the parser.
+    Parse(String),
+    /// A field has the wrong type or is out of range.
+    InvalidField(&'static str),
+    /// The parameters can't be used together, see `validate()`.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Parse(ref msg) => write!(f, "{}: {}", self.description(), msg),
+            ConfigError::InvalidField(field) => write!(f, "{} '{}'", self.description(), field),
+            ConfigError::Invalid(reason) => write!(f, "{}: {}", self.description(), reason),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Parse(_) => "invalid TOML",
+            ConfigError::InvalidField(_) => "invalid field",
+            ConfigError::Invalid(_) => "invalid world generator configuration",
+        }
+    }
+}
+
+/// Reads the erosion stages from the `erosion` table. Stages without a table
+/// are disabled, missing parameters of enabled stages keep their default.
+fn erosion_from_toml(table: &Table) -> Result<ErosionConfig, ConfigError> {
+    let mut erosion = ErosionConfig::default();
+
+    if let Some(t) = try!(subtable(table, "hydraulic")) {
+        let mut hydraulic = HydraulicErosion::default();
+        if let Some(v) = try!(field(t, "droplets_per_pillar", data::float)) {
+            hydraulic.droplets_per_pillar = v;
+        }
+        if let Some(v) = try!(field(t, "max_steps", count)) {
+            hydraulic.max_steps = v;
+        }
+        if let Some(v) = try!(field(t, "capacity", data::float)) {
+            hydraulic.capacity = v;
+        }
+        if let Some(v) = try!(field(t, "erosion_rate", data::float)) {
+            hydraulic.erosion_rate = v;
+        }
+        if let Some(v) = try!(field(t, "deposition_rate", data::float)) {
+            hydraulic.deposition_rate = v;
+        }
+        if let Some(v) = try!(field(t, "evaporation", data::float)) {
+            hydraulic.evaporation = v;
+        }
+        if let Some(v) = try!(field(t, "min_slope", data::float)) {
+            hydraulic.min_slope = v;
+        }
+        erosion.hydraulic = Some(hydraulic);
+    }
+
+    if let Some(t) = try!(subtable(table, "thermal")) {
+        let mut thermal = ThermalErosion::default();
+        if let Some(v) = try!(field(t, "talus", data::float)) {
+            thermal.talus = v;
+        }
+        if let Some(v) = try!(field(t, "rate", data::float)) {
+            thermal.rate = v;
+        }
+        if let Some(v) = try!(field(t, "iterations", count)) {
+            thermal.iterations = v;
+        }
+        erosion.thermal = Some(thermal);
+    }
+
+    Ok(erosion)
+}
+
+/// Reads an optional field with the given function.
+fn field<T, F>(table: &Table, name: &'static str, read: F) -> Result<Option<T>, ConfigError>
+    where F: FnOnce(&Value) -> Option<T>
+{
+    match table.get(name) {
+        Some(value) => read(value).map(Some).ok_or(ConfigError::InvalidField(name)),
+        None => Ok(None),
+    }
+}
+
+/// Reads an optional table.
+fn subtable<'a>(table: &'a Table, name: &'static str) -> Result<Option<&'a Table>, ConfigError> {
+    match table.get(name) {
+        Some(&Value::Table(ref t)) => Ok(Some(t)),
+        Some(_) => Err(ConfigError::InvalidField(name)),
+        None => Ok(None),
+    }
+}
+
+/// Reads a height in units.
+fn height(value: &Value) -> Option<u16> {
+    value.as_integer().and_then(|i| if i >= 0 && i <= u16::max_value() as i64 {
+        Some(i as u16)
+    } else {
+        None
+    })
+}
+
+/// Reads a non-negative integer which fits into an `u32`.
+fn count(value: &Value) -> Option<u32> {
+    value.as_integer().and_then(|i| if i >= 0 && i <= u32::max_value() as i64 {
+        Some(i as u32)
+    } else {
+        None
+    })
+}
+
+/// Reads an array of two numbers.
+fn pair(value: &Value) -> Option<(f32, f32)> {
+    data::float_array(value, 2).map(|v| (v[0], v[1]))
+}
+
+/// Reads an array of three numbers.
+fn triple(value: &Value) -> Option<(f32, f32, f32)> {
+    data::float_array(value, 3).map(|v| (v[0], v[1], v[2]))
+}
+
+#[test]
+fn default_is_valid() {
+    assert_eq!(WorldGenConfig::default().validate(), Ok(()));
+    let eroded = WorldGenConfig { erosion: ErosionConfig::enabled(), ..Default::default() };
+    assert_eq!(eroded.validate(), Ok(()));
+
+    let steepness: Vec<_> = [0.0, 0.2, 0.4, 1.0]
+        .iter()
+        .map(|&t| WorldGenConfig::default().steepness_at(t))
+        .collect();
+    assert!(steepness.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(WorldGenConfig::default().steepness_at(2.0), steepness[3]);
+}
+
+#[test]
+fn reject_invalid_configs() {
+    let valid = WorldGenConfig::default();
+    let invalid = [WorldGenConfig { world_height: 1, sea_level: 0, ..valid },
+                   WorldGenConfig { sea_level: 256, ..valid },
+                   WorldGenConfig { land_noise_scale: (0.03, ::std::f32::NAN, 0.05), ..valid },
+                   WorldGenConfig { steepness: (::std::f32::INFINITY, 72.0, 3.5), ..valid },
+                   WorldGenConfig { min_thresh: -1.0, ..valid },
+                   WorldGenConfig { biome_scale: 0.0, ..valid },
+                   WorldGenConfig {
+                       biome_transition: BiomeTransition {
+                           temperature: -0.1,
+                           humidity: 0.04,
+                       },
+                       ..valid
+                   },
+                   WorldGenConfig {
+                       water: WaterConfig { river_threshold: 0, ..valid.water },
+                       ..valid
+                   },
+                   WorldGenConfig {
+                       erosion: ErosionConfig {
+                           hydraulic: Some(HydraulicErosion {
+                               evaporation: 1.5,
+                               ..Default::default()
+                           }),
+                           thermal: None,
+                       },
+                       ..valid
+                   },
+                   WorldGenConfig {
+                       erosion: ErosionConfig {
+                           hydraulic: None,
+                           thermal: Some(ThermalErosion {
+                               talus: ::std::f32::NAN,
+                               ..Default::default()
+                           }),
+                       },
+                       ..valid
+                   }];
+    for config in &invalid {
+        assert!(config.validate().is_err(), "{:?}", config);
+    }
+}
+
+#[test]
+fn parse_toml() {
+    let config = WorldGenConfig::from_toml(r#"
+        sea_level = 80
+        land_noise_scale = [0.02, 0.02, 0.05]
+        biome_scale = 2
+    "#)
+        .unwrap();
+    assert_eq!(config,
+               WorldGenConfig {
+                   sea_level: 80,
+                   land_noise_scale: (0.02, 0.02, 0.05),
+                   biome_scale: 2.0,
+                   ..WorldGenConfig::default()
+               });
+    assert_eq!(WorldGenConfig::from_toml(""), Ok(WorldGenConfig::default()));
+
+    let config = WorldGenConfig::from_toml(r#"
+        biome_transition = [0.1, 0]
+        river_threshold = 50
+        min_lake_depth = 4
+
+        [erosion.thermal]
+        iterations = 2
+    "#)
+        .unwrap();
+    assert_eq!(config.biome_transition,
+               BiomeTransition {
+                   temperature: 0.1,
+                   humidity: 0.0,
+               });
+    assert_eq!(config.water,
+               WaterConfig {
+                   river_threshold: 50,
+                   min_lake_depth: 4,
+               });
+    assert_eq!(config.erosion,
+               ErosionConfig {
+                   hydraulic: None,
+                   thermal: Some(ThermalErosion { iterations: 2, ..Default::default() }),
+               });
+
+    assert_eq!(WorldGenConfig::from_toml("sea_level = -1"),
+               Err(ConfigError::InvalidField("sea_level")));
+    assert_eq!(WorldGenConfig::from_toml("steepness = [1.0, 2.0]"),
+               Err(ConfigError::InvalidField("steepness")));
+    assert_eq!(WorldGenConfig::from_toml("erosion = true"),
+               Err(ConfigError::InvalidField("erosion")));
+    assert_eq!(WorldGenConfig::from_toml("[erosion.hydraulic]\nmax_steps = -1"),
+               Err(ConfigError::InvalidField("max_steps")));
+    assert_eq!(WorldGenConfig::from_toml("world_height = 64\nsea_level = 64"),
+               Err(ConfigError::Invalid("sea level has to be below the world height")));
+    match WorldGenConfig::from_toml("sea_level = ") {
+        Err(ConfigError::Parse(_)) => {}
+        other => panic!("invalid TOML was accepted: {:?}", other),
+    }
+}
@@ -0,0 +1,365 @@
+//! Scheduling where plants grow.
+//!
+//! Plants grow in two layers: canopy plants (trees and cacti) and the
+//! undergrowth (shrubs, grass and flowers) below them. Plants of different
+//! layers don't disturb each other, so grass can grow right next to a tree.
+//!
+//! Every pillar is a candidate for one plant of each layer. Its species is
+//! chosen from the plants of the pillar's biome, and it only becomes an
+//! active candidate with the density of the species and where the cluster
+//! noise of the species exceeds the plant threshold of the biome. The
+//! frequency of the cluster noise determines the size of the groves and
+//! meadows.
+//!
+//! Every candidate has a random priority. An active candidate is scheduled
+//! if no other active candidate of the same layer with a higher priority is
+//! closer than the spacing of either species. This keeps the spacing of all
+//! scheduled plants, and since the candidates only depend on their position,
+//! whether a plant is scheduled only depends on the candidates within
+//! `MAX_SPACING` around it: every chunk schedules the same plants along its
+//! borders as its neighbours.
+//!
+//! Whether a scheduled plant actually grows also depends on the terrain at
+//! its pillar, see `Placement`. The placement of every species is part of
+//! its `[[species]]` table in `base/data/plants.toml`.
+
+use gen::plant::registry::SpeciesId;
+use gen::seeded_rng;
+use math::{AxialPoint, InnerSpace, spiral};
+use noise::open_simplex2;
+use rand::Rng;
+use toml::{Table, Value};
+use data::{float, short};
+use world::HEX_OUTER_RADIUS;
+use super::WorldGenerator;
+use super::biome::BiomeId;
+
+/// The largest spacing of all plant species (in world units).
+pub const MAX_SPACING: f32 = 4.0;
+
+/// The layer a plant grows in, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, RustcEncodable, RustcDecodable)]
+pub enum PlantLayer {
+    Canopy,
+    Undergrowth,
+}
+
+impl PlantLayer {
+    /// Returns the name used for this layer in data files.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PlantLayer::Canopy => "canopy",
+            PlantLayer::Undergrowth => "undergrowth",
+        }
+    }
+
+    /// Returns the layer with the given name, see `name()`.
+    pub fn from_name(name: &str) -> Option<PlantLayer> {
+        PLANT_LAYERS.iter().cloned().find(|l| l.name() == name)
+    }
+}
+
+/// All plant layers.
+pub const PLANT_LAYERS: [PlantLayer; 2] = [PlantLayer::Canopy, PlantLayer::Undergrowth];
+
+/// The rules for placing a plant species.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Placement {
+    pub layer: PlantLayer,
+    /// Minimum distance (in world units) to all other plants of the same
+    /// layer.
+    pub spacing: f32,
+    /// The probability that a pillar within a cluster is a candidate for
+    /// this species.
+    pub density: f32,
+    /// Frequency of the cluster noise. Lower frequencies result in larger
+    /// groves and meadows.
+    pub cluster_frequency: f32,
+    /// The maximum difference of height (in height units) between the
+    /// pillar and its neighbours.
+    pub max_slope: u16,
+    /// The range of heights (in height units above the sea level) in which
+    /// the species grows, both inclusive.
+    pub altitude: (u16, u16),
+}
+
+impl Placement {
+    /// Checks whether the species can be scheduled with these rules. Returns
+    /// the name of the first invalid field otherwise.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(self.spacing > 0.0 && self.spacing <= MAX_SPACING) {
+            Err("spacing")
+        } else if !(self.density > 0.0 && self.density <= 1.0) {
+            Err("density")
+        } else if !(self.cluster_frequency.is_finite() && self.cluster_frequency >= 0.0) {
+            Err("cluster_frequency")
+        } else if self.altitude.0 > self.altitude.1 {
+            Err("max_altitude")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads the rules from the given TOML table. Fields which aren't given
+    /// are taken from `base`; without a base, all fields except
+    /// `max_altitude` (which is unlimited by default) are required. Returns
+    /// the name of the first missing or invalid field on failure. The rules
+    /// are validated.
+    pub fn from_toml(table: &Table, base: Option<&Placement>) -> Result<Self, &'static str> {
+        fn read<T, F>(table: &Table,
+                      name: &'static str,
+                      base: Option<T>,
+                      read: F)
+                      -> Result<T, &'static str>
+            where F: FnOnce(&Value) -> Option<T>
+        {
+            match (table.get(name), base) {
+                (Some(value), _) => read(value).ok_or(name),
+                (None, Some(base)) => Ok(base),
+                (None, None) => Err(name),
+            }
+        }
+
+        let placement = Placement {
+            layer: try!(read(table,
+                             "layer",
+                             base.map(|b| b.layer),
+                             |v| v.as_str().and_then(PlantLayer::from_name))),
+            spacing: try!(read(table, "spacing", base.map(|b| b.spacing), float)),
+            density: try!(read(table, "density", base.map(|b| b.density), float)),
+            cluster_frequency: try!(read(table,
+                                         "cluster_frequency",
+                                         base.map(|b| b.cluster_frequency),
+                                         float)),
+            max_slope: try!(read(table, "max_slope", base.map(|b| b.max_slope), short)),
+            altitude: (try!(read(table, "min_altitude", base.map(|b| b.altitude.0), short)),
+                       try!(read(table,
+                                 "max_altitude",
+                                 Some(base.map_or(u16::max_value(), |b| b.altitude.1)),
+                                 short))),
+        };
+        try!(placement.validate());
+        Ok(placement)
+    }
+
+    /// Returns whether the species grows on a pillar with its surface at
+    /// `top`, whose neighbours have their surface at the heights
+    /// `neighbors`.
+    pub fn allows(&self, top: u16, neighbors: &[u16], sea_level: u16) -> bool {
+        let altitude = top.saturating_sub(sea_level);
+        top > sea_level && altitude >= self.altitude.0 && altitude <= self.altitude.1 &&
+        neighbors.iter().all(|&n| (n as i32 - top as i32).abs() <= self.max_slope as i32)
+    }
+}
+
+/// A plant scheduled by `schedule()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledPlant {
+    pub pos: AxialPoint,
+    pub species: SpeciesId,
+}
+
+/// An active candidate, see the module documentation.
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    species: SpeciesId,
+    spacing: f32,
+    priority: u32,
+}
+
+/// Returns the plants scheduled on the pillars between `min` and `max`
+/// (inclusive, in axial coordinates), sorted by their position and layer.
+pub fn schedule(gen: &WorldGenerator, min: AxialPoint, max: AxialPoint) -> Vec<ScheduledPlant> {
+    // Pillars with a hex distance of `d` are at least `1.5 * d` outer radii
+    // apart, so only candidates this far outside of the area are needed
+    let reach = (MAX_SPACING / (1.5 * HEX_OUTER_RADIUS)).ceil() as i32;
+    let origin = AxialPoint::new(min.q - reach, min.r - reach);
+    let width = max.q - min.q + 1 + 2 * reach;
+    let height = max.r - min.r + 1 + 2 * reach;
+
+    // The candidates of both layers of all pillars, row by row
+    let mut candidates = Vec::with_capacity((width * height) as usize);
+    for r in 0..height {
+        for q in 0..width {
+            let pos = AxialPoint::new(origin.q + q, origin.r + r);
+            let (temperature, humidity) = gen.climate_at(pos.to_real());
+            let (blend, biome) = gen.pillar_biome(pos, temperature, humidity);
+            let threshold = blend.blend(|b| gen.biomes.biome(b).plant_threshold);
+            candidates.push([candidate(gen, pos, PlantLayer::Canopy, biome, threshold),
+                             candidate(gen, pos, PlantLayer::Undergrowth, biome, threshold)]);
+        }
+    }
+    let candidate_at = |pos: AxialPoint, layer: usize| {
+        let (q, r) = (pos.q - origin.q, pos.r - origin.r);
+        candidates[(r * width + q) as usize][layer]
+    };
+
+    let mut plants = Vec::new();
+    for r in min.r..max.r + 1 {
+        for q in min.q..max.q + 1 {
+            let pos = AxialPoint::new(q, r);
+            for layer in 0..PLANT_LAYERS.len() {
+                let candidate = match candidate_at(pos, layer) {
+                    Some(candidate) => candidate,
+                    None => continue,
+                };
+
+                let beaten = spiral(pos, reach).any(|other_pos| {
+                    let other = match candidate_at(other_pos, layer) {
+                        Some(other) if other_pos != pos => other,
+                        _ => return false,
+                    };
+                    let spacing = candidate.spacing.max(other.spacing);
+                    (other_pos.to_real() - pos.to_real()).magnitude() < spacing &&
+                    (other.priority, other_pos.q, other_pos.r) >
+                    (candidate.priority, pos.q, pos.r)
+                });
+                if !beaten {
+                    plants.push(ScheduledPlant {
+                        pos: pos,
+                        species: candidate.species,
+                    });
+                }
+            }
+        }
+    }
+    plants
+}
+
+/// Returns the candidate of the given layer at the given pillar, if it's
+/// active.
+fn candidate(gen: &WorldGenerator,
+             pos: AxialPoint,
+             layer: PlantLayer,
+             biome: BiomeId,
+             threshold: f32)
+             -> Option<Candidate> {
+    let mut rng = seeded_rng(gen.seed, "PLANT", (pos.q, pos.r, layer as u8));
+    // Species which aren't registered don't grow
+    let in_layer = |id| gen.plants.get(id).map_or(false, |s| s.placement.layer == layer);
+    let species = match gen.biomes.biome(biome).pick_plant_where(rng.gen(), in_layer) {
+        Some(id) => gen.plants.get(id).unwrap(),
+        None => return None,
+    };
+
+    let placement = species.placement;
+    if rng.gen::<f32>() >= placement.density {
+        return None;
+    }
+
+    // Every species has its own clusters. The offset is kept small, as the
+    // noise loses precision far away from the origin. Ids start at 1.
+    let real = pos.to_real();
+    let offset = 100.0 * (species.id.0.wrapping_sub(1) % 256) as f32;
+    let f = placement.cluster_frequency;
+    let noise = open_simplex2::<f32>(&gen.plant_table, &[real.x * f + offset, real.y * f]);
+    if noise <= threshold {
+        return None;
+    }
+
+    Some(Candidate {
+        species: species.id,
+        spacing: placement.spacing,
+        priority: rng.gen(),
+    })
+}
+
+/// Returns the distance between the centers of two pillars.
+#[cfg(test)]
+fn distance(a: AxialPoint, b: AxialPoint) -> f32 {
+    (a.to_real() - b.to_real()).magnitude()
+}
+
+#[test]
+fn placements_are_valid() {
+    use data;
+    use gen::plant::registry::PlantRegistry;
+
+    let plants = PlantRegistry::builtin();
+    for species in plants.iter() {
+        assert_eq!(species.placement.validate(), Ok(()));
+    }
+
+    let oak = plants.by_name("oak_tree").unwrap().placement;
+    assert_eq!(oak,
+               Placement {
+                   layer: PlantLayer::Canopy,
+                   spacing: 3.0,
+                   density: 0.9,
+                   cluster_frequency: 0.03,
+                   max_slope: 3,
+                   altitude: (2, 100),
+               });
+    assert!(oak.allows(110, &[110, 111, 109, 113, 110, 110], 100));
+    assert!(!oak.allows(110, &[110, 114], 100));
+    assert!(!oak.allows(100, &[100], 100));
+    assert!(!oak.allows(250, &[250], 100));
+
+    let table = data::parse("layer = \"undergrowth\"\nspacing = 1\nmax_altitude = 40").unwrap();
+    assert_eq!(Placement::from_toml(&table, Some(&oak)),
+               Ok(Placement {
+                   layer: PlantLayer::Undergrowth,
+                   spacing: 1.0,
+                   altitude: (2, 40),
+                   ..oak
+               }));
+    assert_eq!(Placement::from_toml(&table, None), Err("density"));
+
+    let table = data::parse("layer = \"undergrowth\"\nspacing = 1\ndensity = 0.5\n\
+                             cluster_frequency = 0.1\nmax_slope = 2\nmin_altitude = 1")
+        .unwrap();
+    assert_eq!(Placement::from_toml(&table, None).unwrap().altitude,
+               (1, u16::max_value()));
+
+    let invalid = |src: &str| Placement::from_toml(&data::parse(src).unwrap(), Some(&oak));
+    assert_eq!(invalid("layer = \"roots\""), Err("layer"));
+    assert_eq!(invalid("spacing = 10.0"), Err("spacing"));
+    assert_eq!(invalid("density = 0"), Err("density"));
+    assert_eq!(invalid("min_altitude = 200"), Err("max_altitude"));
+}
+
+#[test]
+fn scheduled_plants_keep_their_spacing() {
+    let gen = WorldGenerator::with_seed(42);
+    let plants = schedule(&gen, AxialPoint::new(-20, -20), AxialPoint::new(40, 40));
+
+    let mut layers = [0, 0];
+    let mut close = 0;
+    let placement = |plant: &ScheduledPlant| gen.plants().get(plant.species).unwrap().placement;
+    for (i, a) in plants.iter().enumerate() {
+        let own = placement(a);
+        layers[own.layer as usize] += 1;
+        for b in &plants[i + 1..] {
+            let other = placement(b);
+            let d = distance(a.pos, b.pos);
+            if other.layer == own.layer {
+                assert!(d >= own.spacing.max(other.spacing),
+                        "{:?} and {:?} are too close",
+                        a,
+                        b);
+            } else if d < own.spacing.max(other.spacing) {
+                close += 1;
+            }
+        }
+    }
+
+    // Both layers grow, and the undergrowth grows close to the trees
+    assert!(layers[0] > 0 && layers[1] > layers[0]);
+    assert!(close > 0);
+}
+
+#[test]
+fn schedule_does_not_depend_on_the_area() {
+    let gen = WorldGenerator::with_seed(7);
+    let a = schedule(&gen, AxialPoint::new(-16, -16), AxialPoint::new(15, 15));
+    let b = schedule(&gen, AxialPoint::new(0, 0), AxialPoint::new(31, 31));
+    assert!(!a.is_empty());
+
+    let overlap = |plant: &&ScheduledPlant| {
+        plant.pos.q >= 0 && plant.pos.r >= 0 && plant.pos.q <= 15 && plant.pos.r <= 15
+    };
+    let from_a: Vec<_> = a.iter().filter(overlap).collect();
+    let from_b: Vec<_> = b.iter().filter(overlap).collect();
+    assert!(!from_a.is_empty());
+    assert_eq!(from_a, from_b);
+}
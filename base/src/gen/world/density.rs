@@ -0,0 +1,238 @@
+//! Sampling of the 3D "fill noise" which decides where terrain is.
+//!
+//! Evaluating 3D noise for every height unit of every pillar is by far the
+//! most expensive part of generating a chunk. Instead, the noise is sampled
+//! on a coarse lattice with a spacing of `LATTICE_XY` world units in x and y
+//! direction and `LATTICE_Z` height units in z direction and trilinearly
+//! interpolated in between. The noise changes slowly compared to the
+//! spacing, so the interpolated field hardly differs from the exact one.
+//!
+//! The lattice is aligned to the world coordinates: the value at a position
+//! only depends on the eight lattice points around it, so the same value is
+//! found no matter which chunk or query asks for it. Lattice points are only
+//! sampled when they are needed, so nothing is sampled above the highest
+//! unit of a column which could be filled.
+
+use std::cmp;
+use std::f32;
+use math::Point2f;
+use noise::{PermutationTable, open_simplex3};
+use world::PILLAR_STEP_HEIGHT;
+
+/// Distance between two lattice points in x and y direction (in world
+/// units).
+pub const LATTICE_XY: f32 = 4.0;
+
+/// Distance between two lattice points in z direction (in height units).
+pub const LATTICE_Z: usize = 4;
+
+/// Lazily sampled fill noise of a rectangular area.
+pub struct DensityLattice<'a> {
+    table: &'a PermutationTable,
+    scale: (f32, f32, f32),
+    /// Lattice coordinates of the first sample.
+    origin: (i32, i32),
+    /// Number of lattice points in x and y direction.
+    size: (i32, i32),
+    levels: usize,
+    /// Noise of all lattice points, level by level and row by row. Points
+    /// which weren't sampled yet are NaN.
+    samples: Vec<f32>,
+}
+
+impl<'a> DensityLattice<'a> {
+    /// Creates the lattice for all positions between `min` and `max` (in
+    /// world units) and all units below the height `height`. `scale` is the
+    /// scaling of the noise in x, y and z direction.
+    pub fn new(table: &'a PermutationTable,
+               scale: (f32, f32, f32),
+               min: Point2f,
+               max: Point2f,
+               height: usize)
+               -> Self {
+        let origin = ((min.x / LATTICE_XY).floor() as i32, (min.y / LATTICE_XY).floor() as i32);
+        let end = ((max.x / LATTICE_XY).floor() as i32 + 1,
+                   (max.y / LATTICE_XY).floor() as i32 + 1);
+        let size = (end.0 - origin.0 + 1, end.1 - origin.1 + 1);
+        let levels = height / LATTICE_Z + 2;
+
+        DensityLattice {
+            table: table,
+            scale: scale,
+            origin: origin,
+            size: size,
+            levels: levels,
+            samples: vec![f32::NAN; (size.0 * size.1) as usize * levels],
+        }
+    }
+
+    /// Returns the interpolated fill noise (in the range 0..1) of the unit
+    /// at height `i` of the column at `(x, y)`, which has to lie within the
+    /// area of the lattice.
+    pub fn fill_noise(&mut self, x: f32, y: f32, i: usize) -> f32 {
+        let cell = Cell::containing(x, y);
+        let lz = i / LATTICE_Z;
+        let below = self.level_noise(cell, lz);
+        let above = self.level_noise(cell, lz + 1);
+        lerp(below, above, (i % LATTICE_Z) as f32 / LATTICE_Z as f32)
+    }
+
+    /// Returns the interpolated fill noise of all units below `height` of
+    /// the column at `(x, y)`. This is the same as calling `fill_noise()` for
+    /// every unit, but faster.
+    pub fn column_noise(&mut self, x: f32, y: f32, height: usize) -> Vec<f32> {
+        let cell = Cell::containing(x, y);
+        let mut noise = Vec::with_capacity(height);
+        let mut above = self.level_noise(cell, 0);
+        for lz in 0..(height + LATTICE_Z - 1) / LATTICE_Z {
+            let below = above;
+            above = self.level_noise(cell, lz + 1);
+            for i in lz * LATTICE_Z..cmp::min((lz + 1) * LATTICE_Z, height) {
+                noise.push(lerp(below, above, (i % LATTICE_Z) as f32 / LATTICE_Z as f32));
+            }
+        }
+        noise
+    }
+
+    /// Interpolates the noise of the given lattice level within the cell.
+    fn level_noise(&mut self, cell: Cell, lz: usize) -> f32 {
+        let (lx, ly) = (cell.x, cell.y);
+        let front = lerp(self.sample(lx, ly, lz), self.sample(lx + 1, ly, lz), cell.tx);
+        let back = lerp(self.sample(lx, ly + 1, lz), self.sample(lx + 1, ly + 1, lz), cell.tx);
+        lerp(front, back, cell.ty)
+    }
+
+    /// Returns the noise at the given lattice point, sampling it if needed.
+    fn sample(&mut self, lx: i32, ly: i32, lz: usize) -> f32 {
+        let (x, y) = (lx - self.origin.0, ly - self.origin.1);
+        assert!(x >= 0 && y >= 0 && x < self.size.0 && y < self.size.1 && lz < self.levels,
+                "position outside of the density lattice");
+
+        let index = (lz as i32 * self.size.1 + y) * self.size.0 + x;
+        let sample = self.samples[index as usize];
+        if !sample.is_nan() {
+            return sample;
+        }
+
+        let sample = exact_fill_noise(self.table,
+                                      self.scale,
+                                      lx as f32 * LATTICE_XY,
+                                      ly as f32 * LATTICE_XY,
+                                      lz * LATTICE_Z);
+        self.samples[index as usize] = sample;
+        sample
+    }
+}
+
+/// The lattice cell containing a position and the relative position within
+/// the cell (in the range 0..1).
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    x: i32,
+    y: i32,
+    tx: f32,
+    ty: f32,
+}
+
+impl Cell {
+    fn containing(x: f32, y: f32) -> Self {
+        let (fx, fy) = (x / LATTICE_XY, y / LATTICE_XY);
+        let (lx, ly) = (fx.floor(), fy.floor());
+        Cell {
+            x: lx as i32,
+            y: ly as i32,
+            tx: fx - lx,
+            ty: fy - ly,
+        }
+    }
+}
+
+/// Evaluates the fill noise (in the range 0..1) of the unit at height `i` of
+/// the column at `(x, y)` without interpolation.
+pub fn exact_fill_noise(table: &PermutationTable,
+                        scale: (f32, f32, f32),
+                        x: f32,
+                        y: f32,
+                        i: usize)
+                        -> f32 {
+    let z = i as f32 * PILLAR_STEP_HEIGHT;
+    let noise = open_simplex3::<f32>(table, &[x * scale.0, y * scale.1, z * scale.2]);
+
+    // The noise is (theoretically) in the range -1..1
+    // Map the noise to a range of 0..1
+    (noise + 1.0) / 2.0
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[test]
+fn interpolation_is_close_to_exact_noise() {
+    use gen::seeded_rng;
+    use gen::world::config::WorldGenConfig;
+    use rand::{Rand, Rng};
+
+    let table = PermutationTable::rand(&mut seeded_rng(0, 0, ()));
+    let scale = WorldGenConfig::default().land_noise_scale;
+    let min = Point2f::new(-100.0, -60.0);
+    let max = Point2f::new(100.0, 60.0);
+    let mut lattice = DensityLattice::new(&table, scale, min, max, 256);
+
+    let mut rng = seeded_rng(0, 1, ());
+    let mut total = 0.0;
+    for _ in 0..10_000 {
+        let x = rng.gen_range(min.x, max.x);
+        let y = rng.gen_range(min.y, max.y);
+        let i = rng.gen_range(0, 256);
+        let deviation = (lattice.fill_noise(x, y, i) - exact_fill_noise(&table, scale, x, y, i))
+            .abs();
+        assert!(deviation < 0.05, "deviation {} at ({}, {}, {})", deviation, x, y, i);
+        total += deviation;
+    }
+    assert!(total / 10_000.0 < 0.01);
+
+    let column = lattice.column_noise(5.5, -7.25, 255);
+    assert_eq!(column.len(), 255);
+    for (i, &noise) in column.iter().enumerate() {
+        assert_eq!(noise, lattice.fill_noise(5.5, -7.25, i));
+    }
+
+    // Lattice points are exact
+    for &(x, y, i) in &[(0.0, 0.0, 0), (-8.0, 12.0, 40), (96.0, -60.0, 252)] {
+        assert_eq!(lattice.fill_noise(x, y, i), exact_fill_noise(&table, scale, x, y, i));
+    }
+}
+
+#[test]
+fn lattices_agree() {
+    use gen::seeded_rng;
+    use gen::world::config::WorldGenConfig;
+    use rand::Rand;
+
+    // Two overlapping lattices and one which only covers a single point
+    let table = PermutationTable::rand(&mut seeded_rng(0, 0, ()));
+    let scale = WorldGenConfig::default().land_noise_scale;
+    let mut a = DensityLattice::new(&table,
+                                    scale,
+                                    Point2f::new(-30.0, -30.0),
+                                    Point2f::new(10.0, 10.0),
+                                    100);
+    let mut b = DensityLattice::new(&table,
+                                    scale,
+                                    Point2f::new(-5.0, -5.0),
+                                    Point2f::new(40.0, 40.0),
+                                    100);
+    for &(x, y) in &[(-3.3, 7.1), (0.0, 0.0), (9.9, -4.2)] {
+        let mut single = DensityLattice::new(&table,
+                                             scale,
+                                             Point2f::new(x, y),
+                                             Point2f::new(x, y),
+                                             100);
+        for i in 0..100 {
+            let noise = single.fill_noise(x, y, i);
+            assert_eq!(a.fill_noise(x, y, i), noise);
+            assert_eq!(b.fill_noise(x, y, i), noise);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! Hydraulic and thermal erosion of the terrain surface.
+//!
+//! Erosion works on a heightfield (the height of the surface of every
+//! pillar) and consists of two optional stages:
+//!
+//! - Hydraulic erosion simulates single rain droplets, which run downhill
+//!   from pillar to pillar. A fast droplet on a steep slope picks up
+//!   sediment, a slow one deposits it again. This carves gullies into
+//!   slopes and fills small pits.
+//! - Thermal erosion lets material slide down wherever the difference in
+//!   height between two neighbouring pillars exceeds the talus height. This
+//!   rounds off cliffs and spikes.
+//!
+//! Like the water, erosion is calculated for regions of `REGION_SIZE`²
+//! pillars, each with a margin of `REGION_MARGIN` pillars on every side.
+//! Regions overlap within the margins and the results of overlapping regions
+//! are blended, with weights that fall off linearly towards the outer edge of
+//! each region (see `covering_regions()`). The eroded terrain thus only
+//! depends on the position and doesn't have any seams at region borders,
+//! no matter in which order chunks are generated.
+
+use gen::seeded_rng;
+use math::{AxialPoint, HEX_DIRECTIONS, div_floor};
+use rand::Rng;
+
+/// Size of a region in pillars (in both axial directions).
+pub const REGION_SIZE: i32 = 64;
+
+/// Number of additional pillars on each side of a region, see module
+/// documentation.
+pub const REGION_MARGIN: i32 = 16;
+
+/// Parameters of the hydraulic erosion. All heights are measured in height
+/// units.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct HydraulicErosion {
+    /// The number of droplets which are simulated per pillar of a region.
+    pub droplets_per_pillar: f32,
+    /// The maximum number of pillars a droplet runs over.
+    pub max_steps: u32,
+    /// How much sediment a droplet can carry relative to its speed, water
+    /// and the slope it runs down.
+    pub capacity: f32,
+    /// The part of the free capacity which is picked up in every step.
+    pub erosion_rate: f32,
+    /// The part of the excess sediment which is dropped in every step.
+    pub deposition_rate: f32,
+    /// The part of the water which evaporates in every step.
+    pub evaporation: f32,
+    /// The capacity is calculated with at least this slope, so that
+    /// droplets on flat ground still carry some sediment.
+    pub min_slope: f32,
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        HydraulicErosion {
+            droplets_per_pillar: 0.5,
+            max_steps: 48,
+            capacity: 4.0,
+            erosion_rate: 0.1,
+            deposition_rate: 0.3,
+            evaporation: 0.02,
+            min_slope: 0.05,
+        }
+    }
+}
+
+/// Parameters of the thermal erosion. All heights are measured in height
+/// units.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct ThermalErosion {
+    /// Neighbouring pillars may differ by this height without any material
+    /// sliding down.
+    pub talus: f32,
+    /// The part of the excess height which slides down in every iteration.
+    pub rate: f32,
+    /// The number of iterations.
+    pub iterations: u32,
+}
+
+impl Default for ThermalErosion {
+    fn default() -> Self {
+        ThermalErosion {
+            talus: 4.0,
+            rate: 0.5,
+            iterations: 8,
+        }
+    }
+}
+
+/// Parameters of the erosion. Stages which are `None` are skipped; by
+/// default, the terrain isn't eroded at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct ErosionConfig {
+    pub hydraulic: Option<HydraulicErosion>,
+    pub thermal: Option<ThermalErosion>,
+}
+
+impl ErosionConfig {
+    /// Returns a config with both stages enabled and default parameters.
+    pub fn enabled() -> Self {
+        ErosionConfig {
+            hydraulic: Some(HydraulicErosion::default()),
+            thermal: Some(ThermalErosion::default()),
+        }
+    }
+
+    /// Returns whether any of the stages is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.hydraulic.is_some() || self.thermal.is_some()
+    }
+}
+
+/// Returns all regions whose eroded heightfield contains the given pillar
+/// together with the weight of each region. The weights add up to 1.
+pub fn covering_regions(pos: AxialPoint) -> Vec<(AxialPoint, f32)> {
+    let mut out = Vec::with_capacity(4);
+    for &(q, q_weight) in &axis_weights(pos.q) {
+        for &(r, r_weight) in &axis_weights(pos.r) {
+            let weight = q_weight * r_weight;
+            if weight > 0.0 {
+                out.push((AxialPoint::new(q, r), weight));
+            }
+        }
+    }
+    out
+}
+
+/// Returns the (at most two) regions along one axis which contain the given
+/// coordinate and their weights.
+fn axis_weights(u: i32) -> [(i32, f32); 2] {
+    let region = div_floor(u, REGION_SIZE);
+    // Use the center of the pillar, so that no pillar gets the weight 0
+    let local = (u - region * REGION_SIZE) as f32 + 0.5;
+    let margin = REGION_MARGIN as f32;
+    let fade = 2.0 * margin;
+
+    if local < margin {
+        let weight = (local + margin) / fade;
+        [(region, weight), (region - 1, 1.0 - weight)]
+    } else if local > REGION_SIZE as f32 - margin {
+        let weight = (REGION_SIZE as f32 + margin - local) / fade;
+        [(region, weight), (region + 1, 1.0 - weight)]
+    } else {
+        [(region, 1.0), (region, 0.0)]
+    }
+}
+
+/// The eroded heightfield of a region.
+#[derive(Clone, Debug)]
+pub struct ErosionRegion {
+    /// The first pillar of the region including the margin.
+    origin: AxialPoint,
+    /// The number of pillars in both directions, including the margins.
+    size: i32,
+    heights: Vec<f32>,
+}
+
+impl ErosionRegion {
+    /// Erodes the given region. `height_at` returns the height of the
+    /// surface at a pillar (in height units).
+    pub fn generate<F>(region: AxialPoint, config: &ErosionConfig, seed: u64, mut height_at: F)
+                       -> Self
+        where F: FnMut(AxialPoint) -> u16
+    {
+        let size = REGION_SIZE + 2 * REGION_MARGIN;
+        let origin = AxialPoint::new(region.q * REGION_SIZE - REGION_MARGIN,
+                                     region.r * REGION_SIZE - REGION_MARGIN);
+
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        for r in 0..size {
+            for q in 0..size {
+                heights.push(height_at(AxialPoint::new(origin.q + q, origin.r + r)) as f32);
+            }
+        }
+
+        let mut eroded = ErosionRegion {
+            origin: origin,
+            size: size,
+            heights: heights,
+        };
+        if let Some(ref hydraulic) = config.hydraulic {
+            let mut rng = seeded_rng(seed, "EROSION", (region.q, region.r));
+            let droplets = (hydraulic.droplets_per_pillar * (size * size) as f32) as u32;
+            for _ in 0..droplets {
+                let start = (rng.gen_range(0, size), rng.gen_range(0, size));
+                eroded.run_droplet(start, hydraulic);
+            }
+        }
+        if let Some(ref thermal) = config.thermal {
+            for _ in 0..thermal.iterations {
+                eroded.slide(thermal);
+            }
+        }
+        eroded
+    }
+
+    /// Returns the eroded height of the surface at the given pillar, if it
+    /// lies within this region (including the margins).
+    pub fn height_at(&self, pos: AxialPoint) -> Option<f32> {
+        self.index_of(pos.q - self.origin.q, pos.r - self.origin.r).map(|i| self.heights[i])
+    }
+
+    /// Simulates a single droplet starting at the given local position.
+    fn run_droplet(&mut self, start: (i32, i32), params: &HydraulicErosion) {
+        let (mut q, mut r) = start;
+        let mut sediment: f32 = 0.0;
+        let mut water: f32 = 1.0;
+        let mut speed: f32 = 1.0;
+
+        for _ in 0..params.max_steps {
+            let index = self.index_of(q, r).unwrap();
+            let height = self.heights[index];
+
+            // At the border of the region, the droplet leaves the heightfield
+            // together with its sediment
+            if self.neighbor_count(q, r) < HEX_DIRECTIONS.len() {
+                break;
+            }
+
+            // The droplet runs to the lowest neighbour
+            let (next_q, next_r) = HEX_DIRECTIONS.iter()
+                .map(|d| d.to_vector())
+                .map(|v| (q + v.q, r + v.r))
+                .fold(None, |lowest: Option<(i32, i32)>, (q, r)| {
+                    match lowest {
+                        Some((lq, lr)) if self.local_height(lq, lr) <= self.local_height(q, r) => {
+                            lowest
+                        }
+                        _ => Some((q, r)),
+                    }
+                })
+                .unwrap();
+            let next = self.index_of(next_q, next_r).unwrap();
+
+            let drop = height - self.heights[next];
+            if drop <= 0.0 {
+                // The droplet is stuck in a pit: fill it as far as possible
+                self.heights[index] += sediment.min(-drop);
+                break;
+            }
+
+            let capacity = drop.max(params.min_slope) * speed * water * params.capacity;
+            if sediment > capacity {
+                let deposit = (sediment - capacity) * params.deposition_rate;
+                self.heights[index] += deposit;
+                sediment -= deposit;
+            } else {
+                // Never dig below the next pillar, which would create pits
+                let erode = ((capacity - sediment) * params.erosion_rate).min(drop);
+                self.heights[index] -= erode;
+                sediment += erode;
+            }
+
+            speed = (speed * speed + drop).sqrt();
+            water *= 1.0 - params.evaporation;
+            q = next_q;
+            r = next_r;
+        }
+    }
+
+    /// Runs one iteration of the thermal erosion. Material is only moved
+    /// after all pillars were visited, so the result doesn't depend on the
+    /// order of the pillars.
+    fn slide(&mut self, params: &ThermalErosion) {
+        let mut delta = vec![0.0; self.heights.len()];
+
+        for r in 0..self.size {
+            for q in 0..self.size {
+                if self.neighbor_count(q, r) < HEX_DIRECTIONS.len() {
+                    continue;
+                }
+                let index = self.index_of(q, r).unwrap();
+                let height = self.heights[index];
+
+                let lower: Vec<_> = HEX_DIRECTIONS.iter()
+                    .map(|d| d.to_vector())
+                    .map(|v| self.index_of(q + v.q, r + v.r).unwrap())
+                    .map(|i| (i, height - self.heights[i] - params.talus))
+                    .filter(|&(_, excess)| excess > 0.0)
+                    .collect();
+                let total: f32 = lower.iter().map(|&(_, excess)| excess).sum();
+                let max = lower.iter().fold(0.0, |max: f32, &(_, excess)| max.max(excess));
+                if total == 0.0 {
+                    continue;
+                }
+
+                // Half of the largest excess would level both pillars, the
+                // moved material is shared according to the excess heights
+                let moved = params.rate * max / 2.0;
+                for &(i, excess) in &lower {
+                    let share = moved * excess / total;
+                    delta[i] += share;
+                    delta[index] -= share;
+                }
+            }
+        }
+
+        for (height, delta) in self.heights.iter_mut().zip(delta) {
+            *height += delta;
+        }
+    }
+
+    /// Returns the number of neighbours of the given local position which
+    /// lie within the heightfield.
+    fn neighbor_count(&self, q: i32, r: i32) -> usize {
+        HEX_DIRECTIONS.iter()
+            .map(|d| d.to_vector())
+            .filter(|v| self.index_of(q + v.q, r + v.r).is_some())
+            .count()
+    }
+
+    /// Returns the height at the given local position, which has to lie
+    /// within the heightfield.
+    fn local_height(&self, q: i32, r: i32) -> f32 {
+        self.heights[self.index_of(q, r).unwrap()]
+    }
+
+    fn index_of(&self, q: i32, r: i32) -> Option<usize> {
+        if q >= 0 && r >= 0 && q < self.size && r < self.size {
+            Some((r * self.size + q) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn regions_cover_every_pillar() {
+    for u in -3 * REGION_SIZE..3 * REGION_SIZE {
+        let pos = AxialPoint::new(u, 7 - u);
+        let regions = covering_regions(pos);
+        assert!(!regions.is_empty() && regions.len() <= 4);
+
+        let total: f32 = regions.iter().map(|&(_, weight)| weight).sum();
+        assert!((total - 1.0).abs() < 1e-5);
+        for &(region, _) in &regions {
+            for &(coord, region) in &[(pos.q, region.q), (pos.r, region.r)] {
+                let local = coord - region * REGION_SIZE;
+                assert!(local >= -REGION_MARGIN && local < REGION_SIZE + REGION_MARGIN);
+            }
+        }
+    }
+}
+
+#[test]
+fn thermal_erosion_flattens_cliffs() {
+    let config = ErosionConfig {
+        hydraulic: None,
+        thermal: Some(ThermalErosion { iterations: 300, ..ThermalErosion::default() }),
+    };
+    let cliff = |pos: AxialPoint| if pos.q < REGION_SIZE / 2 { 10 } else { 60 };
+    let region = ErosionRegion::generate(AxialPoint::new(0, 0), &config, 0, cliff);
+
+    // No material is lost
+    let before: f32 = region.heights.len() as f32 * 35.0;
+    let after: f32 = region.heights.iter().sum();
+    assert!((before - after).abs() / before < 1e-4);
+
+    // Away from the border of the heightfield, the slope is at most the
+    // talus height
+    for r in 1..region.size - 1 {
+        for q in 1..region.size - 2 {
+            let diff = (region.local_height(q + 1, r) - region.local_height(q, r)).abs();
+            assert!(diff < config.thermal.unwrap().talus + 1.0);
+        }
+    }
+}
+
+#[test]
+fn hydraulic_erosion_carves_slopes() {
+    let config = ErosionConfig {
+        hydraulic: Some(HydraulicErosion::default()),
+        thermal: None,
+    };
+    let slope = |pos: AxialPoint| (200 - pos.q - pos.r / 2) as u16;
+    let region = ErosionRegion::generate(AxialPoint::new(0, 0), &config, 7, slope);
+    let again = ErosionRegion::generate(AxialPoint::new(0, 0), &config, 7, slope);
+    assert_eq!(region.heights, again.heights);
+
+    // The lowest and highest pillar of the slope
+    let (low, high) = (slope(AxialPoint::new(REGION_SIZE + REGION_MARGIN - 1,
+                                             REGION_SIZE + REGION_MARGIN - 1)) as f32,
+                       slope(AxialPoint::new(-REGION_MARGIN, -REGION_MARGIN)) as f32);
+
+    let mut lowered = 0;
+    for r in -REGION_MARGIN..REGION_SIZE + REGION_MARGIN {
+        for q in -REGION_MARGIN..REGION_SIZE + REGION_MARGIN {
+            let pos = AxialPoint::new(q, r);
+            let eroded = region.height_at(pos).unwrap();
+            if eroded < slope(pos) as f32 - 0.5 {
+                lowered += 1;
+            }
+            // Droplets neither dig holes nor pile up towers
+            assert!(eroded >= low && eroded <= high);
+        }
+    }
+    assert!(lowered > 100);
+    assert_eq!(region.height_at(AxialPoint::new(-REGION_MARGIN - 1, 0)), None);
+}
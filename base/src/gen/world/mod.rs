@@ -1,26 +1,51 @@
 //! Procedurally generating the game world.
 pub mod biome;
-
-use world::{Chunk, ChunkIndex, ChunkProvider, HeightType, HexPillar};
-use world::{CHUNK_SIZE, GroundMaterial, PILLAR_STEP_HEIGHT, PillarSection, Prop};
+pub mod config;
+pub mod decoration;
+pub mod density;
+pub mod erosion;
+pub mod pass;
+pub mod spawn;
+pub mod strata;
+pub mod structure;
+pub mod water;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use world::{Chunk, ChunkIndex, ChunkProvider, HeightType, PILLAR_STEP_HEIGHT};
+use math::{AxialPoint, Point2f, Point3f};
 use rand::{Rand, Rng};
-use gen::{PlantGenerator, seeded_rng};
-use noise::{PermutationTable, open_simplex2, open_simplex3};
-use gen::world::biome::Biome;
-use prop::plant::Plant;
-use gen::plant::tree::PlantType;
-
-/// Land "fill noise" scaling in x, y, and z direction.
-const LAND_NOISE_SCALE: (f32, f32, f32) = (0.03, 0.03, 0.05);
+use gen::seeded_rng;
+use noise::{PermutationTable, open_simplex2};
+use gen::world::biome::{BiomeBlend, BiomeId, BiomeRegistry};
+use gen::plant::registry::{PlantList, PlantRegistry};
+use self::config::WorldGenConfig;
+use self::density::DensityLattice;
+use self::erosion::{ErosionRegion, covering_regions};
+use self::pass::{GenerationPass, ProtoChunk};
+use self::strata::Strata;
+use self::water::{PillarWater, WaterRegion, region_of};
+
+/// The maximum number of regions whose water or erosion is kept in memory.
+const MAX_CACHED_REGIONS: usize = 16;
 
 /// Main type to generate the game world. Implements the `ChunkProvider` trait
 /// (TODO, see #8).
 pub struct WorldGenerator {
     seed: u64,
+    config: WorldGenConfig,
     terrain_table: PermutationTable,
     plant_table: PermutationTable,
     temperature_table: PermutationTable,
     humidity_table: PermutationTable,
+    ore_table: PermutationTable,
+    strata: Strata,
+    biomes: BiomeRegistry,
+    plants: PlantRegistry,
+    water_regions: Mutex<RegionCache<WaterRegion>>,
+    erosion_regions: Mutex<RegionCache<ErosionRegion>>,
+    passes: Vec<Box<GenerationPass>>,
 }
 
 impl WorldGenerator {
@@ -30,212 +55,558 @@ impl WorldGenerator {
         let mut plant_rng = seeded_rng(seed, 1, ());
         let mut temperature_rng = seeded_rng(seed, 2, ());
         let mut humidity_rng = seeded_rng(seed, 3, ());
+        let mut ore_rng = seeded_rng(seed, 4, ());
 
         WorldGenerator {
             seed: seed,
+            config: WorldGenConfig::default(),
             terrain_table: PermutationTable::rand(&mut terrain_rng),
             plant_table: PermutationTable::rand(&mut plant_rng),
             temperature_table: PermutationTable::rand(&mut temperature_rng),
             humidity_table: PermutationTable::rand(&mut humidity_rng),
+            ore_table: PermutationTable::rand(&mut ore_rng),
+            strata: Strata::default(),
+            biomes: BiomeRegistry::builtin(),
+            plants: PlantRegistry::builtin(),
+            water_regions: Mutex::new(RegionCache::new()),
+            erosion_regions: Mutex::new(RegionCache::new()),
+            passes: pass::default_pipeline(),
         }
     }
 
+    /// Replaces the parameters of the terrain generation.
+    pub fn with_config(mut self, config: WorldGenConfig) -> Self {
+        self.config = config;
+        self.water_regions = Mutex::new(RegionCache::new());
+        self.erosion_regions = Mutex::new(RegionCache::new());
+        self
+    }
+
+    /// Returns the parameters of the terrain generation.
+    pub fn config(&self) -> &WorldGenConfig {
+        &self.config
+    }
+
+    /// Replaces the biomes which are generated.
+    pub fn with_biomes(mut self, biomes: BiomeRegistry) -> Self {
+        self.biomes = biomes;
+        self
+    }
+
+    /// Returns the biomes which are generated.
+    pub fn biomes(&self) -> &BiomeRegistry {
+        &self.biomes
+    }
+
+    /// Replaces the plant species. Biomes refer to species by their id, so
+    /// they should be loaded with the same species. Plants of biomes whose
+    /// species isn't registered don't grow.
+    pub fn with_plants(mut self, plants: PlantRegistry) -> Self {
+        self.plants = plants;
+        self
+    }
+
+    /// Returns the plant species, see `get_plant_list()`.
+    pub fn plants(&self) -> &PlantRegistry {
+        &self.plants
+    }
+
+    /// Replaces the layers of the generated terrain.
+    pub fn with_strata(mut self, strata: Strata) -> Self {
+        self.strata = strata;
+        self
+    }
+
+    /// Returns the layers of the generated terrain.
+    pub fn strata(&self) -> &Strata {
+        &self.strata
+    }
+
     /// Returns the seed of this world generator.
     pub fn seed(&self) -> u64 {
         self.seed
     }
 
-    /// trying aproximate the
-    /// steepvalues       10   20   60  200
-    /// for temperature  0.0  0.2  0.4  1.0
-    fn steepness_from_temperature(temperature: f32) -> f32 {
-        120.0 * temperature * temperature + 72.0 * temperature + 3.5
+    /// Replaces the passes which generate a chunk, see `pass` for the
+    /// default pipeline.
+    pub fn with_passes(mut self, passes: Vec<Box<GenerationPass>>) -> Self {
+        self.passes = passes;
+        self
     }
-}
 
-impl ChunkProvider for WorldGenerator {
-    fn load_chunk(&self, index: ChunkIndex) -> Option<Chunk> {
-        const WORLDGEN_HEIGHT: usize = 256;
-        // Create a 3D-Array of booleans indicating which pillar sections to fill
-        // (Map height is unlimited in theory, but we'll limit worldgen to 64 height
-        // units)
-        let mut fill = [[[false; WORLDGEN_HEIGHT]; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
-
-        Some(Chunk::with_pillars(index, |pos| {
-
-            let real_pos = pos.to_real();
-            let x = real_pos.x;
-            let y = real_pos.y;
-            // Pillar pos relative to first pillar
-            let rel_pos = pos - index.0 * CHUNK_SIZE as i32;
-
-            // noises
-            let mut temperature_noise = (open_simplex2::<f32>(&self.temperature_table,
-                                                              &[(x as f32) * 0.0015,
-                                                                (y as f32) * 0.0015]) +
-                                         0.6) / 2.0;
-            temperature_noise += 0.035 *
-                                 open_simplex2::<f32>(&self.temperature_table,
-                                                      &[(x as f32) * 0.15, (y as f32) * 0.15]);
-
-
-            let mut humidity_noise = (open_simplex2::<f32>(&self.humidity_table,
-                                                           &[(x as f32) * 0.0015,
-                                                             (y as f32) * 0.0015]) +
-                                      0.6) / 2.0;
-            humidity_noise += 0.035 *
-                              open_simplex2::<f32>(&self.humidity_table,
-                                                   &[(x as f32) * 0.15, (y as f32) * 0.15]);
-
-
-            let current_biome = Biome::from_climate(temperature_noise, humidity_noise);
-
-            for i in 0..WORLDGEN_HEIGHT {
-                if i == 0 {
-                    fill[rel_pos.q as usize][rel_pos.r as usize][i as usize] = true;
-                    continue;
-                }
+    /// Returns temperature and humidity at the given position (in world
+    /// units). Both are roughly in the range 0..1. Generated pillars use the
+    /// climate at their center.
+    pub fn climate_at(&self, pos: Point2f) -> (f32, f32) {
+        let (x, y) = (pos.x, pos.y);
+        let coarse = self.config.climate_frequency / self.config.biome_scale;
+        let fine = 0.15 / self.config.biome_scale;
+
+        let mut temperature = (open_simplex2::<f32>(&self.temperature_table,
+                                                    &[x * coarse, y * coarse]) +
+                               0.6) / 2.0;
+        temperature += 0.035 *
+                       open_simplex2::<f32>(&self.temperature_table, &[x * fine, y * fine]);
+
+        let mut humidity = (open_simplex2::<f32>(&self.humidity_table,
+                                                 &[x * coarse, y * coarse]) +
+                            0.6) / 2.0;
+        humidity += 0.035 * open_simplex2::<f32>(&self.humidity_table, &[x * fine, y * fine]);
+
+        (temperature, humidity)
+    }
 
-                let z = i as f32 * PILLAR_STEP_HEIGHT;
-                let fill_noise = open_simplex3::<f32>(&self.terrain_table,
-                                                      &[x * LAND_NOISE_SCALE.0,
-                                                        y * LAND_NOISE_SCALE.1,
-                                                        z * LAND_NOISE_SCALE.2]);
+    /// Returns the biome of the pillar containing the given position.
+    pub fn biome_at(&self, pos: Point2f) -> BiomeId {
+        let pillar = AxialPoint::from_real(pos);
+        let (temperature, humidity) = self.climate_at(pillar.to_real());
+        self.pillar_biome(pillar, temperature, humidity).1
+    }
 
-                // The noise is (theoretically) in the range -1..1
-                // Map the noise to a range of 0..1
-                let fill_noise = (fill_noise + 1.0) / 2.0;
+    /// Returns the height of the ground of the pillar containing the given
+    /// position: the top of its highest solid unit, below the water if there
+    /// is any.
+    ///
+    /// This is the height the pillar is generated with by the default
+    /// pipeline. It's only an estimate for the game world, which also
+    /// contains plants and the changes of the players.
+    pub fn surface_height_estimate_at(&self, pos: Point2f) -> HeightType {
+        let column = pass::terrain_column(self, AxialPoint::from_real(pos));
+        HeightType::from_units(column.top())
+    }
 
-                // Calculate threshold to fill this "block". The lower the threshold, the more
-                // likely this voxel is filled, so it should increase with height.
-                let height_pct = i as f32 / WORLDGEN_HEIGHT as f32;
+    /// Returns whether the given position lies within the solid terrain
+    /// generated by the default pipeline. Water isn't solid.
+    pub fn is_solid_at(&self, pos: Point3f) -> bool {
+        if pos.z < 0.0 {
+            return false;
+        }
 
-                // The threshold is calculated using a sigmoid function. These are the
-                // parameters used:
+        let column = pass::terrain_column(self, AxialPoint::from_real(Point2f::new(pos.x, pos.y)));
+        let unit = (pos.z / PILLAR_STEP_HEIGHT) as usize;
+        unit < column.filled.len() && column.filled[unit]
+    }
 
-                /// Minimum threshold to prevent threshold to reach 0,
-                /// needed to have any caves at all
-                const MIN_THRESH: f32 = 0.6;
-                /// Threshold at half value (max. steepness, avg. terrain
-                /// height)
-                const THRESH_MID: f32 = 0.5;
+    /// Returns the blend of the biomes at the given pillar with the given
+    /// climate and the biome chosen from the blend.
+    fn pillar_biome(&self,
+                    pos: AxialPoint,
+                    temperature: f32,
+                    humidity: f32)
+                    -> (BiomeBlend, BiomeId) {
+        let blend = self.biomes
+            .blend_from_climate(temperature, humidity, &self.config.biome_transition);
+
+        // Along the borders, the biome of every pillar is chosen randomly
+        // according to the weights, so that materials and plants of both
+        // biomes are mixed
+        let mut rng = seeded_rng(self.seed, "BIOME", (pos.q, pos.r));
+        let biome = blend.pick(rng.gen());
+        (blend, biome)
+    }
+
+    /// "Steepness" of the sigmoid function used by `is_filled()`.
+    fn thresh_steepness(&self, blend: &BiomeBlend, temperature: f32) -> f32 {
+        blend.blend(|b| self.biomes.biome(b).steepness) * self.config.steepness_at(temperature)
+    }
 
-                // "Steepness" of the sigmoid function.
-                let thresh_steepness: f32 =
-                    WorldGenerator::steepness_from_temperature(temperature_noise);
+    /// Returns the threshold which the fill noise of the unit at height `i`
+    /// has to exceed, so that the unit is filled with terrain.
+    fn fill_threshold(&self, i: usize, thresh_steepness: f32) -> f32 {
+        // The lower the threshold, the more likely this unit is filled, so it
+        // should increase with height.
+        let height_pct = i as f32 / self.config.world_height as f32;
 
-                let sig_thresh = 1.0 /
-                                 (1.0 + f32::exp(-thresh_steepness * (height_pct - THRESH_MID)));
+        // The threshold is calculated using a sigmoid function, see
+        // `WorldGenConfig` for its parameters
+        let min_thresh = self.config.min_thresh;
+        let thresh_mid = self.config.thresh_mid;
+        let sig_thresh = 1.0 / (1.0 + f32::exp(-thresh_steepness * (height_pct - thresh_mid)));
 
-                let threshold = (sig_thresh + MIN_THRESH) / (1.0 + MIN_THRESH);
+        (sig_thresh + min_thresh) / (1.0 + min_thresh)
+    }
 
-                fill[rel_pos.q as usize][rel_pos.r as usize][i as usize] = fill_noise > threshold;
+    /// Returns the number of units of a column which can be filled with
+    /// terrain. Above, the sigmoid has reached 1, so the threshold can't be
+    /// exceeded by the fill noise.
+    fn fill_limit(&self, thresh_steepness: f32) -> usize {
+        let height = self.config.world_height as usize;
+        if !(thresh_steepness > 0.0) {
+            return height;
+        }
+
+        // The threshold grows with the height, so search the lowest unit at
+        // which it reached 1. The lowest unit is always filled.
+        let (mut low, mut high) = (1, height);
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.fill_threshold(mid, thresh_steepness) < 1.0 {
+                low = mid + 1;
+            } else {
+                high = mid;
             }
+        }
+        low
+    }
 
-            let column = &fill[rel_pos.q as usize][rel_pos.r as usize];
+    /// Returns whether the unit at height `i` of a column with the given fill
+    /// noise is filled with terrain.
+    fn is_filled(&self, i: usize, thresh_steepness: f32, fill_noise: f32) -> bool {
+        i == 0 || fill_noise > self.fill_threshold(i, thresh_steepness)
+    }
 
-            // Create sections for all connected `true`s in the array
-            let mut sections = Vec::new();
-            let mut low = 0;
-            let mut height = None;
-            for i in 0..WORLDGEN_HEIGHT {
-                let material = current_biome.material();
+    /// Creates the lattice of the fill noise for all positions between `min`
+    /// and `max` and all units below `height`.
+    fn density_lattice(&self, min: Point2f, max: Point2f, height: usize) -> DensityLattice {
+        DensityLattice::new(&self.terrain_table, self.config.land_noise_scale, min, max, height)
+    }
 
-                match (height, column[i]) {
+    /// Returns the height (in units) of the terrain surface at the given
+    /// pillar, before it is eroded and rivers are carved into it.
+    fn surface_height(&self, pos: AxialPoint) -> u16 {
+        let real = pos.to_real();
+        let (temperature, humidity) = self.climate_at(real);
+        let blend = self.biomes
+            .blend_from_climate(temperature, humidity, &self.config.biome_transition);
+        let thresh_steepness = self.thresh_steepness(&blend, temperature);
+        let limit = self.fill_limit(thresh_steepness);
+        let mut lattice = self.density_lattice(real, real, limit);
+
+        // The lowest unit is always filled
+        (1..limit)
+            .rev()
+            .find(|&i| self.is_filled(i, thresh_steepness, lattice.fill_noise(real.x, real.y, i)))
+            .map_or(1, |i| i as u16 + 1)
+    }
 
-                    (Some(h), true) => {
-                        // Next one's still solid, increase section height
-                        height = Some(h + 1);
-                    }
-                    (Some(h), false) => {
-                        // Create a section of height `h` and start over
-                        sections.push(PillarSection::new(material,
-                                                         HeightType::from_units(low),
-                                                         HeightType::from_units(low + h)));
-                        height = None;
-                    }
-                    (None, true) => {
-                        low = i as u16;
-                        height = Some(1);
-                    }
-                    (None, false) => {}
-                };
+    /// Returns the height (in units) of the terrain surface at the given
+    /// pillar after the erosion, but before rivers are carved into it.
+    fn terrain_height(&self, pos: AxialPoint) -> u16 {
+        if !self.config.erosion.is_enabled() {
+            return self.surface_height(pos);
+        }
+
+        let height: f32 = covering_regions(pos)
+            .into_iter()
+            .map(|(region, weight)| {
+                let eroded = cached_region(&self.erosion_regions, region, || {
+                    ErosionRegion::generate(region, &self.config.erosion, self.seed, |pos| {
+                        self.surface_height(pos)
+                    })
+                });
+                eroded.height_at(pos).unwrap() * weight
+            })
+            .sum();
+        height.round().max(1.0).min(self.config.world_height as f32) as u16
+    }
+
+    /// Returns the water of the given pillar, whose terrain surface is at
+    /// `top`: the height down to which the terrain is removed and the level
+    /// of the water surface.
+    fn water_at(&self, pos: AxialPoint, top: u16) -> Option<(u16, u16)> {
+        let (bottom, level) = match self.water_region(region_of(pos)).water_at(pos, top) {
+            Some(PillarWater::Lake(level)) => (top, level),
+            Some(PillarWater::River { level, depth }) => {
+                let level = cmp::min(level, top);
+                (cmp::max(level.saturating_sub(depth), 1), level)
             }
+            None => (top, top),
+        };
 
-            if let Some(h) = height {
-                // Create the topmost pillar
-                sections.push(PillarSection::new(GroundMaterial::Dirt,
-                                                 HeightType::from_units(low),
-                                                 HeightType::from_units(low + h)));
+        let level = cmp::max(level, self.config.sea_level);
+        if level > bottom { Some((bottom, level)) } else { None }
+    }
+
+    /// Returns the lakes and rivers of the given region. They are only
+    /// calculated once and cached.
+    ///
+    /// The coarse heightmap of the water ignores the erosion: it's expensive
+    /// and hardly changes the height averaged over a whole cell.
+    fn water_region(&self, region: AxialPoint) -> Arc<WaterRegion> {
+        cached_region(&self.water_regions, region, || {
+            WaterRegion::generate(region,
+                                  self.config.water,
+                                  self.config.sea_level,
+                                  |pos| self.surface_height(pos))
+        })
+    }
+}
+
+/// The most recently used regions of water or erosion.
+struct RegionCache<T> {
+    /// The cached regions together with the time they were used last.
+    regions: HashMap<AxialPoint, (Arc<T>, u64)>,
+    /// Counts the accesses to the cache.
+    time: u64,
+}
+
+impl<T> RegionCache<T> {
+    fn new() -> Self {
+        RegionCache {
+            regions: HashMap::new(),
+            time: 0,
+        }
+    }
+
+    /// Returns the given region, if it's cached, and marks it as used.
+    fn get(&mut self, region: AxialPoint) -> Option<Arc<T>> {
+        self.time += 1;
+        let time = self.time;
+        self.regions.get_mut(&region).map(|entry| {
+            entry.1 = time;
+            entry.0.clone()
+        })
+    }
+
+    /// Adds the given region. If `MAX_CACHED_REGIONS` regions are cached
+    /// already, the least recently used one is dropped.
+    fn insert(&mut self, region: AxialPoint, generated: Arc<T>) {
+        if self.regions.len() >= MAX_CACHED_REGIONS {
+            let oldest = self.regions
+                .iter()
+                .min_by_key(|&(_, &(_, time))| time)
+                .map(|(&pos, _)| pos);
+            if let Some(oldest) = oldest {
+                self.regions.remove(&oldest);
             }
+        }
 
-            let mut props = Vec::new();
+        self.time += 1;
+        self.regions.insert(region, (generated, self.time));
+    }
+}
 
-            // plants
-            let plant_noise = open_simplex2::<f32>(&self.plant_table,
-                                                   &[(x as f32) * 0.25, (y as f32) * 0.25]);
+/// Returns the region from the cache or generates and caches it.
+///
+/// The cache isn't locked while the region is generated, so other threads
+/// can use it in the meantime. If one of them generated the same region
+/// meanwhile, its region is returned, so that there is only one copy.
+fn cached_region<T, F>(cache: &Mutex<RegionCache<T>>, region: AxialPoint, generate: F) -> Arc<T>
+    where F: FnOnce() -> T
+{
+    if let Some(cached) = cache.lock().unwrap().get(region) {
+        return cached;
+    }
 
-            if plant_noise > current_biome.plant_threshold() {
-                let mut rng = super::seeded_rng(self.seed, "TREE", (pos.q, pos.r));
+    let generated = Arc::new(generate());
+    let mut cache = cache.lock().unwrap();
+    if let Some(cached) = cache.get(region) {
+        return cached;
+    }
+    cache.insert(region, generated.clone());
+    generated
+}
 
-                let tmp = current_biome.plant_distribution();
-                let plant_type = rng.choose(tmp).unwrap();
+impl ChunkProvider for WorldGenerator {
+    fn load_chunk(&self, index: ChunkIndex) -> Option<Chunk> {
+        let margin = self.passes.iter().map(|pass| pass.margin()).max().unwrap_or(0);
+        let mut chunk = ProtoChunk::new(index, margin, self.config.world_height);
+        for pass in &self.passes {
+            pass.apply(self, &mut chunk);
+        }
+        Some(chunk.into_chunk())
+    }
 
-                let type_index = match *plant_type {
-                    PlantType::WitheredTree => 0,
-                    PlantType::Shrub => 1,
-                    PlantType::Cactus => 2,
-                    PlantType::JungleTree => 3,
-                    PlantType::ClumpOfGrass => 4,
-                    PlantType::Conifer => 5,
-                    PlantType::OakTree => 6,
-                    PlantType::Flower => 7,
-                };
+    /// Returns the variants of all species growing in any biome.
+    fn get_plant_list(&self) -> PlantList {
+        self.plants.generate(self.seed, &self.biomes.plant_species())
+    }
 
-                let plant_instance = rng.gen_range(0, 5);
-                let plant_index = 8 * plant_instance + type_index;
+    fn is_chunk_loadable(&self, _: ChunkIndex) -> bool {
+        // All chunks can be generated from nothing
+        true
+    }
+}
 
+#[test]
+fn region_cache_drops_least_recently_used() {
+    let mut cache = RegionCache::new();
+    let region = |q| AxialPoint::new(q, 0);
+    for q in 0..MAX_CACHED_REGIONS as i32 {
+        cache.insert(region(q), Arc::new(q));
+    }
+    assert_eq!(cache.get(region(0)), Some(Arc::new(0)));
+
+    // The first region was used again, so the second one is dropped
+    cache.insert(region(-1), Arc::new(-1));
+    assert_eq!(cache.regions.len(), MAX_CACHED_REGIONS);
+    assert_eq!(cache.get(region(1)), None);
+    assert_eq!(cache.get(region(0)), Some(Arc::new(0)));
+    assert_eq!(cache.get(region(-1)), Some(Arc::new(-1)));
+
+    let cache = Mutex::new(cache);
+    assert_eq!(cached_region(&cache, region(2), || unreachable!()), Arc::new(2));
+    assert_eq!(cached_region(&cache, region(1), || 10), Arc::new(10));
+    assert_eq!(cached_region(&cache, region(1), || unreachable!()), Arc::new(10));
+}
 
-                //     // put the tree at the highest position
-                let height = match sections.last() {
-                    Some(section) => section.top,
-                    None => HeightType::from_units(0),
+#[test]
+fn generated_terrain_is_stratified() {
+    use self::strata::Stratum;
+    use world::materials;
+
+    // Structures aren't stratified
+    let mut passes = pass::default_pipeline();
+    passes.pop();
+    let gen = WorldGenerator::with_seed(42).with_passes(passes);
+    let index = ChunkIndex(::math::AxialPoint::new(1, -2));
+    let chunk = gen.load_chunk(index).unwrap();
+    assert_eq!(gen.load_chunk(index).as_ref(), Some(&chunk));
+
+    let mut rock = 0;
+    let mut ore = 0;
+    for (_, pillar) in chunk.pillars() {
+        let solid: Vec<_> = pillar.sections().iter().filter(|s| !s.ground.is_fluid()).collect();
+        let top = solid.last().unwrap().top.units();
+        for section in solid {
+            for unit in section.bottom.units()..section.top.units() {
+                let depth = top - 1 - unit;
+                let expected = match gen.strata().stratum_at(depth) {
+                    Stratum::Surface => gen.biomes().biome(pillar.biome()).ground.surface,
+                    Stratum::Subsoil => gen.biomes().biome(pillar.biome()).ground.subsoil,
+                    Stratum::Rock => {
+                        rock += 1;
+                        if section.ground != materials::STONE {
+                            let veins = &gen.strata().veins;
+                            assert!(veins.iter().any(|v| v.material == section.ground));
+                            ore += 1;
+                            continue;
+                        }
+                        materials::STONE
+                    }
                 };
+                assert_eq!(section.ground, expected);
+            }
+        }
+    }
+    assert!(rock > 0);
+    assert!(ore > 0 && ore < rock);
+}
 
-                props.push(Prop {
-                    baseline: height,
-                    // for now, you can here set which plants should be placed
-                    // all over the world
-                    plant_index: plant_index as usize,
-                });
 
+#[test]
+fn water_does_not_depend_on_generation_order() {
+    // Chunks on both sides of region borders
+    let indices: Vec<_> = [-1, 0, 7, 8]
+        .iter()
+        .flat_map(|&q| [-1, 0, 7, 8].iter().map(move |&r| ChunkIndex(AxialPoint::new(q, r))))
+        .collect();
+    let forward = WorldGenerator::with_seed(42);
+    let backward = WorldGenerator::with_seed(42);
+    let chunks: Vec<_> = indices.iter().map(|&index| forward.load_chunk(index)).collect();
+    for (&index, chunk) in indices.iter().zip(&chunks).rev() {
+        assert_eq!(&backward.load_chunk(index), chunk);
+    }
+
+    // Water is always the topmost section and rests on the ground
+    let mut water = 0;
+    for chunk in &chunks {
+        for (_, pillar) in chunk.as_ref().unwrap().pillars() {
+            let sections = pillar.sections();
+            for (i, section) in sections.iter().enumerate() {
+                if section.ground.is_fluid() {
+                    assert_eq!(i, sections.len() - 1);
+                    assert_eq!(sections[i - 1].top, section.bottom);
+                    assert!(pillar.props().is_empty());
+                    water += 1;
+                }
             }
+        }
+    }
+    assert!(water > 0);
+}
+
+#[test]
+fn erosion_does_not_depend_on_generation_order() {
+    use self::erosion::{ErosionConfig, REGION_SIZE};
+    use world::CHUNK_SIZE;
+
+    // Two chunks on both sides of a region border
+    let border = REGION_SIZE / CHUNK_SIZE as i32;
+    let indices = [ChunkIndex(AxialPoint::new(border - 1, 1)),
+                   ChunkIndex(AxialPoint::new(border, 1))];
+    let config = WorldGenConfig { erosion: ErosionConfig::enabled(), ..Default::default() };
+    let forward = WorldGenerator::with_seed(42).with_config(config);
+    let backward = WorldGenerator::with_seed(42).with_config(config);
+    let chunks: Vec<_> = indices.iter().map(|&index| forward.load_chunk(index)).collect();
+    for (&index, chunk) in indices.iter().zip(&chunks).rev() {
+        assert_eq!(&backward.load_chunk(index), chunk);
+    }
 
-            HexPillar::new(sections,
-                           props,
-                           Biome::from_climate(temperature_noise, humidity_noise))
-        }))
-    }
-
-    fn get_plant_list(&self) -> Vec<Plant> {
-        let mut rng = super::seeded_rng(self.seed, "TREE", 42);
-
-        let mut vec = Vec::new();
-        for _ in 0..5 {
-            vec.push(PlantGenerator::new(PlantType::WitheredTree).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Shrub).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Cactus).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::JungleTree).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::ClumpOfGrass).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Conifer).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::OakTree).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Flower).generate(&mut rng));
+    // Erosion changes the terrain, but it's disabled by default
+    let uneroded = WorldGenerator::with_seed(42);
+    assert!(!uneroded.config().erosion.is_enabled());
+    assert!(indices.iter()
+        .zip(&chunks)
+        .any(|(&index, chunk)| &uneroded.load_chunk(index) != chunk));
+}
+
+#[test]
+fn config_changes_the_terrain() {
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    let default = WorldGenerator::with_seed(42).load_chunk(index);
+    let config = WorldGenConfig {
+        world_height: 128,
+        sea_level: 20,
+        ..WorldGenConfig::default()
+    };
+    let gen = WorldGenerator::with_seed(42).with_config(config);
+    let chunk = gen.load_chunk(index);
+    assert!(chunk != default);
+    assert_eq!(WorldGenerator::with_seed(42).with_config(config).load_chunk(index), chunk);
+
+    for (_, pillar) in chunk.unwrap().pillars() {
+        assert!(pillar.sections().last().unwrap().top.units() <= config.world_height);
+    }
+}
+
+#[test]
+fn queries_match_generated_chunks() {
+    let gen = WorldGenerator::with_seed(42);
+    for &(q, r) in &[(0, 0), (1, -2)] {
+        let index = ChunkIndex(AxialPoint::new(q, r));
+        let chunk = gen.load_chunk(index).unwrap();
+        let origin = index.origin().0;
+
+        // Only check some pillars to keep the test fast
+        for (rel, pillar) in chunk.pillars().filter(|&(rel, _)| (rel.q + rel.r) % 3 == 0) {
+            // Not exactly at the center of the pillar
+            let real = (origin + rel).to_real() + ::math::Vector2f::new(0.3, -0.2);
+            assert_eq!(gen.biome_at(real), pillar.biome());
+
+            let solid: Vec<_> = pillar.sections().iter().filter(|s| !s.ground.is_fluid()).collect();
+            assert_eq!(gen.surface_height_estimate_at(real), solid.last().unwrap().top);
+
+            let top = pillar.sections().last().unwrap().top.units();
+            for unit in 0..top + 2 {
+                let z = (unit as f32 + 0.5) * PILLAR_STEP_HEIGHT;
+                let expected = solid.iter()
+                    .any(|s| s.bottom.units() <= unit && unit < s.top.units());
+                assert_eq!(gen.is_solid_at(Point3f::new(real.x, real.y, z)), expected);
+            }
         }
-        vec
     }
+}
 
-    fn is_chunk_loadable(&self, _: ChunkIndex) -> bool {
-        // All chunks can be generated from nothing
-        true
+#[test]
+fn props_refer_to_generated_plants() {
+    let gen = WorldGenerator::with_seed(42);
+    let plants = gen.get_plant_list();
+    let mut props = 0;
+    for &(q, r) in &[(0, 0), (-1, 2)] {
+        let chunk = gen.load_chunk(ChunkIndex(AxialPoint::new(q, r))).unwrap();
+        for (_, pillar) in chunk.pillars() {
+            for prop in pillar.props() {
+                assert!(plants.get(prop.plant).is_some(), "{:?} is unknown", prop);
+                props += 1;
+            }
+        }
     }
+    assert!(props > 0);
+
+    // Plants of unregistered species don't grow
+    let gen = WorldGenerator::with_seed(42).with_plants(PlantRegistry::new());
+    assert!(gen.get_plant_list().is_empty());
+    let chunk = gen.load_chunk(ChunkIndex(AxialPoint::new(0, 0))).unwrap();
+    assert!(chunk.pillars().all(|(_, pillar)| pillar.props().is_empty()));
 }
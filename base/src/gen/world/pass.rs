@@ -0,0 +1,706 @@
+//! The passes in which a chunk is generated.
+//!
+//! A chunk is generated by running a list of passes (the pipeline) one
+//! after another over a `ProtoChunk`. The default pipeline consists of:
+//!
+//! 1. `ClimatePass`: temperature, humidity and biome of every pillar
+//! 2. `DensityPass`: which height units are filled with terrain
+//! 3. `SurfaceMaterialsPass`: the material of every filled unit
+//! 4. `CarvingPass`: river beds, sea and lakes
+//! 5. `DecorationPass`: plants (see `decoration`)
+//! 6. `StructurePass`: ruins and other structures (see `structure`)
+//!
+//! Passes only see the proto chunk and the world generator. To look at
+//! neighbouring pillars of other chunks, a pass can request a margin: the
+//! proto chunk then contains additional columns around the chunk, which are
+//! generated by all passes just like the columns of the chunk itself. Only
+//! the columns within the chunk end up in the final `Chunk`.
+
+use std::cmp;
+use gen::plant::registry::PlantId;
+use gen::seeded_rng;
+use math::{AxialPoint, Point2f, Point3f};
+use rand::Rng;
+use world::{CHUNK_SIZE, Chunk, ChunkIndex, HeightType, HexPillar, MaterialId, PILLAR_STEP_HEIGHT,
+            PillarSection, Prop, materials};
+use super::biome::{BiomeBlend, BiomeId};
+use super::decoration;
+use super::density::exact_fill_noise;
+use super::structure::{self, Structure};
+use super::WorldGenerator;
+
+/// A step of the world generation, see the module documentation.
+pub trait GenerationPass: Send {
+    /// Number of pillars around the chunk which this pass needs to look at.
+    fn margin(&self) -> i32 {
+        0
+    }
+
+    /// Runs the pass over all columns of the chunk, including the margin.
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk);
+}
+
+/// Returns the passes which generate the world as described in the module
+/// documentation.
+pub fn default_pipeline() -> Vec<Box<GenerationPass>> {
+    vec![Box::new(ClimatePass),
+         Box::new(DensityPass),
+         Box::new(SurfaceMaterialsPass),
+         Box::new(CarvingPass),
+         Box::new(DecorationPass),
+         Box::new(StructurePass)]
+}
+
+/// A single column of terrain while it is generated.
+#[derive(Clone, Debug)]
+pub struct Column {
+    /// The position of the pillar in the world.
+    pub pos: AxialPoint,
+    pub temperature: f32,
+    pub humidity: f32,
+    /// The weights of the biomes at this pillar.
+    pub blend: BiomeBlend,
+    /// The biome of the pillar, which is chosen from the blend.
+    pub biome: BiomeId,
+    /// Whether the height unit with the same index is filled with terrain.
+    /// There are `world_height` units, see `WorldGenConfig`.
+    pub filled: Vec<bool>,
+    /// The material of the height unit with the same index, only meaningful
+    /// for filled units.
+    pub ground: Vec<MaterialId>,
+    /// The level of the water surface above the terrain, if there is water.
+    pub water: Option<u16>,
+    pub props: Vec<Prop>,
+}
+
+impl Column {
+    /// Creates an empty column of the debug biome with `height` height
+    /// units.
+    pub fn new(pos: AxialPoint, height: u16) -> Self {
+        Column {
+            pos: pos,
+            temperature: 0.0,
+            humidity: 0.0,
+            blend: BiomeBlend::single(BiomeId::default()),
+            biome: BiomeId::default(),
+            filled: vec![false; height as usize],
+            ground: vec![materials::DEBUG; height as usize],
+            water: None,
+            props: Vec::new(),
+        }
+    }
+
+    /// Returns the height of the highest filled unit plus one.
+    pub fn top(&self) -> u16 {
+        self.filled.iter().rposition(|&filled| filled).map_or(0, |i| i as u16 + 1)
+    }
+
+    /// Creates the pillar from the column: all connected filled units of the
+    /// same material become one section, the water is put on top.
+    fn into_pillar(self) -> HexPillar {
+        let mut sections = Vec::new();
+        let mut current = None;
+        for i in 0..self.filled.len() + 1 {
+            let material = if i < self.filled.len() && self.filled[i] {
+                Some(self.ground[i])
+            } else {
+                None
+            };
+
+            match current {
+                Some((m, _)) if Some(m) == material => continue,
+                Some((m, low)) => {
+                    // The section ends here, create it and start over
+                    sections.push(PillarSection::new(m,
+                                                     HeightType::from_units(low),
+                                                     HeightType::from_units(i as u16)));
+                }
+                None => {}
+            }
+            current = material.map(|m| (m, i as u16));
+        }
+
+        if let Some(level) = self.water {
+            sections.push(PillarSection::new(materials::WATER,
+                                             HeightType::from_units(self.top()),
+                                             HeightType::from_units(level)));
+        }
+
+        HexPillar::new(sections, self.props, self.biome)
+    }
+}
+
+/// A chunk while it is generated: the columns of the chunk and of the margin
+/// around it.
+#[derive(Clone, Debug)]
+pub struct ProtoChunk {
+    index: ChunkIndex,
+    margin: i32,
+    /// The columns of all rows (same r-value) one after another, like in
+    /// `Chunk`.
+    columns: Vec<Column>,
+}
+
+impl ProtoChunk {
+    /// Creates empty columns with `height` height units for the given chunk
+    /// and a margin of `margin` pillars on each side.
+    pub fn new(index: ChunkIndex, margin: i32, height: u16) -> Self {
+        let origin = index.origin().0;
+        let size = CHUNK_SIZE as i32 + 2 * margin;
+        let mut columns = Vec::with_capacity((size * size) as usize);
+        for r in 0..size {
+            for q in 0..size {
+                let pos = AxialPoint::new(origin.q - margin + q, origin.r - margin + r);
+                columns.push(Column::new(pos, height));
+            }
+        }
+
+        ProtoChunk {
+            index: index,
+            margin: margin,
+            columns: columns,
+        }
+    }
+
+    /// Returns the index of the generated chunk.
+    pub fn index(&self) -> ChunkIndex {
+        self.index
+    }
+
+    /// The number of pillars around the chunk which are generated as well.
+    pub fn margin(&self) -> i32 {
+        self.margin
+    }
+
+    /// Returns all columns, including the margin.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Returns all columns, including the margin.
+    pub fn columns_mut(&mut self) -> &mut [Column] {
+        &mut self.columns
+    }
+
+    /// Returns the column at the given position in the world, if it's part of
+    /// the chunk or the margin.
+    pub fn column(&self, pos: AxialPoint) -> Option<&Column> {
+        self.column_index(pos).map(|i| &self.columns[i])
+    }
+
+    /// Returns the column at the given position in the world, if it's part of
+    /// the chunk or the margin.
+    pub fn column_mut(&mut self, pos: AxialPoint) -> Option<&mut Column> {
+        self.column_index(pos).map(move |i| &mut self.columns[i])
+    }
+
+    /// Creates the chunk from all columns within it, the margin is dropped.
+    pub fn into_chunk(self) -> Chunk {
+        let size = CHUNK_SIZE as i32 + 2 * self.margin;
+        let margin = self.margin;
+        let pillars = self.columns
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, _)| {
+                let (q, r) = (i as i32 % size, i as i32 / size);
+                q >= margin && r >= margin && q < size - margin && r < size - margin
+            })
+            .map(|(_, column)| column.into_pillar())
+            .collect();
+        Chunk::from_pillars(pillars)
+    }
+
+    fn column_index(&self, pos: AxialPoint) -> Option<usize> {
+        let size = CHUNK_SIZE as i32 + 2 * self.margin;
+        let origin = self.index.origin().0;
+        let q = pos.q - origin.q + self.margin;
+        let r = pos.r - origin.r + self.margin;
+        if q >= 0 && r >= 0 && q < size && r < size {
+            Some((r * size + q) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Determines the climate of every column and chooses its biome.
+#[derive(Clone, Copy, Debug)]
+pub struct ClimatePass;
+
+impl GenerationPass for ClimatePass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        for column in chunk.columns_mut() {
+            determine_climate(gen, column);
+        }
+    }
+}
+
+/// Sets the climate and the biome of a single column.
+fn determine_climate(gen: &WorldGenerator, column: &mut Column) {
+    let (temperature, humidity) = gen.climate_at(column.pos.to_real());
+    let (blend, biome) = gen.pillar_biome(column.pos, temperature, humidity);
+    column.temperature = temperature;
+    column.humidity = humidity;
+    column.blend = blend;
+    column.biome = biome;
+}
+
+/// Fills the columns with terrain according to the 3D noise, which is
+/// interpolated from a coarse lattice (see `density`). If the erosion is
+/// enabled, the surface is moved to the eroded height.
+#[derive(Clone, Copy, Debug)]
+pub struct DensityPass;
+
+impl GenerationPass for DensityPass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        let first = chunk.columns()[0].pos.to_real();
+        let (min, max) = chunk.columns().iter().fold((first, first), |(min, max), column| {
+            let real = column.pos.to_real();
+            (Point2f::new(min.x.min(real.x), min.y.min(real.y)),
+             Point2f::new(max.x.max(real.x), max.y.max(real.y)))
+        });
+        let mut lattice = gen.density_lattice(min, max, gen.config.world_height as usize);
+
+        fill_columns(gen, chunk, |x, y, height| lattice.column_noise(x, y, height));
+    }
+}
+
+/// Like `DensityPass`, but evaluates the noise of every unit without
+/// interpolation. It's a lot slower and only meant to measure how much the
+/// interpolation changes the terrain.
+#[derive(Clone, Copy, Debug)]
+pub struct ExactDensityPass;
+
+impl GenerationPass for ExactDensityPass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        let scale = gen.config.land_noise_scale;
+        fill_columns(gen, chunk, |x, y, height| {
+            (0..height).map(|i| exact_fill_noise(&gen.terrain_table, scale, x, y, i)).collect()
+        });
+    }
+}
+
+/// Fills the columns with terrain where the fill noise exceeds the threshold
+/// of the unit and applies the erosion. `fill_noise(x, y, height)` returns
+/// the noise of all units below `height` of the column at `(x, y)`.
+fn fill_columns<F>(gen: &WorldGenerator, chunk: &mut ProtoChunk, mut fill_noise: F)
+    where F: FnMut(f32, f32, usize) -> Vec<f32>
+{
+    for column in chunk.columns_mut() {
+        fill_column(gen, column, &mut fill_noise);
+    }
+}
+
+/// Fills a single column with terrain, see `fill_columns()`.
+fn fill_column<F>(gen: &WorldGenerator, column: &mut Column, fill_noise: &mut F)
+    where F: FnMut(f32, f32, usize) -> Vec<f32>
+{
+    let real = column.pos.to_real();
+    let thresh_steepness = gen.thresh_steepness(&column.blend, column.temperature);
+    let limit = gen.fill_limit(thresh_steepness);
+    let noise = fill_noise(real.x, real.y, limit);
+    for i in 0..column.filled.len() {
+        column.filled[i] = i < limit && gen.is_filled(i, thresh_steepness, noise[i]);
+    }
+
+    if gen.config.erosion.is_enabled() {
+        let top = column.top();
+        let eroded = gen.terrain_height(column.pos) as usize;
+        for i in cmp::min(top, eroded as u16) as usize..column.filled.len() {
+            column.filled[i] = i < eroded;
+        }
+        column.filled[eroded - 1] = true;
+    }
+}
+
+/// Assigns the materials of the strata to all filled units.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceMaterialsPass;
+
+impl GenerationPass for SurfaceMaterialsPass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        for column in chunk.columns_mut() {
+            assign_materials(gen, column);
+        }
+    }
+}
+
+/// Sets the material of every filled unit according to its depth below the
+/// top of the column.
+fn assign_materials(gen: &WorldGenerator, column: &mut Column) {
+    let biome = gen.biomes.biome(column.biome);
+    let real = column.pos.to_real();
+    let top = column.top();
+    for i in 0..column.filled.len() {
+        if column.filled[i] {
+            let pos = Point3f::new(real.x, real.y, i as f32 * PILLAR_STEP_HEIGHT);
+            let depth = top - 1 - i as u16;
+            column.ground[i] = gen.strata.material_at(biome, depth, pos, &gen.ore_table);
+        }
+    }
+}
+
+/// Carves river beds into the terrain and fills rivers, lakes and the sea
+/// with water.
+#[derive(Clone, Copy, Debug)]
+pub struct CarvingPass;
+
+impl GenerationPass for CarvingPass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        for column in chunk.columns_mut() {
+            // Like everywhere else, the surface of the river bed is covered
+            // with the surface material
+            if carve_column(gen, column) {
+                assign_materials(gen, column);
+            }
+        }
+    }
+}
+
+/// Carves the river bed into a single column and fills it with water.
+/// Returns whether terrain was removed.
+fn carve_column(gen: &WorldGenerator, column: &mut Column) -> bool {
+    let top = column.top();
+    let (bottom, level) = match gen.water_at(column.pos, top) {
+        Some(water) => water,
+        None => return false,
+    };
+
+    // The water rests on the highest remaining unit
+    for i in bottom as usize..top as usize {
+        column.filled[i] = false;
+    }
+    column.water = Some(level);
+    bottom < top
+}
+
+/// Generates the terrain of a single column like `ClimatePass`,
+/// `DensityPass`, `CarvingPass` and `StructurePass`, but without assigning
+/// materials. The query functions of the `WorldGenerator` are based on it, so
+/// that they agree with the generated chunks.
+pub fn terrain_column(gen: &WorldGenerator, pos: AxialPoint) -> Column {
+    let mut column = ground_column(gen, pos);
+    for structure in structures_within(gen, pos, pos) {
+        stamp_structure(&structure, &mut column);
+    }
+    column
+}
+
+/// Like `terrain_column()`, but without any structures.
+fn ground_column(gen: &WorldGenerator, pos: AxialPoint) -> Column {
+    let real = pos.to_real();
+    let mut column = Column::new(pos, gen.config.world_height);
+    let mut lattice = gen.density_lattice(real, real, gen.config.world_height as usize);
+
+    determine_climate(gen, &mut column);
+    fill_column(gen,
+                &mut column,
+                &mut |x, y, height| lattice.column_noise(x, y, height));
+    carve_column(gen, &mut column);
+    column
+}
+
+/// Places the plants scheduled by `decoration::schedule()` on all columns
+/// without water whose terrain suits the species.
+#[derive(Clone, Copy, Debug)]
+pub struct DecorationPass;
+
+impl GenerationPass for DecorationPass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        let (min, max) = bounds(chunk);
+        for plant in decoration::schedule(gen, min, max) {
+            let top = {
+                let column = chunk.column(plant.pos).unwrap();
+                if column.water.is_some() {
+                    continue;
+                }
+                column.top()
+            };
+
+            // Neighbours outside of the chunk and its margin are generated
+            // like the columns of the chunk at this point
+            let neighbors: Vec<_> = plant.pos
+                .neighbors()
+                .iter()
+                .map(|&pos| match chunk.column(pos) {
+                    Some(column) => column.top(),
+                    None => ground_column(gen, pos).top(),
+                })
+                .collect();
+            let species = match gen.plants.get(plant.species) {
+                Some(species) => species,
+                None => continue,
+            };
+            if !species.placement.allows(top, &neighbors, gen.config.sea_level) {
+                continue;
+            }
+
+            // Plants of both layers may grow on the same pillar, so the
+            // species is part of the seed
+            let mut rng = seeded_rng(gen.seed, "TREE", (plant.pos.q, plant.pos.r, species.id.0));
+            let id = PlantId {
+                species: species.id,
+                variant: rng.gen_range(0, species.variants),
+            };
+
+            // put the plant at the highest position
+            chunk.column_mut(plant.pos).unwrap().props.push(Prop {
+                baseline: HeightType::from_units(top),
+                plant: id,
+            });
+        }
+    }
+}
+
+/// Stamps the structures into the terrain. Pillars covered by a structure
+/// lose their plants.
+#[derive(Clone, Copy, Debug)]
+pub struct StructurePass;
+
+impl GenerationPass for StructurePass {
+    fn apply(&self, gen: &WorldGenerator, chunk: &mut ProtoChunk) {
+        let (min, max) = bounds(chunk);
+        let structures = structures_within(gen, min, max);
+
+        for column in chunk.columns_mut() {
+            let mut stamped = false;
+            for structure in &structures {
+                stamped |= stamp_structure(structure, column);
+            }
+            if stamped {
+                column.props.clear();
+            }
+        }
+    }
+}
+
+/// Returns the smallest and largest axial coordinates of all columns.
+fn bounds(chunk: &ProtoChunk) -> (AxialPoint, AxialPoint) {
+    let first = chunk.columns()[0].pos;
+    chunk.columns().iter().fold((first, first), |(min, max), column| {
+        let pos = column.pos;
+        (AxialPoint::new(cmp::min(min.q, pos.q), cmp::min(min.r, pos.r)),
+         AxialPoint::new(cmp::max(max.q, pos.q), cmp::max(max.r, pos.r)))
+    })
+}
+
+/// Returns all structures reaching into the area between `min` and `max`.
+/// Structures stand on the terrain without any structures and aren't built
+/// on water.
+fn structures_within(gen: &WorldGenerator, min: AxialPoint, max: AxialPoint) -> Vec<Structure> {
+    structure::structures_within(gen.seed, min, max, |pos| {
+        let column = ground_column(gen, pos);
+        if column.water.is_some() { None } else { Some(column.top()) }
+    })
+}
+
+/// Stamps the blocks of the structure within the column into it. Returns
+/// whether the column was changed.
+fn stamp_structure(structure: &Structure, column: &mut Column) -> bool {
+    // Water would end up below the blocks
+    if column.water.is_some() {
+        return false;
+    }
+
+    let pos = column.pos;
+    let mut stamped = false;
+    for block in structure.blocks.iter().filter(|block| block.pos == pos) {
+        let bottom = structure.base as i32 + block.bottom;
+        let top = cmp::min(bottom + block.height as i32, column.filled.len() as i32);
+
+        // The gap between the ground and the block is filled as well, so
+        // that nothing floats
+        let start = cmp::max(cmp::min(column.top() as i32, bottom), 0);
+        for i in start as usize..cmp::max(top, 0) as usize {
+            column.filled[i] = true;
+            column.ground[i] = block.material;
+        }
+        stamped |= start < top;
+    }
+    stamped
+}
+
+/// Lowers every column to the height of its lowest neighbour.
+#[cfg(test)]
+struct LowerToNeighbors;
+
+#[cfg(test)]
+impl GenerationPass for LowerToNeighbors {
+    fn margin(&self) -> i32 {
+        1
+    }
+
+    fn apply(&self, _: &WorldGenerator, chunk: &mut ProtoChunk) {
+        let lowest: Vec<_> = chunk.columns()
+            .iter()
+            .map(|column| {
+                column.pos
+                    .neighbors()
+                    .iter()
+                    .filter_map(|&pos| chunk.column(pos))
+                    .fold(column.top(), |lowest, neighbor| cmp::min(lowest, neighbor.top()))
+            })
+            .collect();
+        for (column, lowest) in chunk.columns_mut().iter_mut().zip(lowest) {
+            for i in lowest as usize..column.filled.len() {
+                column.filled[i] = false;
+            }
+        }
+    }
+}
+
+#[test]
+fn passes_run_in_isolation() {
+    let gen = WorldGenerator::with_seed(42);
+    let index = ChunkIndex(AxialPoint::new(2, -1));
+    let mut chunk = ProtoChunk::new(index, 0, gen.config().world_height);
+    assert_eq!(chunk.columns().len(), (CHUNK_SIZE * CHUNK_SIZE) as usize);
+
+    ClimatePass.apply(&gen, &mut chunk);
+    DensityPass.apply(&gen, &mut chunk);
+    assert!(chunk.columns().iter().all(|column| column.top() > 0));
+    let unmaterialized = chunk.clone().into_chunk();
+    for (_, pillar) in unmaterialized.pillars() {
+        assert!(pillar.sections().iter().all(|section| section.ground == materials::DEBUG));
+    }
+
+    SurfaceMaterialsPass.apply(&gen, &mut chunk);
+    for (_, pillar) in chunk.into_chunk().pillars() {
+        assert!(pillar.sections().iter().all(|section| section.ground != materials::DEBUG));
+    }
+}
+
+#[test]
+fn passes_can_be_removed() {
+    use world::ChunkProvider;
+
+    // Without the `DecorationPass`
+    let mut passes = default_pipeline();
+    passes.remove(4);
+    let without_plants = WorldGenerator::with_seed(42).with_passes(passes);
+    let gen = WorldGenerator::with_seed(42);
+    let index = ChunkIndex(AxialPoint::new(0, 1));
+    let plain = without_plants.load_chunk(index).unwrap();
+    let decorated = gen.load_chunk(index).unwrap();
+
+    let mut plants = 0;
+    for ((_, plain), (_, decorated)) in plain.pillars().zip(decorated.pillars()) {
+        assert_eq!(plain.sections(), decorated.sections());
+        assert!(plain.props().is_empty());
+        plants += decorated.props().len();
+    }
+    assert!(plants > 0);
+}
+
+#[test]
+fn passes_see_the_margin() {
+    use world::ChunkProvider;
+
+    let passes = || -> Vec<Box<GenerationPass>> {
+        vec![Box::new(ClimatePass), Box::new(DensityPass), Box::new(LowerToNeighbors)]
+    };
+    let gen = WorldGenerator::with_seed(42).with_passes(passes());
+    let index = ChunkIndex(AxialPoint::new(-1, 3));
+    let chunk = gen.load_chunk(index).unwrap();
+
+    // The unmodified terrain including the pillars of the neighbouring chunks
+    let mut terrain = ProtoChunk::new(index, 1, gen.config().world_height);
+    ClimatePass.apply(&gen, &mut terrain);
+    DensityPass.apply(&gen, &mut terrain);
+
+    let origin = index.origin().0;
+    for (rel, pillar) in chunk.pillars() {
+        let pos = origin + rel;
+        let column = terrain.column(pos).unwrap();
+        let lowest = pos.neighbors()
+            .iter()
+            .map(|&neighbor| terrain.column(neighbor).unwrap().top())
+            .fold(column.top(), cmp::min);
+        let expected = column.filled[..lowest as usize].iter().rposition(|&filled| filled);
+        assert_eq!(pillar.sections().last().unwrap().top.units(),
+                   expected.unwrap() as u16 + 1);
+    }
+
+    // The pipeline can be reordered: lowering the terrain before it exists
+    // doesn't change anything
+    let mut reordered = passes();
+    reordered.swap(1, 2);
+    let plain = WorldGenerator::with_seed(42)
+        .with_passes(vec![Box::new(ClimatePass), Box::new(DensityPass)]);
+    assert_eq!(WorldGenerator::with_seed(42).with_passes(reordered).load_chunk(index),
+               plain.load_chunk(index));
+}
+
+#[test]
+fn interpolated_density_is_close_to_exact() {
+    let gen = WorldGenerator::with_seed(42);
+    let height = gen.config().world_height;
+
+    let mut units = 0;
+    let mut different_units = 0;
+    let mut height_difference = 0;
+    for &(q, r) in &[(0, 0), (3, -2), (-5, 4), (10, 10)] {
+        let index = ChunkIndex(AxialPoint::new(q, r));
+        let mut interpolated = ProtoChunk::new(index, 0, height);
+        let mut exact = ProtoChunk::new(index, 0, height);
+        ClimatePass.apply(&gen, &mut interpolated);
+        ClimatePass.apply(&gen, &mut exact);
+        DensityPass.apply(&gen, &mut interpolated);
+        ExactDensityPass.apply(&gen, &mut exact);
+
+        for (a, b) in interpolated.columns().iter().zip(exact.columns()) {
+            units += a.filled.len();
+            different_units += a.filled.iter().zip(&b.filled).filter(|&(a, b)| a != b).count();
+            height_difference += (a.top() as i32 - b.top() as i32).abs();
+        }
+    }
+
+    // Less than 1% of the units and less than a unit of the surface height
+    // on average differ
+    assert!(different_units * 100 < units);
+    assert!((height_difference as f32) < units as f32 / height as f32);
+}
+
+#[test]
+fn structures_do_not_depend_on_generation_order() {
+    use std::collections::HashSet;
+    use world::{ChunkProvider, PillarIndex};
+
+    // A structure which is stamped into several chunks
+    let gen = WorldGenerator::with_seed(42);
+    let structure = structures_within(&gen, AxialPoint::new(-64, -64), AxialPoint::new(64, 64))
+        .into_iter()
+        .find(|structure| {
+            let first = PillarIndex(structure.blocks[0].pos).chunk();
+            structure.blocks.iter().any(|block| PillarIndex(block.pos).chunk() != first)
+        })
+        .unwrap();
+    let mut indices: Vec<_> = structure.blocks
+        .iter()
+        .map(|block| PillarIndex(block.pos).chunk())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    indices.sort_by_key(|index| (index.0.q, index.0.r));
+
+    let backward = WorldGenerator::with_seed(42);
+    let chunks: Vec<_> = indices.iter().map(|&index| gen.load_chunk(index).unwrap()).collect();
+    for (&index, chunk) in indices.iter().zip(&chunks).rev() {
+        assert_eq!(&backward.load_chunk(index).unwrap(), chunk);
+    }
+
+    // Every chunk contains its part of the structure, at the same height
+    for block in &structure.blocks {
+        let (index, local) = PillarIndex(block.pos).to_chunk_local();
+        let pillar = &chunks[indices.iter().position(|&i| i == index).unwrap()][local];
+        if pillar.sections().iter().any(|section| section.ground.is_fluid()) {
+            continue;
+        }
+
+        let top = (structure.base as i32 + block.bottom) as u16 + block.height - 1;
+        assert!(pillar.sections()
+            .iter()
+            .any(|s| s.ground == block.material && s.bottom.units() <= top && top < s.top.units()));
+        assert!(pillar.props().is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! Searching a safe position for a player to enter the world.
+//!
+//! Which terrain surrounds a fixed position depends on the seed and the
+//! configuration of the world generator: it might be in the middle of a lake
+//! or on a thin crust above a cave. The `SpawnLocator` instead searches
+//! outward from a position, ring by ring, for the nearest pillar whose
+//! surface is dry, flat and solid. Like the queries of the `WorldGenerator`,
+//! this doesn't need any generated chunks.
+
+use std::cmp;
+use std::collections::HashMap;
+use math::{AxialPoint, AxialType, Point2f, Point3f, spiral};
+use world::HeightType;
+use super::WorldGenerator;
+use super::pass;
+
+/// Parameters of the search for a spawn point. All heights are measured in
+/// height units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnLocator {
+    /// Pillars up to this distance (in pillars) from the requested position
+    /// are searched.
+    pub max_distance: AxialType,
+    /// The surface of the neighbours may differ by this height from the
+    /// surface of the spawn pillar.
+    pub max_slope: u16,
+    /// This many units below the surface have to be solid, so that the
+    /// player doesn't stand on a thin crust above a cave.
+    pub floor_depth: u16,
+    /// The free space above the surface, which has to fit below the top of
+    /// the world.
+    pub headroom: u16,
+}
+
+impl Default for SpawnLocator {
+    fn default() -> Self {
+        SpawnLocator {
+            max_distance: 128,
+            // The player can step up one world unit
+            max_slope: 2,
+            floor_depth: 8,
+            headroom: 4,
+        }
+    }
+}
+
+impl SpawnLocator {
+    /// Returns the spawn point nearest to the origin of the world, see
+    /// `find()`.
+    pub fn find_near_origin(&self, gen: &WorldGenerator) -> Option<Point3f> {
+        self.find(gen, Point2f::new(0.0, 0.0))
+    }
+
+    /// Searches the pillar nearest to `near` (in world units) which is a
+    /// safe place to spawn and returns the position of the feet of a player
+    /// standing in its center. Returns `None` if no pillar within
+    /// `max_distance` is safe.
+    pub fn find(&self, gen: &WorldGenerator, near: Point2f) -> Option<Point3f> {
+        // Every pillar is checked as a candidate and as the neighbour of up
+        // to six other candidates, but its terrain is only generated once
+        let mut surfaces = HashMap::new();
+        spiral(AxialPoint::from_real(near), self.max_distance)
+            .filter_map(|pos| {
+                self.surface_if_safe(gen, pos, &mut surfaces).map(|top| standing_on(pos, top))
+            })
+            .next()
+    }
+
+    /// Like `find()`, but if no safe pillar is found, the player is placed
+    /// on the pillar containing `near`, on top of the ground or the water.
+    /// The player is never placed inside of the terrain.
+    pub fn locate(&self, gen: &WorldGenerator, near: Point2f) -> Point3f {
+        self.find(gen, near).unwrap_or_else(|| {
+            warn!("no safe spawn point near ({}, {}) found", near.x, near.y);
+            let pos = AxialPoint::from_real(near);
+            let column = pass::terrain_column(gen, pos);
+            let top = cmp::max(column.top(), column.water.unwrap_or(0));
+            standing_on(pos, top)
+        })
+    }
+
+    /// Returns the height of the surface of the given pillar if it's a safe
+    /// place to spawn. The surfaces of all checked pillars are cached in
+    /// `surfaces`.
+    fn surface_if_safe(&self,
+                       gen: &WorldGenerator,
+                       pos: AxialPoint,
+                       surfaces: &mut HashMap<AxialPoint, Surface>)
+                       -> Option<u16> {
+        let surface = self.surface(gen, pos, surfaces);
+        if !surface.solid {
+            return None;
+        }
+
+        // Checked last, as it's the most expensive condition
+        let flat = pos.neighbors().iter().all(|&n| {
+            let height = self.surface(gen, n, surfaces).top;
+            (height as i32 - surface.top as i32).abs() <= self.max_slope as i32
+        });
+        if flat { Some(surface.top) } else { None }
+    }
+
+    /// Returns the surface of the given pillar, which is only generated if it
+    /// isn't in `surfaces` yet.
+    fn surface(&self,
+               gen: &WorldGenerator,
+               pos: AxialPoint,
+               surfaces: &mut HashMap<AxialPoint, Surface>)
+               -> Surface {
+        *surfaces.entry(pos).or_insert_with(|| {
+            let column = pass::terrain_column(gen, pos);
+            let top = column.top();
+
+            // The highest filled unit is the surface, so there is nothing
+            // but the sky above it
+            let headroom = top as usize + self.headroom as usize <= column.filled.len();
+            let floor = top >= self.floor_depth &&
+                        column.filled[(top - self.floor_depth) as usize..top as usize]
+                            .iter()
+                            .all(|&f| f);
+
+            Surface {
+                top: top,
+                solid: column.water.is_none() && headroom && floor,
+            }
+        })
+    }
+}
+
+/// The surface of a pillar as far as the search is concerned.
+#[derive(Clone, Copy, Debug)]
+struct Surface {
+    /// The height of the highest filled unit.
+    top: u16,
+    /// Whether the pillar is dry, has a solid floor and enough headroom.
+    solid: bool,
+}
+
+/// Returns the position of the feet of a player standing in the center of
+/// the given pillar at the height `top` (in units).
+fn standing_on(pos: AxialPoint, top: u16) -> Point3f {
+    let center = pos.to_real();
+    Point3f::new(center.x, center.y, HeightType::from_units(top).to_real())
+}
+
+#[test]
+fn spawn_points_are_safe() {
+    use world::PILLAR_STEP_HEIGHT;
+
+    let locator = SpawnLocator::default();
+    for seed in 0..4 {
+        let gen = WorldGenerator::with_seed(seed);
+        let spawn = locator.find_near_origin(&gen).unwrap();
+        let center = Point2f::new(spawn.x, spawn.y);
+
+        // Solid ground below the feet, nothing above
+        let at = |dz: f32| Point3f::new(spawn.x, spawn.y, spawn.z + dz);
+        assert!(gen.is_solid_at(at(-PILLAR_STEP_HEIGHT / 2.0)));
+        assert!(!gen.is_solid_at(at(PILLAR_STEP_HEIGHT / 2.0)));
+        assert!(!gen.is_solid_at(at(2.0)));
+        assert_eq!(gen.surface_height_estimate_at(center).to_real(), spawn.z);
+        assert!(pass::terrain_column(&gen, AxialPoint::from_real(center)).water.is_none());
+
+        for i in 1..locator.floor_depth + 1 {
+            assert!(gen.is_solid_at(at(-(i as f32 - 0.5) * PILLAR_STEP_HEIGHT)));
+        }
+
+        // The same spawn point is found again
+        assert_eq!(locator.find_near_origin(&gen), Some(spawn));
+    }
+}
+
+#[test]
+fn spawn_point_is_nearest_safe_pillar() {
+    let gen = WorldGenerator::with_seed(7);
+    let locator = SpawnLocator::default();
+
+    let mut searched = 0;
+    for &(x, y) in &[(0.0, 0.0), (200.0, -150.0), (-80.0, 320.0), (-400.0, -40.0)] {
+        let near = Point2f::new(x, y);
+        let spawn = locator.find(&gen, near).unwrap();
+        let start = AxialPoint::from_real(near);
+        let found = AxialPoint::from_real(Point2f::new(spawn.x, spawn.y));
+        let distance = start.hex_distance(found);
+        if distance == 0 {
+            continue;
+        }
+
+        searched += 1;
+        let mut surfaces = HashMap::new();
+        for pos in spiral(start, distance - 1) {
+            assert_eq!(locator.surface_if_safe(&gen, pos, &mut surfaces), None);
+        }
+    }
+    assert!(searched > 0);
+}
+
+#[test]
+fn no_spawn_point_in_the_sea() {
+    use super::config::WorldGenConfig;
+
+    // The whole world is below the sea level
+    let config = WorldGenConfig {
+        world_height: 64,
+        sea_level: 63,
+        ..WorldGenConfig::default()
+    };
+    let gen = WorldGenerator::with_seed(0).with_config(config);
+    let locator = SpawnLocator { max_distance: 4, ..SpawnLocator::default() };
+    assert_eq!(locator.find_near_origin(&gen), None);
+
+    // The fallback is the water surface
+    let spawn = locator.locate(&gen, Point2f::new(0.0, 0.0));
+    assert_eq!(spawn.z, 63.0 * ::world::PILLAR_STEP_HEIGHT);
+}
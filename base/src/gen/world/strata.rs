@@ -0,0 +1,221 @@
+//! Depth based material layers of the generated terrain.
+//!
+//! The material of generated ground only depends on its depth below the
+//! surface of the column (the top of the highest section), not on the section
+//! it belongs to. From top to bottom there is:
+//!
+//! - the surface layer
+//! - the subsoil (dirt in most biomes)
+//! - rock (stone in most biomes), which is interspersed with ore veins
+//!
+//! The materials of the layers are defined by the biome.
+//!
+//! This way, caves expose rock instead of grass.
+
+use math::Point3f;
+use noise::{PermutationTable, open_simplex3};
+use world::{MaterialId, materials};
+use super::biome::Biome;
+
+/// Offset between the noise coordinates of two veins, so that every vein
+/// samples a different part of the noise.
+const VEIN_NOISE_OFFSET: f32 = 1000.0;
+
+/// A layer of the terrain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stratum {
+    Surface,
+    Subsoil,
+    Rock,
+}
+
+/// A kind of ore which is found in the rock layer. Veins are placed where 3D
+/// noise exceeds a threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OreVein {
+    pub material: MaterialId,
+    /// Scaling of the noise in x, y and z direction. Smaller values result
+    /// in larger veins.
+    pub scale: (f32, f32, f32),
+    /// The noise (in the range 0..1) has to exceed this value. The higher
+    /// the threshold, the rarer the ore.
+    pub threshold: f32,
+    /// The minimum depth below the surface (in height units) at which the
+    /// ore is found.
+    pub min_depth: u16,
+}
+
+/// Describes the layers of the generated terrain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Strata {
+    /// Thickness of the surface layer in height units.
+    pub surface_depth: u16,
+    /// Thickness of the subsoil below the surface layer in height units.
+    pub subsoil_depth: u16,
+    /// The ore veins in the rock. If veins overlap, the first one wins.
+    pub veins: Vec<OreVein>,
+}
+
+impl Default for Strata {
+    fn default() -> Self {
+        Strata {
+            surface_depth: 2,
+            subsoil_depth: 8,
+            veins: vec![OreVein {
+                            material: materials::COAL,
+                            scale: (0.12, 0.12, 0.2),
+                            threshold: 0.75,
+                            min_depth: 6,
+                        },
+                        OreVein {
+                            material: materials::IRON_ORE,
+                            scale: (0.2, 0.2, 0.3),
+                            threshold: 0.8,
+                            min_depth: 20,
+                        }],
+        }
+    }
+}
+
+impl Strata {
+    /// Returns the layer at the given depth (in height units) below the
+    /// surface. The topmost unit of a column has the depth 0.
+    pub fn stratum_at(&self, depth: u16) -> Stratum {
+        if depth < self.surface_depth {
+            Stratum::Surface
+        } else if depth - self.surface_depth < self.subsoil_depth {
+            Stratum::Subsoil
+        } else {
+            Stratum::Rock
+        }
+    }
+
+    /// Returns the material at the given position, which lies `depth` height
+    /// units below the surface. The noise table is used to place ore veins.
+    pub fn material_at(&self,
+                       biome: &Biome,
+                       depth: u16,
+                       pos: Point3f,
+                       ore_table: &PermutationTable)
+                       -> MaterialId {
+        match self.stratum_at(depth) {
+            Stratum::Surface => biome.ground.surface,
+            Stratum::Subsoil => biome.ground.subsoil,
+            Stratum::Rock => {
+                self.veins
+                    .iter()
+                    .enumerate()
+                    .find(|&(i, vein)| {
+                        depth >= vein.min_depth &&
+                        vein_noise(vein, i, pos, ore_table) > vein.threshold
+                    })
+                    .map_or(biome.ground.rock, |(_, vein)| vein.material)
+            }
+        }
+    }
+}
+
+/// Samples the noise of the `i`-th vein, mapped to the range 0..1.
+fn vein_noise(vein: &OreVein, i: usize, pos: Point3f, table: &PermutationTable) -> f32 {
+    let offset = i as f32 * VEIN_NOISE_OFFSET;
+    let noise = open_simplex3::<f32>(table,
+                                     &[pos.x * vein.scale.0 + offset,
+                                       pos.y * vein.scale.1 + offset,
+                                       pos.z * vein.scale.2 + offset]);
+    (noise + 1.0) / 2.0
+}
+
+#[test]
+fn layers_at_depth() {
+    let strata = Strata {
+        surface_depth: 2,
+        subsoil_depth: 5,
+        veins: vec![],
+    };
+    let expected = [Stratum::Surface,
+                    Stratum::Surface,
+                    Stratum::Subsoil,
+                    Stratum::Subsoil,
+                    Stratum::Subsoil,
+                    Stratum::Subsoil,
+                    Stratum::Subsoil,
+                    Stratum::Rock,
+                    Stratum::Rock];
+    for (depth, &stratum) in expected.iter().enumerate() {
+        assert_eq!(strata.stratum_at(depth as u16), stratum);
+    }
+    assert_eq!(strata.stratum_at(u16::max_value()), Stratum::Rock);
+
+    let no_surface = Strata { surface_depth: 0, ..strata };
+    assert_eq!(no_surface.stratum_at(0), Stratum::Subsoil);
+}
+
+#[test]
+fn materials_at_depth() {
+    use gen::seeded_rng;
+    use super::biome::{BiomeRegistry, biomes};
+    use rand::Rand;
+
+    let table = PermutationTable::rand(&mut seeded_rng(0, 0, ()));
+    let pos = Point3f::new(3.0, 4.0, 5.0);
+    let strata = Strata {
+        surface_depth: 1,
+        subsoil_depth: 3,
+        veins: vec![],
+    };
+
+    let registry = BiomeRegistry::builtin();
+    let at = |biome, depth| strata.material_at(registry.biome(biome), depth, pos, &table);
+    assert_eq!(at(biomes::RAIN_FOREST, 0), materials::JUNGLE_GRASS);
+    assert_eq!(at(biomes::RAIN_FOREST, 1), materials::DIRT);
+    assert_eq!(at(biomes::RAIN_FOREST, 3), materials::DIRT);
+    assert_eq!(at(biomes::RAIN_FOREST, 4), materials::STONE);
+    assert_eq!(at(biomes::DESERT, 0), materials::SAND);
+    assert_eq!(at(biomes::DESERT, 2), materials::SAND);
+    assert_eq!(at(biomes::DESERT, 200), materials::STONE);
+}
+
+#[test]
+fn ore_veins() {
+    use gen::seeded_rng;
+    use super::biome::{BiomeRegistry, biomes};
+    use rand::Rand;
+
+    let table = PermutationTable::rand(&mut seeded_rng(0, 0, ()));
+    let vein = |material, threshold| {
+        OreVein {
+            material: material,
+            scale: (0.1, 0.1, 0.1),
+            threshold: threshold,
+            min_depth: 10,
+        }
+    };
+    let strata = Strata {
+        surface_depth: 1,
+        subsoil_depth: 1,
+        // A threshold below 0 always places the ore, the first vein is never
+        // placed
+        veins: vec![vein(materials::COAL, 1.0), vein(materials::IRON_ORE, -1.0)],
+    };
+
+    let registry = BiomeRegistry::builtin();
+    let forest = registry.biome(biomes::FOREST);
+    let pos = Point3f::new(10.0, -3.0, 20.0);
+    assert_eq!(strata.material_at(forest, 9, pos, &table), materials::STONE);
+    assert_eq!(strata.material_at(forest, 10, pos, &table), materials::IRON_ORE);
+
+    // Ore is only found in some places and deterministically so
+    let strata = Strata { veins: vec![vein(materials::COAL, 0.6)], ..strata };
+    let found: Vec<_> = (0..400)
+        .map(|i| {
+            let pos = Point3f::new((i % 20) as f32, (i / 20) as f32, 0.0);
+            strata.material_at(forest, 50, pos, &table)
+        })
+        .collect();
+    assert!(found.contains(&materials::COAL));
+    assert!(found.contains(&materials::STONE));
+    for (i, &material) in found.iter().enumerate() {
+        let pos = Point3f::new((i % 20) as f32, (i / 20) as f32, 0.0);
+        assert_eq!(strata.material_at(forest, 50, pos, &table), material);
+    }
+}
@@ -0,0 +1,247 @@
+//! Structures which span several pillars: ruins, stone circles, fallen logs
+//! and the remains of greenhouses.
+//!
+//! The world is divided into cells of `CELL_SIZE`² pillars (in both axial
+//! directions). Every cell contains at most one structure, which is planned
+//! with a random number generator seeded with the position of the cell. The
+//! center of the structure lies within its cell, all its blocks lie within
+//! `MAX_RADIUS` pillars of the center. To generate a chunk, all cells whose
+//! structure might reach into the chunk are planned and the blocks within
+//! the chunk are stamped into the terrain (see `StructurePass`).
+//!
+//! A structure stands on the ground at its center. Like the plan, this
+//! height only depends on the position of the structure, so every chunk
+//! stamps its part of a structure at the same height, no matter in which
+//! order the chunks are generated.
+
+use gen::seeded_rng;
+use math::{AxialPoint, AxialVector, div_floor, line, ring, spiral};
+use rand::Rng;
+use world::{MaterialId, materials};
+
+/// Size of a cell in pillars (in both axial directions).
+pub const CELL_SIZE: i32 = 32;
+
+/// The maximum distance (in pillars) of a block from the center of its
+/// structure.
+pub const MAX_RADIUS: i32 = 8;
+
+/// The probability that a cell contains a structure.
+const STRUCTURE_CHANCE: f32 = 0.6;
+
+/// The different types of structures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructureKind {
+    /// Broken walls around a paved floor.
+    Ruin,
+    /// A ring of standing stones around a low altar.
+    StoneCircle,
+    /// The trunk of a tree lying on the ground.
+    FallenLog,
+    /// A stone foundation with broken glass panes around beds of mulch.
+    Greenhouse,
+}
+
+/// A vertical piece of a structure within a single pillar. All heights are
+/// measured in height units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Block {
+    pub pos: AxialPoint,
+    /// The height of the bottom of the block relative to the base of the
+    /// structure. Negative values replace the ground below the base.
+    pub bottom: i32,
+    pub height: u16,
+    pub material: MaterialId,
+}
+
+/// A structure placed in the world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Structure {
+    pub kind: StructureKind,
+    pub center: AxialPoint,
+    /// The height of the ground at the center (in height units), on which
+    /// the structure stands.
+    pub base: u16,
+    /// The blocks of the structure. Later blocks are stamped over earlier
+    /// ones within the same pillar.
+    pub blocks: Vec<Block>,
+}
+
+/// Returns all structures with blocks between `min` and `max` (inclusive, in
+/// axial coordinates), sorted by their cell. `ground_at` returns the height
+/// of the ground at a pillar (in height units) or `None`, if nothing can be
+/// built there (e.g. because it's under water).
+pub fn structures_within<F>(seed: u64,
+                            min: AxialPoint,
+                            max: AxialPoint,
+                            mut ground_at: F)
+                            -> Vec<Structure>
+    where F: FnMut(AxialPoint) -> Option<u16>
+{
+    let inside = |pos: AxialPoint| {
+        pos.q >= min.q && pos.r >= min.r && pos.q <= max.q && pos.r <= max.r
+    };
+
+    // All cells whose structure might reach into the area
+    let cell = |a: i32| div_floor(a, CELL_SIZE);
+    let (first_q, first_r) = (cell(min.q - MAX_RADIUS), cell(min.r - MAX_RADIUS));
+    let (last_q, last_r) = (cell(max.q + MAX_RADIUS), cell(max.r + MAX_RADIUS));
+
+    let mut structures = Vec::new();
+    for cr in first_r..last_r + 1 {
+        for cq in first_q..last_q + 1 {
+            let (kind, center, blocks) = match plan(seed, AxialPoint::new(cq, cr)) {
+                Some(plan) => plan,
+                None => continue,
+            };
+            if !blocks.iter().any(|block| inside(block.pos)) {
+                continue;
+            }
+
+            if let Some(base) = ground_at(center) {
+                structures.push(Structure {
+                    kind: kind,
+                    center: center,
+                    base: base,
+                    blocks: blocks,
+                });
+            }
+        }
+    }
+    structures
+}
+
+/// Plans the structure of the given cell, if it has one: its type, center
+/// and blocks.
+fn plan(seed: u64, cell: AxialPoint) -> Option<(StructureKind, AxialPoint, Vec<Block>)> {
+    let mut rng = seeded_rng(seed, "STRUCTURE", (cell.q, cell.r));
+    if rng.gen::<f32>() >= STRUCTURE_CHANCE {
+        return None;
+    }
+
+    let center = AxialPoint::new(cell.q * CELL_SIZE + rng.gen_range(0, CELL_SIZE),
+                                 cell.r * CELL_SIZE + rng.gen_range(0, CELL_SIZE));
+    let kind = *rng.choose(&[StructureKind::Ruin,
+                              StructureKind::StoneCircle,
+                              StructureKind::FallenLog,
+                              StructureKind::Greenhouse])
+        .unwrap();
+
+    let mut blocks = Vec::new();
+    {
+        let mut add = |pos: AxialPoint, bottom: i32, height: u16, material: MaterialId| {
+            blocks.push(Block {
+                pos: pos,
+                bottom: bottom,
+                height: height,
+                material: material,
+            })
+        };
+
+        match kind {
+            StructureKind::Ruin => {
+                let radius = rng.gen_range(3, 7);
+                for pos in spiral(center, radius - 1) {
+                    // Some of the paving stones are missing
+                    if rng.gen::<f32>() < 0.7 {
+                        add(pos, -1, 1, materials::STONE);
+                    }
+                }
+                for pos in ring(center, radius) {
+                    if rng.gen::<f32>() < 0.7 {
+                        add(pos, 0, rng.gen_range(1, 8), materials::STONE);
+                    }
+                }
+            }
+            StructureKind::StoneCircle => {
+                let radius = rng.gen_range(3, 6);
+                add(center, 0, 1, materials::STONE);
+                for (_, pos) in ring(center, radius).enumerate().filter(|&(i, _)| i % 2 == 0) {
+                    // Some stones have toppled over
+                    let height = if rng.gen::<f32>() < 0.2 {
+                        1
+                    } else {
+                        rng.gen_range(3, 9)
+                    };
+                    add(pos, 0, height, materials::STONE);
+                }
+            }
+            StructureKind::FallenLog => {
+                // The log runs through the center in a random direction
+                let mut half = AxialVector::new(0, 0);
+                while half.hex_length() < 2 || half.hex_length() > 4 {
+                    half = AxialVector::new(rng.gen_range(-4, 5), rng.gen_range(-4, 5));
+                }
+                for pos in line(center - half, center + half) {
+                    add(pos, 0, 2, materials::WOOD);
+                }
+            }
+            StructureKind::Greenhouse => {
+                let radius = rng.gen_range(2, 5);
+                for pos in spiral(center, radius - 1) {
+                    add(pos, -1, 1, materials::MULCH);
+                }
+                for pos in ring(center, radius) {
+                    add(pos, 0, 1, materials::STONE);
+                    // Most panes are broken
+                    if rng.gen::<f32>() < 0.5 {
+                        add(pos, 1, rng.gen_range(1, 6), materials::GLASS);
+                    }
+                }
+            }
+        }
+    }
+
+    Some((kind, center, blocks))
+}
+
+#[test]
+fn structures_stay_within_their_radius() {
+    let mut kinds = Vec::new();
+    for q in -10..10 {
+        for r in -10..10 {
+            let cell = AxialPoint::new(q, r);
+            if let Some((kind, center, blocks)) = plan(7, cell) {
+                assert_eq!(AxialPoint::new(div_floor(center.q, CELL_SIZE),
+                                           div_floor(center.r, CELL_SIZE)),
+                           cell);
+                assert!(!blocks.is_empty());
+                for block in &blocks {
+                    assert!(center.hex_distance(block.pos) <= MAX_RADIUS);
+                    assert!(block.height > 0);
+                }
+                if !kinds.contains(&kind) {
+                    kinds.push(kind);
+                }
+            }
+        }
+    }
+    assert_eq!(kinds.len(), 4);
+}
+
+#[test]
+fn structures_do_not_depend_on_the_area() {
+    fn ground_at(pos: AxialPoint) -> Option<u16> {
+        Some(100 + (pos.q % 7 + 7) as u16)
+    }
+
+    // Structures in both areas are the same, structures in the overlap are
+    // found by both
+    let a = structures_within(3, AxialPoint::new(-40, -40), AxialPoint::new(20, 20), ground_at);
+    let b = structures_within(3, AxialPoint::new(0, 0), AxialPoint::new(70, 50), ground_at);
+    assert!(!a.is_empty() && !b.is_empty());
+
+    let overlap = |s: &Structure| {
+        s.blocks.iter().any(|b| b.pos.q >= 0 && b.pos.r >= 0 && b.pos.q <= 20 && b.pos.r <= 20)
+    };
+    for structure in a.iter().filter(|s| overlap(s)) {
+        assert!(b.contains(structure));
+    }
+    for structure in b.iter().filter(|s| overlap(s)) {
+        assert!(a.contains(structure));
+    }
+
+    // No structures are built where `ground_at` doesn't allow it
+    assert!(structures_within(3, AxialPoint::new(-40, -40), AxialPoint::new(20, 20), |_| None)
+        .is_empty());
+}
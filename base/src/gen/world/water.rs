@@ -0,0 +1,419 @@
+//! Sea, lakes and rivers.
+//!
+//! All columns whose surface lies below the sea level are flooded up to it.
+//! Lakes and rivers are found on a coarse heightmap with one sample per cell
+//! of `CELL_SIZE`² pillars:
+//!
+//! - The heightmap is flooded from its border and from the sea ("priority
+//!   flood"): every cell gets the lowest water level at which it drains to
+//!   the border or the sea. Cells whose level lies clearly above their
+//!   height are lakes.
+//! - Every cell drains into the cell from which it was flooded. Counting how
+//!   many cells drain through a cell ("flow accumulation") gives the size of
+//!   its catchment area. Cells with a large catchment area are rivers, which
+//!   run in straight lines from the center of the cell to the center of the
+//!   cell it drains into.
+//!
+//! The world is split into regions of `REGION_CELLS`² cells, which are
+//! generated independently. To get the same result no matter in which order
+//! chunks are generated, the water of a pillar only depends on the region
+//! containing it. Every region is calculated with a margin of
+//! `REGION_MARGIN` cells on each side, so that rivers and lakes near the
+//! borders of a region continue in the neighbouring one, as long as their
+//! catchment area doesn't extend beyond the margin.
+
+use std::cmp::{self, Ordering};
+use std::collections::BinaryHeap;
+use math::{AxialPoint, InnerSpace, Point2f, div_floor};
+
+/// Size of a cell of the coarse heightmap in pillars (in both axial
+/// directions).
+pub const CELL_SIZE: i32 = 4;
+
+/// Size of a region in cells (in both axial directions).
+pub const REGION_CELLS: i32 = 32;
+
+/// Number of additional cells on each side of a region, see module
+/// documentation.
+pub const REGION_MARGIN: i32 = 12;
+
+/// Parameters for the generation of water.
+#[derive(Clone, Copy, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct WaterConfig {
+    /// The number of cells which have to drain through a cell to make it a
+    /// river.
+    pub river_threshold: u32,
+    /// Lakes need to be at least this deep (in height units) at the center
+    /// of a cell.
+    pub min_lake_depth: u16,
+}
+
+impl Default for WaterConfig {
+    fn default() -> Self {
+        WaterConfig {
+            river_threshold: 30,
+            min_lake_depth: 2,
+        }
+    }
+}
+
+/// The water of a single pillar, see `WaterRegion::water_at()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PillarWater {
+    /// Columns below this height are filled with water up to it.
+    Lake(u16),
+    /// A river with its surface at `level` at this pillar. The terrain has
+    /// to be carved out `depth` units below the surface.
+    River { level: u16, depth: u16 },
+}
+
+/// Returns the region containing the given pillar.
+pub fn region_of(pos: AxialPoint) -> AxialPoint {
+    let cell = cell_of(pos);
+    AxialPoint::new(div_floor(cell.q, REGION_CELLS), div_floor(cell.r, REGION_CELLS))
+}
+
+/// Returns the cell containing the given pillar.
+fn cell_of(pos: AxialPoint) -> AxialPoint {
+    AxialPoint::new(div_floor(pos.q, CELL_SIZE), div_floor(pos.r, CELL_SIZE))
+}
+
+/// Returns the pillar at the center of the given cell.
+fn cell_center(cell: AxialPoint) -> AxialPoint {
+    AxialPoint::new(cell.q * CELL_SIZE + CELL_SIZE / 2,
+                    cell.r * CELL_SIZE + CELL_SIZE / 2)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    /// The height of the surface at the center of the cell.
+    height: u16,
+    /// The water level at which the cell drains.
+    level: u16,
+    /// The index of the cell this cell drains into.
+    downstream: Option<usize>,
+    /// The number of cells which drain through this cell (including itself).
+    flow: u32,
+}
+
+/// Lakes and rivers of a region.
+#[derive(Clone, Debug)]
+pub struct WaterRegion {
+    config: WaterConfig,
+    sea_level: u16,
+    /// The first cell of the region including the margin.
+    origin: AxialPoint,
+    /// The number of cells in both directions, including the margins.
+    size: i32,
+    cells: Vec<Cell>,
+}
+
+impl WaterRegion {
+    /// Calculates the water of the given region. `height_at` returns the
+    /// height of the surface at a pillar (in height units), all cells below
+    /// `sea_level` belong to the sea.
+    pub fn generate<F>(region: AxialPoint,
+                       config: WaterConfig,
+                       sea_level: u16,
+                       mut height_at: F)
+                       -> Self
+        where F: FnMut(AxialPoint) -> u16
+    {
+        let size = REGION_CELLS + 2 * REGION_MARGIN;
+        let origin = AxialPoint::new(region.q * REGION_CELLS - REGION_MARGIN,
+                                     region.r * REGION_CELLS - REGION_MARGIN);
+
+        let mut cells = Vec::with_capacity((size * size) as usize);
+        for r in 0..size {
+            for q in 0..size {
+                let cell = AxialPoint::new(origin.q + q, origin.r + r);
+                let height = height_at(cell_center(cell));
+                cells.push(Cell {
+                    height: height,
+                    level: height,
+                    downstream: None,
+                    flow: 1,
+                });
+            }
+        }
+
+        let mut water = WaterRegion {
+            config: config,
+            sea_level: sea_level,
+            origin: origin,
+            size: size,
+            cells: cells,
+        };
+        water.flood();
+        water
+    }
+
+    /// Returns the water at the given pillar, whose column has its surface
+    /// at `top`. The pillar has to lie within this region.
+    pub fn water_at(&self, pos: AxialPoint, top: u16) -> Option<PillarWater> {
+        let cell = cell_of(pos);
+        let index = match self.index_of(cell) {
+            Some(index) => index,
+            None => return None,
+        };
+
+        if self.is_lake(index) {
+            let level = self.cells[index].level;
+            return if top < level { Some(PillarWater::Lake(level)) } else { None };
+        }
+
+        // A river passes through the pillar, if it is close enough to the
+        // line between the centers of a river cell and its downstream cell.
+        // Only the cell of the pillar and its neighbours have to be checked.
+        let real = pos.to_real();
+        let mut best: Option<(f32, PillarWater)> = None;
+        let mut candidates = vec![cell];
+        candidates.extend_from_slice(&cell.neighbors());
+        for &candidate in &candidates {
+            let (index, downstream) = match self.index_of(candidate) {
+                Some(index) => {
+                    match self.cells[index].downstream {
+                        Some(downstream) => (index, downstream),
+                        None => continue,
+                    }
+                }
+                None => continue,
+            };
+            let flow = self.cells[index].flow;
+            if flow < self.config.river_threshold || self.is_lake(index) {
+                continue;
+            }
+
+            let from = cell_center(candidate).to_real();
+            let to = cell_center(self.cell_at(downstream)).to_real();
+            let (distance, t) = distance_to_segment(real, from, to);
+            let strength = flow as f32 / self.config.river_threshold as f32;
+            if distance > river_width(strength) ||
+               best.map_or(false, |(best_distance, _)| best_distance <= distance) {
+                continue;
+            }
+
+            let upper = self.cells[index].level as f32;
+            let lower = self.cells[downstream].level as f32;
+            let level = (upper + (lower - upper) * t).round() as u16;
+            best = Some((distance,
+                         PillarWater::River {
+                level: level,
+                depth: river_depth(strength),
+            }));
+        }
+
+        best.map(|(_, water)| water)
+    }
+
+    /// Calculates the water levels, the directions of flow and the flow
+    /// accumulation of all cells.
+    fn flood(&mut self) {
+        let sea_level = self.sea_level;
+        let mut open = BinaryHeap::new();
+        let mut queued = vec![false; self.cells.len()];
+
+        // Water leaves the region at its border and flows into the sea
+        for index in 0..self.cells.len() {
+            let (q, r) = (index as i32 % self.size, index as i32 / self.size);
+            let border = q == 0 || r == 0 || q == self.size - 1 || r == self.size - 1;
+            if border || self.cells[index].height < sea_level {
+                self.cells[index].level = cmp::max(self.cells[index].height, sea_level);
+                queued[index] = true;
+                open.push(FloodNode {
+                    level: self.cells[index].level,
+                    cell: self.cell_at(index),
+                    index: index,
+                });
+            }
+        }
+
+        // Cells are visited in the order of their water level, so every cell
+        // is reached from the lowest possible outlet
+        let mut order = Vec::with_capacity(self.cells.len());
+        while let Some(FloodNode { level, index, .. }) = open.pop() {
+            order.push(index);
+            for &neighbor in &self.cell_at(index).neighbors() {
+                let next = match self.index_of(neighbor) {
+                    Some(next) if !queued[next] => next,
+                    _ => continue,
+                };
+                queued[next] = true;
+                self.cells[next].level = cmp::max(self.cells[next].height, level);
+                self.cells[next].downstream = Some(index);
+                open.push(FloodNode {
+                    level: self.cells[next].level,
+                    cell: neighbor,
+                    index: next,
+                });
+            }
+        }
+
+        // Every cell is visited after the cell it drains into
+        for &index in order.iter().rev() {
+            if let Some(downstream) = self.cells[index].downstream {
+                self.cells[downstream].flow += self.cells[index].flow;
+            }
+        }
+    }
+
+    fn is_lake(&self, index: usize) -> bool {
+        let cell = &self.cells[index];
+        cell.level > self.sea_level &&
+        cell.level >= cell.height + self.config.min_lake_depth
+    }
+
+    fn index_of(&self, cell: AxialPoint) -> Option<usize> {
+        let q = cell.q - self.origin.q;
+        let r = cell.r - self.origin.r;
+        if q >= 0 && r >= 0 && q < self.size && r < self.size {
+            Some((r * self.size + q) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn cell_at(&self, index: usize) -> AxialPoint {
+        let index = index as i32;
+        AxialPoint::new(self.origin.q + index % self.size,
+                        self.origin.r + index / self.size)
+    }
+}
+
+/// An entry of the priority queue used by `WaterRegion::flood()`. The lowest
+/// level comes first. Ties are broken by the position of the cell, so that
+/// overlapping regions agree on the direction of flow.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FloodNode {
+    level: u16,
+    cell: AxialPoint,
+    index: usize,
+}
+
+impl PartialOrd for FloodNode {
+    fn partial_cmp(&self, other: &FloodNode) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodNode {
+    fn cmp(&self, other: &FloodNode) -> Ordering {
+        // `BinaryHeap` is a max-heap
+        (other.level, other.cell).cmp(&(self.level, self.cell))
+    }
+}
+
+/// The distance (in world units) from the center of a river in which
+/// pillars are part of it. `strength` is the flow relative to the river
+/// threshold.
+fn river_width(strength: f32) -> f32 {
+    (0.6 + 0.4 * strength.sqrt()).min(3.0)
+}
+
+/// The depth of a river in height units.
+fn river_depth(strength: f32) -> u16 {
+    (1.0 + strength.sqrt() / 2.0).min(4.0) as u16
+}
+
+/// Returns the distance of `p` to the segment from `a` to `b` and the
+/// position of the closest point on the segment (in the range 0..1).
+fn distance_to_segment(p: Point2f, a: Point2f, b: Point2f) -> (f32, f32) {
+    let ab = b - a;
+    let len2 = ab.magnitude2();
+    let t = if len2 > 0.0 { ((p - a).dot(ab) / len2).max(0.0).min(1.0) } else { 0.0 };
+    ((a + ab * t - p).magnitude(), t)
+}
+
+#[cfg(test)]
+fn test_config() -> WaterConfig {
+    WaterConfig {
+        river_threshold: 20,
+        min_lake_depth: 2,
+    }
+}
+
+#[test]
+fn lakes_fill_depressions() {
+    // A plateau at height 50 with a pit around the origin and a sea far away
+    let height_at = |pos: AxialPoint| {
+        let distance = ((pos.q * pos.q + pos.r * pos.r) as f32).sqrt();
+        if pos.q > 80 {
+            0
+        } else if distance < 20.0 {
+            30
+        } else {
+            50
+        }
+    };
+    let water = WaterRegion::generate(AxialPoint::new(0, 0), test_config(), 10, height_at);
+
+    assert_eq!(water.water_at(AxialPoint::new(2, 2), 30), Some(PillarWater::Lake(50)));
+    // Pillars above the lake level stay dry
+    assert_eq!(water.water_at(AxialPoint::new(2, 2), 60), None);
+}
+
+#[test]
+fn rivers_flow_downhill() {
+    // A valley sloping down towards positive q, its lowest line is r = 60
+    let height_at = |pos: AxialPoint| (200 - pos.q + 2 * (pos.r - 60).abs()) as u16;
+    // Only the bottom of the valley collects enough water
+    let config = WaterConfig { river_threshold: 60, ..test_config() };
+    let water = WaterRegion::generate(AxialPoint::new(0, 0), config, 10, height_at);
+    let water_at = |q, r| water.water_at(AxialPoint::new(q, r), height_at(AxialPoint::new(q, r)));
+
+    // The river runs along the bottom of the valley and descends
+    let river = |q| {
+        (40..80)
+            .filter_map(|r| match water_at(q, r) {
+                Some(PillarWater::River { level, depth }) => Some((r, level, depth)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let upper = river(30);
+    let lower = river(110);
+    assert!(!upper.is_empty() && !lower.is_empty());
+    assert!(upper.iter().chain(&lower).all(|&(r, _, depth)| (r - 60).abs() <= 5 && depth > 0));
+    assert!(lower.len() >= upper.len());
+    assert!(upper[0].1 > lower[0].1);
+
+    // Slopes of the valley don't have rivers
+    assert_eq!(water_at(30, 20), None);
+    assert_eq!(water_at(110, 100), None);
+}
+
+#[test]
+fn regions_agree_at_borders() {
+    // A valley crossing the border between two regions, which drains into
+    // the sea. A high ridge keeps its catchment within the margins of both
+    // regions.
+    let height_at = |pos: AxialPoint| if pos.q >= 170 {
+        0
+    } else if pos.q < 100 {
+        (300 + pos.q) as u16
+    } else {
+        (200 - pos.q + 2 * (pos.r - 60).abs()) as u16
+    };
+    let config = test_config();
+    let size = REGION_CELLS * CELL_SIZE;
+    let left = WaterRegion::generate(AxialPoint::new(0, 0), config, 10, height_at);
+    let right = WaterRegion::generate(AxialPoint::new(1, 0), config, 10, height_at);
+
+    // Both regions know the water near their common border
+    let mut rivers = 0;
+    for q in size - 8..size + 8 {
+        for r in 40..80 {
+            let pos = AxialPoint::new(q, r);
+            let top = height_at(pos);
+            let water = left.water_at(pos, top);
+            assert_eq!(water, right.water_at(pos, top));
+            if let Some(PillarWater::River { .. }) = water {
+                rivers += 1;
+            }
+        }
+    }
+    assert!(rivers > 0);
+    assert_eq!(region_of(AxialPoint::new(size - 1, 0)), AxialPoint::new(0, 0));
+    assert_eq!(region_of(AxialPoint::new(size, 0)), AxialPoint::new(1, 0));
+    assert_eq!(region_of(AxialPoint::new(-1, 0)), AxialPoint::new(-1, 0));
+}
@@ -3,6 +3,8 @@
 //! - the `math` module reexports everything from the `cgmath` crate and
 //!   defines a few own type
 //! - the world module is all about saving and managing the game world
+//! - the `physics` module moves bodies through the world and detects
+//!   collisions
 
 pub extern crate rand;
 extern crate num_traits;
@@ -10,9 +12,12 @@ pub extern crate noise;
 #[macro_use]
 extern crate log;
 extern crate rustc_serialize;
+extern crate toml;
 
+mod data;
 pub mod gen;
 pub mod math;
+pub mod physics;
 pub mod prop;
 pub mod world;
 pub mod msg;
@@ -1,27 +1,25 @@
 use std::fmt;
 use std::cmp::{max, min};
 use world::{HEX_INNER_RADIUS, HEX_OUTER_RADIUS};
-use super::{AxialType, DefaultFloat, Point2f};
-use std::ops::{Add, Div, Index, IndexMut, Mul, Rem, Sub};
+use super::{AxialType, DefaultFloat, Point2f, Vector2f, round_axial};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Rem, RemAssign,
+               Sub, SubAssign};
 use math::cgmath::{Array, EuclideanSpace, MetricSpace};
 use super::AxialVector;
 /// A 2-dimensional point in axial coordinates. See [here][hex-blog] for more
 /// information.
 ///
 /// [hex-blog]: http://www.redblobgames.com/grids/hexagons/#coordinates
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+///
+/// Like with `cgmath::Point2`, the difference of two points is an
+/// `AxialVector` and only vectors can be added to points. Points are ordered
+/// lexicographically (first by `q`, then by `r`).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(C, packed)]
 pub struct AxialPoint {
     pub q: AxialType,
     pub r: AxialType,
 }
-// TODO: implement cgmath::Array
-// TODO: implement cgmath::MatricSpace
-// TODO: implement cgmath::EuclideanSpace
-// TODO: implement ops::{ ... }
-// For all of the above, see
-// http://bjz.github.io/cgmath/cgmath/struct.Point2.html
-//
 impl AxialPoint {
     pub fn new(q: AxialType, r: AxialType) -> Self {
         AxialPoint { q: q, r: r }
@@ -36,39 +34,14 @@ impl AxialPoint {
         }
     }
 
-    /// Return the `AxialPoint` from a `Point2f`. It's the [pixel to hex
+    /// Returns the hexagon which contains the given point. The fractional
+    /// axial coordinates of the point are rounded with the [cube rounding
     /// algorithm from redblobgames.com][1].
     ///
-    /// [1]: http://www.redblobgames.com/grids/hexagons/#pixel-to-hex
+    /// [1]: http://www.redblobgames.com/grids/hexagons/#rounding
     pub fn from_real(real: Point2f) -> Self {
-        let q = (real.x * ::math::SQRT_3 / 3.0 - real.y / 3.0) / HEX_OUTER_RADIUS;
-        let r = (real.y * 2.0 / 3.0) / HEX_OUTER_RADIUS;
-
-        let s = -q - r;
-
-        // Rounding
-        let rq = q.round();
-        let mut rr = r.round();
-        let mut rs = s.round();
-
-        // To test the right rounding
-        let q_diff = (rq - q).abs();
-        let r_diff = (rr - r).abs();
-        let s_diff = (rs - s).abs();
-
-        if q_diff > r_diff && q_diff > s_diff {
-            // The following line is not needed since we convert from cube
-            // coordinates to axial coordinates right away. To match the
-            // algorithm from the blog post more closely, this line and the
-            // if-else structure is kept.
-            // rq = -rr - rs;
-        } else if s_diff > r_diff {
-            rr = -rq - rs;
-        } else {
-            rs = -rq - rr;
-        }
-
-        AxialPoint { q: -rs as AxialType, r: rr as AxialType}
+        let frac = AxialVector::fractional_from_real(Vector2f::new(real.x, real.y));
+        round_axial(frac.0, frac.1)
     }
 
     /// Returns the `s` component of corresponding cube coordinates. In cube
@@ -96,6 +69,27 @@ impl Add<AxialVector> for AxialPoint {
         }
     }
 }
+impl AddAssign<AxialVector> for AxialPoint {
+    fn add_assign(&mut self, rhs: AxialVector) {
+        self.q += rhs.q;
+        self.r += rhs.r;
+    }
+}
+impl Sub<AxialVector> for AxialPoint {
+    type Output = AxialPoint;
+    fn sub(self, rhs: AxialVector) -> AxialPoint {
+        AxialPoint {
+            q: self.q - rhs.q,
+            r: self.r - rhs.r,
+        }
+    }
+}
+impl SubAssign<AxialVector> for AxialPoint {
+    fn sub_assign(&mut self, rhs: AxialVector) {
+        self.q -= rhs.q;
+        self.r -= rhs.r;
+    }
+}
 impl Sub<AxialPoint> for AxialPoint {
     type Output = AxialVector;
     fn sub(self, rhs: AxialPoint) -> AxialVector {
@@ -114,6 +108,12 @@ impl Mul<AxialType> for AxialPoint {
         }
     }
 }
+impl MulAssign<AxialType> for AxialPoint {
+    fn mul_assign(&mut self, rhs: AxialType) {
+        self.q *= rhs;
+        self.r *= rhs;
+    }
+}
 impl Div<AxialType> for AxialPoint {
     type Output = AxialPoint;
     fn div(self, rhs: AxialType) -> AxialPoint {
@@ -123,6 +123,12 @@ impl Div<AxialType> for AxialPoint {
         }
     }
 }
+impl DivAssign<AxialType> for AxialPoint {
+    fn div_assign(&mut self, rhs: AxialType) {
+        self.q /= rhs;
+        self.r /= rhs;
+    }
+}
 impl Rem<AxialType> for AxialPoint {
     type Output = AxialPoint;
     fn rem(self, d: AxialType) -> AxialPoint {
@@ -132,6 +138,12 @@ impl Rem<AxialType> for AxialPoint {
         }
     }
 }
+impl RemAssign<AxialType> for AxialPoint {
+    fn rem_assign(&mut self, d: AxialType) {
+        self.q %= d;
+        self.r %= d;
+    }
+}
 /// ********************Index************
 impl Index<usize> for AxialPoint {
     type Output = AxialType;
@@ -210,6 +222,36 @@ fn arithmetic_test_point() {
     assert!(b.mul(4) == AxialPoint { q: -8, r: 0 });
     assert!(b.div(2) == AxialPoint { q: -1, r: 0 });
     assert!(a.rem(3) == AxialPoint { q: 2, r: 1 });
+    assert!(a - v == AxialPoint { q: -3, r: 6 });
+    assert!((a - v) + v == a);
+    assert!(b + (a - b) == a);
+}
+#[test]
+fn assign_ops_test_point() {
+    let v = AxialVector { q: 8, r: 1 };
+    let mut a = AxialPoint { q: 5, r: 7 };
+    a += v;
+    assert!(a == AxialPoint { q: 13, r: 8 });
+    a -= v * 2;
+    assert!(a == AxialPoint { q: -3, r: 6 });
+    a *= 2;
+    assert!(a == AxialPoint { q: -6, r: 12 });
+    a /= 3;
+    assert!(a == AxialPoint { q: -2, r: 4 });
+    a %= 3;
+    assert!(a == AxialPoint { q: -2, r: 1 });
+}
+#[test]
+fn ord_test_point() {
+    use std::collections::BTreeMap;
+
+    let mut map = BTreeMap::new();
+    map.insert(AxialPoint::new(2, -1), "c");
+    map.insert(AxialPoint::new(-3, 8), "a");
+    map.insert(AxialPoint::new(2, -4), "b");
+    let values: Vec<_> = map.values().cloned().collect();
+    assert_eq!(values, vec!["a", "b", "c"]);
+    assert!(AxialPoint::new(0, 5) < AxialPoint::new(1, -5));
 }
 #[test]
 fn index_test_point() {
@@ -228,8 +270,8 @@ fn array_test_point() {
     assert!(a.q == 7 && a.r == 7);
     assert!(a.sum() == 14);
     assert!(a.product() == 49);
-    assert!(b.min() == 5);
-    assert!(b.max() == 7);
+    assert!(Array::min(b) == 5);
+    assert!(Array::max(b) == 7);
 }
 #[test]
 fn meticspace_test_point() {
@@ -245,3 +287,66 @@ fn euclideanspace_test_point() {
     assert!(a.to_vec() == AxialVector { q: 1, r: 1 });
     assert!(a.dot(v) == 9);
 }
+
+#[cfg(test)]
+fn sample_points_in_hexagon() -> Vec<Vector2f> {
+    use world::HEX_OUTER_RADIUS;
+
+    // Corners of a hexagon around the origin, clockwise starting at the
+    // top-left corner
+    let corners: Vec<_> = (0..6)
+        .map(|i| {
+            let angle = (150.0 - 60.0 * i as f32).to_radians();
+            Vector2f::new(angle.cos(), angle.sin()) * HEX_OUTER_RADIUS
+        })
+        .collect();
+
+    // Points on a grid of triangles spanned by the center and two
+    // neighbouring corners, slightly pulled towards the center. This includes
+    // points right next to the corners and edges.
+    let mut points = Vec::new();
+    const STEPS: usize = 10;
+    for i in 0..6 {
+        let (a, b) = (corners[i], corners[(i + 1) % 6]);
+        for j in 0..STEPS + 1 {
+            for k in 0..STEPS + 1 - j {
+                let p = a * (j as f32 / STEPS as f32) + b * (k as f32 / STEPS as f32);
+                points.push(p * 0.999);
+            }
+        }
+    }
+    points
+}
+
+#[test]
+fn from_real_rounds_to_containing_hexagon() {
+    use world::HEX_INNER_RADIUS;
+
+    let samples = sample_points_in_hexagon();
+    for &p in &samples {
+        assert!(p.x.abs() < HEX_INNER_RADIUS);
+    }
+
+    for q in -20..21 {
+        for r in -20..21 {
+            let hex = AxialPoint::new(q, r);
+            let center = hex.to_real();
+            for &offset in &samples {
+                let p = center + offset;
+                assert_eq!(AxialPoint::from_real(p), hex, "{:?} + {:?}", center, offset);
+            }
+        }
+    }
+}
+
+#[test]
+fn from_real_is_inverse_of_to_real() {
+    for q in -50..51 {
+        for r in -50..51 {
+            let hex = AxialPoint::new(q, r);
+            assert_eq!(AxialPoint::from_real(hex.to_real()), hex);
+            let v = AxialVector::new(q, r);
+            assert_eq!(AxialVector::from_real(v.to_real()), v);
+        }
+    }
+}
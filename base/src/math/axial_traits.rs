@@ -1,4 +1,4 @@
-use super::{Point2i, Vector2i};
+use super::{AxialPoint, AxialVector, Point2i, Vector2i};
 
 /// Types that can be constructed from their axial representation.
 pub trait FromAxial<T> {
@@ -32,16 +32,26 @@ pub trait IntoAxial<T> {
 impl IntoAxial<AxialPoint> for Point2i {
     fn into_axial(self) -> AxialPoint {
         AxialPoint {
-            q: axial.x,
-            r: axial.y,
+            q: self.x,
+            r: self.y,
         }
     }
 }
 impl IntoAxial<AxialVector> for Vector2i {
     fn into_axial(self) -> AxialVector {
         AxialVector {
-            q: axial.x,
-            r: axial.y,
+            q: self.x,
+            r: self.y,
         }
     }
 }
+
+#[test]
+fn axial_conversions() {
+    let p = AxialPoint::new(3, -7);
+    let v = AxialVector::new(-1, 4);
+    assert_eq!(Point2i::from_axial(p), Point2i::new(3, -7));
+    assert_eq!(Vector2i::from_axial(v), Vector2i::new(-1, 4));
+    assert_eq!(Point2i::new(3, -7).into_axial(), p);
+    assert_eq!(Vector2i::new(-1, 4).into_axial(), v);
+}
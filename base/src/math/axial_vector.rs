@@ -1,7 +1,7 @@
 use std::fmt;
 use world::{HEX_INNER_RADIUS, HEX_OUTER_RADIUS};
-use super::{AxialType, DefaultFloat, Vector2f};
-use math::cgmath::{VectorSpace, Zero};
+use super::{AxialType, DefaultFloat, Vector2f, round_axial};
+use math::cgmath::{EuclideanSpace, VectorSpace, Zero};
 use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Rem,
                RemAssign, Sub, SubAssign};
 use math::cgmath::prelude::{Array, MetricSpace};
@@ -11,22 +11,19 @@ use std::cmp;
 /// information.
 ///
 /// [hex-blog]: http://www.redblobgames.com/grids/hexagons/#coordinates
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+///
+/// Vectors are ordered lexicographically (first by `q`, then by `r`), so they
+/// can be used as keys of ordered maps.
+///
+/// `cgmath::InnerSpace` can't be implemented, because it requires a floating
+/// point scalar. `dot()` and `magnitude2()` are provided as inherent methods
+/// instead; for lengths measured in hexagons use `hex_length()`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(C, packed)]
 pub struct AxialVector {
     pub q: AxialType,
     pub r: AxialType,
 }
-// TODO: implement cgmath::Zero
-// TODO: implement cgmath::Array
-// TODO: implement cgmath::MatricSpace
-// TODO: implement cgmath::VectorSpace
-// TODO: implement cgmath::InnerSpace
-// TODO: implement ops::{ ... }
-// TODO: Add `unit_q()` and `unit_r()` (see `Vector2::unit_x()` for reference)
-// For all of the above, see
-// http://bjz.github.io/cgmath/cgmath/struct.Vector2.html
-//
 /// AxialVector defines a vector specifically for Axial cordinate system.
 impl AxialVector {
     pub fn new(q: AxialType, r: AxialType) -> Self {
@@ -40,6 +37,22 @@ impl AxialVector {
             y: (self.r as DefaultFloat) * (3.0 / 2.0) * HEX_OUTER_RADIUS,
         }
     }
+    /// Returns the vector to the center of the hexagon containing the given
+    /// real vector (when starting from the origin). This is the inverse of
+    /// `to_real()`, see `AxialPoint::from_real()`.
+    pub fn from_real(real: Vector2f) -> Self {
+        let (q, r) = Self::fractional_from_real(real);
+        round_axial(q, r).to_vec()
+    }
+
+    /// Converts a real vector into fractional axial coordinates, without any
+    /// rounding.
+    pub fn fractional_from_real(real: Vector2f) -> (f64, f64) {
+        let r = real.y as f64 / (1.5 * HEX_OUTER_RADIUS as f64);
+        let q = (real.x as f64 / HEX_INNER_RADIUS as f64 + r) / 2.0;
+        (q, r)
+    }
+
     /// Returns the `s` component of corresponding cube coordinates. In cube
     /// coordinates 'q + r + s = 0', so saving `s` is redundant and can be
     /// calculated on the fly when needed.
@@ -54,6 +67,16 @@ impl AxialVector {
     pub fn unit_r() -> AxialVector {
         AxialVector { q: 0, r: 1 }
     }
+    /// Returns the dot product of both vectors in axial coordinates (the same
+    /// as `EuclideanSpace::dot()` of `AxialPoint`). Note that the axes aren't
+    /// orthogonal in real space.
+    pub fn dot(self, other: AxialVector) -> AxialType {
+        self.q * other.q + self.r * other.r
+    }
+    /// Returns the squared magnitude in axial coordinates, see `dot()`.
+    pub fn magnitude2(self) -> AxialType {
+        self.dot(self)
+    }
 }
 impl fmt::Debug for AxialVector {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -113,6 +136,12 @@ impl Mul<AxialType> for AxialVector {
         }
     }
 }
+impl Mul<AxialVector> for AxialType {
+    type Output = AxialVector;
+    fn mul(self, arg2: AxialVector) -> AxialVector {
+        arg2 * self
+    }
+}
 impl MulAssign<AxialType> for AxialVector {
     fn mul_assign(&mut self, arg2: AxialType) {
         self.r *= arg2;
@@ -292,6 +321,25 @@ fn array_test() {
     assert!(test3.q == 3 && test3.r == 3);
     assert_eq!(test3.sum(), 6);
     assert_eq!(test3.product(), 9);
-    assert_eq!(test1.min(), -5);
-    assert_eq!(test2.max(), 13);
+    assert_eq!(Array::min(test1), -5);
+    assert_eq!(Array::max(test2), 13);
+}
+#[test]
+fn scalar_and_dot_test() {
+    let v = AxialVector::new(3, -2);
+    assert_eq!(2 * v, v * 2);
+    assert_eq!(v.dot(AxialVector::new(1, 4)), -5);
+    assert_eq!(v.magnitude2(), 13);
+}
+#[test]
+fn ord_test() {
+    use std::collections::BTreeSet;
+
+    let set: BTreeSet<_> =
+        vec![AxialVector::new(1, 0), AxialVector::new(-1, 5), AxialVector::new(1, -3)]
+            .into_iter()
+            .collect();
+    let sorted: Vec<_> = set.into_iter().collect();
+    assert_eq!(sorted,
+               vec![AxialVector::new(-1, 5), AxialVector::new(1, -3), AxialVector::new(1, 0)]);
 }
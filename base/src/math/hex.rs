@@ -0,0 +1,566 @@
+//! Algorithms on the hexagon grid, like neighbours, distances, rings, spirals
+//! and lines. See [this blog post][hex-blog] for more information.
+//!
+//! Besides the axial coordinates `q` and `r`, some of the algorithms use cube
+//! coordinates internally. With the layout of our grid those are `x = q`,
+//! `y = -r` and `z = r - q` (so that `x + y + z = 0`).
+//!
+//! [hex-blog]: http://www.redblobgames.com/grids/hexagons/
+
+use std::cmp::{max, min};
+use super::{AxialPoint, AxialType, AxialVector};
+
+/// One of the six directions from a hexagon to its neighbours, in clockwise
+/// order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+    TopLeft,
+    TopRight,
+    Right,
+    BottomRight,
+    BottomLeft,
+    Left,
+}
+
+/// All six directions in clockwise order starting with `TopLeft`. The
+/// position in this array equals `HexDirection::index()`.
+pub const HEX_DIRECTIONS: [HexDirection; 6] = [HexDirection::TopLeft,
+                                               HexDirection::TopRight,
+                                               HexDirection::Right,
+                                               HexDirection::BottomRight,
+                                               HexDirection::BottomLeft,
+                                               HexDirection::Left];
+
+impl HexDirection {
+    /// Returns the position of this direction in `HEX_DIRECTIONS`.
+    pub fn index(&self) -> usize {
+        match *self {
+            HexDirection::TopLeft => 0,
+            HexDirection::TopRight => 1,
+            HexDirection::Right => 2,
+            HexDirection::BottomRight => 3,
+            HexDirection::BottomLeft => 4,
+            HexDirection::Left => 5,
+        }
+    }
+
+    /// Returns the direction with the given index (modulo 6).
+    pub fn from_index(index: usize) -> Self {
+        HEX_DIRECTIONS[index % 6]
+    }
+
+    /// Returns the direction of the given vector, if it points to a direct
+    /// neighbour.
+    pub fn from_vector(v: AxialVector) -> Option<Self> {
+        HEX_DIRECTIONS.iter().cloned().find(|dir| dir.to_vector() == v)
+    }
+
+    /// Returns the vector from a hexagon to its neighbour in this direction.
+    pub fn to_vector(&self) -> AxialVector {
+        match *self {
+            HexDirection::TopLeft => AxialVector::new(0, 1),
+            HexDirection::TopRight => AxialVector::new(1, 1),
+            HexDirection::Right => AxialVector::new(1, 0),
+            HexDirection::BottomRight => AxialVector::new(0, -1),
+            HexDirection::BottomLeft => AxialVector::new(-1, -1),
+            HexDirection::Left => AxialVector::new(-1, 0),
+        }
+    }
+
+    /// Returns the opposite direction.
+    pub fn opposite(&self) -> Self {
+        Self::from_index(self.index() + 3)
+    }
+
+    /// Returns the next direction in clockwise order.
+    pub fn rotate_cw(&self) -> Self {
+        Self::from_index(self.index() + 1)
+    }
+
+    /// Returns the next direction in counter clockwise order.
+    pub fn rotate_ccw(&self) -> Self {
+        Self::from_index(self.index() + 5)
+    }
+}
+
+impl AxialVector {
+    /// Returns the number of steps between two neighbouring hexagons needed
+    /// to walk along this vector.
+    pub fn hex_length(&self) -> AxialType {
+        max(max(self.q.abs(), self.r.abs()), (self.q - self.r).abs())
+    }
+}
+
+impl AxialPoint {
+    /// Returns the neighbour in the given direction.
+    pub fn neighbor(&self, dir: HexDirection) -> AxialPoint {
+        *self + dir.to_vector()
+    }
+
+    /// Returns all six neighbours in the order of `HEX_DIRECTIONS`.
+    pub fn neighbors(&self) -> [AxialPoint; 6] {
+        let mut out = [*self; 6];
+        for (n, &dir) in out.iter_mut().zip(&HEX_DIRECTIONS) {
+            *n = self.neighbor(dir);
+        }
+        out
+    }
+
+    /// Returns the distance to the other hexagon, counted in steps between
+    /// neighbouring hexagons.
+    pub fn hex_distance(&self, other: AxialPoint) -> AxialType {
+        (other - *self).hex_length()
+    }
+}
+
+/// Rounds fractional axial coordinates to the hexagon containing them.
+pub fn round_axial(q: f64, r: f64) -> AxialPoint {
+    let (x, y, z) = (q, -r, r - q);
+    let (mut rx, mut ry, rz) = (x.round(), y.round(), z.round());
+    let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+
+    // The component with the largest rounding error is restored from the
+    // other two, so that the sum is zero again.
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dy > dz {
+        ry = -rx - rz;
+    }
+
+    AxialPoint::new(rx as AxialType, -ry as AxialType)
+}
+
+/// Iterator over all hexagons with a specific distance to a center hexagon,
+/// in clockwise order. Created by `ring()`.
+#[derive(Clone, Debug)]
+pub struct Ring {
+    pos: AxialPoint,
+    radius: AxialType,
+    side: usize,
+    step: AxialType,
+}
+
+/// Returns an iterator over all `6 * radius` hexagons with the distance
+/// `radius` to `center`. For the radius 0 only the center is returned.
+pub fn ring(center: AxialPoint, radius: AxialType) -> Ring {
+    assert!(radius >= 0, "ring radius must not be negative");
+
+    Ring {
+        pos: center + HexDirection::BottomLeft.to_vector() * radius,
+        radius: radius,
+        side: 0,
+        step: 0,
+    }
+}
+
+impl Iterator for Ring {
+    type Item = AxialPoint;
+
+    fn next(&mut self) -> Option<AxialPoint> {
+        if self.radius == 0 {
+            // The ring of radius zero is just the center
+            if self.side == 0 {
+                self.side = 6;
+                return Some(self.pos);
+            }
+            return None;
+        }
+        if self.side == 6 {
+            return None;
+        }
+
+        let out = self.pos;
+        self.pos = self.pos.neighbor(HEX_DIRECTIONS[self.side]);
+        self.step += 1;
+        if self.step == self.radius {
+            self.step = 0;
+            self.side += 1;
+        }
+        Some(out)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = if self.radius == 0 {
+            if self.side == 0 { 1 } else { 0 }
+        } else {
+            (6 - self.side) * self.radius as usize - self.step as usize
+        };
+        (len, Some(len))
+    }
+}
+
+/// Iterator over all hexagons within a distance to a center hexagon, sorted by
+/// distance. Created by `spiral()`.
+#[derive(Clone, Debug)]
+pub struct Spiral {
+    center: AxialPoint,
+    radius: AxialType,
+    ring: Ring,
+}
+
+/// Returns an iterator over all hexagons with a distance of at most `radius`
+/// to `center`: first the center, then the ring with radius 1 and so on.
+pub fn spiral(center: AxialPoint, radius: AxialType) -> Spiral {
+    assert!(radius >= 0, "spiral radius must not be negative");
+
+    Spiral {
+        center: center,
+        radius: radius,
+        ring: ring(center, 0),
+    }
+}
+
+impl Iterator for Spiral {
+    type Item = AxialPoint;
+
+    fn next(&mut self) -> Option<AxialPoint> {
+        loop {
+            if let Some(pos) = self.ring.next() {
+                return Some(pos);
+            }
+            if self.ring.radius >= self.radius {
+                return None;
+            }
+            self.ring = ring(self.center, self.ring.radius + 1);
+        }
+    }
+}
+
+/// Iterator over the hexagons on a line between two hexagons. Created by
+/// `line()`.
+#[derive(Clone, Debug)]
+pub struct Line {
+    start: AxialPoint,
+    end: AxialPoint,
+    len: AxialType,
+    i: AxialType,
+}
+
+/// Returns an iterator over the hexagons crossed by the straight line from the
+/// center of `start` to the center of `end`, including both. Consecutive
+/// hexagons are always neighbours.
+pub fn line(start: AxialPoint, end: AxialPoint) -> Line {
+    Line {
+        start: start,
+        end: end,
+        len: start.hex_distance(end),
+        i: 0,
+    }
+}
+
+impl Iterator for Line {
+    type Item = AxialPoint;
+
+    fn next(&mut self) -> Option<AxialPoint> {
+        if self.i > self.len {
+            return None;
+        }
+        let i = self.i;
+        self.i += 1;
+
+        if i == 0 {
+            return Some(self.start);
+        }
+        if i == self.len {
+            return Some(self.end);
+        }
+
+        let t = i as f64 / self.len as f64;
+        let q = self.start.q as f64 + (self.end.q - self.start.q) as f64 * t;
+        let r = self.start.r as f64 + (self.end.r - self.start.r) as f64 * t;
+
+        // Nudge the point a tiny bit, so that points exactly on an edge
+        // between two hexagons are always rounded in the same direction.
+        Some(round_axial(q + 1e-6, r - 2e-6))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.len + 1 - self.i) as usize;
+        (len, Some(len))
+    }
+}
+
+/// All hexagons within a distance of `radius` around `center`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HexRange {
+    center: AxialPoint,
+    radius: AxialType,
+}
+
+impl HexRange {
+    /// Creates the range of all hexagons within `radius` around `center`.
+    /// The radius must not be negative.
+    pub fn new(center: AxialPoint, radius: AxialType) -> Self {
+        assert!(radius >= 0, "range radius must not be negative");
+
+        HexRange {
+            center: center,
+            radius: radius,
+        }
+    }
+
+    /// Returns the hexagon in the middle of this range.
+    pub fn center(&self) -> AxialPoint {
+        self.center
+    }
+
+    /// Returns the distance of the outermost hexagons from the center.
+    pub fn radius(&self) -> AxialType {
+        self.radius
+    }
+
+    /// Returns `true` if the given hexagon is part of this range.
+    pub fn contains(&self, pos: AxialPoint) -> bool {
+        self.center.hex_distance(pos) <= self.radius
+    }
+
+    /// Returns the number of hexagons in this range.
+    pub fn len(&self) -> usize {
+        let r = self.radius as usize;
+        3 * r * (r + 1) + 1
+    }
+
+    /// Always returns `false`, because every range contains at least its
+    /// center.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns an iterator over all hexagons of this range (ordered by `q`
+    /// first and `r` second).
+    pub fn iter(&self) -> HexRangeIter {
+        HexRangeIter::new(CubeBounds::of(self))
+    }
+
+    /// Returns an iterator over all hexagons which are part of both ranges.
+    pub fn intersection(&self, other: &HexRange) -> HexRangeIter {
+        HexRangeIter::new(CubeBounds::of(self).intersect(&CubeBounds::of(other)))
+    }
+}
+
+impl IntoIterator for HexRange {
+    type Item = AxialPoint;
+    type IntoIter = HexRangeIter;
+
+    fn into_iter(self) -> HexRangeIter {
+        self.iter()
+    }
+}
+
+/// A range in cube coordinates: all hexagons whose cube coordinates are
+/// between the minimums and maximums (inclusive).
+#[derive(Clone, Copy, Debug)]
+struct CubeBounds {
+    x: (AxialType, AxialType),
+    y: (AxialType, AxialType),
+    z: (AxialType, AxialType),
+}
+
+impl CubeBounds {
+    fn of(range: &HexRange) -> Self {
+        let (x, y, z) = (range.center.q, -range.center.r, range.center.r - range.center.q);
+        let n = range.radius;
+
+        CubeBounds {
+            x: (x - n, x + n),
+            y: (y - n, y + n),
+            z: (z - n, z + n),
+        }
+    }
+
+    fn intersect(&self, other: &CubeBounds) -> Self {
+        let both = |a: (AxialType, AxialType), b: (AxialType, AxialType)| {
+            (max(a.0, b.0), min(a.1, b.1))
+        };
+
+        CubeBounds {
+            x: both(self.x, other.x),
+            y: both(self.y, other.y),
+            z: both(self.z, other.z),
+        }
+    }
+}
+
+/// Iterator over the hexagons of a `HexRange` or over the intersection of two
+/// ranges.
+#[derive(Clone, Debug)]
+pub struct HexRangeIter {
+    bounds: CubeBounds,
+    x: AxialType,
+    y: AxialType,
+    y_end: AxialType,
+}
+
+impl HexRangeIter {
+    fn new(bounds: CubeBounds) -> Self {
+        let mut iter = HexRangeIter {
+            bounds: bounds,
+            x: bounds.x.0,
+            y: 0,
+            y_end: -1,
+        };
+        iter.start_column();
+        iter
+    }
+
+    /// Sets the bounds of `y` for the current `x`, taking into account that
+    /// `z = -x - y` has to be within its bounds, too.
+    fn start_column(&mut self) {
+        self.y = max(self.bounds.y.0, -self.x - self.bounds.z.1);
+        self.y_end = min(self.bounds.y.1, -self.x - self.bounds.z.0);
+    }
+}
+
+impl Iterator for HexRangeIter {
+    type Item = AxialPoint;
+
+    fn next(&mut self) -> Option<AxialPoint> {
+        while self.x <= self.bounds.x.1 {
+            if self.y <= self.y_end {
+                let out = AxialPoint::new(self.x, -self.y);
+                self.y += 1;
+                return Some(out);
+            }
+            self.x += 1;
+            self.start_column();
+        }
+        None
+    }
+}
+
+#[test]
+fn directions() {
+    for (i, &dir) in HEX_DIRECTIONS.iter().enumerate() {
+        assert_eq!(dir.index(), i);
+        assert_eq!(HexDirection::from_index(i), dir);
+        assert_eq!(HexDirection::from_vector(dir.to_vector()), Some(dir));
+        assert_eq!(dir.opposite().to_vector(), -dir.to_vector());
+        assert_eq!(dir.rotate_cw().rotate_ccw(), dir);
+        assert_eq!(dir.to_vector().hex_length(), 1);
+
+        // Neighbouring directions point to neighbouring hexagons
+        let next = dir.rotate_cw().to_vector();
+        assert_eq!((next - dir.to_vector()).hex_length(), 1);
+    }
+    assert_eq!(HexDirection::from_vector(AxialVector::new(1, -1)), None);
+
+    let p = AxialPoint::new(3, -2);
+    let neighbors = p.neighbors();
+    assert_eq!(neighbors[HexDirection::Right.index()], AxialPoint::new(4, -2));
+    for &n in &neighbors {
+        assert_eq!(p.hex_distance(n), 1);
+    }
+}
+
+#[test]
+fn distance() {
+    let o = AxialPoint::new(0, 0);
+    assert_eq!(o.hex_distance(o), 0);
+    assert_eq!(o.hex_distance(AxialPoint::new(3, 3)), 3);
+    assert_eq!(o.hex_distance(AxialPoint::new(3, -3)), 6);
+    assert_eq!(o.hex_distance(AxialPoint::new(-2, 5)), 7);
+    assert_eq!(AxialPoint::new(2, 1).hex_distance(AxialPoint::new(-1, 4)), 6);
+
+    // The distance is the length of the shortest path of neighbour steps
+    let mut dist = vec![(o, 0)];
+    let mut i = 0;
+    while i < dist.len() {
+        let (p, d) = dist[i];
+        assert_eq!(o.hex_distance(p), d);
+        if d < 4 {
+            for &n in &p.neighbors() {
+                if !dist.iter().any(|&(q, _)| q == n) {
+                    dist.push((n, d + 1));
+                }
+            }
+        }
+        i += 1;
+    }
+    assert_eq!(dist.len(), HexRange::new(o, 4).len());
+}
+
+#[test]
+fn rings_and_spirals() {
+    let center = AxialPoint::new(-4, 7);
+    assert_eq!(ring(center, 0).collect::<Vec<_>>(), vec![center]);
+
+    for radius in 1..6 {
+        let ring: Vec<_> = ring(center, radius).collect();
+        assert_eq!(ring.len(), 6 * radius as usize);
+        for (i, &p) in ring.iter().enumerate() {
+            assert_eq!(center.hex_distance(p), radius);
+            // Consecutive hexagons (including last and first) are neighbours
+            assert_eq!(p.hex_distance(ring[(i + 1) % ring.len()]), 1);
+        }
+    }
+
+    let spiral: Vec<_> = spiral(center, 4).collect();
+    assert_eq!(spiral.len(), HexRange::new(center, 4).len());
+    assert_eq!(spiral[0], center);
+    for w in spiral.windows(2) {
+        assert!(center.hex_distance(w[0]) <= center.hex_distance(w[1]));
+    }
+    let mut range: Vec<_> = HexRange::new(center, 4).iter().collect();
+    let mut sorted = spiral.clone();
+    let key = |p: &AxialPoint| (p.q, p.r);
+    range.sort_by_key(&key);
+    sorted.sort_by_key(&key);
+    assert_eq!(range, sorted);
+}
+
+#[test]
+fn lines() {
+    let a = AxialPoint::new(0, 0);
+    assert_eq!(line(a, a).collect::<Vec<_>>(), vec![a]);
+
+    let targets = [AxialPoint::new(5, 0), AxialPoint::new(4, -3), AxialPoint::new(-7, 2),
+                   AxialPoint::new(3, 6), AxialPoint::new(-2, -9)];
+    for &b in &targets {
+        let l: Vec<_> = line(a, b).collect();
+        assert_eq!(l.len() as AxialType, a.hex_distance(b) + 1);
+        assert_eq!(l[0], a);
+        assert_eq!(*l.last().unwrap(), b);
+        for w in l.windows(2) {
+            assert_eq!(w[0].hex_distance(w[1]), 1);
+        }
+    }
+
+    // Lines along a direction are straight
+    let l: Vec<_> = line(a, AxialPoint::new(0, 4)).collect();
+    assert_eq!(l,
+               vec![a, AxialPoint::new(0, 1), AxialPoint::new(0, 2), AxialPoint::new(0, 3),
+                    AxialPoint::new(0, 4)]);
+}
+
+#[test]
+fn ranges() {
+    let range = HexRange::new(AxialPoint::new(2, -1), 3);
+    let points: Vec<_> = range.iter().collect();
+    assert_eq!(points.len(), range.len());
+    assert_eq!(range.len(), 37);
+    assert!(!range.is_empty());
+    for &p in &points {
+        assert!(range.contains(p));
+    }
+    assert!(!range.contains(AxialPoint::new(6, -1)));
+
+    let other = HexRange::new(AxialPoint::new(-1, 1), 4);
+    let intersection: Vec<_> = range.intersection(&other).collect();
+    let expected: Vec<_> = range.iter().filter(|&p| other.contains(p)).collect();
+    assert!(!expected.is_empty());
+    assert_eq!(intersection, expected);
+
+    let far = HexRange::new(AxialPoint::new(20, 20), 2);
+    assert_eq!(range.intersection(&far).count(), 0);
+}
+
+#[test]
+fn rounding() {
+    for q in -5..6 {
+        for r in -5..6 {
+            let p = AxialPoint::new(q, r);
+            assert_eq!(round_axial(q as f64 + 0.2, r as f64 - 0.1), p);
+            assert_eq!(round_axial(q as f64 - 0.3, r as f64 - 0.3), p);
+        }
+    }
+}
@@ -5,15 +5,19 @@
 extern crate cgmath;
 
 mod axial_point;
+mod axial_traits;
 mod axial_vector;
 mod dimension;
+mod hex;
 mod random;
 pub mod billboard;
 
 pub use self::cgmath::*;
 pub use self::axial_vector::*;
 pub use self::axial_point::*;
+pub use self::axial_traits::*;
 pub use self::dimension::*;
+pub use self::hex::*;
 pub use self::random::*;
 
 pub type DefaultFloat = f32;
@@ -43,3 +47,36 @@ pub type Point3u = Point3<DefaultUnsigned>;
 
 // A bunch of compile time constants
 pub const SQRT_3: f32 = 1.73205080757;
+
+/// Integer division which rounds towards negative infinity (unlike `/` which
+/// rounds towards zero). `b` has to be positive.
+pub fn div_floor(a: DefaultInt, b: DefaultInt) -> DefaultInt {
+    debug_assert!(b > 0);
+    if a < 0 { (a - b + 1) / b } else { a / b }
+}
+
+/// The remainder of `div_floor`, which is always in `0..b`. `b` has to be
+/// positive.
+pub fn mod_floor(a: DefaultInt, b: DefaultInt) -> DefaultInt {
+    a - div_floor(a, b) * b
+}
+
+#[test]
+fn test_div_mod_floor() {
+    assert_eq!(div_floor(7, 16), 0);
+    assert_eq!(div_floor(16, 16), 1);
+    assert_eq!(div_floor(-1, 16), -1);
+    assert_eq!(div_floor(-16, 16), -1);
+    assert_eq!(div_floor(-17, 16), -2);
+    assert_eq!(mod_floor(-1, 16), 15);
+    assert_eq!(mod_floor(-16, 16), 0);
+    assert_eq!(mod_floor(17, 16), 1);
+
+    for a in -100..100 {
+        for b in 1..20 {
+            let (d, m) = (div_floor(a, b), mod_floor(a, b));
+            assert!(m >= 0 && m < b);
+            assert_eq!(d * b + m, a);
+        }
+    }
+}
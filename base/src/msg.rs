@@ -1,5 +1,7 @@
 //! Messages for client-server-communication.
 
+use gen::plant::registry::PlantRegistry;
+use gen::world::config::WorldGenConfig;
 use math::{Point3f, Vector3f};
 
 /// A message from the server to a client.
@@ -12,6 +14,17 @@ pub enum ServerMessage {
     RegisterPlayer {
         id: u32,
     },
+    /// The parameters of the world the client joined. The client generates
+    /// all chunks it doesn't receive from the server with these parameters.
+    /// The plant species determine which plants the props of the chunks
+    /// refer to.
+    ///
+    /// Sent right after the client connected to the server.
+    WorldInfo {
+        seed: u64,
+        config: WorldGenConfig,
+        plants: PlantRegistry,
+    },
 }
 
 /// A message from a client to the server.
@@ -22,3 +35,27 @@ pub enum ClientMessage {
         orientation: Vector3f,
     },
 }
+
+#[test]
+fn world_info_round_trip() {
+    use rustc_serialize::json;
+
+    let mut config = WorldGenConfig::default();
+    config.sea_level = 80;
+    config.land_noise_scale = (0.1, 0.2, 0.3);
+    let msg = ServerMessage::WorldInfo {
+        seed: u64::max_value(),
+        config: config,
+        plants: PlantRegistry::builtin(),
+    };
+
+    let encoded = json::encode(&msg).unwrap();
+    match json::decode(&encoded).unwrap() {
+        ServerMessage::WorldInfo { seed, config: decoded, plants } => {
+            assert_eq!(seed, u64::max_value());
+            assert_eq!(decoded, config);
+            assert_eq!(plants, PlantRegistry::builtin());
+        }
+        _ => panic!("decoded the wrong message"),
+    }
+}
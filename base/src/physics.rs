@@ -0,0 +1,459 @@
+//! Collision detection and response for bodies moving through the hex world.
+//!
+//! Bodies are approximated by axis-aligned boxes and every pillar section is
+//! a hexagonal prism. Both shapes are convex and extruded along the z-axis,
+//! so according to the separating axis theorem it's enough to check the
+//! z-axis, the x- and y-axis (the sides of the box) and the side normals of
+//! the hexagon. To sweep a box, we calculate the time interval in which the
+//! projections of both shapes overlap on each of these axes: the box touches
+//! the section at the latest of all entry times, if that is earlier than all
+//! exit times. This is exact, so fast bodies can't tunnel through pillars.
+//!
+//! Fluid sections (water) don't collide with anything: bodies move through
+//! them and `MoveResult::in_fluid` tells whether they are submerged.
+//!
+//! The client and the server both use this module to move bodies, so that
+//! movement is resolved identically on both sides.
+
+use math::*;
+use std::f32;
+use world::{HEX_INNER_RADIUS, HEX_OUTER_RADIUS, PillarIndex, World};
+#[cfg(test)]
+use world::{materials, test_world};
+
+/// The distance which is kept between a moving box and the surfaces it
+/// collides with, so that it doesn't get stuck in them due to rounding
+/// errors.
+pub const SKIN_WIDTH: f32 = 0.001;
+
+/// Contacts whose normal has at least this z component count as ground.
+const GROUND_NORMAL_Z: f32 = 0.7;
+
+/// The maximum number of surfaces a box slides along in one movement.
+const MAX_SLIDES: usize = 4;
+
+/// An axis-aligned box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3f,
+    pub max: Point3f,
+}
+
+impl Aabb {
+    pub fn new(min: Point3f, max: Point3f) -> Self {
+        Aabb { min: min, max: max }
+    }
+
+    /// Creates a box with a square base of size `2 * half_width`, whose
+    /// bottom is centered at `feet`.
+    pub fn standing_at(feet: Point3f, half_width: f32, height: f32) -> Self {
+        Aabb {
+            min: Point3f::new(feet.x - half_width, feet.y - half_width, feet.z),
+            max: Point3f::new(feet.x + half_width, feet.y + half_width, feet.z + height),
+        }
+    }
+
+    /// Returns the center of the bottom face.
+    pub fn feet(&self) -> Point3f {
+        Point3f::new((self.min.x + self.max.x) / 2.0,
+                     (self.min.y + self.max.y) / 2.0,
+                     self.min.z)
+    }
+
+    pub fn center(&self) -> Point3f {
+        self.min + (self.max - self.min) / 2.0
+    }
+
+    /// Returns half of the size of the box in every dimension.
+    pub fn half_extents(&self) -> Vector3f {
+        (self.max - self.min) / 2.0
+    }
+
+    /// Returns the box moved by the given offset.
+    pub fn translate(&self, offset: Vector3f) -> Aabb {
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Returns the smallest box containing all positions of this box while
+    /// it is moved by `motion`.
+    pub fn swept(&self, motion: Vector3f) -> Aabb {
+        let moved = self.translate(motion);
+        Aabb {
+            min: Point3f::new(self.min.x.min(moved.min.x),
+                              self.min.y.min(moved.min.y),
+                              self.min.z.min(moved.min.z)),
+            max: Point3f::new(self.max.x.max(moved.max.x),
+                              self.max.y.max(moved.max.y),
+                              self.max.z.max(moved.max.z)),
+        }
+    }
+}
+
+/// A pillar section touched by a moving box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// The pillar which was touched.
+    pub pillar: PillarIndex,
+    /// The index of the touched section in `HexPillar::sections()`.
+    pub section: usize,
+    /// The fraction of the motion (in `0..1`) after which the box touches the
+    /// section.
+    pub time: f32,
+    /// The normal of the touched surface, pointing towards the box (unit
+    /// length).
+    pub normal: Vector3f,
+}
+
+/// The result of `move_aabb()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveResult {
+    /// The box at its new position.
+    pub aabb: Aabb,
+    /// All contacts which stopped or deflected the box, in order.
+    pub contacts: Vec<Contact>,
+    /// Whether the box stands on the ground after the movement.
+    pub on_ground: bool,
+    /// Whether the center of the box is inside a fluid after the movement.
+    pub in_fluid: bool,
+}
+
+/// Sweeps the box by `motion` and returns the first pillar section it
+/// touches, if any.
+///
+/// Sections which already intersect the box are ignored, so that boxes stuck
+/// in the ground can move out of it. Fluid sections and pillars in chunks
+/// which aren't loaded are treated as empty.
+pub fn sweep_aabb(world: &World, aabb: &Aabb, motion: Vector3f) -> Option<Contact> {
+    let swept = aabb.swept(motion);
+    let center = swept.center();
+    let half = swept.half_extents();
+
+    // All hexagons intersecting the swept box have their center within
+    // `reach` of the center of the swept box. Hexagons with a hex distance
+    // of `n` are at least `n * sqrt(3) * HEX_INNER_RADIUS` apart.
+    let reach = Vector2f::new(half.x, half.y).magnitude() + HEX_OUTER_RADIUS;
+    let radius = (reach / (SQRT_3 * HEX_INNER_RADIUS)).ceil() as AxialType + 1;
+    let range = HexRange::new(AxialPoint::from_real(Point2f::new(center.x, center.y)), radius);
+
+    let mut best: Option<Contact> = None;
+    for pos in range {
+        let pillar = match world.pillar_at(PillarIndex(pos)) {
+            Some(pillar) => pillar,
+            None => continue,
+        };
+        let hex_center = pos.to_real();
+        if hex_center.x + HEX_INNER_RADIUS < swept.min.x ||
+           hex_center.x - HEX_INNER_RADIUS > swept.max.x ||
+           hex_center.y + HEX_OUTER_RADIUS < swept.min.y ||
+           hex_center.y - HEX_OUTER_RADIUS > swept.max.y {
+            continue;
+        }
+
+        for (i, section) in pillar.sections().iter().enumerate() {
+            if section.ground.is_fluid() {
+                continue;
+            }
+            let bottom = section.bottom.to_real();
+            let top = section.top.to_real();
+            if top < swept.min.z || bottom > swept.max.z {
+                continue;
+            }
+
+            let hit = sweep_against_prism(aabb, motion, hex_center, bottom, top);
+            if let Some((time, normal)) = hit {
+                if best.map_or(true, |contact| time < contact.time) {
+                    best = Some(Contact {
+                        pillar: PillarIndex(pos),
+                        section: i,
+                        time: time,
+                        normal: normal,
+                    });
+                }
+            }
+        }
+    }
+
+    best
+}
+
+/// Moves the box by `motion`, sliding along all surfaces it touches.
+///
+/// The horizontal part of the motion is applied first. If the box is blocked
+/// by a wall, it tries to step onto it, if the wall isn't higher than
+/// `step_height`. Afterwards the vertical part of the motion is applied.
+pub fn move_aabb(world: &World, aabb: Aabb, motion: Vector3f, step_height: f32) -> MoveResult {
+    let mut contacts = Vec::new();
+    let horizontal = Vector3f::new(motion.x, motion.y, 0.0);
+    let mut moved = slide(world, aabb, horizontal, &mut contacts);
+
+    let blocked = contacts.iter().any(|contact| contact.normal.z == 0.0);
+    if blocked && step_height > 0.0 {
+        let mut step_contacts = Vec::new();
+        let raised = slide(world, aabb, Vector3f::new(0.0, 0.0, step_height), &mut step_contacts);
+        let rise = raised.min.z - aabb.min.z;
+        let stepped = slide(world, raised, horizontal, &mut step_contacts);
+        let lowered = slide(world, stepped, Vector3f::new(0.0, 0.0, -rise), &mut step_contacts);
+
+        if horizontal_distance(&aabb, &lowered) > horizontal_distance(&aabb, &moved) + SKIN_WIDTH {
+            moved = lowered;
+            contacts = step_contacts;
+        }
+    }
+
+    moved = slide(world, moved, Vector3f::new(0.0, 0.0, motion.z), &mut contacts);
+
+    let on_ground = sweep_aabb(world, &moved, Vector3f::new(0.0, 0.0, -2.0 * SKIN_WIDTH))
+        .map_or(false, |contact| contact.normal.z >= GROUND_NORMAL_Z);
+    let in_fluid = is_fluid_at(world, moved.center());
+
+    MoveResult {
+        aabb: moved,
+        contacts: contacts,
+        on_ground: on_ground,
+        in_fluid: in_fluid,
+    }
+}
+
+/// Returns whether the given point lies inside a fluid section.
+pub fn is_fluid_at(world: &World, point: Point3f) -> bool {
+    let pos = AxialPoint::from_real(Point2f::new(point.x, point.y));
+    world.pillar_at(PillarIndex(pos)).map_or(false, |pillar| {
+        pillar.sections().iter().any(|section| {
+            section.ground.is_fluid() && section.bottom.to_real() <= point.z &&
+            point.z < section.top.to_real()
+        })
+    })
+}
+
+/// Moves the box by `motion`. Whenever it touches a surface, the rest of the
+/// motion is projected onto the surface.
+fn slide(world: &World, aabb: Aabb, motion: Vector3f, contacts: &mut Vec<Contact>) -> Aabb {
+    let mut aabb = aabb;
+    let mut motion = motion;
+
+    for _ in 0..MAX_SLIDES {
+        if motion.magnitude2() == 0.0 {
+            break;
+        }
+        let contact = match sweep_aabb(world, &aabb, motion) {
+            Some(contact) => contact,
+            None => return aabb.translate(motion),
+        };
+
+        // Stop early enough to keep a distance of `SKIN_WIDTH` to the surface
+        let approach = -motion.dot(contact.normal);
+        let time = (contact.time - SKIN_WIDTH / approach).max(0.0);
+        aabb = aabb.translate(motion * time);
+        contacts.push(contact);
+
+        let rest = motion * (1.0 - time);
+        motion = rest - contact.normal * rest.dot(contact.normal);
+    }
+
+    aabb
+}
+
+fn horizontal_distance(from: &Aabb, to: &Aabb) -> f32 {
+    let diff = to.min - from.min;
+    Vector2f::new(diff.x, diff.y).magnitude()
+}
+
+/// Sweeps the box against a single hexagonal prism. Returns the time of
+/// impact and the normal of the touched surface.
+fn sweep_against_prism(aabb: &Aabb,
+                       motion: Vector3f,
+                       hex_center: Point2f,
+                       bottom: f32,
+                       top: f32)
+                       -> Option<(f32, Vector3f)> {
+    let box_center = aabb.center();
+    let box_half = aabb.half_extents();
+    let hex_center = Point3f::new(hex_center.x, hex_center.y, (bottom + top) / 2.0);
+
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    let mut normal = Vector3f::new(0.0, 0.0, 0.0);
+
+    for &(axis, hex_radius) in &separating_axes((top - bottom) / 2.0) {
+        let box_radius = box_half.x * axis.x.abs() + box_half.y * axis.y.abs() +
+                         box_half.z * axis.z.abs();
+        let radius = box_radius + hex_radius;
+        let dist = (hex_center - box_center).dot(axis);
+        let speed = motion.dot(axis);
+
+        // The projections overlap while `|dist - speed * t| < radius`
+        if speed == 0.0 {
+            if dist.abs() >= radius {
+                return None;
+            }
+            continue;
+        }
+        let t_a = (dist - radius) / speed;
+        let t_b = (dist + radius) / speed;
+        let (enter, exit) = if t_a < t_b { (t_a, t_b) } else { (t_b, t_a) };
+
+        if enter > t_enter {
+            t_enter = enter;
+            normal = if speed > 0.0 { -axis } else { axis };
+        }
+        t_exit = t_exit.min(exit);
+    }
+
+    // `t_enter < 0` means that the box already intersects the prism (or
+    // moves away from it)
+    if t_enter >= t_exit || t_enter < 0.0 || t_enter > 1.0 {
+        return None;
+    }
+    Some((t_enter, normal))
+}
+
+/// Returns all axes which need to be checked to separate a box from a hex
+/// prism with the given half height, together with the half size of the
+/// prism along each axis.
+fn separating_axes(half_height: f32) -> [(Vector3f, f32); 5] {
+    let sin_60 = SQRT_3 / 2.0;
+    [(Vector3f::new(0.0, 0.0, 1.0), half_height),
+     (Vector3f::new(1.0, 0.0, 0.0), HEX_INNER_RADIUS),
+     (Vector3f::new(0.0, 1.0, 0.0), HEX_OUTER_RADIUS),
+     (Vector3f::new(0.5, sin_60, 0.0), HEX_INNER_RADIUS),
+     (Vector3f::new(-0.5, sin_60, 0.0), HEX_INNER_RADIUS)]
+}
+
+#[cfg(test)]
+fn test_box(x: f32, y: f32, z: f32) -> Aabb {
+    Aabb::standing_at(Point3f::new(x, y, z), 0.25, 1.8)
+}
+
+#[test]
+fn fall_onto_ground() {
+    let world = test_world(materials::STONE, &[(0, 4)], &[]);
+    let result = move_aabb(&world, test_box(0.3, 0.2, 10.0), Vector3f::new(0.0, 0.0, -20.0), 0.0);
+
+    assert!((result.aabb.min.z - (2.0 + SKIN_WIDTH)).abs() < 1e-4);
+    assert!((result.aabb.feet().x - 0.3).abs() < 1e-4);
+    assert!(result.on_ground);
+    assert_eq!(result.contacts.len(), 1);
+    assert_eq!(result.contacts[0].normal, Vector3f::new(0.0, 0.0, 1.0));
+
+    // Without any motion nothing changes
+    let again = move_aabb(&world, result.aabb, Vector3f::new(0.0, 0.0, 0.0), 0.0);
+    assert_eq!(again.aabb, result.aabb);
+    assert!(again.on_ground);
+    assert!(!move_aabb(&world, test_box(0.0, 0.0, 3.0), Vector3f::new(0.0, 0.0, 0.0), 0.0)
+        .on_ground);
+}
+
+#[test]
+fn blocked_by_walls() {
+    // A single high pillar three hexagons to the right
+    let world = test_world(materials::STONE, &[(0, 4)], &[((3, 0), &[(0, 20)])]);
+    let start = test_box(0.0, 0.0, 2.0 + SKIN_WIDTH);
+
+    let contact = sweep_aabb(&world, &start, Vector3f::new(10.0, 0.0, 0.0)).unwrap();
+    assert_eq!(contact.pillar, PillarIndex(AxialPoint::new(3, 0)));
+    assert_eq!(contact.normal, Vector3f::new(-1.0, 0.0, 0.0));
+    // The left side of the pillar is at `5 * HEX_INNER_RADIUS`
+    assert!((contact.time * 10.0 - (5.0 * HEX_INNER_RADIUS - 0.25)).abs() < 1e-4);
+
+    let result = move_aabb(&world, start, Vector3f::new(10.0, 0.0, 0.0), 0.0);
+    assert!((result.aabb.max.x - (5.0 * HEX_INNER_RADIUS - SKIN_WIDTH)).abs() < 1e-4);
+    assert_eq!(result.aabb.min.z, start.min.z);
+    assert!(result.on_ground);
+
+    // Moving away from the wall isn't affected by it
+    let back = move_aabb(&world, result.aabb, Vector3f::new(-3.0, 0.0, 0.0), 0.0);
+    assert!((back.aabb.min.x - (result.aabb.min.x - 3.0)).abs() < 1e-4);
+    assert!(back.contacts.is_empty());
+}
+
+#[test]
+fn fast_bodies_do_not_tunnel() {
+    let world = test_world(materials::STONE, &[(0, 4)], &[((5, 0), &[(0, 20)])]);
+    let start = test_box(0.0, 0.0, 2.0 + SKIN_WIDTH);
+
+    let result = move_aabb(&world, start, Vector3f::new(1000.0, 0.0, 0.0), 0.0);
+    assert!(result.aabb.max.x < 9.0 * HEX_INNER_RADIUS);
+    assert_eq!(result.contacts[0].pillar, PillarIndex(AxialPoint::new(5, 0)));
+
+    // Falling through the floor isn't possible either
+    let result = move_aabb(&world, test_box(0.0, 0.0, 50.0), Vector3f::new(0.0, 0.0, -1e4), 0.0);
+    assert!((result.aabb.min.z - (2.0 + SKIN_WIDTH)).abs() < 1e-3);
+}
+
+#[test]
+fn slide_along_walls() {
+    let world = test_world(materials::STONE,
+                           &[(0, 4)],
+                           &[((3, 0), &[(0, 20)]), ((0, 2), &[(0, 20)])]);
+
+    // Along the left side of (3, 0) the y part of the motion is kept
+    let start = test_box(0.0, 0.0, 2.0 + SKIN_WIDTH);
+    let result = move_aabb(&world, start, Vector3f::new(10.0, 0.3, 0.0), 0.0);
+    assert_eq!(result.contacts[0].normal, Vector3f::new(-1.0, 0.0, 0.0));
+    assert!((result.aabb.max.x - (5.0 * HEX_INNER_RADIUS - SKIN_WIDTH)).abs() < 1e-4);
+    assert!((result.aabb.feet().y - 0.3).abs() < 1e-4);
+
+    // The lower right side of (0, 2) deflects the box to the right
+    let start = test_box(-1.0, 0.0, 2.0 + SKIN_WIDTH);
+    let result = move_aabb(&world, start, Vector3f::new(0.0, 3.0, 0.0), 0.0);
+    let normal = result.contacts[0].normal;
+    assert!((normal - Vector3f::new(0.5, -SQRT_3 / 2.0, 0.0)).magnitude() < 1e-4);
+    assert!(result.aabb.feet().x > -0.9);
+    assert!(result.aabb.max.y > 2.3);
+}
+
+#[test]
+fn step_onto_ledges() {
+    // Everything right of `q = 3` is one step higher than the floor
+    let step: &[(u16, u16)] = &[(0, 5)];
+    let ledge: Vec<_> = (3..16)
+        .flat_map(|q| (-3..4).map(move |r| ((q, r), step)))
+        .collect();
+    let world = test_world(materials::STONE, &[(0, 4)], &ledge);
+    let start = test_box(0.0, 0.0, 2.0 + SKIN_WIDTH);
+    let motion = Vector3f::new(10.0, 0.0, 0.0);
+
+    let blocked = move_aabb(&world, start, motion, 0.0);
+    assert!(blocked.aabb.max.x < 5.0 * HEX_INNER_RADIUS);
+
+    let too_low = move_aabb(&world, start, motion, 0.4);
+    assert_eq!(too_low.aabb, blocked.aabb);
+
+    let stepped = move_aabb(&world, start, motion, 0.6);
+    assert!((stepped.aabb.feet().x - 10.0).abs() < 1e-4);
+    assert!((stepped.aabb.min.z - (2.5 + SKIN_WIDTH)).abs() < 1e-4);
+    assert!(stepped.on_ground);
+}
+
+#[test]
+fn move_through_water() {
+    use world::{HeightType, PillarSection};
+
+    // A pool with its surface at height `5.0` right of `q = 2`
+    let mut world = test_world(materials::STONE, &[(0, 4)], &[]);
+    for q in 2..16 {
+        for r in -3..4 {
+            let pillar = world.pillar_at_mut(PillarIndex(AxialPoint::new(q, r))).unwrap();
+            pillar.sections_mut()
+                .push(PillarSection::new(materials::WATER, HeightType(4), HeightType(10)));
+        }
+    }
+
+    // Walking into the pool isn't blocked by the water surface
+    let start = test_box(0.0, 0.0, 2.0 + SKIN_WIDTH);
+    let result = move_aabb(&world, start, Vector3f::new(10.0, 0.0, 0.0), 0.0);
+    assert!(result.contacts.is_empty());
+    assert!(result.on_ground);
+    assert!(result.in_fluid);
+    assert!(!move_aabb(&world, start, Vector3f::new(0.0, 0.0, 0.0), 0.0).in_fluid);
+
+    // Bodies sink to the ground of the pool
+    let result = move_aabb(&world, test_box(10.0, 0.0, 20.0), Vector3f::new(0.0, 0.0, -30.0), 0.0);
+    assert!((result.aabb.min.z - (2.0 + SKIN_WIDTH)).abs() < 1e-4);
+    assert!(result.in_fluid);
+    assert!(is_fluid_at(&world, Point3f::new(10.0, 0.0, 4.9)));
+    assert!(!is_fluid_at(&world, Point3f::new(10.0, 0.0, 5.1)));
+}
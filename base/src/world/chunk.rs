@@ -13,7 +13,7 @@ use std::iter::Iterator;
 /// `AxialPoint`.
 ///
 /// [1]: http://www.redblobgames.com/grids/hexagons/#map-storage
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Chunk {
     /// All pillars are layed out in this one dimensional vector which saves
     /// all rows (same r-value) consecutive.
@@ -94,8 +94,8 @@ impl Chunk {
         where F: FnMut(AxialPoint) -> HexPillar
     {
         let mut hec = Vec::new();
-        let start_q = CHUNK_SIZE as i32 * chunk_index.0.q;
-        let start_r = CHUNK_SIZE as i32 * chunk_index.0.r;
+        let origin = chunk_index.origin().0;
+        let (start_q, start_r) = (origin.q, origin.r);
 
         for r in start_r..start_r + CHUNK_SIZE as i32 {
             for q in start_q..start_q + CHUNK_SIZE as i32 {
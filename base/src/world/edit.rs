@@ -0,0 +1,38 @@
+//! Errors of the editing methods of a `World`.
+
+use std::error::Error;
+use std::fmt;
+use super::ChunkIndex;
+
+/// The error type for the editing methods of `World`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The chunk containing the pillar to edit is not loaded.
+    ChunkNotLoaded(ChunkIndex),
+    /// The given height range is empty (`bottom >= top`).
+    InvalidRange,
+    /// The pillar doesn't have a prop with the given index.
+    NoSuchProp(usize),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EditError::ChunkNotLoaded(index) => {
+                write!(f, "{} ({:?})", self.description(), index)
+            }
+            EditError::NoSuchProp(index) => write!(f, "{} ({})", self.description(), index),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
+
+impl Error for EditError {
+    fn description(&self) -> &str {
+        match *self {
+            EditError::ChunkNotLoaded(_) => "chunk is not loaded",
+            EditError::InvalidRange => "empty height range",
+            EditError::NoSuchProp(_) => "pillar has no prop with this index",
+        }
+    }
+}
@@ -0,0 +1,1243 @@
+//! Compact, versioned binary encoding of the world types.
+//!
+//! Every type of the chunk tree (`Chunk`, `HexPillar`, `PillarSection` and
+//! `Prop`) implements `Encode` and `Decode`. Whole chunks should be encoded
+//! with `encode_chunk()` which prepends a small header, so that the data can
+//! be identified and old versions can be rejected by `decode_chunk()`:
+//!
+//! ```text
+//! +------------------+-----------------+----------------------------------
+//! | magic (`PXCH`)   | version (u16)   | chunk data
+//! +------------------+-----------------+----------------------------------
+//! ```
+//!
+//! Most numbers are stored as unsigned LEB128 variable length integers
+//! ("varints"); fixed size numbers are stored in big endian byte order.
+//!
+//! Neighbouring pillars often share the same biome and frequently have the
+//! very same sections, so the chunk data is stored in three parts:
+//!
+//! - the biomes of all pillars as runs of `(length, biome)`
+//! - the sections of all pillars as runs of `(length, sections)`
+//! - the props of every pillar
+//!
+//! Within a pillar, every section is stored relative to the top of the
+//! section below it (or zero for the lowest section), which keeps the numbers
+//! small.
+//!
+//! The seed, the `WorldGenConfig`, the biomes and the plant species of a
+//! world are encoded with `encode_world()`, which uses the same header with
+//! the magic `PXWD`. Floats are stored as the big endian bytes of their bit
+//! pattern, strings as their length followed by their UTF-8 bytes.
+//!
+//! Decoding never panics: truncated or corrupted input is rejected with a
+//! `DecodeError`.
+
+use std::error::Error;
+use std::fmt;
+use gen::plant::GeneratorKind;
+use gen::plant::preset::{HeightCurve, Preset};
+use gen::plant::registry::{PlantId, PlantRegistry, Species, SpeciesId};
+use gen::world::biome::{Biome, BiomeId, BiomeRegistry, BiomeTransition, Climate, GroundLayers,
+                        PlantChance, WeatherChances, WeatherKind};
+use gen::world::config::WorldGenConfig;
+use gen::world::decoration::{PLANT_LAYERS, Placement};
+use gen::world::erosion::{ErosionConfig, HydraulicErosion, ThermalErosion};
+use gen::world::water::WaterConfig;
+use super::{CHUNK_SIZE, Chunk, HeightType, HexPillar, MaterialId, PillarSection, Prop};
+
+/// The magic bytes every encoded chunk starts with.
+pub const CHUNK_MAGIC: &'static [u8; 4] = b"PXCH";
+
+/// The magic bytes the encoded seed and configuration of a world start with.
+pub const WORLD_MAGIC: &'static [u8; 4] = b"PXWD";
+
+/// The current version of the encoding. Data with another version is
+/// rejected by `decode_chunk()`.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Everything which is stored about a world besides its chunks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldData {
+    pub seed: u64,
+    pub config: WorldGenConfig,
+    /// The biomes the world was generated with. They are stored with the
+    /// world, so that a changed `biomes.toml` doesn't change existing worlds.
+    pub biomes: BiomeRegistry,
+    /// The plant species the props of the chunks refer to.
+    pub plants: PlantRegistry,
+}
+
+/// Types which can be written in the binary format of this module.
+pub trait Encode {
+    /// Appends the encoded form of `self` to `out`.
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// Types which can be read from the binary format of this module.
+pub trait Decode: Sized {
+    /// Reads one value from the decoder.
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError>;
+}
+
+/// Encodes the given chunk including the header.
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(CHUNK_MAGIC);
+    write_u16(&mut out, FORMAT_VERSION);
+    chunk.encode(&mut out);
+    out
+}
+
+/// Decodes a chunk which was encoded with `encode_chunk()`. All of `data`
+/// has to be consumed.
+pub fn decode_chunk(data: &[u8]) -> Result<Chunk, DecodeError> {
+    let mut d = Decoder::new(data);
+    try!(read_header(&mut d, CHUNK_MAGIC));
+
+    let chunk = try!(Chunk::decode(&mut d));
+    if !d.is_empty() {
+        return Err(DecodeError::TrailingBytes(d.remaining()));
+    }
+
+    Ok(chunk)
+}
+
+/// Encodes the data of a world including the header.
+pub fn encode_world(world: &WorldData) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(WORLD_MAGIC);
+    write_u16(&mut out, FORMAT_VERSION);
+    write_varint(&mut out, world.seed);
+    world.config.encode(&mut out);
+    world.biomes.encode(&mut out);
+    world.plants.encode(&mut out);
+    out
+}
+
+/// Decodes the data of a world which was encoded with `encode_world()`. The
+/// configuration, the biomes and the plant species are validated.
+pub fn decode_world(data: &[u8]) -> Result<WorldData, DecodeError> {
+    let mut d = Decoder::new(data);
+    try!(read_header(&mut d, WORLD_MAGIC));
+
+    let seed = try!(d.read_varint());
+    let config = try!(WorldGenConfig::decode(&mut d));
+    let biomes = try!(BiomeRegistry::decode(&mut d));
+    let plants = try!(PlantRegistry::decode(&mut d));
+    for biome in biomes.iter() {
+        if biome.plants.iter().any(|p| plants.get(p.species).is_none()) {
+            return Err(DecodeError::InvalidBiome(biome.id.0));
+        }
+    }
+    if !d.is_empty() {
+        return Err(DecodeError::TrailingBytes(d.remaining()));
+    }
+
+    Ok(WorldData {
+        seed: seed,
+        config: config,
+        biomes: biomes,
+        plants: plants,
+    })
+}
+
+/// Checks the magic bytes and the version in front of the data.
+fn read_header(d: &mut Decoder, magic: &[u8; 4]) -> Result<(), DecodeError> {
+    if try!(d.read_bytes(magic.len())) != magic {
+        return Err(DecodeError::InvalidMagic);
+    }
+    let version = try!(d.read_u16());
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Reads encoded values from a byte slice.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data: data }
+    }
+
+    /// Returns the number of bytes which haven't been read yet.
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if all bytes have been read.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        self.read_bytes(1).map(|b| b[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        self.read_bytes(2).map(|b| ((b[0] as u16) << 8) | b[1] as u16)
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, DecodeError> {
+        let bits = try!(self.read_bytes(4)).iter().fold(0u32, |bits, &b| (bits << 8) | b as u32);
+        Ok(f32::from_bits(bits))
+    }
+
+    /// Reads an unsigned LEB128 encoded number.
+    pub fn read_varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for i in 0..10 {
+            let byte = try!(self.read_u8());
+            let bits = (byte & 0x7f) as u64;
+            // The tenth byte may only contain the most significant bit
+            if i == 9 && bits > 1 {
+                return Err(DecodeError::InvalidVarint);
+            }
+            value |= bits << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::InvalidVarint)
+    }
+
+    /// Reads a varint and checks that it fits into an `u16`.
+    fn read_varint_u16(&mut self) -> Result<u16, DecodeError> {
+        let v = try!(self.read_varint());
+        if v > u16::max_value() as u64 {
+            return Err(DecodeError::InvalidVarint);
+        }
+        Ok(v as u16)
+    }
+
+    /// Reads a varint and checks that it fits into an `u32`.
+    fn read_varint_u32(&mut self) -> Result<u32, DecodeError> {
+        let v = try!(self.read_varint());
+        if v > u32::max_value() as u64 {
+            return Err(DecodeError::InvalidVarint);
+        }
+        Ok(v as u32)
+    }
+
+    /// Reads a string which is stored as its length followed by its bytes.
+    pub fn read_str(&mut self) -> Result<&'a str, DecodeError> {
+        let len = try!(self.read_varint());
+        if len > self.remaining() as u64 {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let bytes = try!(self.read_bytes(len as usize));
+        ::std::str::from_utf8(bytes).map_err(|_| DecodeError::InvalidString)
+    }
+
+    /// Reads the flag in front of an optional value.
+    fn read_flag(&mut self) -> Result<bool, DecodeError> {
+        match try!(self.read_u8()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::InvalidFlag),
+        }
+    }
+}
+
+/// The error type for decoding data of this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data ended in the middle of a value.
+    UnexpectedEnd,
+    /// The data doesn't start with the expected magic bytes.
+    InvalidMagic,
+    /// The data was encoded with an unknown version of the format.
+    UnsupportedVersion(u16),
+    /// A varint is too long or doesn't fit into the expected type.
+    InvalidVarint,
+    /// The flag of an optional value is neither `0` nor `1`.
+    InvalidFlag,
+    /// Invalid id of a ground material. Only the reserved id `0` is rejected,
+    /// ids which aren't in the `MaterialRegistry` are drawn as placeholder.
+    UnknownMaterial(u8),
+    /// Invalid id of a biome. Only the reserved id `255` is rejected, ids
+    /// which aren't in the `BiomeRegistry` use a placeholder biome.
+    UnknownBiome(u8),
+    /// A string isn't valid UTF-8.
+    InvalidString,
+    /// The biome with the given id is invalid: it refers to an unknown plant
+    /// species or weather or it can't be added to the `BiomeRegistry`.
+    InvalidBiome(u8),
+    /// The plant species with the given id is invalid: it refers to an
+    /// unknown plant generator or layer, its height curve is invalid or it
+    /// can't be added to the `PlantRegistry`.
+    InvalidSpecies(u16),
+    /// A pillar section is empty or lies outside of the valid height range.
+    InvalidSection,
+    /// A run is empty or longer than the number of remaining pillars.
+    InvalidRun,
+    /// The given number of bytes was left over after decoding.
+    TrailingBytes(usize),
+    /// The decoded world generator configuration is invalid.
+    InvalidConfig(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "{} (found {}, expected {})", self.description(), v, FORMAT_VERSION)
+            }
+            DecodeError::UnknownMaterial(id) |
+            DecodeError::UnknownBiome(id) |
+            DecodeError::InvalidBiome(id) => write!(f, "{} ({})", self.description(), id),
+            DecodeError::InvalidSpecies(id) => write!(f, "{} ({})", self.description(), id),
+            DecodeError::TrailingBytes(n) => write!(f, "{} ({} bytes)", self.description(), n),
+            DecodeError::InvalidConfig(reason) => write!(f, "{}: {}", self.description(), reason),
+            _ => f.write_str(self.description()),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            DecodeError::UnexpectedEnd => "unexpected end of data",
+            DecodeError::InvalidMagic => "invalid magic bytes",
+            DecodeError::UnsupportedVersion(_) => "unsupported format version",
+            DecodeError::InvalidVarint => "invalid variable length integer",
+            DecodeError::InvalidFlag => "invalid flag",
+            DecodeError::UnknownMaterial(_) => "unknown ground material",
+            DecodeError::UnknownBiome(_) => "unknown biome",
+            DecodeError::InvalidString => "invalid UTF-8 string",
+            DecodeError::InvalidBiome(_) => "invalid biome definition",
+            DecodeError::InvalidSpecies(_) => "invalid plant species definition",
+            DecodeError::InvalidSection => "invalid pillar section",
+            DecodeError::InvalidRun => "invalid run length",
+            DecodeError::TrailingBytes(_) => "trailing bytes after encoded data",
+            DecodeError::InvalidConfig(_) => "invalid world generator configuration",
+        }
+    }
+}
+
+// ===========================================================================
+// Implementations for the world types
+// ===========================================================================
+
+impl Encode for MaterialId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.0);
+    }
+}
+
+impl Decode for MaterialId {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        match try!(d.read_u8()) {
+            0 => Err(DecodeError::UnknownMaterial(0)),
+            id => Ok(MaterialId(id)),
+        }
+    }
+}
+
+impl Encode for BiomeId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.0);
+    }
+}
+
+impl Decode for BiomeId {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let id = BiomeId(try!(d.read_u8()));
+        if id.is_reserved() {
+            return Err(DecodeError::UnknownBiome(id.0));
+        }
+        Ok(id)
+    }
+}
+
+impl Encode for PillarSection {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.ground.encode(out);
+        write_u16(out, self.bottom.units());
+        write_u16(out, self.top.units());
+    }
+}
+
+impl Decode for PillarSection {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let ground = try!(MaterialId::decode(d));
+        let bottom = try!(d.read_u16());
+        let top = try!(d.read_u16());
+        section(ground, bottom as i64, top as i64)
+    }
+}
+
+impl Encode for Prop {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.baseline.units());
+        write_varint(out, self.plant.species.0 as u64);
+        write_varint(out, self.plant.variant as u64);
+    }
+}
+
+impl Decode for Prop {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let baseline = HeightType::from_units(try!(d.read_u16()));
+        let species = SpeciesId(try!(d.read_varint_u16()));
+        let variant = try!(d.read_varint_u16());
+
+        Ok(Prop {
+            baseline: baseline,
+            plant: PlantId {
+                species: species,
+                variant: variant,
+            },
+        })
+    }
+}
+
+impl Encode for HexPillar {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.biome().encode(out);
+        encode_sections(self.sections(), out);
+        encode_props(self.props(), out);
+    }
+}
+
+impl Decode for HexPillar {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let biome = try!(BiomeId::decode(d));
+        let sections = try!(decode_sections(d));
+        let props = try!(decode_props(d));
+        Ok(HexPillar::new(sections, props, biome))
+    }
+}
+
+impl Encode for Chunk {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let pillars = &self.pillars;
+
+        for run in runs(pillars, |a, b| a.biome() == b.biome()) {
+            write_varint(out, run.len() as u64);
+            run[0].biome().encode(out);
+        }
+        for run in runs(pillars, |a, b| a.sections() == b.sections()) {
+            write_varint(out, run.len() as u64);
+            encode_sections(run[0].sections(), out);
+        }
+        for pillar in pillars {
+            encode_props(pillar.props(), out);
+        }
+    }
+}
+
+impl Decode for Chunk {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let count = (CHUNK_SIZE as usize).pow(2);
+
+        let mut biomes = Vec::with_capacity(count);
+        while biomes.len() < count {
+            let len = try!(read_run_len(d, count - biomes.len()));
+            let biome = try!(BiomeId::decode(d));
+            for _ in 0..len {
+                biomes.push(biome);
+            }
+        }
+
+        let mut sections = Vec::with_capacity(count);
+        while sections.len() < count {
+            let len = try!(read_run_len(d, count - sections.len()));
+            let run = try!(decode_sections(d));
+            for _ in 0..len {
+                sections.push(run.clone());
+            }
+        }
+
+        let mut pillars = Vec::with_capacity(count);
+        for (biome, sections) in biomes.into_iter().zip(sections) {
+            let props = try!(decode_props(d));
+            pillars.push(HexPillar::new(sections, props, biome));
+        }
+
+        Ok(Chunk::from_pillars(pillars))
+    }
+}
+
+impl Encode for WorldGenConfig {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.world_height);
+        write_u16(out, self.sea_level);
+        for &f in &[self.land_noise_scale.0,
+                    self.land_noise_scale.1,
+                    self.land_noise_scale.2,
+                    self.min_thresh,
+                    self.thresh_mid,
+                    self.steepness.0,
+                    self.steepness.1,
+                    self.steepness.2,
+                    self.climate_frequency,
+                    self.biome_scale,
+                    self.biome_transition.temperature,
+                    self.biome_transition.humidity] {
+            write_f32(out, f);
+        }
+
+        write_varint(out, self.water.river_threshold as u64);
+        write_u16(out, self.water.min_lake_depth);
+
+        match self.erosion.hydraulic {
+            Some(hydraulic) => {
+                out.push(1);
+                write_f32(out, hydraulic.droplets_per_pillar);
+                write_varint(out, hydraulic.max_steps as u64);
+                for &f in &[hydraulic.capacity,
+                            hydraulic.erosion_rate,
+                            hydraulic.deposition_rate,
+                            hydraulic.evaporation,
+                            hydraulic.min_slope] {
+                    write_f32(out, f);
+                }
+            }
+            None => out.push(0),
+        }
+        match self.erosion.thermal {
+            Some(thermal) => {
+                out.push(1);
+                write_f32(out, thermal.talus);
+                write_f32(out, thermal.rate);
+                write_varint(out, thermal.iterations as u64);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+impl Decode for WorldGenConfig {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut config = WorldGenConfig {
+            world_height: try!(d.read_u16()),
+            sea_level: try!(d.read_u16()),
+            land_noise_scale: (try!(d.read_f32()), try!(d.read_f32()), try!(d.read_f32())),
+            min_thresh: try!(d.read_f32()),
+            thresh_mid: try!(d.read_f32()),
+            steepness: (try!(d.read_f32()), try!(d.read_f32()), try!(d.read_f32())),
+            climate_frequency: try!(d.read_f32()),
+            biome_scale: try!(d.read_f32()),
+            biome_transition: BiomeTransition {
+                temperature: try!(d.read_f32()),
+                humidity: try!(d.read_f32()),
+            },
+            water: WaterConfig {
+                river_threshold: try!(d.read_varint_u32()),
+                min_lake_depth: try!(d.read_u16()),
+            },
+            erosion: ErosionConfig::default(),
+        };
+
+        if try!(d.read_flag()) {
+            config.erosion.hydraulic = Some(HydraulicErosion {
+                droplets_per_pillar: try!(d.read_f32()),
+                max_steps: try!(d.read_varint_u32()),
+                capacity: try!(d.read_f32()),
+                erosion_rate: try!(d.read_f32()),
+                deposition_rate: try!(d.read_f32()),
+                evaporation: try!(d.read_f32()),
+                min_slope: try!(d.read_f32()),
+            });
+        }
+        if try!(d.read_flag()) {
+            config.erosion.thermal = Some(ThermalErosion {
+                talus: try!(d.read_f32()),
+                rate: try!(d.read_f32()),
+                iterations: try!(d.read_varint_u32()),
+            });
+        }
+
+        try!(config.validate().map_err(DecodeError::InvalidConfig));
+        Ok(config)
+    }
+}
+
+impl Encode for Biome {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        write_str(out, &self.name);
+        match self.climate {
+            Some(climate) => {
+                out.push(1);
+                for &f in &[climate.temperature.0,
+                            climate.temperature.1,
+                            climate.humidity.0,
+                            climate.humidity.1] {
+                    write_f32(out, f);
+                }
+            }
+            None => out.push(0),
+        }
+        self.ground.surface.encode(out);
+        self.ground.subsoil.encode(out);
+        self.ground.rock.encode(out);
+        write_f32(out, self.steepness);
+        write_f32(out, self.plant_threshold);
+
+        write_varint(out, self.plants.len() as u64);
+        for plant in &self.plants {
+            write_varint(out, plant.species.0 as u64);
+            write_f32(out, plant.weight);
+        }
+
+        match self.weather {
+            Some(weather) => {
+                out.push(1);
+                out.push(match weather.kind {
+                    WeatherKind::Rain => 0,
+                    WeatherKind::Snow => 1,
+                    WeatherKind::Pollen => 2,
+                });
+                write_f32(out, weather.weak);
+                write_f32(out, weather.medium);
+                write_f32(out, weather.heavy);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+impl Decode for Biome {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let id = try!(BiomeId::decode(d));
+        let mut biome = Biome::new(id, try!(d.read_str()));
+        if try!(d.read_flag()) {
+            biome.climate = Some(Climate {
+                temperature: (try!(d.read_f32()), try!(d.read_f32())),
+                humidity: (try!(d.read_f32()), try!(d.read_f32())),
+            });
+        }
+        biome.ground = GroundLayers {
+            surface: try!(MaterialId::decode(d)),
+            subsoil: try!(MaterialId::decode(d)),
+            rock: try!(MaterialId::decode(d)),
+        };
+        biome.steepness = try!(d.read_f32());
+        biome.plant_threshold = try!(d.read_f32());
+
+        let count = try!(d.read_varint_u16());
+        for _ in 0..count {
+            biome.plants.push(PlantChance {
+                species: SpeciesId(try!(d.read_varint_u16())),
+                weight: try!(d.read_f32()),
+            });
+        }
+
+        if try!(d.read_flag()) {
+            let kind = match try!(d.read_u8()) {
+                0 => WeatherKind::Rain,
+                1 => WeatherKind::Snow,
+                2 => WeatherKind::Pollen,
+                _ => return Err(DecodeError::InvalidBiome(id.0)),
+            };
+            biome.weather = Some(WeatherChances {
+                kind: kind,
+                weak: try!(d.read_f32()),
+                medium: try!(d.read_f32()),
+                heavy: try!(d.read_f32()),
+            });
+        }
+
+        Ok(biome)
+    }
+}
+
+impl Encode for BiomeRegistry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.len() as u64);
+        for biome in self.iter() {
+            biome.encode(out);
+        }
+    }
+}
+
+impl Decode for BiomeRegistry {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let count = try!(d.read_varint_u16());
+        let mut registry = BiomeRegistry::new();
+        for _ in 0..count {
+            let biome = try!(Biome::decode(d));
+            let id = biome.id;
+            // `register()` would silently replace a biome with the same id
+            if registry.get(id).is_some() {
+                return Err(DecodeError::InvalidBiome(id.0));
+            }
+            try!(registry.register(biome).map_err(|_| DecodeError::InvalidBiome(id.0)));
+        }
+        Ok(registry)
+    }
+}
+
+impl Encode for Species {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.id.0 as u64);
+        write_str(out, &self.name);
+        write_str(out, self.generator.name());
+        write_varint(out, self.variants as u64);
+
+        let placement = &self.placement;
+        out.push(PLANT_LAYERS.iter().position(|&l| l == placement.layer).unwrap() as u8);
+        write_f32(out, placement.spacing);
+        write_f32(out, placement.density);
+        write_f32(out, placement.cluster_frequency);
+        write_u16(out, placement.max_slope);
+        write_u16(out, placement.altitude.0);
+        write_u16(out, placement.altitude.1);
+
+        encode_preset(&self.preset, out);
+    }
+}
+
+impl Decode for Species {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let id = SpeciesId(try!(d.read_varint_u16()));
+        let name = try!(d.read_str()).to_string();
+        let generator = try!(GeneratorKind::from_name(try!(d.read_str()))
+            .ok_or(DecodeError::InvalidSpecies(id.0)));
+        let variants = try!(d.read_varint_u16());
+
+        let layer = try!(PLANT_LAYERS.get(try!(d.read_u8()) as usize)
+            .ok_or(DecodeError::InvalidSpecies(id.0)));
+        let placement = Placement {
+            layer: *layer,
+            spacing: try!(d.read_f32()),
+            density: try!(d.read_f32()),
+            cluster_frequency: try!(d.read_f32()),
+            max_slope: try!(d.read_u16()),
+            altitude: (try!(d.read_u16()), try!(d.read_u16())),
+        };
+
+        Ok(Species {
+            id: id,
+            name: name,
+            generator: generator,
+            variants: variants,
+            placement: placement,
+            preset: try!(decode_preset(d, id)),
+        })
+    }
+}
+
+impl Encode for PlantRegistry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.len() as u64);
+        for species in self.iter() {
+            species.encode(out);
+        }
+    }
+}
+
+impl Decode for PlantRegistry {
+    fn decode(d: &mut Decoder) -> Result<Self, DecodeError> {
+        let count = try!(d.read_varint_u16());
+        let mut registry = PlantRegistry::new();
+        for _ in 0..count {
+            let species = try!(Species::decode(d));
+            let id = species.id;
+            // `register()` would silently replace a species with the same id
+            if registry.get(id).is_some() {
+                return Err(DecodeError::InvalidSpecies(id.0));
+            }
+            try!(registry.register(species).map_err(|_| DecodeError::InvalidSpecies(id.0)));
+        }
+        Ok(registry)
+    }
+}
+
+/// Writes all parameters of a preset in the order of its fields.
+fn encode_preset(preset: &Preset, out: &mut Vec<u8>) {
+    let ranges = [preset.trunk_diameter,
+                  preset.trunk_height,
+                  preset.trunk_diameter_top,
+                  preset.min_branch_height,
+                  preset.branch_diameter_factor,
+                  preset.branch_angle_deg,
+                  preset.branch_diam_reduction,
+                  preset.branch_segment_length,
+                  preset.branch_segment_length2,
+                  preset.branch_segment_angle];
+    for &(min, max) in &ranges[..4] {
+        write_f32(out, min);
+        write_f32(out, max);
+    }
+    write_f32(out, preset.branch_chance);
+    for &(min, max) in &ranges[4..] {
+        write_f32(out, min);
+        write_f32(out, max);
+    }
+    write_varint(out, preset.branch_segment_count.0 as u64);
+    write_varint(out, preset.branch_segment_count.1 as u64);
+    for &(min, max) in &[preset.trunk_color.0,
+                         preset.trunk_color.1,
+                         preset.trunk_color.2,
+                         preset.leaf_color.0,
+                         preset.leaf_color.1,
+                         preset.leaf_color.2] {
+        write_f32(out, min);
+        write_f32(out, max);
+    }
+    write_u16(out, preset.leaf_depth);
+
+    let points = preset.height_curve.points();
+    write_varint(out, points.len() as u64);
+    for &(height, value) in points {
+        write_f32(out, height);
+        write_f32(out, value);
+    }
+}
+
+/// Reads a preset written by `encode_preset()`. The preset isn't validated,
+/// except for its height curve.
+fn decode_preset(d: &mut Decoder, species: SpeciesId) -> Result<Preset, DecodeError> {
+    fn range(d: &mut Decoder) -> Result<(f32, f32), DecodeError> {
+        Ok((try!(d.read_f32()), try!(d.read_f32())))
+    }
+
+    let trunk_diameter = try!(range(d));
+    let trunk_height = try!(range(d));
+    let trunk_diameter_top = try!(range(d));
+    let min_branch_height = try!(range(d));
+    let branch_chance = try!(d.read_f32());
+    let branch_diameter_factor = try!(range(d));
+    let branch_angle_deg = try!(range(d));
+    let branch_diam_reduction = try!(range(d));
+    let branch_segment_length = try!(range(d));
+    let branch_segment_length2 = try!(range(d));
+    let branch_segment_angle = try!(range(d));
+    let branch_segment_count = (try!(d.read_varint_u32()), try!(d.read_varint_u32()));
+    let trunk_color = (try!(range(d)), try!(range(d)), try!(range(d)));
+    let leaf_color = (try!(range(d)), try!(range(d)), try!(range(d)));
+    let leaf_depth = try!(d.read_u16());
+
+    let count = try!(d.read_varint_u16());
+    let mut points = Vec::with_capacity(::std::cmp::min(count as usize, d.remaining()));
+    for _ in 0..count {
+        points.push(try!(range(d)));
+    }
+    let height_curve = try!(HeightCurve::new(points)
+        .map_err(|_| DecodeError::InvalidSpecies(species.0)));
+
+    Ok(Preset {
+        trunk_diameter: trunk_diameter,
+        trunk_height: trunk_height,
+        trunk_diameter_top: trunk_diameter_top,
+        min_branch_height: min_branch_height,
+        branch_chance: branch_chance,
+        branch_diameter_factor: branch_diameter_factor,
+        branch_angle_deg: branch_angle_deg,
+        branch_diam_reduction: branch_diam_reduction,
+        branch_segment_length: branch_segment_length,
+        branch_segment_length2: branch_segment_length2,
+        branch_segment_angle: branch_segment_angle,
+        branch_segment_count: branch_segment_count,
+        trunk_color: trunk_color,
+        leaf_color: leaf_color,
+        leaf_depth: leaf_depth,
+        height_curve: height_curve,
+    })
+}
+
+/// Writes the sections of a pillar. The bottom of every section is stored
+/// relative to the top of the previous section (as zigzag encoded signed
+/// number, so that unsorted sections can be represented, too), followed by
+/// the height of the section.
+fn encode_sections(sections: &[PillarSection], out: &mut Vec<u8>) {
+    write_varint(out, sections.len() as u64);
+
+    let mut prev_top = 0i64;
+    for section in sections {
+        let bottom = section.bottom.units() as i64;
+        let top = section.top.units() as i64;
+
+        section.ground.encode(out);
+        write_varint(out, zigzag(bottom - prev_top));
+        write_varint(out, (top - bottom) as u64);
+        prev_top = top;
+    }
+}
+
+fn decode_sections(d: &mut Decoder) -> Result<Vec<PillarSection>, DecodeError> {
+    let count = try!(d.read_varint_u16());
+    // Don't trust the count for the allocation: it might be corrupted
+    let mut sections = Vec::with_capacity(::std::cmp::min(count as usize, d.remaining()));
+
+    let mut prev_top = 0i64;
+    for _ in 0..count {
+        let ground = try!(MaterialId::decode(d));
+        let gap = try!(d.read_varint());
+        let height = try!(d.read_varint_u16());
+        // Larger gaps would leave the valid height range anyway
+        if gap > 2 * (u16::max_value() as u64 + 1) {
+            return Err(DecodeError::InvalidSection);
+        }
+
+        let bottom = prev_top + unzigzag(gap);
+        let top = bottom + height as i64;
+        sections.push(try!(section(ground, bottom, top)));
+        prev_top = top;
+    }
+
+    Ok(sections)
+}
+
+fn encode_props(props: &[Prop], out: &mut Vec<u8>) {
+    write_varint(out, props.len() as u64);
+    for prop in props {
+        prop.encode(out);
+    }
+}
+
+fn decode_props(d: &mut Decoder) -> Result<Vec<Prop>, DecodeError> {
+    let count = try!(d.read_varint_u16());
+    let mut props = Vec::with_capacity(::std::cmp::min(count as usize, d.remaining()));
+    for _ in 0..count {
+        props.push(try!(Prop::decode(d)));
+    }
+    Ok(props)
+}
+
+/// Creates a section after checking that it is valid, instead of panicking
+/// like `PillarSection::new()`.
+fn section(ground: MaterialId, bottom: i64, top: i64) -> Result<PillarSection, DecodeError> {
+    let max = u16::max_value() as i64;
+    if bottom < 0 || top > max || bottom >= top {
+        return Err(DecodeError::InvalidSection);
+    }
+
+    Ok(PillarSection::new(ground,
+                          HeightType::from_units(bottom as u16),
+                          HeightType::from_units(top as u16)))
+}
+
+fn read_run_len(d: &mut Decoder, max: usize) -> Result<usize, DecodeError> {
+    let len = try!(d.read_varint());
+    if len == 0 || len > max as u64 {
+        return Err(DecodeError::InvalidRun);
+    }
+    Ok(len as usize)
+}
+
+/// Splits the slice into runs of consecutive elements for which `eq` returns
+/// `true`.
+fn runs<T, F>(items: &[T], eq: F) -> Vec<&[T]>
+    where F: Fn(&T, &T) -> bool
+{
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..items.len() + 1 {
+        if i == items.len() || !eq(&items[start], &items[i]) {
+            runs.push(&items[start..i]);
+            start = i;
+        }
+    }
+    runs
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+fn write_u16(out: &mut Vec<u8>, v: u16) {
+    out.push((v >> 8) as u8);
+    out.push(v as u8);
+}
+
+fn write_f32(out: &mut Vec<u8>, v: f32) {
+    let bits = v.to_bits();
+    out.extend_from_slice(&[(bits >> 24) as u8, (bits >> 16) as u8, (bits >> 8) as u8, bits as u8]);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+fn test_chunk() -> Chunk {
+    use gen::world::biome::biomes;
+    use super::materials;
+
+    let mut pillars = vec![HexPillar::default(); (CHUNK_SIZE as usize).pow(2)];
+    pillars[3] = HexPillar::new(vec![PillarSection::new(materials::STONE,
+                                                        HeightType(0),
+                                                        HeightType(40)),
+                                     PillarSection::new(materials::GRASS,
+                                                        HeightType(60),
+                                                        HeightType(65535))],
+                                vec![Prop {
+                                         baseline: HeightType(65535),
+                                         plant: PlantId {
+                                             species: SpeciesId(300),
+                                             variant: 7,
+                                         },
+                                     }],
+                                biomes::FOREST);
+    // Sections don't have to be sorted
+    pillars[200] = HexPillar::new(vec![PillarSection::new(materials::SAND,
+                                                          HeightType(10),
+                                                          HeightType(11)),
+                                       PillarSection::new(materials::SNOW,
+                                                          HeightType(2),
+                                                          HeightType(5))],
+                                  vec![],
+                                  biomes::SNOW);
+    Chunk::from_pillars(pillars)
+}
+
+#[test]
+fn varint_round_trip() {
+    for &v in &[0, 1, 127, 128, 300, 16383, 16384, u32::max_value() as u64, u64::max_value()] {
+        let mut out = Vec::new();
+        write_varint(&mut out, v);
+        let mut d = Decoder::new(&out);
+        assert_eq!(d.read_varint(), Ok(v));
+        assert!(d.is_empty());
+    }
+    for &v in &[0, 1, -1, 65535, -65535, i64::max_value(), i64::min_value()] {
+        assert_eq!(unzigzag(zigzag(v)), v);
+    }
+
+    let too_long = [0xff; 11];
+    assert_eq!(Decoder::new(&too_long).read_varint(),
+               Err(DecodeError::InvalidVarint));
+}
+
+#[test]
+fn round_trip_chunks() {
+    use gen::WorldGenerator;
+    use super::{ChunkIndex, ChunkProvider};
+    use math::AxialPoint;
+
+    let chunk = test_chunk();
+    assert_eq!(decode_chunk(&encode_chunk(&chunk)), Ok(chunk));
+
+    let gen = WorldGenerator::with_seed(1);
+    for &(q, r) in &[(0, 0), (-3, 2), (5, -7)] {
+        let chunk = gen.load_chunk(ChunkIndex(AxialPoint::new(q, r))).unwrap();
+        assert_eq!(decode_chunk(&encode_chunk(&chunk)), Ok(chunk));
+    }
+}
+
+#[test]
+fn round_trip_single_values() {
+    let chunk = test_chunk();
+    for pillar in &chunk.pillars {
+        let mut out = Vec::new();
+        pillar.encode(&mut out);
+        assert_eq!(HexPillar::decode(&mut Decoder::new(&out)).as_ref(), Ok(pillar));
+
+        for section in pillar.sections() {
+            let mut out = Vec::new();
+            section.encode(&mut out);
+            assert_eq!(PillarSection::decode(&mut Decoder::new(&out)).as_ref(),
+                       Ok(section));
+        }
+        for prop in pillar.props() {
+            let mut out = Vec::new();
+            prop.encode(&mut out);
+            assert_eq!(Prop::decode(&mut Decoder::new(&out)).as_ref(), Ok(prop));
+        }
+    }
+}
+
+#[test]
+fn runs_compress_uniform_chunks() {
+    use gen::world::biome::biomes;
+    use super::materials;
+
+    let pillar = HexPillar::new(vec![PillarSection::new(materials::DIRT,
+                                                        HeightType(0),
+                                                        HeightType(100))],
+                                vec![],
+                                biomes::GRASS_LAND);
+    let chunk = Chunk::from_pillars(vec![pillar; (CHUNK_SIZE as usize).pow(2)]);
+    let data = encode_chunk(&chunk);
+
+    // header + one biome run + one section run + an empty prop list per pillar
+    assert!(data.len() < 6 + 10 + 10 + (CHUNK_SIZE as usize).pow(2));
+    assert_eq!(decode_chunk(&data), Ok(chunk));
+}
+
+#[test]
+fn reject_truncated_data() {
+    let data = encode_chunk(&test_chunk());
+    for len in 0..data.len() {
+        assert_eq!(decode_chunk(&data[..len]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    let mut longer = data.clone();
+    longer.push(0);
+    assert_eq!(decode_chunk(&longer), Err(DecodeError::TrailingBytes(1)));
+}
+
+#[test]
+fn reject_corrupted_data() {
+    let data = encode_chunk(&test_chunk());
+
+    let mut bad_magic = data.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(decode_chunk(&bad_magic), Err(DecodeError::InvalidMagic));
+
+    let mut bad_version = data.clone();
+    bad_version[5] = 99;
+    assert_eq!(decode_chunk(&bad_version),
+               Err(DecodeError::UnsupportedVersion(99)));
+
+    // The first biome run covers all pillars before the forest pillar
+    let mut bad_run = data.clone();
+    bad_run[6] = 0;
+    assert_eq!(decode_chunk(&bad_run), Err(DecodeError::InvalidRun));
+
+    let mut bad_biome = data.clone();
+    bad_biome[7] = 255;
+    assert_eq!(decode_chunk(&bad_biome), Err(DecodeError::UnknownBiome(255)));
+
+    let mut d = Decoder::new(&[0, 0, 5, 0, 5]);
+    assert_eq!(PillarSection::decode(&mut d), Err(DecodeError::UnknownMaterial(0)));
+    let mut d = Decoder::new(&[1, 0, 5, 0, 5]);
+    assert_eq!(PillarSection::decode(&mut d), Err(DecodeError::InvalidSection));
+
+    // No input must make the decoder panic
+    for i in 0..data.len() {
+        for &byte in &[0x00, 0x01, 0x7f, 0x80, 0xff] {
+            let mut corrupted = data.clone();
+            corrupted[i] = byte;
+            let _ = decode_chunk(&corrupted);
+        }
+    }
+}
+
+#[test]
+fn round_trip_world() {
+    use gen::world::erosion::ErosionConfig;
+
+    let config = WorldGenConfig {
+        sea_level: 40,
+        land_noise_scale: (0.01, 0.02, 0.1),
+        biome_scale: 2.5,
+        ..WorldGenConfig::default()
+    };
+    let world = |seed, config| {
+        WorldData {
+            seed: seed,
+            config: config,
+            biomes: BiomeRegistry::builtin(),
+            plants: PlantRegistry::builtin(),
+        }
+    };
+    for &seed in &[0, 42, u64::max_value()] {
+        assert_eq!(decode_world(&encode_world(&world(seed, config))),
+                   Ok(world(seed, config)));
+    }
+    let eroded = WorldGenConfig {
+        water: WaterConfig { river_threshold: 100_000, ..config.water },
+        erosion: ErosionConfig::enabled(),
+        ..config
+    };
+    assert_eq!(decode_world(&encode_world(&world(3, eroded))), Ok(world(3, eroded)));
+    let thermal = WorldGenConfig {
+        erosion: ErosionConfig { hydraulic: None, ..eroded.erosion },
+        ..eroded
+    };
+    assert_eq!(decode_world(&encode_world(&world(3, thermal))), Ok(world(3, thermal)));
+
+    let data = encode_world(&world(7, config));
+    for len in 0..data.len() {
+        assert_eq!(decode_world(&data[..len]), Err(DecodeError::UnexpectedEnd));
+    }
+    assert_eq!(decode_world(&encode_chunk(&test_chunk())),
+               Err(DecodeError::InvalidMagic));
+
+    // The sea level (256) follows the header, the seed and the world height
+    let invalid = WorldGenConfig { sea_level: config.world_height, ..config };
+    let mut data = encode_world(&world(7, invalid));
+    match decode_world(&data) {
+        Err(DecodeError::InvalidConfig(_)) => {}
+        other => panic!("invalid config was accepted: {:?}", other),
+    }
+    data[9] = 0;
+    assert!(decode_world(&data).is_ok());
+}
+
+#[test]
+fn reject_invalid_biomes() {
+    use gen::world::biome::biomes;
+
+    let desert = BiomeRegistry::builtin().biome(biomes::DESERT).clone();
+    let mut data = Vec::new();
+    write_varint(&mut data, 2);
+    desert.encode(&mut data);
+    desert.encode(&mut data);
+    assert_eq!(BiomeRegistry::decode(&mut Decoder::new(&data)),
+               Err(DecodeError::InvalidBiome(biomes::DESERT.0)));
+
+    let renamed = Biome { id: biomes::SNOW, ..desert.clone() };
+    let mut data = Vec::new();
+    write_varint(&mut data, 2);
+    desert.encode(&mut data);
+    renamed.encode(&mut data);
+    assert_eq!(BiomeRegistry::decode(&mut Decoder::new(&data)),
+               Err(DecodeError::InvalidBiome(biomes::SNOW.0)));
+
+    let mut data = Vec::new();
+    Biome::new(biomes::STONE, "stone").encode(&mut data);
+    // Replace the last byte of the name by an invalid UTF-8 byte
+    data[6] = 0xff;
+    assert_eq!(Biome::decode(&mut Decoder::new(&data)),
+               Err(DecodeError::InvalidString));
+}
+
+#[test]
+fn reject_invalid_species() {
+    let species = PlantRegistry::builtin().iter().next().unwrap().clone();
+    let mut data = Vec::new();
+    write_varint(&mut data, 2);
+    species.encode(&mut data);
+    species.encode(&mut data);
+    assert_eq!(PlantRegistry::decode(&mut Decoder::new(&data)),
+               Err(DecodeError::InvalidSpecies(species.id.0)));
+
+    let mut data = Vec::new();
+    write_varint(&mut data, 1);
+    Species { variants: 0, ..species }.encode(&mut data);
+    assert_eq!(PlantRegistry::decode(&mut Decoder::new(&data)),
+               Err(DecodeError::InvalidSpecies(species.id.0)));
+
+    // All plants of the biomes have to be stored, too
+    let world = WorldData {
+        seed: 0,
+        config: WorldGenConfig::default(),
+        biomes: BiomeRegistry::builtin(),
+        plants: PlantRegistry::new(),
+    };
+    match decode_world(&encode_world(&world)) {
+        Err(DecodeError::InvalidBiome(_)) => {}
+        other => panic!("biomes with unknown plants were accepted: {:?}", other),
+    }
+}
@@ -1,40 +1,486 @@
-#[derive(Clone, Copy, Debug)]
-pub enum GroundMaterial {
-    Dirt,
-    Grass,
-    Stone,
-    Sand,
-    Snow,
-    JungleGrass,
-    Mulch,
-    Debug,
-}
-
-impl GroundMaterial {
-    // Returns color of Texture in RGB
-    pub fn get_color(&self) -> [f32; 3] {
+//! Ground materials and the registry describing their properties.
+//!
+//! Pillar sections only store the `MaterialId` of their material. Everything
+//! else (color, texture, physical properties) is looked up in a
+//! `MaterialRegistry`, which is loaded from TOML. The built-in materials are
+//! defined in `base/data/materials.toml` and are always available through
+//! `MaterialRegistry::builtin()`.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::slice;
+use toml::Value;
+use data::{self, byte, float, float_array};
+
+/// The built-in materials in TOML format.
+const BUILTIN_MATERIALS: &'static str = include_str!("../../data/materials.toml");
+
+/// The stable numeric id of a ground material. Ids are stored in saved
+/// chunks, so the id of a material must never change. The id `0` is reserved
+/// and never used by any material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialId(pub u8);
+
+impl MaterialId {
+    /// Returns whether the material is a fluid. Bodies move through fluids
+    /// and can't stand on them. Unlike the other properties of a material,
+    /// this is built into the game: water is the only fluid.
+    pub fn is_fluid(&self) -> bool {
+        *self == materials::WATER
+    }
+}
+
+/// The ids of the built-in materials.
+pub mod materials {
+    use super::MaterialId;
+
+    pub const GRASS: MaterialId = MaterialId(1);
+    pub const SAND: MaterialId = MaterialId(2);
+    pub const SNOW: MaterialId = MaterialId(3);
+    pub const DIRT: MaterialId = MaterialId(4);
+    pub const STONE: MaterialId = MaterialId(5);
+    pub const JUNGLE_GRASS: MaterialId = MaterialId(6);
+    pub const MULCH: MaterialId = MaterialId(7);
+    pub const DEBUG: MaterialId = MaterialId(8);
+    pub const COAL: MaterialId = MaterialId(9);
+    pub const IRON_ORE: MaterialId = MaterialId(10);
+    pub const WATER: MaterialId = MaterialId(11);
+    pub const WOOD: MaterialId = MaterialId(12);
+    pub const GLASS: MaterialId = MaterialId(13);
+}
+
+/// Parameters for the procedural generation of a material's texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureParams {
+    /// The texture set of the chunk shader which is used to draw the
+    /// material. Materials with the same slot share their textures.
+    pub slot: u8,
+    /// The frequencies (x and y) of the three noise octaves.
+    pub frequencies: [[f32; 2]; 3],
+    /// The weights of the second and third octave. Octaves with a weight of
+    /// at most `0.2` aren't sampled, the weight is added as a constant
+    /// instead.
+    pub weights: [f32; 2],
+    /// The height map is raised to this power.
+    pub exponent: f32,
+}
+
+impl Default for TextureParams {
+    fn default() -> Self {
+        TextureParams {
+            slot: 0,
+            frequencies: [[0.25, 0.25], [2.5, 2.5], [1.0, 1.0]],
+            weights: [0.5, 0.0],
+            exponent: 2.3,
+        }
+    }
+}
+
+/// The description of a ground material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub id: MaterialId,
+    /// A unique name, used to refer to the material in data files.
+    pub name: String,
+    /// The color in RGB, which is multiplied with the texture.
+    pub color: [f32; 3],
+    pub texture: TextureParams,
+    /// How hard it is to dig the material (dirt is `1.0`).
+    pub hardness: f32,
+    /// The friction coefficient when walking on the material.
+    pub friction: f32,
+    /// Whether the material can be seen through (e.g. water or ice).
+    pub transparent: bool,
+}
+
+impl Material {
+    /// Creates a material with the given id and name and default properties.
+    pub fn new(id: MaterialId, name: &str) -> Self {
+        Material {
+            id: id,
+            name: name.to_string(),
+            color: [1.0, 1.0, 1.0],
+            texture: TextureParams::default(),
+            hardness: 1.0,
+            friction: 1.0,
+            transparent: false,
+        }
+    }
+
+    /// The material which is used for unknown ids. It is colored bright red,
+    /// so it's easy to spot.
+    fn fallback() -> Self {
+        Material { color: [1.0, 0.0, 0.0], ..Material::new(MaterialId(0), "unknown") }
+    }
+}
+
+/// Maps material ids to their descriptions.
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry {
+    /// Indexed by the material id.
+    materials: Vec<Option<Material>>,
+    fallback: Material,
+}
+
+impl MaterialRegistry {
+    /// Creates a registry without any materials.
+    pub fn new() -> Self {
+        MaterialRegistry {
+            materials: Vec::new(),
+            fallback: Material::fallback(),
+        }
+    }
+
+    /// Creates a registry with all built-in materials.
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN_MATERIALS).expect("built-in materials are invalid")
+    }
+
+    /// Creates a registry with the materials described by the given TOML
+    /// data, see `merge_toml()`.
+    pub fn from_toml(src: &str) -> Result<Self, MaterialError> {
+        let mut registry = Self::new();
+        try!(registry.merge_toml(src));
+        Ok(registry)
+    }
+
+    /// Creates a registry with all built-in materials and merges the
+    /// materials from the given file into it, if it exists.
+    pub fn load(path: &Path) -> Result<Self, Box<Error>> {
+        let mut registry = Self::builtin();
+        if path.exists() {
+            let mut src = String::new();
+            try!(try!(File::open(path)).read_to_string(&mut src));
+            try!(registry.merge_toml(&src));
+        }
+        Ok(registry)
+    }
+
+    /// Adds the materials described by the given TOML data. Every material is
+    /// a `[[material]]` table with at least an `id` and a `name` (see
+    /// `base/data/materials.toml` for all fields).
+    ///
+    /// Materials with the id of an already registered material replace it.
+    /// In this case, all fields which aren't given keep their old value,
+    /// otherwise they get a default value. If there is an error, the
+    /// registry isn't changed at all.
+    pub fn merge_toml(&mut self, src: &str) -> Result<(), MaterialError> {
+        let root = try!(data::parse(src).map_err(MaterialError::Parse));
+        let entries = try!(data::entries(&root, "material")
+            .ok_or(MaterialError::invalid("", "material")));
+
+        let mut merged = self.clone();
+        for entry in entries {
+            let material = try!(merged.parse_material(entry));
+            try!(merged.register(material));
+        }
+        *self = merged;
+        Ok(())
+    }
+
+    /// Adds the material to the registry, replacing the material with the
+    /// same id, if there is one.
+    pub fn register(&mut self, material: Material) -> Result<(), MaterialError> {
+        if material.id.0 == 0 {
+            return Err(MaterialError::ReservedId(material.name));
+        }
+        if self.by_name(&material.name).map_or(false, |other| other.id != material.id) {
+            return Err(MaterialError::DuplicateName(material.name));
+        }
+
+        let index = material.id.0 as usize;
+        if self.materials.len() <= index {
+            self.materials.resize(index + 1, None);
+        }
+        self.materials[index] = Some(material);
+        Ok(())
+    }
+
+    /// Returns the material with the given id, if it is registered.
+    pub fn get(&self, id: MaterialId) -> Option<&Material> {
+        self.materials.get(id.0 as usize).and_then(|m| m.as_ref())
+    }
+
+    /// Returns the material with the given id. For unknown ids, a bright red
+    /// placeholder material is returned.
+    pub fn material(&self, id: MaterialId) -> &Material {
+        self.get(id).unwrap_or(&self.fallback)
+    }
+
+    /// Returns the material with the given name.
+    pub fn by_name(&self, name: &str) -> Option<&Material> {
+        self.iter().find(|m| m.name == name)
+    }
+
+    /// Returns an iterator over all materials, ordered by id.
+    pub fn iter(&self) -> Materials {
+        Materials { inner: self.materials.iter() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Parses a single `[[material]]` table. Fields which aren't given are
+    /// taken from the registered material with the same id, if there is one.
+    fn parse_material(&self, entry: &Value) -> Result<Material, MaterialError> {
+        let table = match entry.as_table() {
+            Some(table) => table,
+            None => return Err(MaterialError::invalid("", "material")),
+        };
+        let name = match table.get("name").and_then(|v| v.as_str()) {
+            Some(name) => name,
+            None => return Err(MaterialError::invalid("", "name")),
+        };
+        let id = match table.get("id").and_then(byte) {
+            Some(id) => MaterialId(id),
+            None => return Err(MaterialError::invalid(name, "id")),
+        };
+
+        let mut material = self.get(id).cloned().unwrap_or_else(|| Material::new(id, name));
+        material.name = name.to_string();
+
+        if let Some(v) = table.get("color") {
+            material.color = try!(float_array3(v).ok_or(MaterialError::invalid(name, "color")));
+        }
+        if let Some(v) = table.get("hardness") {
+            material.hardness = try!(float(v).ok_or(MaterialError::invalid(name, "hardness")));
+        }
+        if let Some(v) = table.get("friction") {
+            material.friction = try!(float(v).ok_or(MaterialError::invalid(name, "friction")));
+        }
+        if let Some(v) = table.get("transparent") {
+            material.transparent = try!(v.as_bool()
+                .ok_or(MaterialError::invalid(name, "transparent")));
+        }
+        if let Some(v) = table.get("texture") {
+            material.texture = try!(parse_texture(v, material.texture, name));
+        }
+
+        Ok(material)
+    }
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Iterator over all materials of a `MaterialRegistry`.
+pub struct Materials<'a> {
+    inner: slice::Iter<'a, Option<Material>>,
+}
+
+impl<'a> Iterator for Materials<'a> {
+    type Item = &'a Material;
+
+    fn next(&mut self) -> Option<&'a Material> {
+        while let Some(entry) = self.inner.next() {
+            if let Some(ref material) = *entry {
+                return Some(material);
+            }
+        }
+        None
+    }
+}
+
+/// The error type for loading materials.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaterialError {
+    /// The data isn't valid TOML. Contains the messages of the parser.
+    Parse(String),
+    /// A field of a material is missing or has the wrong type.
+    InvalidField {
+        material: String,
+        field: &'static str,
+    },
+    /// A material uses the reserved id `0`.
+    ReservedId(String),
+    /// Two materials with different ids have the same name.
+    DuplicateName(String),
+}
+
+impl MaterialError {
+    fn invalid(material: &str, field: &'static str) -> Self {
+        MaterialError::InvalidField {
+            material: material.to_string(),
+            field: field,
+        }
+    }
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            GroundMaterial::Dirt => [0.395, 0.26, 0.13],
-            GroundMaterial::Grass => [0.0, 0.5, 0.0],
-            GroundMaterial::Stone => [0.5, 0.5, 0.5],
-            GroundMaterial::Snow => [0.95, 0.95, 1.0],
-            GroundMaterial::Sand => [0.945, 0.86, 0.49],
-            GroundMaterial::JungleGrass => [0.1, 0.26, 0.04],
-            GroundMaterial::Mulch => [0.332, 0.219, 0.109],
-            GroundMaterial::Debug => [1.0, 0.0, 0.0],
+            MaterialError::Parse(ref msg) => write!(f, "{}: {}", self.description(), msg),
+            MaterialError::InvalidField { ref material, field } => {
+                write!(f, "{} '{}' of material '{}'", self.description(), field, material)
+            }
+            MaterialError::ReservedId(ref name) |
+            MaterialError::DuplicateName(ref name) => {
+                write!(f, "{} ('{}')", self.description(), name)
+            }
         }
     }
+}
 
-    pub fn get_id(&self) -> i32 {
+impl Error for MaterialError {
+    fn description(&self) -> &str {
         match *self {
-            GroundMaterial::Grass => 1,
-            GroundMaterial::Sand => 2,
-            GroundMaterial::Snow => 3,
-            GroundMaterial::Dirt => 4,
-            GroundMaterial::Stone => 5,
-            GroundMaterial::JungleGrass => 1,
-            GroundMaterial::Mulch => 7,
-            GroundMaterial::Debug => 8,
+            MaterialError::Parse(_) => "invalid TOML",
+            MaterialError::InvalidField { .. } => "missing or invalid field",
+            MaterialError::ReservedId(_) => "material uses the reserved id 0",
+            MaterialError::DuplicateName(_) => "material name is used twice",
         }
     }
 }
+
+fn parse_texture(value: &Value,
+                 mut texture: TextureParams,
+                 name: &str)
+                 -> Result<TextureParams, MaterialError> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Err(MaterialError::invalid(name, "texture")),
+    };
+
+    if let Some(v) = table.get("slot") {
+        texture.slot = try!(byte(v).ok_or(MaterialError::invalid(name, "texture.slot")));
+    }
+    if let Some(v) = table.get("frequencies") {
+        let invalid = || MaterialError::invalid(name, "texture.frequencies");
+        let octaves = try!(v.as_slice().ok_or_else(&invalid));
+        if octaves.len() != 3 {
+            return Err(invalid());
+        }
+        for (i, octave) in octaves.iter().enumerate() {
+            let pair = try!(float_array(octave, 2).ok_or_else(&invalid));
+            texture.frequencies[i] = [pair[0], pair[1]];
+        }
+    }
+    if let Some(v) = table.get("weights") {
+        let weights = try!(float_array(v, 2)
+            .ok_or(MaterialError::invalid(name, "texture.weights")));
+        texture.weights = [weights[0], weights[1]];
+    }
+    if let Some(v) = table.get("exponent") {
+        texture.exponent = try!(float(v).ok_or(MaterialError::invalid(name, "texture.exponent")));
+    }
+
+    Ok(texture)
+}
+
+fn float_array3(value: &Value) -> Option<[f32; 3]> {
+    float_array(value, 3).map(|v| [v[0], v[1], v[2]])
+}
+
+#[test]
+fn builtin_materials() {
+    let registry = MaterialRegistry::builtin();
+    assert_eq!(registry.len(), 13);
+
+    let ids = [materials::GRASS,
+               materials::SAND,
+               materials::SNOW,
+               materials::DIRT,
+               materials::STONE,
+               materials::JUNGLE_GRASS,
+               materials::MULCH,
+               materials::DEBUG,
+               materials::COAL,
+               materials::IRON_ORE,
+               materials::WATER,
+               materials::WOOD,
+               materials::GLASS];
+    let names = ["grass", "sand", "snow", "dirt", "stone", "jungle_grass", "mulch", "debug",
+                 "coal", "iron_ore", "water", "wood", "glass"];
+    for (&id, &name) in ids.iter().zip(names.iter()) {
+        assert_eq!(registry.get(id).unwrap().name, name);
+        assert_eq!(registry.by_name(name).unwrap().id, id);
+    }
+    assert_eq!(registry.iter().map(|m| m.id).collect::<Vec<_>>(), ids.to_vec());
+
+    let stone = registry.get(materials::STONE).unwrap();
+    assert_eq!(stone.color, [0.5, 0.5, 0.5]);
+    assert_eq!(stone.texture.slot, 5);
+    assert_eq!(stone.texture.frequencies, [[0.05, 0.05], [0.1, 0.1], [1.0, 1.0]]);
+    assert_eq!(registry.get(materials::JUNGLE_GRASS).unwrap().texture.slot, 1);
+    assert!(registry.get(materials::WATER).unwrap().transparent);
+    assert!(materials::WATER.is_fluid() && !materials::STONE.is_fluid());
+
+    assert_eq!(registry.get(MaterialId(200)), None);
+    assert_eq!(registry.material(MaterialId(200)).color, [1.0, 0.0, 0.0]);
+}
+
+#[test]
+fn merge_materials() {
+    let mut registry = MaterialRegistry::builtin();
+    registry.merge_toml(r#"
+        [[material]]
+        id = 5
+        name = "stone"
+        color = [0.2, 0.2, 0.25]
+
+        [[material]]
+        id = 42
+        name = "ice"
+        friction = 0.1
+        transparent = true
+
+        [material.texture]
+        slot = 3
+        exponent = 1
+    "#)
+        .unwrap();
+
+    // Fields which aren't given keep their value
+    let stone = registry.get(materials::STONE).unwrap();
+    assert_eq!(stone.color, [0.2, 0.2, 0.25]);
+    assert_eq!(stone.hardness, 4.0);
+    assert_eq!(stone.texture.slot, 5);
+
+    // or get a default value
+    let ice = registry.by_name("ice").unwrap();
+    assert_eq!(ice.id, MaterialId(42));
+    assert_eq!((ice.friction, ice.transparent, ice.hardness), (0.1, true, 1.0));
+    assert_eq!(ice.texture.slot, 3);
+    assert_eq!(ice.texture.exponent, 1.0);
+    assert_eq!(registry.len(), 14);
+}
+
+#[test]
+fn reject_invalid_materials() {
+    let mut registry = MaterialRegistry::builtin();
+    let before = registry.clone();
+
+    let invalid = |src: &str| MaterialRegistry::builtin().merge_toml(src).unwrap_err();
+    match invalid("[[material]\nid = 3") {
+        MaterialError::Parse(_) => {}
+        e => panic!("unexpected error {:?}", e),
+    }
+    assert_eq!(invalid("[[material]]\nid = 100"), MaterialError::invalid("", "name"));
+    assert_eq!(invalid("[[material]]\nid = 300\nname = \"a\""),
+               MaterialError::invalid("a", "id"));
+    assert_eq!(invalid("[[material]]\nid = 100\nname = \"a\"\ncolor = [1.0, 0.0]"),
+               MaterialError::invalid("a", "color"));
+    assert_eq!(invalid("[[material]]\nid = 100\nname = \"a\"\n[material.texture]\nweights = 1"),
+               MaterialError::invalid("a", "texture.weights"));
+    assert_eq!(invalid("[[material]]\nid = 0\nname = \"a\""),
+               MaterialError::ReservedId("a".into()));
+    assert_eq!(invalid("[[material]]\nid = 100\nname = \"sand\""),
+               MaterialError::DuplicateName("sand".into()));
+
+    // Nothing is changed if one of the materials is invalid
+    let src = "[[material]]\nid = 100\nname = \"a\"\n[[material]]\nid = 101\nname = \"a\"";
+    assert!(registry.merge_toml(src).is_err());
+    assert_eq!(registry.len(), before.len());
+    assert_eq!(registry.get(MaterialId(100)), None);
+}
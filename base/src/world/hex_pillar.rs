@@ -1,19 +1,21 @@
-use super::{GroundMaterial, HeightType};
-use gen::world::biome::Biome;
+use super::{HeightType, MaterialId};
+use std::cmp::{max, min};
+use gen::plant::registry::PlantId;
+use gen::world::biome::BiomeId;
 
 /// Represents one pillar of hexgonal shape in the game world.
 ///
 /// A pillar consists of multiple sections (each of which has a material) and
 /// optionally props (plants, objects, ...).
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, PartialEq)]
 pub struct HexPillar {
     sections: Vec<PillarSection>,
     props: Vec<Prop>,
-    biome: Biome,
+    biome: BiomeId,
 }
 
 impl HexPillar {
-    pub fn new(sections: Vec<PillarSection>, props: Vec<Prop>, biome: Biome) -> Self {
+    pub fn new(sections: Vec<PillarSection>, props: Vec<Prop>, biome: BiomeId) -> Self {
         HexPillar {
             sections: sections,
             props: props,
@@ -36,22 +38,124 @@ impl HexPillar {
         &self.props
     }
 
-    pub fn biome(&self) -> &Biome {
-        &self.biome
+    /// Returns the id of the biome this pillar belongs to.
+    pub fn biome(&self) -> BiomeId {
+        self.biome
+    }
+
+    /// Removes the height range `bottom..top` from this pillar. Sections which
+    /// only partially overlap this range are shortened or split into two
+    /// sections. Returns whether the pillar changed.
+    pub fn carve(&mut self, bottom: HeightType, top: HeightType) -> bool {
+        let mut changed = false;
+        let mut sections = Vec::with_capacity(self.sections.len() + 1);
+        for section in self.sections.drain(..) {
+            if section.top <= bottom || section.bottom >= top {
+                sections.push(section);
+                continue;
+            }
+
+            changed = true;
+            if section.bottom < bottom {
+                sections.push(PillarSection::new(section.ground, section.bottom, bottom));
+            }
+            if section.top > top {
+                sections.push(PillarSection::new(section.ground, top, section.top));
+            }
+        }
+        self.sections = sections;
+
+        changed
+    }
+
+    /// Fills the height range `bottom..top` with the given material. Existing
+    /// sections in this range are replaced and the new section is merged with
+    /// touching sections of the same material. Returns whether the pillar
+    /// changed.
+    pub fn fill(&mut self, ground: MaterialId, bottom: HeightType, top: HeightType) -> bool {
+        let old = self.sections.clone();
+        self.carve(bottom, top);
+        self.sections.push(PillarSection::new(ground, bottom, top));
+        self.normalize_sections();
+
+        self.sections != old
+    }
+
+    /// Changes the material of everything solid in the height range
+    /// `bottom..top` without adding or removing any solid ground. Returns
+    /// whether the pillar changed.
+    pub fn set_material(&mut self,
+                        ground: MaterialId,
+                        bottom: HeightType,
+                        top: HeightType)
+                        -> bool {
+        let old = self.sections.clone();
+        let mut sections = Vec::with_capacity(self.sections.len() + 2);
+        for section in self.sections.drain(..) {
+            if section.top <= bottom || section.bottom >= top {
+                sections.push(section);
+                continue;
+            }
+
+            let (inner_bottom, inner_top) = (max(section.bottom, bottom), min(section.top, top));
+            if section.bottom < inner_bottom {
+                sections.push(PillarSection::new(section.ground, section.bottom, inner_bottom));
+            }
+            sections.push(PillarSection::new(ground, inner_bottom, inner_top));
+            if section.top > inner_top {
+                sections.push(PillarSection::new(section.ground, inner_top, section.top));
+            }
+        }
+        self.sections = sections;
+        self.normalize_sections();
+
+        self.sections != old
+    }
+
+    /// Adds a prop to this pillar.
+    pub fn add_prop(&mut self, prop: Prop) {
+        self.props.push(prop);
+    }
+
+    /// Removes the prop with the given index (into `props()`) and returns it.
+    pub fn remove_prop(&mut self, index: usize) -> Option<Prop> {
+        if index < self.props.len() {
+            Some(self.props.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Sorts the sections from bottom to top and merges touching sections of
+    /// the same material.
+    fn normalize_sections(&mut self) {
+        self.sections.sort_by_key(|section| section.bottom);
+
+        let mut sections: Vec<PillarSection> = Vec::with_capacity(self.sections.len());
+        for section in self.sections.drain(..) {
+            if let Some(last) = sections.last_mut() {
+                if last.top == section.bottom && last.ground == section.ground {
+                    last.top = section.top;
+                    continue;
+                }
+            }
+            sections.push(section);
+        }
+        self.sections = sections;
     }
 }
 
 /// Represents one section of a hex pillar.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PillarSection {
-    pub ground: GroundMaterial,
+    pub ground: MaterialId,
     pub bottom: HeightType,
     pub top: HeightType,
 }
 
 impl PillarSection {
     /// Creates a new pillar section and asserts `bottom < top`.
-    pub fn new(ground: GroundMaterial, bottom: HeightType, top: HeightType) -> Self {
+    pub fn new(ground: MaterialId, bottom: HeightType, top: HeightType) -> Self {
         assert!(bottom < top, "attempt to create an invalid pillar section");
 
         PillarSection {
@@ -63,10 +167,92 @@ impl PillarSection {
 }
 
 /// A prop in a hex pillar
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Prop {
     /// The height/baseline at which the prop starts
     pub baseline: HeightType,
-    /// index in the plant_list vector
-    pub plant_index: usize,
+    /// The plant growing here, see `ChunkProvider::get_plant_list()`
+    pub plant: PlantId,
+}
+
+#[cfg(test)]
+fn test_pillar(sections: &[(MaterialId, u16, u16)]) -> HexPillar {
+    use gen::world::biome::biomes;
+
+    let sections = sections.iter()
+        .map(|&(ground, bottom, top)| {
+            PillarSection::new(ground, HeightType(bottom), HeightType(top))
+        })
+        .collect();
+    HexPillar::new(sections, vec![], biomes::DEBUG)
+}
+
+#[test]
+fn carve_splits_sections() {
+    use world::materials::{DIRT, STONE};
+
+    let mut pillar = test_pillar(&[(STONE, 0, 10), (DIRT, 15, 20)]);
+    assert!(!pillar.carve(HeightType(10), HeightType(15)));
+    assert!(pillar.carve(HeightType(4), HeightType(6)));
+    assert_eq!(pillar, test_pillar(&[(STONE, 0, 4), (STONE, 6, 10), (DIRT, 15, 20)]));
+
+    // Remove one section completely and shorten the neighbouring ones
+    assert!(pillar.carve(HeightType(8), HeightType(18)));
+    assert_eq!(pillar, test_pillar(&[(STONE, 0, 4), (STONE, 6, 8), (DIRT, 18, 20)]));
+
+    assert!(pillar.carve(HeightType(0), HeightType(100)));
+    assert!(pillar.sections().is_empty());
+}
+
+#[test]
+fn fill_replaces_and_merges_sections() {
+    use world::materials::{DIRT, SAND, STONE};
+
+    let mut pillar = test_pillar(&[(STONE, 0, 4), (STONE, 6, 10), (DIRT, 15, 20)]);
+    assert!(pillar.fill(STONE, HeightType(4), HeightType(6)));
+    assert_eq!(pillar, test_pillar(&[(STONE, 0, 10), (DIRT, 15, 20)]));
+    assert!(!pillar.fill(STONE, HeightType(2), HeightType(8)));
+
+    assert!(pillar.fill(SAND, HeightType(8), HeightType(17)));
+    assert_eq!(pillar, test_pillar(&[(STONE, 0, 8), (SAND, 8, 17), (DIRT, 17, 20)]));
+
+    assert!(pillar.fill(DIRT, HeightType(25), HeightType(30)));
+    assert_eq!(pillar,
+               test_pillar(&[(STONE, 0, 8), (SAND, 8, 17), (DIRT, 17, 20), (DIRT, 25, 30)]));
+}
+
+#[test]
+fn set_material_keeps_shape() {
+    use world::materials::{DIRT, SAND, STONE};
+
+    let mut pillar = test_pillar(&[(STONE, 0, 10), (DIRT, 15, 20)]);
+    assert!(!pillar.set_material(SAND, HeightType(10), HeightType(15)));
+    assert!(pillar.set_material(SAND, HeightType(8), HeightType(17)));
+    assert_eq!(pillar,
+               test_pillar(&[(STONE, 0, 8), (SAND, 8, 10), (SAND, 15, 17), (DIRT, 17, 20)]));
+
+    assert!(pillar.set_material(STONE, HeightType(8), HeightType(10)));
+    assert_eq!(pillar,
+               test_pillar(&[(STONE, 0, 10), (SAND, 15, 17), (DIRT, 17, 20)]));
+    assert!(!pillar.set_material(STONE, HeightType(0), HeightType(10)));
+}
+
+#[test]
+fn add_and_remove_props() {
+    use gen::plant::registry::SpeciesId;
+
+    let mut pillar = HexPillar::default();
+    let prop = Prop {
+        baseline: HeightType(3),
+        plant: PlantId {
+            species: SpeciesId(2),
+            variant: 1,
+        },
+    };
+
+    pillar.add_prop(prop.clone());
+    assert_eq!(pillar.props(), &[prop.clone()]);
+    assert_eq!(pillar.remove_prop(1), None);
+    assert_eq!(pillar.remove_prop(0), Some(prop));
+    assert!(pillar.props().is_empty());
 }
@@ -5,14 +5,23 @@ use math;
 
 pub mod ground;
 pub mod chunk;
+pub mod encoding;
+mod edit;
 mod hex_pillar;
+mod path;
 mod provider;
+mod ray;
+mod save_file;
 mod world;
 
 pub use self::chunk::Chunk;
+pub use self::edit::*;
 pub use self::ground::*;
 pub use self::hex_pillar::*;
+pub use self::path::*;
 pub use self::provider::*;
+pub use self::ray::*;
+pub use self::save_file::*;
 pub use self::world::World;
 
 /// Outer radius of the hexagons (from center to corner)
@@ -47,8 +56,57 @@ pub struct PillarIndex(pub math::AxialPoint);
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ChunkIndex(pub math::AxialPoint);
 
+impl PillarIndex {
+    /// Returns the index of the chunk which contains this pillar.
+    pub fn chunk(&self) -> ChunkIndex {
+        self.to_chunk_local().0
+    }
+
+    /// Returns the position of this pillar relative to the origin of the
+    /// chunk containing it. Both components are in `0..CHUNK_SIZE`, so the
+    /// result can be used to index into a `Chunk`.
+    pub fn local_offset(&self) -> math::AxialPoint {
+        self.to_chunk_local().1
+    }
+
+    /// Splits this index into the index of the containing chunk and the
+    /// position within that chunk (see `local_offset()`).
+    ///
+    /// This uses floor division, so pillars with negative coordinates are
+    /// correctly mapped to chunks with negative indices. The inverse is
+    /// `ChunkIndex::pillar()`.
+    pub fn to_chunk_local(&self) -> (ChunkIndex, math::AxialPoint) {
+        let size = CHUNK_SIZE as PillarIndexComponent;
+        let chunk = math::AxialPoint::new(math::div_floor(self.0.q, size),
+                                          math::div_floor(self.0.r, size));
+        let local = math::AxialPoint::new(math::mod_floor(self.0.q, size),
+                                          math::mod_floor(self.0.r, size));
+        (ChunkIndex(chunk), local)
+    }
+}
+
+impl ChunkIndex {
+    /// Returns the index of the chunk which contains the given pillar.
+    pub fn from_pillar(pillar: PillarIndex) -> Self {
+        pillar.chunk()
+    }
+
+    /// Returns the index of the first pillar of this chunk (the one with the
+    /// local offset `(0, 0)`).
+    pub fn origin(&self) -> PillarIndex {
+        PillarIndex(self.0 * CHUNK_SIZE as PillarIndexComponent)
+    }
+
+    /// Returns the index of the pillar at the given position within this
+    /// chunk. This is the inverse of `PillarIndex::to_chunk_local()`.
+    pub fn pillar(&self, local: math::AxialPoint) -> PillarIndex {
+        use math::EuclideanSpace;
+        PillarIndex(self.origin().0 + local.to_vec())
+    }
+}
+
 /// Represents a discretized height.
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct HeightType(pub u16);
 
 impl HeightType {
@@ -78,3 +136,98 @@ impl fmt::Debug for HeightType {
         write!(f, "[{} -> {}]", self.0, self.to_real())
     }
 }
+
+/// Creates a world of the four chunks around the origin for tests. Every
+/// pillar consists of the `floor` sections, except for the given pillars
+/// which consist of their own sections. All sections are made of `ground`.
+#[cfg(test)]
+pub fn test_world(ground: MaterialId,
+                  floor: &[(u16, u16)],
+                  pillars: &[((i32, i32), &[(u16, u16)])])
+                  -> World {
+    use gen::world::biome::biomes;
+    use math::AxialPoint;
+
+    let pillar = |sections: &[(u16, u16)]| {
+        let sections = sections.iter()
+            .map(|&(bottom, top)| PillarSection::new(ground, HeightType(bottom), HeightType(top)))
+            .collect();
+        HexPillar::new(sections, vec![], biomes::DEBUG)
+    };
+
+    let mut world = World::empty();
+    for &(q, r) in &[(0, 0), (-1, 0), (0, -1), (-1, -1)] {
+        let index = ChunkIndex(AxialPoint::new(q, r));
+        world.add_chunk(index, Chunk::with_pillars(index, |_| pillar(floor))).unwrap();
+    }
+    for &((q, r), sections) in pillars {
+        *world.pillar_at_mut(PillarIndex(AxialPoint::new(q, r))).unwrap() = pillar(sections);
+    }
+    world
+}
+
+#[test]
+fn pillar_chunk_conversion() {
+    use math::AxialPoint;
+
+    let size = CHUNK_SIZE as PillarIndexComponent;
+    let cases = [((0, 0), (0, 0), (0, 0)),
+                 ((15, 15), (0, 0), (15, 15)),
+                 ((16, 0), (1, 0), (0, 0)),
+                 ((-1, 0), (-1, 0), (15, 0)),
+                 ((0, -1), (0, -1), (0, 15)),
+                 ((-16, -17), (-1, -2), (0, 15)),
+                 ((-17, 33), (-2, 2), (15, 1)),
+                 ((40, -40), (2, -3), (8, 8))];
+
+    for &((q, r), (cq, cr), (lq, lr)) in &cases {
+        let pillar = PillarIndex(AxialPoint::new(q, r));
+        let (chunk, local) = pillar.to_chunk_local();
+        assert_eq!(chunk, ChunkIndex(AxialPoint::new(cq, cr)));
+        assert_eq!(local, AxialPoint::new(lq, lr));
+        assert_eq!(pillar.chunk(), chunk);
+        assert_eq!(pillar.local_offset(), local);
+        assert_eq!(ChunkIndex::from_pillar(pillar), chunk);
+    }
+
+    assert_eq!(ChunkIndex(AxialPoint::new(-2, 3)).origin(),
+               PillarIndex(AxialPoint::new(-2 * size, 3 * size)));
+}
+
+#[test]
+fn pillar_chunk_round_trip() {
+    use math::AxialPoint;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    let size = CHUNK_SIZE as PillarIndexComponent;
+    let check = |q, r| {
+        let pillar = PillarIndex(AxialPoint::new(q, r));
+        let (chunk, local) = pillar.to_chunk_local();
+        assert!(local.q >= 0 && local.q < size && local.r >= 0 && local.r < size);
+        assert_eq!(chunk.pillar(local), pillar);
+    };
+
+    // Every pillar of the chunks around the origin, in all four quadrants
+    for q in -3 * size..3 * size {
+        for r in -3 * size..3 * size {
+            check(q, r);
+        }
+    }
+
+    // Random pillars far away from the origin
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    for _ in 0..10_000 {
+        check(rng.gen_range(-1 << 24, 1 << 24), rng.gen_range(-1 << 24, 1 << 24));
+    }
+
+    // And the other way around: every local position of chunks in all quadrants
+    for &(cq, cr) in &[(0, 0), (3, 5), (-3, 5), (-3, -5), (3, -5)] {
+        let chunk = ChunkIndex(AxialPoint::new(cq, cr));
+        for q in 0..size {
+            for r in 0..size {
+                let local = AxialPoint::new(q, r);
+                assert_eq!(chunk.pillar(local).to_chunk_local(), (chunk, local));
+            }
+        }
+    }
+}
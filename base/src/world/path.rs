@@ -0,0 +1,411 @@
+//! Finding paths over the walkable surfaces of a `World`.
+//!
+//! Every top of a pillar section with enough free space above it is a node
+//! of the search graph. Nodes are connected if the surface can be reached by
+//! stepping up or dropping down to a neighbouring pillar or by jumping over
+//! a few pillars in a straight line. The search itself is a plain A* with the
+//! straight distance to the goal as heuristic.
+//!
+//! Like in `physics`, fluid sections are ignored: they can't be stood on, but
+//! they don't block the way either.
+
+use std::cmp::{self, Ordering};
+use std::collections::{BinaryHeap, HashMap};
+use std::f32;
+use math::{AxialType, HEX_DIRECTIONS, InnerSpace, Point3f};
+use super::{HeightType, PillarIndex, World};
+#[cfg(test)]
+use super::{materials, test_world};
+
+/// Jumps are more exhausting than walking the same distance.
+const JUMP_COST_FACTOR: f32 = 1.5;
+
+/// A point on top of a pillar section which can be stood on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SurfacePoint {
+    pub pillar: PillarIndex,
+    /// The height of the top of the section.
+    pub height: HeightType,
+}
+
+impl SurfacePoint {
+    pub fn new(pillar: PillarIndex, height: HeightType) -> Self {
+        SurfacePoint {
+            pillar: pillar,
+            height: height,
+        }
+    }
+
+    /// Returns the center of the surface in world coordinates.
+    pub fn to_real(&self) -> Point3f {
+        let center = self.pillar.0.to_real();
+        Point3f::new(center.x, center.y, self.height.to_real())
+    }
+}
+
+/// The movement abilities used by `find_path()`. All heights are given in
+/// world units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathConfig {
+    /// The free space needed above a surface to stand on it.
+    pub headroom: f32,
+    /// The maximum height which can be stepped up to a neighbouring pillar.
+    pub max_step_up: f32,
+    /// The maximum height which can be dropped down to a neighbouring pillar.
+    pub max_drop_down: f32,
+    /// The maximum number of pillars which can be jumped over in a straight
+    /// line. These pillars need enough free space at the height of the jump.
+    /// `0` disables jumping.
+    pub max_jump: AxialType,
+    /// The maximum number of surfaces to visit before giving up.
+    pub max_visited: usize,
+}
+
+impl Default for PathConfig {
+    fn default() -> Self {
+        PathConfig {
+            headroom: 2.0,
+            max_step_up: 1.0,
+            max_drop_down: 3.0,
+            max_jump: 2,
+            max_visited: 10_000,
+        }
+    }
+}
+
+/// Returns all surfaces of the pillar which can be stood on, from bottom to
+/// top. Pillars in chunks which aren't loaded don't have any surfaces.
+pub fn walkable_surfaces(world: &World,
+                         pos: PillarIndex,
+                         config: &PathConfig)
+                         -> Vec<SurfacePoint> {
+    surfaces(world, pos, config).into_iter().map(|(surface, _)| surface).collect()
+}
+
+/// Searches the shortest path from `start` to `goal`. The returned path
+/// contains both surfaces and every surface in between.
+///
+/// Returns `None` if either surface can't be stood on, if there is no path
+/// or if more than `config.max_visited` surfaces would need to be visited to
+/// find it.
+pub fn find_path(world: &World,
+                 start: SurfacePoint,
+                 goal: SurfacePoint,
+                 config: &PathConfig)
+                 -> Option<Vec<SurfacePoint>> {
+    if ceiling_of(world, start, config).is_none() || ceiling_of(world, goal, config).is_none() {
+        return None;
+    }
+    let goal_real = goal.to_real();
+
+    // The cost of the best known path to every discovered surface and the
+    // previous surface on that path
+    let mut known: HashMap<SurfacePoint, (f32, Option<SurfacePoint>)> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    known.insert(start, (0.0, None));
+    open.push(OpenNode {
+        estimate: (start.to_real() - goal_real).magnitude(),
+        cost: 0.0,
+        surface: start,
+    });
+
+    let mut visited = 0;
+    while let Some(node) = open.pop() {
+        if node.surface == goal {
+            return Some(reconstruct_path(&known, goal));
+        }
+        // A cheaper path to this surface was found after it was queued
+        if node.cost > known[&node.surface].0 {
+            continue;
+        }
+        visited += 1;
+        if visited > config.max_visited {
+            return None;
+        }
+
+        for (next, step_cost) in reachable_from(world, node.surface, config) {
+            let cost = node.cost + step_cost;
+            if known.get(&next).map_or(false, |&(known_cost, _)| known_cost <= cost) {
+                continue;
+            }
+            known.insert(next, (cost, Some(node.surface)));
+            open.push(OpenNode {
+                estimate: cost + (next.to_real() - goal_real).magnitude(),
+                cost: cost,
+                surface: next,
+            });
+        }
+    }
+
+    None
+}
+
+/// An entry of the open list, ordered so that the `BinaryHeap` returns the
+/// lowest estimate first.
+struct OpenNode {
+    estimate: f32,
+    cost: f32,
+    surface: SurfacePoint,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &OpenNode) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &OpenNode) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &OpenNode) -> Ordering {
+        other.estimate.partial_cmp(&self.estimate).unwrap_or(Ordering::Equal)
+    }
+}
+
+fn reconstruct_path(known: &HashMap<SurfacePoint, (f32, Option<SurfacePoint>)>,
+                    goal: SurfacePoint)
+                    -> Vec<SurfacePoint> {
+    let mut path = vec![goal];
+    let mut current = goal;
+    loop {
+        match known[&current].1 {
+            Some(prev) => {
+                path.push(prev);
+                current = prev;
+            }
+            None => break,
+        }
+    }
+    path.reverse();
+    path
+}
+
+/// Returns all walkable surfaces of the pillar together with the height of
+/// the ceiling above them (infinite for the topmost section).
+fn surfaces(world: &World, pos: PillarIndex, config: &PathConfig) -> Vec<(SurfacePoint, f32)> {
+    let sections: Vec<_> = match world.pillar_at(pos) {
+        Some(pillar) => {
+            pillar.sections().iter().filter(|section| !section.ground.is_fluid()).collect()
+        }
+        None => return Vec::new(),
+    };
+
+    sections.iter()
+        .enumerate()
+        .map(|(i, section)| {
+            let ceiling = sections.get(i + 1)
+                .map_or(f32::INFINITY, |above| above.bottom.to_real());
+            (SurfacePoint::new(pos, section.top), ceiling)
+        })
+        .filter(|&(surface, ceiling)| ceiling - surface.height.to_real() >= config.headroom)
+        .collect()
+}
+
+/// Returns the ceiling above the given surface, if it can be stood on.
+fn ceiling_of(world: &World, surface: SurfacePoint, config: &PathConfig) -> Option<f32> {
+    surfaces(world, surface.pillar, config)
+        .into_iter()
+        .find(|&(other, _)| other == surface)
+        .map(|(_, ceiling)| ceiling)
+}
+
+/// Returns all surfaces which can be reached from `from` with a single step
+/// or jump, together with the costs of getting there.
+fn reachable_from(world: &World,
+                  from: SurfacePoint,
+                  config: &PathConfig)
+                  -> Vec<(SurfacePoint, f32)> {
+    let mut out = Vec::new();
+    let from_ceiling = match ceiling_of(world, from, config) {
+        Some(ceiling) => ceiling,
+        None => return out,
+    };
+    let from_height = from.height.to_real();
+
+    // Both the start and the target need enough free space above the
+    // higher of both surfaces
+    let can_move_to = |height: f32, ceiling: f32| {
+        let top = from_height.max(height) + config.headroom;
+        height - from_height <= config.max_step_up &&
+        from_height - height <= config.max_drop_down && from_ceiling >= top && ceiling >= top
+    };
+
+    for &dir in &HEX_DIRECTIONS {
+        for gap in 0..cmp::max(config.max_jump, 0) + 1 {
+            let target = PillarIndex(from.pillar.0 + dir.to_vector() * (gap + 1));
+            for (surface, ceiling) in surfaces(world, target, config) {
+                let height = surface.height.to_real();
+                if !can_move_to(height, ceiling) {
+                    continue;
+                }
+
+                // All pillars jumped over need to be free at the jump height
+                let bottom = from_height.max(height);
+                let free = (1..gap + 1).all(|i| {
+                    let pos = PillarIndex(from.pillar.0 + dir.to_vector() * i);
+                    is_free(world, pos, bottom, bottom + config.headroom)
+                });
+                if !free {
+                    continue;
+                }
+
+                let cost = (surface.to_real() - from.to_real()).magnitude();
+                let cost = if gap == 0 { cost } else { cost * JUMP_COST_FACTOR };
+                out.push((surface, cost));
+            }
+        }
+    }
+
+    out
+}
+
+/// Returns whether the pillar is loaded and doesn't have any solid parts
+/// between `bottom` and `top`.
+fn is_free(world: &World, pos: PillarIndex, bottom: f32, top: f32) -> bool {
+    match world.pillar_at(pos) {
+        Some(pillar) => {
+            pillar.sections().iter().all(|section| {
+                section.ground.is_fluid() || section.top.to_real() <= bottom ||
+                section.bottom.to_real() >= top
+            })
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+fn surface(q: i32, r: i32, height: u16) -> SurfacePoint {
+    use math::AxialPoint;
+    SurfacePoint::new(PillarIndex(AxialPoint::new(q, r)), HeightType(height))
+}
+
+#[test]
+fn surfaces_need_headroom() {
+    use math::AxialPoint;
+
+    let world = test_world(materials::STONE, &[(0, 4)], &[((1, 1), &[(0, 4), (6, 8), (12, 14)])]);
+    let config = PathConfig::default();
+
+    let pos = PillarIndex(AxialPoint::new(1, 1));
+    assert_eq!(walkable_surfaces(&world, pos, &config),
+               vec![surface(1, 1, 8), surface(1, 1, 14)]);
+    assert_eq!(walkable_surfaces(&world, PillarIndex(AxialPoint::new(100, 0)), &config),
+               vec![]);
+
+    // Surfaces which can't be stood on can't be start or goal of a path
+    assert_eq!(find_path(&world, surface(0, 0, 4), surface(1, 1, 4), &config), None);
+    assert_eq!(find_path(&world, surface(1, 1, 4), surface(0, 0, 4), &config), None);
+}
+
+#[test]
+fn walk_on_flat_ground() {
+    let world = test_world(materials::STONE, &[(0, 4)], &[]);
+    let config = PathConfig::default();
+
+    let path = find_path(&world, surface(0, 0, 4), surface(5, 0, 4), &config).unwrap();
+    assert_eq!(path.len(), 6);
+    assert_eq!(path[0], surface(0, 0, 4));
+    assert_eq!(path[5], surface(5, 0, 4));
+    for pair in path.windows(2) {
+        assert_eq!(pair[0].pillar.0.hex_distance(pair[1].pillar.0), 1);
+    }
+
+    assert_eq!(find_path(&world, surface(2, 3, 4), surface(2, 3, 4), &config),
+               Some(vec![surface(2, 3, 4)]));
+}
+
+#[test]
+fn climb_stairs() {
+    use math::{AxialPoint, HexRange};
+
+    // A plateau two units above the floor, which can only be climbed using
+    // the stair at (4, 0)
+    let (plateau, stair): (&[(u16, u16)], &[(u16, u16)]) = (&[(0, 8)], &[(0, 6)]);
+    let mut pillars: Vec<_> = HexRange::new(AxialPoint::new(7, 0), 2)
+        .iter()
+        .map(|pos| ((pos.q, pos.r), plateau))
+        .collect();
+    let without_stair = test_world(materials::STONE, &[(0, 4)], &pillars);
+    pillars.push(((4, 0), stair));
+    let with_stair = test_world(materials::STONE, &[(0, 4)], &pillars);
+
+    let config = PathConfig { max_jump: 0, ..PathConfig::default() };
+    let start = surface(0, 0, 4);
+    let goal = surface(7, 0, 8);
+    assert_eq!(find_path(&without_stair, start, goal, &config), None);
+
+    let path = find_path(&with_stair, start, goal, &config).unwrap();
+    assert!(path.contains(&surface(4, 0, 6)));
+    for pair in path.windows(2) {
+        let diff = pair[1].height.to_real() - pair[0].height.to_real();
+        assert!(diff <= config.max_step_up && -diff <= config.max_drop_down);
+    }
+
+    // Going down is possible with a large enough drop
+    let config = PathConfig { max_drop_down: 2.0, ..config };
+    assert!(find_path(&without_stair, goal, start, &config).is_some());
+    let config = PathConfig { max_drop_down: 1.5, ..config };
+    assert_eq!(find_path(&without_stair, goal, start, &config), None);
+}
+
+#[test]
+fn jump_over_trenches() {
+    // A trench across the whole loaded area, which can be entered but not
+    // left again
+    let deep: &[(u16, u16)] = &[(0, 1)];
+    let trench: Vec<_> = (-16..16).map(|r| ((3, r), deep)).collect();
+    let world = test_world(materials::STONE, &[(0, 4)], &trench);
+    let start = surface(0, 0, 4);
+    let goal = surface(6, 0, 4);
+
+    let config = PathConfig { max_jump: 0, ..PathConfig::default() };
+    assert_eq!(find_path(&world, start, goal, &config), None);
+
+    let config = PathConfig { max_jump: 1, ..config };
+    let path = find_path(&world, start, goal, &config).unwrap();
+    let jumps = path.windows(2)
+        .filter(|pair| pair[0].pillar.0.hex_distance(pair[1].pillar.0) == 2)
+        .count();
+    assert_eq!(jumps, 1);
+    assert!(path.iter().all(|s| s.pillar.0.q != 3));
+
+    // Jumps are blocked by pillars sticking out at the jump height
+    let blocked: &[(u16, u16)] = &[(0, 1), (5, 20)];
+    let wall: Vec<_> = (-16..16).map(|r| ((3, r), blocked)).collect();
+    let world = test_world(materials::STONE, &[(0, 4)], &wall);
+    assert_eq!(find_path(&world, start, goal, &config), None);
+}
+
+#[test]
+fn wade_through_water() {
+    use math::AxialPoint;
+    use super::PillarSection;
+
+    // A shallow pond between the start and the goal, the pillar in the
+    // middle of it is deeper
+    let mut world = test_world(materials::STONE, &[(0, 4)], &[((2, 0), &[(0, 2)])]);
+    for q in 1..4 {
+        let pillar = world.pillar_at_mut(PillarIndex(AxialPoint::new(q, 0))).unwrap();
+        let bottom = pillar.sections()[0].top;
+        pillar.sections_mut()
+            .push(PillarSection::new(materials::WATER, bottom, HeightType(bottom.0 + 6)));
+    }
+    let config = PathConfig::default();
+
+    // Only the ground of the pond can be stood on
+    assert_eq!(walkable_surfaces(&world, PillarIndex(AxialPoint::new(2, 0)), &config),
+               vec![surface(2, 0, 2)]);
+    let path = find_path(&world, surface(0, 0, 4), surface(4, 0, 4), &config).unwrap();
+    assert_eq!(path,
+               vec![surface(0, 0, 4),
+                    surface(1, 0, 4),
+                    surface(2, 0, 2),
+                    surface(3, 0, 4),
+                    surface(4, 0, 4)]);
+}
@@ -1,5 +1,5 @@
 use super::{Chunk, ChunkIndex};
-use prop::plant::Plant;
+use gen::plant::registry::PlantList;
 
 /// A type that can load a game world, specifically single chunks of it. This
 /// could mean loading a saved world from a file, generating a world
@@ -21,8 +21,8 @@ pub trait ChunkProvider: Send {
     /// loaded. This function is expected to return quickly.
     fn is_chunk_loadable(&self, pos: ChunkIndex) -> bool;
 
-    /// Returns a Vector of all specific plants to be rendered in the world.
-    fn get_plant_list(&self) -> Vec<Plant>;
+    /// Returns all plants the props of the world refer to.
+    fn get_plant_list(&self) -> PlantList;
 }
 
 /// A dummy provider that always fails to provide a chunk.
@@ -38,8 +38,8 @@ impl ChunkProvider for NullProvider {
         false
     }
 
-    fn get_plant_list(&self) -> Vec<Plant> {
-        Vec::new()
+    fn get_plant_list(&self) -> PlantList {
+        PlantList::default()
     }
 }
 
@@ -52,20 +52,40 @@ pub struct FallbackProvider<P, F> {
     fallback: F,
 }
 
+impl<P, F> FallbackProvider<P, F> {
+    /// Creates a provider which asks `primary` first and `fallback` for all
+    /// chunks `primary` can't load.
+    pub fn new(primary: P, fallback: F) -> Self {
+        FallbackProvider {
+            primary: primary,
+            fallback: fallback,
+        }
+    }
+}
+
 impl<P: ChunkProvider, F: ChunkProvider> ChunkProvider for FallbackProvider<P, F> {
     fn load_chunk(&self, pos: ChunkIndex) -> Option<Chunk> {
         if self.primary.is_chunk_loadable(pos) {
-            self.primary.load_chunk(pos)
-        } else {
-            self.fallback.load_chunk(pos)
+            if let Some(chunk) = self.primary.load_chunk(pos) {
+                return Some(chunk);
+            }
         }
+
+        self.fallback.load_chunk(pos)
     }
 
     fn is_chunk_loadable(&self, pos: ChunkIndex) -> bool {
         self.primary.is_chunk_loadable(pos) || self.fallback.is_chunk_loadable(pos)
     }
 
-    fn get_plant_list(&self) -> Vec<Plant> {
-        self.primary.get_plant_list()
+    /// Returns the plant list of the primary provider, unless it doesn't know
+    /// any plants (like a `SaveFileProvider`).
+    fn get_plant_list(&self) -> PlantList {
+        let plants = self.primary.get_plant_list();
+        if plants.is_empty() {
+            self.fallback.get_plant_list()
+        } else {
+            plants
+        }
     }
 }
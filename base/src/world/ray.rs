@@ -0,0 +1,307 @@
+//! Exact ray casting against the hex pillars of a `World`.
+//!
+//! The ray is projected onto the xy-plane and the hexagons it crosses are
+//! visited one after another, like a DDA traversal on a square grid: for the
+//! current hexagon we calculate at which of its six sides the ray leaves it
+//! and continue with the neighbour behind that side. Within every hexagon,
+//! the part of the ray inside of the prism is intersected with the sections
+//! of the corresponding pillar. This way no section can be skipped, no matter
+//! how thin it is, and only the pillars actually crossed by the ray are
+//! looked up.
+
+use math::*;
+use super::{HEX_INNER_RADIUS, HexPillar, PillarIndex, World};
+#[cfg(test)]
+use super::{materials, test_world};
+
+/// The face of a pillar section which was hit by a ray.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HitFace {
+    Top,
+    Bottom,
+    /// One of the six sides, given by the direction from the hit pillar to
+    /// the neighbour on the other side of the face.
+    Side(HexDirection),
+}
+
+/// Information about the first pillar section hit by a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The pillar which was hit.
+    pub pillar: PillarIndex,
+    /// The index of the hit section in `HexPillar::sections()`.
+    pub section: usize,
+    /// The face through which the ray entered the section.
+    pub face: HitFace,
+    /// The point where the ray entered the section.
+    pub point: Point3f,
+    /// The normal of the hit face (pointing outwards, unit length).
+    pub normal: Vector3f,
+    /// The distance from the origin of the ray to `point`.
+    pub distance: f32,
+}
+
+/// Casts a ray from `origin` in the given direction (which doesn't need to be
+/// normalized) and returns the first pillar section hit within
+/// `max_distance`.
+///
+/// Sections which contain the origin of the ray are ignored, so that rays
+/// starting inside of the ground can leave it. The traversal stops without a
+/// hit as soon as it reaches a chunk which isn't loaded. Rays with non-finite
+/// coordinates never hit anything.
+pub fn cast_ray(world: &World,
+                origin: Point3f,
+                direction: Vector3f,
+                max_distance: f32)
+                -> Option<RayHit> {
+    let finite = origin.x.is_finite() && origin.y.is_finite() && origin.z.is_finite() &&
+                 direction.magnitude2().is_finite() && max_distance.is_finite();
+    if !finite || direction.magnitude2() == 0.0 || max_distance <= 0.0 {
+        return None;
+    }
+    let dir = direction.normalize();
+    let origin2 = Point2f::new(origin.x, origin.y);
+    let dir2 = Vector2f::new(dir.x, dir.y);
+
+    let mut pos = AxialPoint::from_real(origin2);
+    let mut t_enter = 0.0;
+    let mut entered_from = None;
+
+    loop {
+        let (t_exit, exit_dir) = exit_of(pos, origin2, dir2, max_distance);
+
+        let pillar = match world.pillar_at(PillarIndex(pos)) {
+            Some(pillar) => pillar,
+            None => return None,
+        };
+        let hit = hit_in_pillar(pillar, origin, dir, t_enter, t_exit.min(max_distance));
+        if let Some((section, t, z_face)) = hit {
+            let (face, normal) = match (z_face, entered_from) {
+                (Some(face), _) | (None, Some(face)) => (face, face_normal(face)),
+                // Only the sections containing the origin could be hit
+                // without entering them through a face, but those are
+                // ignored.
+                (None, None) => return None,
+            };
+
+            return Some(RayHit {
+                pillar: PillarIndex(pos),
+                section: section,
+                face: face,
+                point: origin + dir * t,
+                normal: normal,
+                distance: t,
+            });
+        }
+
+        match exit_dir {
+            Some(dir) if t_exit < max_distance => {
+                pos = pos.neighbor(dir);
+                t_enter = t_exit;
+                entered_from = Some(HitFace::Side(dir.opposite()));
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Returns the distance at which the ray leaves the given hexagon and the
+/// direction of the neighbour it enters. If the ray doesn't leave the hexagon
+/// (because it is vertical), `max_distance` and `None` are returned.
+fn exit_of(pos: AxialPoint,
+           origin: Point2f,
+           dir: Vector2f,
+           max_distance: f32)
+           -> (f32, Option<HexDirection>) {
+    let rel = origin - pos.to_real();
+
+    let mut exit = (max_distance, None);
+    for &side in &HEX_DIRECTIONS {
+        let normal = side.to_vector().to_real().normalize();
+        let speed = normal.dot(dir);
+        if speed <= 0.0 {
+            continue;
+        }
+
+        // The side lies at distance `HEX_INNER_RADIUS` from the center
+        let t = (HEX_INNER_RADIUS - normal.dot(rel)) / speed;
+        if exit.1.is_none() || t < exit.0 {
+            exit = (t, Some(side));
+        }
+    }
+
+    exit
+}
+
+/// Intersects the part of the ray between `t_enter` and `t_exit` with the
+/// sections of the pillar. Returns the index of the first section hit, the
+/// distance of the hit and the face, if the section was entered through its
+/// top or bottom.
+fn hit_in_pillar(pillar: &HexPillar,
+                 origin: Point3f,
+                 dir: Vector3f,
+                 t_enter: f32,
+                 t_exit: f32)
+                 -> Option<(usize, f32, Option<HitFace>)> {
+    let mut best: Option<(usize, f32, Option<HitFace>)> = None;
+
+    for (i, section) in pillar.sections().iter().enumerate() {
+        let bottom = section.bottom.to_real();
+        let top = section.top.to_real();
+        if origin.z >= bottom && origin.z <= top && t_enter == 0.0 {
+            continue;
+        }
+
+        // The interval in which the ray is between bottom and top
+        let (t_bottom, t_top) = if dir.z == 0.0 {
+            if origin.z < bottom || origin.z > top {
+                continue;
+            }
+            (::std::f32::NEG_INFINITY, ::std::f32::INFINITY)
+        } else {
+            ((bottom - origin.z) / dir.z, (top - origin.z) / dir.z)
+        };
+        let (t_z_enter, z_face) = if t_bottom < t_top {
+            (t_bottom, HitFace::Bottom)
+        } else {
+            (t_top, HitFace::Top)
+        };
+        let t_z_exit = t_bottom.max(t_top);
+
+        let t_hit = t_enter.max(t_z_enter);
+        if t_hit > t_exit.min(t_z_exit) {
+            continue;
+        }
+        if best.map_or(false, |(_, t, _)| t <= t_hit) {
+            continue;
+        }
+
+        let face = if t_z_enter >= t_enter { Some(z_face) } else { None };
+        best = Some((i, t_hit, face));
+    }
+
+    best
+}
+
+fn face_normal(face: HitFace) -> Vector3f {
+    match face {
+        HitFace::Top => Vector3f::new(0.0, 0.0, 1.0),
+        HitFace::Bottom => Vector3f::new(0.0, 0.0, -1.0),
+        HitFace::Side(dir) => {
+            let n = dir.to_vector().to_real().normalize();
+            Vector3f::new(n.x, n.y, 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_close(a: Point3f, b: Point3f) {
+    assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn hit_top_and_bottom() {
+    let world = test_world(materials::STONE,
+                           &[],
+                           &[((0, 0), &[(0, 2), (10, 12)]), ((3, 1), &[(0, 20)])]);
+
+    // Straight down onto the top of the upper section
+    let hit = cast_ray(&world, Point3f::new(0.1, 0.2, 10.0), Vector3f::new(0.0, 0.0, -3.0), 20.0)
+        .unwrap();
+    assert_eq!(hit.pillar, PillarIndex(AxialPoint::new(0, 0)));
+    assert_eq!((hit.section, hit.face), (1, HitFace::Top));
+    assert_close(hit.point, Point3f::new(0.1, 0.2, 6.0));
+    assert_eq!(hit.normal, Vector3f::new(0.0, 0.0, 1.0));
+    assert!((hit.distance - 4.0).abs() < 1e-4);
+
+    // Between both sections upwards
+    let hit = cast_ray(&world, Point3f::new(0.0, 0.0, 2.0), Vector3f::new(0.0, 0.0, 1.0), 20.0)
+        .unwrap();
+    assert_eq!((hit.section, hit.face), (1, HitFace::Bottom));
+    assert_close(hit.point, Point3f::new(0.0, 0.0, 5.0));
+    assert_eq!(hit.normal, Vector3f::new(0.0, 0.0, -1.0));
+
+    // Too far away and looking in the wrong direction
+    assert_eq!(cast_ray(&world, Point3f::new(0.0, 0.0, 7.0), Vector3f::new(0.0, 0.0, -1.0), 0.5),
+               None);
+    assert_eq!(cast_ray(&world, Point3f::new(0.0, 0.0, 7.0), Vector3f::new(0.0, 0.0, 1.0), 100.0),
+               None);
+}
+
+#[test]
+fn hit_sides() {
+    // A wall of pillars in front of the origin in every direction
+    let wall: &[(u16, u16)] = &[(0, 2)];
+    let mut pillars = Vec::new();
+    for &side in &HEX_DIRECTIONS {
+        let offset = side.to_vector() * 3;
+        pillars.push(((offset.q, offset.r), wall));
+    }
+    let world = test_world(materials::STONE, &[], &pillars);
+
+    for &side in &HEX_DIRECTIONS {
+        let offset = side.to_vector();
+        let target = (offset * 3).to_real();
+        let dir = Vector3f::new(target.x, target.y, 0.0);
+        let hit = cast_ray(&world, Point3f::new(0.0, 0.0, 0.5), dir, 100.0).unwrap();
+
+        assert_eq!(hit.pillar, PillarIndex(AxialPoint::new(0, 0) + offset * 3));
+        assert_eq!((hit.section, hit.face), (0, HitFace::Side(side.opposite())));
+        // The side lies one inner radius in front of the center
+        let expected = dir.normalize() * (dir.magnitude() - HEX_INNER_RADIUS);
+        assert_close(hit.point, Point3f::new(expected.x, expected.y, 0.5));
+        assert!((hit.normal + dir.normalize()).magnitude() < 1e-4);
+    }
+}
+
+#[test]
+fn thin_sections_are_not_skipped() {
+    // A flat ray which enters the prism of a section of the smallest possible
+    // height above the section and leaves it below the section
+    let world = test_world(materials::STONE, &[], &[((5, 0), &[(3, 4)])]);
+    let origin = Point3f::new(0.0, 0.0, 2.5);
+    let target = AxialPoint::new(5, 0).to_real();
+    let dir = Vector3f::new(target.x, target.y, -0.058 * target.x);
+
+    let hit = cast_ray(&world, origin, dir, 100.0).unwrap();
+    assert_eq!(hit.pillar, PillarIndex(AxialPoint::new(5, 0)));
+    assert_eq!(hit.face, HitFace::Top);
+    assert!((hit.point.z - 2.0).abs() < 1e-4);
+}
+
+#[test]
+fn ignore_sections_containing_origin() {
+    let world = test_world(materials::STONE, &[], &[((0, 0), &[(0, 10)]), ((-2, -2), &[(0, 10)])]);
+    let target = AxialPoint::new(-2, -2).to_real();
+    let dir = Vector3f::new(target.x, target.y, 0.0);
+
+    let hit = cast_ray(&world, Point3f::new(0.0, 0.0, 1.0), dir, 100.0).unwrap();
+    assert_eq!(hit.pillar, PillarIndex(AxialPoint::new(-2, -2)));
+    assert_eq!(hit.face, HitFace::Side(HexDirection::TopRight));
+}
+
+#[test]
+fn stop_at_unloaded_chunks() {
+    let world = test_world(materials::STONE, &[], &[]);
+    assert_eq!(cast_ray(&world,
+                        Point3f::new(0.0, 0.0, 1.0),
+                        Vector3f::new(1.0, 0.0, 0.0),
+                        1000.0),
+               None);
+}
+
+#[test]
+fn ignore_non_finite_rays() {
+    use std::f32::{INFINITY, NAN};
+
+    let world = test_world(materials::STONE, &[], &[((0, 0), &[(0, 10)])]);
+    let origin = Point3f::new(0.0, 0.0, 20.0);
+    let down = Vector3f::new(0.0, 0.0, -1.0);
+    assert!(cast_ray(&world, origin, down, 100.0).is_some());
+
+    assert_eq!(cast_ray(&world, Point3f::new(NAN, 0.0, 20.0), down, 100.0), None);
+    assert_eq!(cast_ray(&world, origin, Vector3f::new(0.0, NAN, -1.0), 100.0), None);
+    assert_eq!(cast_ray(&world, origin, Vector3f::new(0.0, 0.0, -INFINITY), 100.0), None);
+    assert_eq!(cast_ray(&world, origin, down, NAN), None);
+    assert_eq!(cast_ray(&world, origin, down, INFINITY), None);
+}
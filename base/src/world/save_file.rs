@@ -0,0 +1,460 @@
+//! Persistent storage of chunks in region files.
+//!
+//! The world is split into regions of `REGION_SIZE`² chunks and every region
+//! is stored in its own file inside the save directory. A region file starts
+//! with a small header followed by a table which holds the offset and length
+//! of every chunk stored in this region:
+//!
+//! ```text
+//! +-------+---------+---------+----------------------------+-------------
+//! | magic | version | padding | offset table (u32, u32)... | chunk data
+//! +-------+---------+---------+----------------------------+-------------
+//! ```
+//!
+//! Saving a chunk appends its data to the file and updates the corresponding
+//! table entry afterwards. Old versions of a chunk stay in the file until the
+//! region is compacted, which happens automatically once more than half of
+//! the file is unused.
+//!
+//! The chunks themselves are stored in the format of the `encoding` module.
+//! All numbers of the header and the table are stored in big endian byte
+//! order.
+//!
+//! Besides the region files, the save directory contains the file
+//! `world.dat` with the seed, the `WorldGenConfig`, the biomes and the plant
+//! species of the world (see `WorldData`), so that chunks which weren't saved
+//! are generated with the same parameters.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use math::*;
+use gen::plant::registry::PlantList;
+use super::{Chunk, ChunkIndex, ChunkProvider};
+use super::encoding::{WorldData, decode_chunk, decode_world, encode_chunk, encode_world};
+
+/// Number of chunks along one axis of a region. So one region file holds
+/// `REGION_SIZE`² chunks.
+pub const REGION_SIZE: i32 = 16;
+
+/// Name of the file which holds the seed and the configuration of the world.
+const WORLD_FILE: &'static str = "world.dat";
+
+/// Magic bytes every region file starts with.
+const REGION_MAGIC: &'static [u8; 4] = b"PXRG";
+
+/// Version of the region file format. Region files with another version are
+/// rejected.
+const REGION_VERSION: u16 = 1;
+
+/// Size of one entry in the offset table (offset and length, both `u32`).
+const TABLE_ENTRY_LEN: u64 = 8;
+
+/// Size of the magic bytes, the version and the padding in front of the
+/// offset table.
+const TABLE_START: u64 = 8;
+
+/// Size of the whole region header including the offset table.
+const HEADER_LEN: u64 = TABLE_START + TABLE_ENTRY_LEN * (REGION_SIZE * REGION_SIZE) as u64;
+
+/// A chunk provider which loads chunks from a directory of region files.
+///
+/// Chunks are written with `save_chunk()`. Usually this provider is used as
+/// primary provider of a `FallbackProvider` in front of a `WorldGenerator`:
+/// chunks that were saved before are loaded from disk, all other chunks are
+/// generated.
+///
+/// Cloned providers refer to the same save directory and share a lock, so one
+/// clone can save chunks while another one loads chunks in a different
+/// thread.
+#[derive(Clone, Debug)]
+pub struct SaveFileProvider {
+    dir: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl SaveFileProvider {
+    /// Opens the save directory at the given path. The directory is created
+    /// if it doesn't exist yet.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        try!(fs::create_dir_all(dir.as_ref()));
+
+        Ok(SaveFileProvider {
+            dir: dir.as_ref().to_path_buf(),
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Returns the path of the save directory.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves the seed, the configuration, the biomes and the plant species of
+    /// the world.
+    pub fn save_world(&self, world: &WorldData) -> io::Result<()> {
+        let path = self.dir.join(WORLD_FILE);
+        let tmp_path = path.with_extension("dat.tmp");
+
+        let _guard = self.lock.lock().unwrap();
+        try!(try!(File::create(&tmp_path)).write_all(&encode_world(world)));
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Loads the seed, the configuration, the biomes and the plant species of
+    /// the world. Returns `Ok(None)` if they weren't saved yet.
+    pub fn load_world(&self) -> io::Result<Option<WorldData>> {
+        let _guard = self.lock.lock().unwrap();
+        let mut file = match File::open(self.dir.join(WORLD_FILE)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+        decode_world(&data).map(Some).map_err(|e| invalid_data(&e.to_string()))
+    }
+
+    /// Saves the given chunk. A previously saved version of the chunk is
+    /// replaced.
+    pub fn save_chunk(&self, index: ChunkIndex, chunk: &Chunk) -> io::Result<()> {
+        let data = encode_chunk(chunk);
+
+        let (region, slot) = region_of(index);
+        let path = self.region_path(region);
+
+        let _guard = self.lock.lock().unwrap();
+        let mut file = try!(OpenOptions::new().read(true).write(true).create(true).open(&path));
+        if try!(file.metadata()).len() == 0 {
+            try!(write_header(&mut file));
+        } else {
+            try!(check_header(&mut file));
+        }
+
+        // Append the data first and update the table afterwards, so the file
+        // is never pointing to incomplete chunk data.
+        let offset = try!(file.seek(SeekFrom::End(0)));
+        if offset + data.len() as u64 > u32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::Other, "region file is too large"));
+        }
+        try!(file.write_all(&data));
+        try!(write_table_entry(&mut file, slot, offset as u32, data.len() as u32));
+
+        // Get rid of old chunk versions if they take up too much space
+        let table = try!(read_table(&mut file));
+        let used = table.iter().fold(HEADER_LEN, |acc, &(_, len)| acc + len as u64);
+        let file_len = try!(file.metadata()).len();
+        if file_len > 2 * used {
+            drop(file);
+            try!(compact_region(&path, &table));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path of the file for the region with the given index.
+    fn region_path(&self, region: AxialPoint) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.region", region.q, region.r))
+    }
+
+    /// Returns the table entry (offset and length) of the given chunk, if
+    /// the region file exists.
+    fn table_entry(&self, index: ChunkIndex) -> io::Result<Option<(File, u32, u32)>> {
+        let (region, slot) = region_of(index);
+        let mut file = match File::open(self.region_path(region)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        try!(check_header(&mut file));
+        let mut entry = [0; TABLE_ENTRY_LEN as usize];
+        try!(file.seek(SeekFrom::Start(TABLE_START + slot * TABLE_ENTRY_LEN)));
+        try!(file.read_exact(&mut entry));
+        let (offset, len) = decode_table_entry(&entry);
+
+        Ok(Some((file, offset, len)))
+    }
+
+    /// Reads the given chunk from its region file. Returns `Ok(None)` if the
+    /// chunk wasn't saved yet.
+    fn read_saved_chunk(&self, index: ChunkIndex) -> io::Result<Option<Chunk>> {
+        let _guard = self.lock.lock().unwrap();
+        let (mut file, offset, len) = match try!(self.table_entry(index)) {
+            Some((_, _, 0)) | None => return Ok(None),
+            Some(entry) => entry,
+        };
+
+        let mut data = vec![0; len as usize];
+        try!(file.seek(SeekFrom::Start(offset as u64)));
+        try!(file.read_exact(&mut data));
+
+        decode_chunk(&data).map(Some).map_err(|e| invalid_data(&e.to_string()))
+    }
+}
+
+impl ChunkProvider for SaveFileProvider {
+    fn load_chunk(&self, pos: ChunkIndex) -> Option<Chunk> {
+        match self.read_saved_chunk(pos) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!("failed to load chunk {:?} from {}: {}",
+                      pos,
+                      self.dir.display(),
+                      e);
+                None
+            }
+        }
+    }
+
+    fn is_chunk_loadable(&self, pos: ChunkIndex) -> bool {
+        let _guard = self.lock.lock().unwrap();
+        match self.table_entry(pos) {
+            Ok(Some((_, _, len))) => len > 0,
+            Ok(None) => false,
+            Err(e) => {
+                warn!("failed to read region file for chunk {:?}: {}", pos, e);
+                false
+            }
+        }
+    }
+
+    fn get_plant_list(&self) -> PlantList {
+        // Plants are not stored in the save file, they are generated from
+        // the world seed.
+        PlantList::default()
+    }
+}
+
+/// Returns the index of the region containing the given chunk and the index
+/// of the chunk within this region's offset table.
+fn region_of(index: ChunkIndex) -> (AxialPoint, u64) {
+    let region = AxialPoint::new(div_floor(index.0.q, REGION_SIZE),
+                                 div_floor(index.0.r, REGION_SIZE));
+    let local = index.0 - region * REGION_SIZE;
+    let slot = local.r * REGION_SIZE + local.q;
+
+    (region, slot as u64)
+}
+
+fn write_header(file: &mut File) -> io::Result<()> {
+    try!(file.seek(SeekFrom::Start(0)));
+    try!(file.write_all(REGION_MAGIC));
+    try!(write_u16(file, REGION_VERSION));
+    try!(write_u16(file, 0));
+    try!(file.write_all(&vec![0; (HEADER_LEN - TABLE_START) as usize]));
+    Ok(())
+}
+
+fn check_header(file: &mut File) -> io::Result<()> {
+    let mut magic = [0; 4];
+    try!(file.seek(SeekFrom::Start(0)));
+    try!(file.read_exact(&mut magic));
+    if &magic != REGION_MAGIC {
+        return Err(invalid_data("not a region file"));
+    }
+
+    let version = try!(read_u16(file));
+    if version != REGION_VERSION {
+        return Err(invalid_data("unsupported region file version"));
+    }
+
+    Ok(())
+}
+
+/// Writes the offset and length of a chunk into its table entry. Both are
+/// written at once, so that the entry never pairs the new offset with the old
+/// length.
+fn write_table_entry(file: &mut File, slot: u64, offset: u32, len: u32) -> io::Result<()> {
+    let mut entry = Vec::with_capacity(TABLE_ENTRY_LEN as usize);
+    try!(write_u32(&mut entry, offset));
+    try!(write_u32(&mut entry, len));
+
+    try!(file.seek(SeekFrom::Start(TABLE_START + slot * TABLE_ENTRY_LEN)));
+    file.write_all(&entry)
+}
+
+fn read_table(file: &mut File) -> io::Result<Vec<(u32, u32)>> {
+    let mut data = [0; (HEADER_LEN - TABLE_START) as usize];
+    try!(file.seek(SeekFrom::Start(TABLE_START)));
+    try!(file.read_exact(&mut data));
+
+    Ok(data.chunks(TABLE_ENTRY_LEN as usize).map(decode_table_entry).collect())
+}
+
+/// Decodes the offset and length of one table entry.
+fn decode_table_entry(entry: &[u8]) -> (u32, u32) {
+    let mut entry = entry;
+    // Reading from a slice of the right length can't fail
+    let offset = read_u32(&mut entry).unwrap();
+    let len = read_u32(&mut entry).unwrap();
+    (offset, len)
+}
+
+/// Rewrites the given region file so that it only contains the chunk data
+/// referenced by the offset table.
+fn compact_region(path: &Path, table: &[(u32, u32)]) -> io::Result<()> {
+    let tmp_path = path.with_extension("region.tmp");
+
+    {
+        let mut old = try!(File::open(path));
+        let mut new = try!(File::create(&tmp_path));
+        try!(write_header(&mut new));
+
+        for (slot, &(offset, len)) in table.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+
+            let mut data = vec![0; len as usize];
+            try!(old.seek(SeekFrom::Start(offset as u64)));
+            try!(old.read_exact(&mut data));
+
+            let new_offset = try!(new.seek(SeekFrom::End(0)));
+            try!(new.write_all(&data));
+            try!(write_table_entry(&mut new, slot as u64, new_offset as u32, len));
+        }
+    }
+
+    fs::rename(&tmp_path, path)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u16<W: Write>(w: &mut W, v: u16) -> io::Result<()> {
+    w.write_all(&[(v >> 8) as u8, v as u8])
+}
+
+fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8])
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    try!(r.read_exact(&mut buf));
+    Ok(((buf[0] as u16) << 8) | buf[1] as u16)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    try!(r.read_exact(&mut buf));
+    Ok(((buf[0] as u32) << 24) | ((buf[1] as u32) << 16) | ((buf[2] as u32) << 8) |
+       buf[3] as u32)
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    use rand;
+
+    ::std::env::temp_dir().join(format!("plantex-{}-{}", name, rand::random::<u32>()))
+}
+
+#[test]
+fn round_trip_generated_chunks() {
+    use gen::WorldGenerator;
+
+    let dir = test_dir("round-trip");
+    let save = SaveFileProvider::open(&dir).unwrap();
+    let gen = WorldGenerator::with_seed(7);
+    let indices = [AxialPoint::new(0, 0),
+                   AxialPoint::new(-1, 3),
+                   AxialPoint::new(15, 15),
+                   AxialPoint::new(16, -17),
+                   AxialPoint::new(-16, -16)];
+
+    for &pos in &indices {
+        let index = ChunkIndex(pos);
+        save.save_chunk(index, &gen.load_chunk(index).unwrap()).unwrap();
+    }
+
+    for &pos in &indices {
+        let index = ChunkIndex(pos);
+        assert!(save.is_chunk_loadable(index));
+        assert_eq!(save.load_chunk(index), gen.load_chunk(index));
+    }
+    assert!(!save.is_chunk_loadable(ChunkIndex(AxialPoint::new(1, 0))));
+    assert!(!save.is_chunk_loadable(ChunkIndex(AxialPoint::new(100, 100))));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overwrite_and_compact() {
+    use gen::WorldGenerator;
+
+    let dir = test_dir("overwrite");
+    let save = SaveFileProvider::open(&dir).unwrap();
+    let gen = WorldGenerator::with_seed(3);
+    let index = ChunkIndex(AxialPoint::new(2, -5));
+    let other = ChunkIndex(AxialPoint::new(3, -5));
+    let first = gen.load_chunk(index).unwrap();
+    let second = gen.load_chunk(other).unwrap();
+
+    save.save_chunk(other, &second).unwrap();
+    // Saving the same chunk over and over again has to trigger compaction
+    for i in 0..10 {
+        let chunk = if i % 2 == 0 { &first } else { &second };
+        save.save_chunk(index, chunk).unwrap();
+        assert_eq!(save.load_chunk(index).as_ref(), Some(chunk));
+    }
+    assert_eq!(save.load_chunk(other), Some(second));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fallback_to_generator() {
+    use gen::WorldGenerator;
+    use super::{CHUNK_SIZE, FallbackProvider, HexPillar};
+
+    let dir = test_dir("fallback");
+    let save = SaveFileProvider::open(&dir).unwrap();
+    let gen = WorldGenerator::with_seed(11);
+    let saved = ChunkIndex(AxialPoint::new(0, 0));
+    let generated = ChunkIndex(AxialPoint::new(0, 1));
+
+    // Save an "edited" chunk without any sections
+    let edited = Chunk::from_pillars(vec![HexPillar::default(); (CHUNK_SIZE as usize).pow(2)]);
+    save.save_chunk(saved, &edited).unwrap();
+
+    let provider = FallbackProvider::new(save, WorldGenerator::with_seed(11));
+    assert_eq!(provider.load_chunk(saved), Some(edited));
+    assert_eq!(provider.load_chunk(generated), gen.load_chunk(generated));
+    assert!(!provider.get_plant_list().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn save_world_data() {
+    use gen::plant::registry::PlantRegistry;
+    use gen::world::biome::BiomeRegistry;
+    use gen::world::config::WorldGenConfig;
+
+    let dir = test_dir("world");
+    let save = SaveFileProvider::open(&dir).unwrap();
+    assert_eq!(save.load_world().unwrap(), None);
+
+    let mut world = WorldData {
+        seed: 5,
+        config: WorldGenConfig::default(),
+        biomes: BiomeRegistry::builtin(),
+        plants: PlantRegistry::builtin(),
+    };
+    save.save_world(&world).unwrap();
+    world.seed = 9;
+    world.config.sea_level = 60;
+    save.save_world(&world).unwrap();
+    assert_eq!(save.load_world().unwrap(), Some(world.clone()));
+    assert_eq!(SaveFileProvider::open(&dir).unwrap().load_world().unwrap(),
+               Some(world));
+
+    File::create(dir.join(WORLD_FILE)).unwrap().write_all(b"PXWD").unwrap();
+    assert_eq!(save.load_world().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
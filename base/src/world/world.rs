@@ -1,20 +1,32 @@
-use std::collections::HashMap;
-use super::{Chunk, ChunkIndex, HexPillar, PillarIndex};
-use math::*;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map;
+use super::{Chunk, ChunkIndex, EditError, HeightType, HexPillar, MaterialId, PillarIndex,
+            Prop};
 
 /// Represents a whole game world consisting of multiple `Chunk`s.
 ///
 /// Chunks are parallelograms (roughly) that are placed next to each other
 /// in the world.
+///
+/// The world should be modified with the editing methods (`carve()`,
+/// `fill()`, `set_material()`, `place_prop()` and `remove_prop()`). Those
+/// keep track of all chunks which need to be redrawn (see
+/// `take_dirty_chunks()`) and of all chunks which were edited since they
+/// were last saved (see `take_unsaved_chunks()`).
 pub struct World {
-    // TODO: make it private after we can access it immutable via a method! (see #7)
-    pub chunks: HashMap<ChunkIndex, Chunk>,
+    chunks: HashMap<ChunkIndex, Chunk>,
+    dirty_chunks: HashSet<ChunkIndex>,
+    unsaved_chunks: HashSet<ChunkIndex>,
 }
 
 impl World {
     /// Creates an empty world without any chunks.
     pub fn empty() -> Self {
-        World { chunks: HashMap::new() }
+        World {
+            chunks: HashMap::new(),
+            dirty_chunks: HashSet::new(),
+            unsaved_chunks: HashSet::new(),
+        }
     }
 
     /// Inserts the given chunk into the world and replaces the chunk that
@@ -36,31 +48,35 @@ impl World {
         }
     }
 
+    /// Removes the chunk at the given position from the world and returns it.
+    /// The chunk is neither dirty nor unsaved afterwards, see
+    /// `is_unsaved()`.
+    pub fn remove_chunk(&mut self, index: ChunkIndex) -> Option<Chunk> {
+        self.dirty_chunks.remove(&index);
+        self.unsaved_chunks.remove(&index);
+        self.chunks.remove(&index)
+    }
+
+    /// Returns `true` if the chunk at the given position is loaded.
+    pub fn contains_chunk(&self, index: ChunkIndex) -> bool {
+        self.chunks.contains_key(&index)
+    }
+
+    /// Returns an iterator over all loaded chunks.
+    pub fn chunks(&self) -> hash_map::Iter<ChunkIndex, Chunk> {
+        self.chunks.iter()
+    }
+
+    /// Returns the number of loaded chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
     /// Returns the hex pillar at the given world position, iff the
     /// corresponding chunk is loaded.
     pub fn pillar_at(&self, pos: PillarIndex) -> Option<&HexPillar> {
-        let chunk_size = super::CHUNK_SIZE as i32;
-        let mut new_pos = pos;
-        // TODO: use `/` operator once it's implemented
-        // let chunk_pos = pos / (super::CHUNK_SIZE as i32);
-        if new_pos.0.q < 0 {
-            new_pos.0.q -= 15;
-        }
-        if new_pos.0.r < 0 {
-            new_pos.0.r -= 15;
-        }
-        let chunk_pos = new_pos.0 / chunk_size;
-        let out = self.chunks.get(&ChunkIndex(chunk_pos)).map(|chunk| {
-            let mut inner_pos = pos.0 % chunk_size;
-            if inner_pos.q < 0 {
-                inner_pos.q += chunk_size;
-            }
-            if inner_pos.r < 0 {
-                inner_pos.r += chunk_size;
-            }
-            &chunk[inner_pos]
-        });
-
+        let (chunk_pos, local) = pos.to_chunk_local();
+        let out = self.chunks.get(&chunk_pos).map(|chunk| &chunk[local]);
 
         if out.is_none() {
             debug!("chunk {:?} is not loaded (position request {:?})",
@@ -72,32 +88,12 @@ impl World {
 
     /// Returns the hex pillar at the given world position, iff the
     /// corresponding chunk is loaded.
+    ///
+    /// Changes made through this reference are neither tracked as dirty nor
+    /// as unsaved. Use the editing methods instead, if possible.
     pub fn pillar_at_mut(&mut self, pos: PillarIndex) -> Option<&mut HexPillar> {
-        let chunk_size = super::CHUNK_SIZE as i32;
-        let mut new_pos = pos;
-        // TODO: use `/` operator once it's implemented
-        // let chunk_pos = pos / (super::CHUNK_SIZE as i32);
-        if new_pos.0.q < 0 {
-            new_pos.0.q -= 15;
-        }
-        if new_pos.0.r < 0 {
-            new_pos.0.r -= 15;
-        }
-        let chunk_pos = new_pos.0 / chunk_size;
-        let out = self.chunks.get_mut(&ChunkIndex(chunk_pos)).map(|chunk| {
-            let mut inner_pos = pos.0 % chunk_size;
-            if inner_pos.q < 0 {
-                inner_pos.q += chunk_size;
-            }
-            if inner_pos.r < 0 {
-                inner_pos.r += chunk_size;
-            }
-            if chunk_pos.q != chunk_pos.r {
-                ::std::mem::swap(&mut inner_pos.r, &mut inner_pos.q);
-            }
-            &mut chunk[inner_pos]
-        });
-
+        let (chunk_pos, local) = pos.to_chunk_local();
+        let out = self.chunks.get_mut(&chunk_pos).map(|chunk| &mut chunk[local]);
 
         if out.is_none() {
             debug!("chunk {:?} is not loaded (position request {:?})",
@@ -109,10 +105,7 @@ impl World {
 
     /// Returns the chunk in which the given pillar exists.
     pub fn chunk_from_pillar(&self, pos: PillarIndex) -> Option<&Chunk> {
-        let tmp = AxialPoint::new(pos.0.q / (super::CHUNK_SIZE as i32),
-                                  pos.0.r / (super::CHUNK_SIZE as i32));
-        let chunk_pos = ChunkIndex(tmp);
-        self.chunk_at(chunk_pos)
+        self.chunk_at(pos.chunk())
     }
 
     /// Returns the requested chunk.
@@ -125,4 +118,269 @@ impl World {
 
         out
     }
+
+    /// Removes the height range `bottom..top` from the given pillar (see
+    /// `HexPillar::carve()`). Returns whether the pillar changed.
+    pub fn carve(&mut self,
+                 pos: PillarIndex,
+                 bottom: HeightType,
+                 top: HeightType)
+                 -> Result<bool, EditError> {
+        try!(check_range(bottom, top));
+        let changed = try!(self.edit_pillar(pos, |pillar| pillar.carve(bottom, top)));
+        if changed {
+            self.pillar_changed(pos);
+        }
+        Ok(changed)
+    }
+
+    /// Fills the height range `bottom..top` of the given pillar with the
+    /// given material (see `HexPillar::fill()`). Returns whether the pillar
+    /// changed.
+    pub fn fill(&mut self,
+                pos: PillarIndex,
+                ground: MaterialId,
+                bottom: HeightType,
+                top: HeightType)
+                -> Result<bool, EditError> {
+        try!(check_range(bottom, top));
+        let changed = try!(self.edit_pillar(pos, |pillar| pillar.fill(ground, bottom, top)));
+        if changed {
+            self.pillar_changed(pos);
+        }
+        Ok(changed)
+    }
+
+    /// Changes the material of the solid parts of the given pillar in the
+    /// height range `bottom..top` (see `HexPillar::set_material()`). Returns
+    /// whether the pillar changed.
+    pub fn set_material(&mut self,
+                        pos: PillarIndex,
+                        ground: MaterialId,
+                        bottom: HeightType,
+                        top: HeightType)
+                        -> Result<bool, EditError> {
+        try!(check_range(bottom, top));
+        let changed = try!(self.edit_pillar(pos,
+                                            |pillar| pillar.set_material(ground, bottom, top)));
+        if changed {
+            self.pillar_changed(pos);
+        }
+        Ok(changed)
+    }
+
+    /// Adds a prop to the given pillar.
+    pub fn place_prop(&mut self, pos: PillarIndex, prop: Prop) -> Result<(), EditError> {
+        try!(self.edit_pillar(pos, |pillar| pillar.add_prop(prop)));
+        self.pillar_changed(pos);
+        Ok(())
+    }
+
+    /// Removes the prop with the given index (into `HexPillar::props()`) from
+    /// the given pillar and returns it.
+    pub fn remove_prop(&mut self, pos: PillarIndex, index: usize) -> Result<Prop, EditError> {
+        let prop = match try!(self.edit_pillar(pos, |pillar| pillar.remove_prop(index))) {
+            Some(prop) => prop,
+            None => return Err(EditError::NoSuchProp(index)),
+        };
+        self.pillar_changed(pos);
+        Ok(prop)
+    }
+
+    /// Returns all chunks whose appearance changed since the last call. This
+    /// includes loaded neighbour chunks of edited pillars at the border of a
+    /// chunk, because their meshes touch the edited pillar.
+    pub fn take_dirty_chunks(&mut self) -> Vec<ChunkIndex> {
+        self.dirty_chunks.drain().collect()
+    }
+
+    /// Returns whether the chunk at the given position was edited since it
+    /// was loaded or last returned by `take_unsaved_chunks()`.
+    pub fn is_unsaved(&self, index: ChunkIndex) -> bool {
+        self.unsaved_chunks.contains(&index)
+    }
+
+    /// Returns all chunks which were edited since the last call and have to
+    /// be saved. Unlike the dirty chunks, this doesn't include the neighbours
+    /// of edited pillars.
+    pub fn take_unsaved_chunks(&mut self) -> Vec<ChunkIndex> {
+        self.unsaved_chunks.drain().collect()
+    }
+
+    /// Calls the given closure with the given pillar, if its chunk is loaded.
+    fn edit_pillar<F, R>(&mut self, pos: PillarIndex, func: F) -> Result<R, EditError>
+        where F: FnOnce(&mut HexPillar) -> R
+    {
+        let (chunk_pos, local) = pos.to_chunk_local();
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => Ok(func(&mut chunk[local])),
+            None => Err(EditError::ChunkNotLoaded(chunk_pos)),
+        }
+    }
+
+    /// Marks the chunk of the changed pillar as unsaved and the chunks
+    /// around it as dirty.
+    fn pillar_changed(&mut self, pos: PillarIndex) {
+        self.unsaved_chunks.insert(pos.chunk());
+        self.dirty_chunks.insert(pos.chunk());
+        for &neighbor in &pos.0.neighbors() {
+            let chunk_pos = PillarIndex(neighbor).chunk();
+            if self.chunks.contains_key(&chunk_pos) {
+                self.dirty_chunks.insert(chunk_pos);
+            }
+        }
+    }
+}
+
+fn check_range(bottom: HeightType, top: HeightType) -> Result<(), EditError> {
+    if bottom < top {
+        Ok(())
+    } else {
+        Err(EditError::InvalidRange)
+    }
+}
+
+#[test]
+fn pillar_access_with_negative_coordinates() {
+    use math::AxialPoint;
+    use super::{CHUNK_SIZE, HeightType, PillarSection, materials};
+    use gen::world::biome::biomes;
+
+    let size = CHUNK_SIZE as i32;
+    let mut world = World::empty();
+    for &(q, r) in &[(0, 0), (-1, 0), (0, -1), (-1, -1), (1, -2)] {
+        let index = ChunkIndex(AxialPoint::new(q, r));
+        // Every pillar knows its own position in its single section's height
+        let chunk = Chunk::with_pillars(index, |pos| {
+            let height = ((pos.q + 4 * size) * 8 * size + pos.r + 4 * size) as u16;
+            HexPillar::new(vec![PillarSection::new(materials::DIRT,
+                                                   HeightType(0),
+                                                   HeightType(height))],
+                           vec![],
+                           biomes::DEBUG)
+        });
+        world.add_chunk(index, chunk).unwrap();
+    }
+
+    let height_at = |world: &World, q: i32, r: i32| {
+        world.pillar_at(PillarIndex(AxialPoint::new(q, r))).map(|p| p.sections()[0].top.0)
+    };
+    for &(q, r) in &[(0, 0), (-1, 0), (0, -1), (-16, -16), (-1, -16), (5, -13), (20, -30)] {
+        let expected = ((q + 4 * size) * 8 * size + r + 4 * size) as u16;
+        assert_eq!(height_at(&world, q, r), Some(expected));
+
+        let pos = PillarIndex(AxialPoint::new(q, r));
+        assert_eq!(world.chunk_from_pillar(pos).map(|c| c as *const _),
+                   world.chunk_at(pos.chunk()).map(|c| c as *const _));
+        assert!(world.chunk_from_pillar(pos).is_some());
+
+        world.pillar_at_mut(pos).unwrap().sections_mut()[0].top = HeightType(1);
+        assert_eq!(height_at(&world, q, r), Some(1));
+    }
+    assert_eq!(height_at(&world, 16, 0), None);
+    assert_eq!(height_at(&world, -17, 0), None);
+}
+
+#[cfg(test)]
+fn flat_world(chunks: &[(i32, i32)]) -> World {
+    use math::AxialPoint;
+    use super::{PillarSection, materials};
+    use gen::world::biome::biomes;
+
+    let mut world = World::empty();
+    for &(q, r) in chunks {
+        let index = ChunkIndex(AxialPoint::new(q, r));
+        let chunk = Chunk::with_pillars(index, |_| {
+            HexPillar::new(vec![PillarSection::new(materials::STONE,
+                                                   HeightType(0),
+                                                   HeightType(20))],
+                           vec![],
+                           biomes::DEBUG)
+        });
+        world.add_chunk(index, chunk).unwrap();
+    }
+    world
+}
+
+#[test]
+fn edits_mark_chunks_dirty() {
+    use math::AxialPoint;
+    use super::materials;
+
+    let chunk = |q, r| ChunkIndex(AxialPoint::new(q, r));
+    let pillar = |q, r| PillarIndex(AxialPoint::new(q, r));
+    let sorted = |mut v: Vec<ChunkIndex>| {
+        v.sort_by_key(|c| (c.0.q, c.0.r));
+        v
+    };
+    let mut world = flat_world(&[(0, 0), (-1, 0), (0, -1), (-1, -1), (1, 0)]);
+
+    // In the middle of a chunk
+    assert_eq!(world.carve(pillar(5, 5), HeightType(10), HeightType(30)), Ok(true));
+    assert_eq!(world.take_dirty_chunks(), vec![chunk(0, 0)]);
+    assert!(world.take_dirty_chunks().is_empty());
+
+    // Edits which don't change anything don't make anything dirty
+    assert_eq!(world.carve(pillar(5, 5), HeightType(10), HeightType(30)), Ok(false));
+    assert!(world.take_dirty_chunks().is_empty());
+
+    // At the corner of four chunks. Only loaded neighbours are dirty.
+    assert_eq!(world.fill(pillar(0, 0), materials::SAND, HeightType(20), HeightType(21)),
+               Ok(true));
+    assert_eq!(sorted(world.take_dirty_chunks()),
+               vec![chunk(-1, -1), chunk(-1, 0), chunk(0, -1), chunk(0, 0)]);
+    assert_eq!(world.set_material(pillar(15, 3),
+                                  materials::DIRT,
+                                  HeightType(0),
+                                  HeightType(1)),
+               Ok(true));
+    assert_eq!(sorted(world.take_dirty_chunks()), vec![chunk(0, 0), chunk(1, 0)]);
+
+    // Removing a chunk also removes it from the dirty set
+    world.carve(pillar(16, 5), HeightType(0), HeightType(1)).unwrap();
+    world.remove_chunk(chunk(1, 0));
+    assert_eq!(world.take_dirty_chunks(), vec![chunk(0, 0)]);
+}
+
+#[test]
+fn edits_mark_chunks_unsaved() {
+    use gen::plant::registry::{PlantId, SpeciesId};
+    use math::AxialPoint;
+
+    let pos = PillarIndex(AxialPoint::new(-3, 7));
+    let border = PillarIndex(AxialPoint::new(0, 0));
+    let prop = Prop {
+        baseline: HeightType(20),
+        plant: PlantId {
+            species: SpeciesId(4),
+            variant: 0,
+        },
+    };
+    let mut world = flat_world(&[(0, 0), (-1, 0), (0, -1), (-1, -1)]);
+
+    // Only the chunk of the edited pillar has to be saved, not its neighbours
+    world.carve(border, HeightType(5), HeightType(8)).unwrap();
+    world.place_prop(pos, prop.clone()).unwrap();
+    assert!(world.is_unsaved(border.chunk()));
+    let mut unsaved = world.take_unsaved_chunks();
+    unsaved.sort_by_key(|c| (c.0.q, c.0.r));
+    assert_eq!(unsaved, vec![pos.chunk(), border.chunk()]);
+    assert!(world.take_unsaved_chunks().is_empty());
+    assert!(!world.is_unsaved(border.chunk()));
+
+    // Removing a prop is an edit, too. Removing the chunk forgets it.
+    assert_eq!(world.remove_prop(pos, 0), Ok(prop));
+    assert!(world.is_unsaved(pos.chunk()));
+    world.remove_chunk(pos.chunk());
+    assert!(world.take_unsaved_chunks().is_empty());
+
+    // Failed edits neither change anything nor make anything unsaved
+    let unloaded = PillarIndex(AxialPoint::new(16, 0));
+    assert_eq!(world.remove_prop(border, 0), Err(EditError::NoSuchProp(0)));
+    assert_eq!(world.carve(border, HeightType(8), HeightType(8)),
+               Err(EditError::InvalidRange));
+    assert_eq!(world.carve(unloaded, HeightType(0), HeightType(8)),
+               Err(EditError::ChunkNotLoaded(ChunkIndex(AxialPoint::new(1, 0)))));
+    assert!(world.take_unsaved_chunks().is_empty());
+    assert_eq!(world.pillar_at(border).unwrap().sections().len(), 2);
 }
@@ -115,6 +115,10 @@ void main() {
     } else if (x_ground == 7) {
         normal_map = texture(normal_mulch, tex).rgb;
         diffuse_color = texture(mulch_texture, x_tex_coords).rgb;
+    } else if (x_ground == 9) {
+        // Water is flat and only colored by its material color
+        normal_map = vec3(0.5, 0.5, 1.0);
+        diffuse_color = vec3(1.0);
     }
 
     // Calculate Tangent Binormal Normal (tbn) Matrix to multiply with normal_map
@@ -21,6 +21,7 @@ pub struct Config {
     pub bloom: bool,
     pub vsync: bool,
     pub highlight_pillar: bool,
+    pub erosion: bool,
     pub seed: u64, /* view range
                     * anti aliasing
                     * Controls
@@ -61,6 +62,10 @@ impl Config {
                 .help("[on/off]")
                 .takes_value(true)
                 .long("highlight"))
+            .arg(Arg::with_name("Erosion")
+                .help("[on/off] 'Erodes the terrain of new worlds'")
+                .takes_value(true)
+                .long("erosion"))
             .arg(Arg::with_name("Seed")
                 .help("'Takes a specified seed to generate map'")
                 .takes_value(true)
@@ -90,6 +95,7 @@ vsync = false
 
 [Game_settings]
 seed = 42
+erosion = false
 highlight_pillar = true
             "#;
 
@@ -117,6 +123,7 @@ impl Default for Config {
             bloom: true,
             vsync: false,
             highlight_pillar: true,
+            erosion: false,
             seed: 42,
         }
     }
@@ -258,6 +265,15 @@ fn config_toml(mut default_config: Config, matches: &ArgMatches) -> Result<Confi
         };
 
 
+        // Erosion of the generated terrain, older config files don't have it
+        if let Some(erosion) = value.lookup("Game_settings.erosion") {
+            match erosion.as_bool() {
+                Some(n) => default_config.erosion = n,
+                None => return Err("erosion value in config file is invalid".into()),
+            };
+        }
+
+
         // world seed
         let seed = match value.lookup("Game_settings.seed") {
             Some(n) => n,
@@ -347,6 +363,15 @@ fn config_command(mut toml_config: Config, matches: &ArgMatches) -> Result<Confi
         }
     }
 
+    // Erosion
+    if let Some(erosion) = matches.value_of("Erosion") {
+        match erosion {
+            "on" => toml_config.erosion = true,
+            "off" => toml_config.erosion = false,
+            _ => return Err("Erosion can only be set on or off on command line".into()),
+        }
+    }
+
     // world Seed
     if let Some(seed) = matches.value_of("Seed") {
         match seed.parse::<u64>() {
@@ -1,4 +1,4 @@
-use base::world::ChunkProvider;
+use base::world::{FallbackProvider, SaveFileProvider};
 use ghost::Ghost;
 use event_manager::{CloseHandler, EventManager, EventResponse};
 use glium::backend::glutin_backend::GlutinFacade;
@@ -7,22 +7,27 @@ use super::Renderer;
 use super::{Config, GameContext, WorldManager};
 use config::WindowMode;
 use base::gen::WorldGenerator;
+use base::gen::plant::registry::PlantRegistry;
+use base::gen::world::biome::BiomeRegistry;
+use base::gen::world::config::WorldGenConfig;
+use base::gen::world::erosion::ErosionConfig;
+use base::gen::world::spawn::SpawnLocator;
+use base::world::encoding::WorldData;
+use base::world::ground::MaterialRegistry;
 use std::time::{Duration, Instant};
 use std::rc::Rc;
 use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
 use std::error::Error;
-use base::world::World;
+use base::world::{HitFace, World};
 use camera::Camera;
-use base::world::PillarSection;
 use base::world;
 use base::math::*;
-use base::world::PillarIndex;
 use view::{SkyView, Sun};
 use super::DayTime;
 use super::weather::Weather;
 use player::Player;
 use control_switcher::ControlSwitcher;
-use base::world::HexPillar;
 
 pub struct Game {
     renderer: Renderer,
@@ -42,8 +47,19 @@ impl Game {
         info!("connecting to {}", server);
         let server = try!(TcpStream::connect(server));
         let facade = try!(create_context(&config));
-        let context = Rc::new(GameContext::new(facade, config.clone()));
-        let world_manager = WorldManager::new(create_chunk_provider(context.get_config()),
+        let materials = load_materials();
+        let save_file = try!(SaveFileProvider::open(save_path(&config)));
+        let world = try!(load_world(&config, &materials, &save_file));
+        let context = Rc::new(GameContext::new(facade,
+                                               config.clone(),
+                                               materials,
+                                               world.biomes.clone()));
+        let generator = create_generator(world);
+        let spawn = SpawnLocator::default().locate(&generator, Point2f::new(0.0, 0.0));
+        info!("spawning at ({}, {}, {})", spawn.x, spawn.y, spawn.z);
+        let provider = Box::new(FallbackProvider::new(save_file.clone(), generator));
+        let world_manager = WorldManager::new(provider,
+                                              Some(save_file),
                                               context.clone());
         let world_weather = Weather::new(context.clone());
 
@@ -56,8 +72,10 @@ impl Game {
             sky_view: SkyView::new(context.clone()),
             daytime: DayTime::default(),
             weather: world_weather,
-            control_switcher: ControlSwitcher::new(Player::new(context.clone(), world_manager),
-                                                   Ghost::new(context.clone())),
+            control_switcher: ControlSwitcher::new(Player::new(context.clone(),
+                                                               world_manager,
+                                                               spawn),
+                                                   Ghost::new(context.clone(), spawn)),
         })
     }
 
@@ -99,14 +117,13 @@ impl Game {
             // Check for pillar outline highlight switch
             if self.world_manager.get_context().get_config().highlight_pillar {
                 // Display Outline of Hexagon looking at
-                let vec = get_pillarsectionpos_looking_at(&self.world_manager.get_world(),
-                                                          self.control_switcher.get_camera());
-                match vec {
-                    Some(n) => {
-                        // self.remove_hexagon_at(n.1, n.0.z);
+                let pos = outline_pos_looking_at(&self.world_manager.get_world(),
+                                                 self.control_switcher.get_camera());
+                match pos {
+                    Some(pos) => {
                         let mut view = self.world_manager.get_mut_view();
                         view.outline.display = true;
-                        view.outline.pos = n.0;
+                        view.outline.pos = pos;
                     }
                     None => {
                         let mut view = self.world_manager.get_mut_view();
@@ -140,59 +157,129 @@ impl Game {
             }
         }
 
+        self.world_manager.save_world();
+
         Ok(())
     }
 }
 
-fn get_pillarsectionpos_looking_at(world: &World,
-                                   cam: Camera)
-                                   -> Option<(Vector3f, AxialPoint, f32)> {
-    let cam_pos = cam.position;
-    let mut look_vec = cam.get_look_at_vector().normalize();
-    let view_distance = 12.0;
-
-    let mut step = 0.0;
-    while (look_vec.x * look_vec.x + look_vec.y * look_vec.y + look_vec.z * look_vec.z).sqrt() <=
-          view_distance {
-        step += 0.0005;
-        look_vec = cam.get_look_at_vector().normalize() * step;
-
-        let view_pos = Point2f::new(cam_pos.x + look_vec.x, cam_pos.y + look_vec.y);
-        let ax_point = AxialPoint::from_real(view_pos);
-
-        let pillar_index = PillarIndex(ax_point.clone());
-
-        let mut height = cam_pos.z + look_vec.z;
-        height -= height % world::PILLAR_STEP_HEIGHT;
-
-        let final_pos = match world.pillar_at(pillar_index) {
-            Some(n) => get_pillar_section_at_position(n, height),
-            None => None,
-        };
-
-        match final_pos {
-            Some(_) => {
-                return Some((Vector3f::new(ax_point.to_real().x, ax_point.to_real().y, height),
-                             ax_point,
-                             height));
-            }
-            None => {}
-        };
+/// Maximum distance at which the pillar the camera is looking at is
+/// highlighted.
+const HIGHLIGHT_DISTANCE: f32 = 12.0;
+
+/// Returns the position of the outline for the pillar section the camera is
+/// looking at, if any.
+fn outline_pos_looking_at(world: &World, cam: Camera) -> Option<Vector3f> {
+    let hit = match world::cast_ray(world,
+                                    cam.position,
+                                    cam.get_look_at_vector(),
+                                    HIGHLIGHT_DISTANCE) {
+        Some(hit) => hit,
+        None => return None,
+    };
+
+    let height = match (hit.face, world.pillar_at(hit.pillar)) {
+        (HitFace::Top, Some(pillar)) => pillar.sections()[hit.section].top.to_real(),
+        (HitFace::Bottom, Some(pillar)) => pillar.sections()[hit.section].bottom.to_real(),
+        _ => hit.point.z - hit.point.z % world::PILLAR_STEP_HEIGHT,
+    };
+    let center = hit.pillar.0.to_real();
+
+    Some(Vector3f::new(center.x, center.y, height))
+}
+
+/// Directory in which all worlds are saved.
+const SAVE_DIR: &'static str = "saves";
+
+/// Returns the directory in which the world for the configured seed is saved.
+fn save_path(config: &Config) -> PathBuf {
+    Path::new(SAVE_DIR).join(format!("world-{}", config.seed))
+}
+
+/// Returns the data of the world which is stored in the save directory. New
+/// worlds use the parameters from `worldgen.toml`, the biomes from
+/// `biomes.toml` and the plant species from `plants.toml` (or the default
+/// ones, if a file doesn't exist), which are saved right away. The `erosion`
+/// setting enables the erosion of new worlds.
+///
+/// If the stored data can't be read, an error is returned instead of
+/// generating the rest of the world with other parameters.
+fn load_world(config: &Config,
+              materials: &MaterialRegistry,
+              save_file: &SaveFileProvider)
+              -> Result<WorldData, Box<Error>> {
+    if let Some(world) = try!(save_file.load_world()) {
+        return Ok(world);
+    }
+
+    let mut world_config = match WorldGenConfig::load(Path::new("worldgen.toml")) {
+        Ok(world_config) => world_config,
+        Err(e) => {
+            warn!("failed to load 'worldgen.toml', using the default parameters: {}", e);
+            WorldGenConfig::default()
+        }
+    };
+    if config.erosion && !world_config.erosion.is_enabled() {
+        world_config.erosion = ErosionConfig::enabled();
+    }
+    let plants = load_plants();
+    let world = WorldData {
+        seed: config.seed,
+        config: world_config,
+        biomes: load_biomes(materials, &plants),
+        plants: plants,
+    };
+    if let Err(e) = save_file.save_world(&world) {
+        warn!("failed to save the world data: {}", e);
     }
-    None
+    Ok(world)
 }
 
-fn get_pillar_section_at_position(pillar: &HexPillar, pos_z: f32) -> Option<&PillarSection> {
-    for section in pillar.sections() {
-        if section.top.to_real() > pos_z && section.bottom.to_real() < pos_z {
-            return Some(section);
+/// Creates the generator for all chunks which aren't saved yet.
+fn create_generator(world: WorldData) -> WorldGenerator {
+    WorldGenerator::with_seed(world.seed)
+        .with_config(world.config)
+        .with_biomes(world.biomes)
+        .with_plants(world.plants)
+}
+
+/// Loads the ground materials from `materials.toml`. The file is optional and
+/// extends or overrides the built-in materials. If it is invalid, the built-in
+/// materials are used.
+fn load_materials() -> MaterialRegistry {
+    match MaterialRegistry::load(Path::new("materials.toml")) {
+        Ok(materials) => materials,
+        Err(e) => {
+            warn!("failed to load 'materials.toml', using built-in materials: {}", e);
+            MaterialRegistry::builtin()
         }
     }
-    None
 }
 
-fn create_chunk_provider(config: &Config) -> Box<ChunkProvider> {
-    Box::new(WorldGenerator::with_seed(config.seed))
+/// Loads the biomes of new worlds from `biomes.toml`. The file is optional
+/// and extends or overrides the built-in biomes. If it is invalid, the
+/// built-in biomes are used. Biomes refer to the plant species by name.
+fn load_biomes(materials: &MaterialRegistry, plants: &PlantRegistry) -> BiomeRegistry {
+    match BiomeRegistry::load(Path::new("biomes.toml"), materials, plants) {
+        Ok(biomes) => biomes,
+        Err(e) => {
+            warn!("failed to load 'biomes.toml', using built-in biomes: {}", e);
+            BiomeRegistry::builtin()
+        }
+    }
+}
+
+/// Loads the plant species of new worlds from `plants.toml`. The file is
+/// optional and extends or overrides the built-in species. If it is invalid,
+/// the built-in species are used.
+fn load_plants() -> PlantRegistry {
+    match PlantRegistry::load(Path::new("plants.toml")) {
+        Ok(plants) => plants,
+        Err(e) => {
+            warn!("failed to load 'plants.toml', using built-in plant species: {}", e);
+            PlantRegistry::builtin()
+        }
+    }
 }
 
 /// Creates the OpenGL context and prints useful information about the
@@ -2,6 +2,8 @@ use glium::backend::glutin_backend::GlutinFacade;
 use glium::program;
 use glium::Program;
 use super::Config;
+use base::gen::world::biome::BiomeRegistry;
+use base::world::ground::MaterialRegistry;
 use std::fs::File;
 use std::io::{self, Read};
 use std::error::Error;
@@ -10,13 +12,21 @@ use std::error::Error;
 pub struct GameContext {
     facade: GlutinFacade,
     config: Config, // TODO: we might want to wrap it into `Rc` (performance)
+    materials: MaterialRegistry,
+    biomes: BiomeRegistry,
 }
 
 impl GameContext {
-    pub fn new(facade: GlutinFacade, config: Config) -> Self {
+    pub fn new(facade: GlutinFacade,
+               config: Config,
+               materials: MaterialRegistry,
+               biomes: BiomeRegistry)
+               -> Self {
         GameContext {
             facade: facade,
             config: config,
+            materials: materials,
+            biomes: biomes,
         }
     }
 
@@ -28,6 +38,16 @@ impl GameContext {
         &self.config
     }
 
+    /// Gets the registry of all ground materials.
+    pub fn get_materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    /// Gets the registry of all biomes.
+    pub fn get_biomes(&self) -> &BiomeRegistry {
+        &self.biomes
+    }
+
     /// Loads vertex and fragment shader automatically to prevent recompiling
     /// the application
     /// everytime a shader is changed.
@@ -1,6 +1,7 @@
 use super::camera::*;
 use super::GameContext;
 use super::event_manager::*;
+use base::math::*;
 use glium::glutin::{CursorState, ElementState, Event, MouseButton, VirtualKeyCode};
 use std::rc::Rc;
 
@@ -20,13 +21,18 @@ pub struct Ghost {
 // Speed per second
 const DEFAULT_SPEED: f32 = 12.0;
 const SHIFT_SPEED: f32 = 60.0;
+/// The `Ghost` starts this high above the spawn point
+const SPAWN_HEIGHT: f32 = 4.0;
 
 
 
 impl Ghost {
-    pub fn new(context: Rc<GameContext>) -> Self {
+    /// Creates the `Ghost` above the given spawn point
+    pub fn new(context: Rc<GameContext>, spawn: Point3f) -> Self {
+        let mut cam = Camera::new(context.get_config().resolution.aspect_ratio());
+        cam.position = spawn + Vector3f::new(0.0, 0.0, SPAWN_HEIGHT);
         Ghost {
-            cam: Camera::new(context.get_config().resolution.aspect_ratio()),
+            cam: cam,
             context: context,
             speed: DEFAULT_SPEED,
             forward: false,
@@ -3,17 +3,30 @@ use super::event_manager::*;
 use GameContext;
 use glium::glutin::{CursorState, ElementState, Event, MouseButton, VirtualKeyCode};
 use base::math::*;
+use base::physics::{self, Aabb};
 use base::world::*;
 use std::rc::Rc;
 use super::world_manager::*;
-use std::f32;
 
 
 const GRAVITY: f32 = 9.81;
+/// Height of the camera above the feet of the `Player`
+const EYE_HEIGHT: f32 = 1.75;
+/// Height of the `Player`'s bounding box
+const PLAYER_HEIGHT: f32 = 1.85;
+/// Half of the width of the `Player`'s bounding box
+const PLAYER_HALF_WIDTH: f32 = 0.25;
+/// Water pushes the `Player` up, so that only this part of the gravity is left
+const WATER_GRAVITY_FACTOR: f32 = 0.2;
+/// The `Player` can't sink faster than this in water
+const MAX_SINK_VELOCITY: f32 = 0.05;
+/// The `Player` respawns when falling below this height, out of the world
+const RESPAWN_DEPTH: f32 = -32.0;
 
 
 /// Represents a `Player` in the world, the `Player` can move up, right, down
-/// left, right with w, a, s, d, jump with space and speed with shift
+/// left, right with w, a, s, d, jump (or swim up) with space and speed with
+/// shift
 pub struct Player {
     cam: Camera,
     context: Rc<GameContext>,
@@ -25,12 +38,19 @@ pub struct Player {
     mouselock: bool,
     shift_speed: f32,
     step_size: f32,
+    on_ground: bool,
+    in_water: bool,
+    /// Position of the feet at which the `Player` (re)spawns
+    spawn: Point3f,
 }
 
 impl Player {
-    pub fn new(context: Rc<GameContext>, world_manager: WorldManager) -> Self {
+    /// Creates the `Player` standing at the given spawn point
+    pub fn new(context: Rc<GameContext>, world_manager: WorldManager, spawn: Point3f) -> Self {
+        let mut cam = Camera::new(context.get_config().resolution.aspect_ratio());
+        cam.position = eye_at(spawn);
         Player {
-            cam: Camera::new(context.get_config().resolution.aspect_ratio()),
+            cam: cam,
             world_manager: world_manager,
             context: context,
             timer_velx: 1.0,
@@ -40,51 +60,25 @@ impl Player {
             mouselock: false,
             shift_speed: 1.0,
             step_size: 1.0,
+            on_ground: false,
+            in_water: false,
+            spawn: spawn,
         }
     }
 
-    // Return the `Pillar` at the given `Vector2`
-    pub fn get_ground_height_at(&mut self,
-                                add_vec: Vector2f)
-                                -> (Option<f32>, Option<f32>, Option<f32>) {
-        let mut height = 0.0;
-        let mut above = 0.0;
-        let world = self.world_manager.get_world();
-        let real_pos = Point2f::new(self.cam.position.x + add_vec.x,
-                                    self.cam.position.y + add_vec.y);
-        let pillar_index = PillarIndex(AxialPoint::from_real(real_pos));
-        let vec_len =
-            world.pillar_at(pillar_index).map(|pillar| pillar.sections().len()).unwrap_or(0);
-
-        let pillar_vec = world.pillar_at(pillar_index).map(|pillar| pillar.sections());
-
-        if pillar_vec.is_some() {
-            let new_pillar_vec = pillar_vec.unwrap();
-
-            if vec_len == 1 {
-                height = new_pillar_vec[0].top.to_real();
-                above = f32::INFINITY;
-            } else {
-                for i in 0..vec_len {
-                    if i != vec_len - 1 {
-                        if new_pillar_vec[i].top.to_real() < self.cam.position.z &&
-                           self.cam.position.z < new_pillar_vec[i + 1].bottom.to_real() {
-                            height = new_pillar_vec[i].top.to_real();
-                            above = new_pillar_vec[i + 1].bottom.to_real();
-                            break;
-                        } else {
-                            continue;
-                        }
-                    } else {
-                        height = new_pillar_vec[i].top.to_real();
-                        above = f32::INFINITY;
-                        break;
-                    }
-                }
-            }
-        }
+    /// Moves the `Player` back to the spawn point and stops all movement
+    pub fn respawn(&mut self) {
+        self.cam.position = eye_at(self.spawn);
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.on_ground = false;
+        self.in_water = false;
+    }
 
-        (Some(height), Some(above), Some(above - height))
+    /// Returns the bounding box of the `Player`'s body
+    fn bounding_box(&self) -> Aabb {
+        let eye = self.cam.position;
+        let feet = Point3f::new(eye.x, eye.y, eye.z - EYE_HEIGHT);
+        Aabb::standing_at(feet, PLAYER_HALF_WIDTH, PLAYER_HEIGHT)
     }
 
     /// Getter method for the `Camera`
@@ -98,14 +92,6 @@ impl Player {
     /// Update the `Player` after every iteration
     pub fn update(&mut self, delta: f32) {
 
-        // Get current pillar floor (`height`) and the ceiling (`above`)
-        let height = (self.get_ground_height_at(Vector2 { x: 0.0, y: 0.0 }).0).unwrap_or(0.0) +
-                     1.75;
-        let above = (self.get_ground_height_at(Vector2 { x: 0.0, y: 0.0 }).1).unwrap_or(0.0);
-
-        // Calculate `angle` for the next `Pillar`
-        let angle: f32 = (60.0 as f32).to_radians();
-
         // Move the Player forward or backward with the acceleration and delta
         // (1.0 - (-((self.timer_vel * delta) / (1.0))).exp()) -> this is a formula
         // that calculates a
@@ -159,162 +145,55 @@ impl Player {
 
         }
 
-        // Let the `Player` jump with the given start-velocity
-        if self.velocity.z != 0.0 {
-
-            self.velocity.z += (-delta * GRAVITY) * 0.2;
-
-            if self.cam.position.z + self.velocity.z > above {
-                self.velocity.z = 0.0;
-            } else {
-                self.cam.move_up(self.velocity.z);
-            }
-            // Needed: Update to reflect multiple level pillars
-            if self.cam.position.z + self.velocity.z < height {
-                self.velocity.z = 0.0;
-            }
-        }
-
-        // Check if the `Player` is higher than the actual `Pillar` on which he is
-        // standing and let him fall on that
-        if self.cam.position.z > height && self.velocity.z == 0.0 {
-            self.velocity.z += (-delta * GRAVITY) / 16.0;
-            if self.cam.position.z + self.velocity.z < height {
-                self.velocity.z = 0.0;
-            }
-            self.cam.move_down(self.velocity.z);
+        if self.cam.position.z - EYE_HEIGHT < RESPAWN_DEPTH {
+            info!("fell out of the world, respawning");
+            self.respawn();
         }
 
-        // Calculate the vectors arround the `Player` in the direction where he is
-        // moving
-        let vec_0 = Vector2 {
-            x: self.cam.phi.cos(),
-            y: self.cam.phi.sin(),
-        };
-        let vec_60 = Vector2 {
-            x: vec_0.x * angle.cos() + vec_0.y * angle.sin(),
-            y: vec_0.x * (-(angle.sin())) + vec_0.y * angle.cos(),
-        };
-        let vec_120 = Vector2 {
-            x: vec_60.x * angle.cos() + vec_60.y * angle.sin(),
-            y: vec_60.x * (-(angle.sin())) + vec_60.y * angle.cos(),
-        };
-        let vec_180 = Vector2 {
-            x: vec_120.x * angle.cos() + vec_120.y * angle.sin(),
-            y: vec_120.x * (-(angle.sin())) + vec_120.y * angle.cos(),
-        };
-        let vec_240 = Vector2 {
-            x: vec_180.x * angle.cos() + vec_180.y * angle.sin(),
-            y: vec_180.x * (-(angle.sin())) + vec_180.y * angle.cos(),
-        };
-        let vec_300 = Vector2 {
-            x: vec_240.x * angle.cos() + vec_240.y * angle.sin(),
-            y: vec_240.x * (-(angle.sin())) + vec_240.y * angle.cos(),
-        };
-
-        // Return the six `Pillar`s around the `Player`
-        let pillar_0 = (vec_0,
-                        self.get_ground_height_at(vec_0).0.unwrap_or(0.0),
-                        self.get_ground_height_at(vec_0).2.unwrap_or(0.0));
-        let pillar_60 = (vec_60,
-                         self.get_ground_height_at(vec_60).0.unwrap_or(0.0),
-                         self.get_ground_height_at(vec_60).2.unwrap_or(0.0));
-        let pillar_120 = (vec_120,
-                          self.get_ground_height_at(vec_120).0.unwrap_or(0.0),
-                          self.get_ground_height_at(vec_120).2.unwrap_or(0.0));
-        let pillar_180 = (vec_180,
-                          self.get_ground_height_at(vec_180).0.unwrap_or(0.0),
-                          self.get_ground_height_at(vec_180).2.unwrap_or(0.0));
-        let pillar_240 = (vec_240,
-                          self.get_ground_height_at(vec_240).0.unwrap_or(0.0),
-                          self.get_ground_height_at(vec_240).2.unwrap_or(0.0));
-        let pillar_300 = (vec_300,
-                          self.get_ground_height_at(vec_300).0.unwrap_or(0.0),
-                          self.get_ground_height_at(vec_300).2.unwrap_or(0.0));
-
-        // Collison-detection
-        // Move forward, compare the front `Pillar` and the two side `Pillar`s
-        if (self.velocity.x > 0.001 &&
-            (self.get_ground_height_at(Vector2 {
-                x: vec_0.x - 0.2,
-                y: vec_0.y - 0.1,
-            })
-            .0
-            .unwrap_or(0.0) > height + self.step_size ||
-             self.get_ground_height_at(Vector2 {
-                x: vec_0.x + 0.2,
-                y: vec_0.y + 0.1,
-            })
-            .0
-            .unwrap_or(0.0) > height + self.step_size ||
-             pillar_0.1 > height + self.step_size)) || (pillar_0.2 < 3.0) {
-            if (self.velocity.y > 0.001 && pillar_60.1 > height + self.step_size) ||
-               pillar_60.2 < 3.0 {
-                self.velocity.x = 0.0;
-                self.velocity.y = 0.0;
-            }
-            if (self.velocity.y < -0.001 && pillar_300.1 > height + self.step_size) ||
-               pillar_300.2 < 3.0 {
-                self.velocity.x = 0.0;
-                self.velocity.y = 0.0;
-            } else {
-                self.velocity.x = 0.0;
-            }
-        }
-
-        // Move right, compare the front `Pillar`
-        if (self.velocity.y > 0.001 &&
-            (pillar_60.1 > height + self.step_size || pillar_120.1 > height + self.step_size)) ||
-           (pillar_60.2 < 3.0 || pillar_120.2 < 3.0) {
-            self.velocity.y = 0.0;
+        // Don't move until the ground below the `Player` is loaded, so that
+        // the `Player` can't fall through it
+        let world = self.world_manager.get_world();
+        let pos = Point2f::new(self.cam.position.x, self.cam.position.y);
+        if world.pillar_at(PillarIndex(AxialPoint::from_real(pos))).is_none() {
+            return;
         }
 
-        // Move backward, compare the `Pillar` behind and the two side `Pillar`s
-        if (self.velocity.x < -0.001 &&
-            (self.get_ground_height_at(Vector2 {
-                x: vec_180.x - 0.2,
-                y: vec_180.y - 0.1,
-            })
-            .0
-            .unwrap_or(0.0) > height + self.step_size ||
-             self.get_ground_height_at(Vector2 {
-                x: vec_180.x + 0.2,
-                y: vec_180.y + 0.1,
-            })
-            .0
-            .unwrap_or(0.0) > height + self.step_size ||
-             pillar_180.1 > height + self.step_size)) || pillar_180.2 < 3.0 {
-            if (self.velocity.y > 0.001 && pillar_120.1 > height + self.step_size) ||
-               pillar_120.2 < 3.0 {
-                self.velocity.x = 0.0;
-                self.velocity.y = 0.0;
-            }
-            if (self.velocity.y < -0.001 && pillar_240.1 > height + self.step_size) ||
-               pillar_240.2 < 3.0 {
-                self.velocity.x = 0.0;
-                self.velocity.y = 0.0;
-            } else {
-                self.velocity.x = 0.0;
-            }
+        // Gravity, which is weakened by the buoyancy in water
+        if self.in_water {
+            self.velocity.z -= delta * GRAVITY * 0.2 * WATER_GRAVITY_FACTOR;
+            self.velocity.z = self.velocity.z.max(-MAX_SINK_VELOCITY);
+        } else {
+            self.velocity.z -= delta * GRAVITY * 0.2;
         }
 
-        // Move right, compare the front `Pillar`
-        if (self.velocity.y < -0.001 &&
-            (pillar_240.1 > height + self.step_size || pillar_300.1 > height + self.step_size)) ||
-           (pillar_240.2 < 3.0 || pillar_300.2 < 3.0) {
-            self.velocity.y = 0.0;
+        // Move the `Player` with the given velocity relative to the viewing
+        // direction and let the physics resolve all collisions
+        let forward = Vector3f::new(self.cam.phi.cos(), self.cam.phi.sin(), 0.0);
+        let right = Vector3f::new(self.cam.phi.sin(), -self.cam.phi.cos(), 0.0);
+        let motion = forward * self.velocity.x + right * self.velocity.y +
+                     Vector3f::new(0.0, 0.0, self.velocity.z);
+        let result = physics::move_aabb(&world, self.bounding_box(), motion, self.step_size);
+
+        let feet = result.aabb.feet();
+        self.cam.position = eye_at(feet);
+
+        // Stop falling when landing and stop jumping when hitting the ceiling
+        self.on_ground = result.on_ground;
+        self.in_water = result.in_fluid;
+        if self.on_ground && self.velocity.z < 0.0 {
+            self.velocity.z = 0.0;
         }
-
-        if self.cam.position.z < height && self.velocity.z == 0.0 &&
-           height - self.cam.position.z < 3.0 {
-            self.cam.position.z = height;
+        if result.contacts.iter().any(|contact| contact.normal.z < 0.0) && self.velocity.z > 0.0 {
+            self.velocity.z = 0.0;
         }
-
-        // Move the `Player` with the given velocity
-        self.cam.move_forward(self.velocity.x);
-        self.cam.move_right(self.velocity.y);
     }
 }
+
+/// Returns the position of the camera of a `Player` whose feet are at `feet`
+fn eye_at(feet: Point3f) -> Point3f {
+    Point3f::new(feet.x, feet.y, feet.z + EYE_HEIGHT)
+}
+
 /// `EventHandler` for the `Player`
 impl EventHandler for Player {
     fn handle_event(&mut self, e: &Event) -> EventResponse {
@@ -352,8 +231,10 @@ impl EventHandler for Player {
                 EventResponse::Continue
             }
             Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Space)) => {
-                if self.velocity.z == 0.0 {
+                if self.on_ground {
                     self.velocity.z = 0.7;
+                } else if self.in_water {
+                    self.velocity.z = 0.3;
                 }
                 EventResponse::Continue
             }
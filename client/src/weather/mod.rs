@@ -5,7 +5,7 @@ use glium::draw_parameters::{BlendingFunction, DepthTest};
 use super::camera::Camera;
 use util::ToArr;
 use base::math::*;
-use base::gen::world::biome;
+use base::gen::world::biome::{BiomeId, WeatherKind, WeatherStrength, biomes};
 use base::world::PillarIndex;
 use GameContext;
 use DayTime;
@@ -152,7 +152,7 @@ pub struct Weather {
     wind_speed: f32,
     delta_time: f32,
     weather_time: f32,
-    last_biome: biome::Biome,
+    last_biome: BiomeId,
     change: bool,
     sun_color: Vector3f,
     sky_light: Vector3f,
@@ -186,7 +186,7 @@ impl Weather {
             wind_speed: (rand::random::<f32>() + 0.2) * 5.0,
             delta_time: 0.0,
             weather_time: 0.0,
-            last_biome: biome::Biome::GrassLand,
+            last_biome: biomes::GRASS_LAND,
             change: false,
             sun_color: Vector3f::new(0.0, 0.0, 0.0),
             sky_light: Vector3f::new(0.0, 0.0, 0.0),
@@ -291,121 +291,28 @@ impl Weather {
             let biome = pillar_vec.unwrap().biome();
             self.weather_time += delta;
 
-            if *biome != self.last_biome && self.weather_time > 5.0 || self.weather_time >= 120.0 {
+            if biome != self.last_biome && self.weather_time > 5.0 || self.weather_time >= 120.0 {
                 let chance = rand::random::<f32>() * 100.0;
-                match *biome {
-                    biome::Biome::GrassLand => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::GrassLand;
-                        self.strength = match chance {
-                            0.0...5.0 => Strength::Weak,
-                            5.0...8.0 => Strength::Medium,
-                            8.0...10.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Desert => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::Desert;
-                        self.strength = match chance {
-                            0.0...1.0 => Strength::Weak,
-                            1.0...2.0 => Strength::Medium,
-                            2.0...4.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Snow => {
-                        if (self.form == Form::Pollen || self.form == Form::Rain) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Snow;
-                        self.last_biome = biome::Biome::Snow;
-                        self.strength = match chance {
-                            0.0...20.0 => Strength::Weak,
-                            20.0...50.0 => Strength::Medium,
-                            50.0...75.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Forest => {
-                        if (self.form == Form::Rain || self.form == Form::Snow) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Pollen;
-                        self.last_biome = biome::Biome::Forest;
-                        self.strength = match chance {
-                            0.0...15.0 => Strength::Weak,
-                            15.0...35.0 => Strength::Medium,
-                            35.0...65.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::RainForest => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::RainForest;
-                        self.strength = match chance {
-                            0.0...21.0 => Strength::Weak,
-                            21.0...51.0 => Strength::Medium,
-                            51.0...81.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Savanna => {
-                        if (self.form == Form::Rain || self.form == Form::Snow) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Pollen;
-                        self.last_biome = biome::Biome::Savanna;
-                        self.strength = match chance {
-                            0.0...5.0 => Strength::Weak,
-                            5.0...7.0 => Strength::Medium,
-                            7.0...9.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Stone => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow) &&
-                           self.particles.len() > 0 {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::Stone;
-                        self.strength = match chance {
-                            0.0...7.0 => Strength::Weak,
-                            7.0...12.0 => Strength::Medium,
-                            12.0...17.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
+                let weather = self.context.get_biomes().get(biome).and_then(|b| b.weather);
+                if let Some(weather) = weather {
+                    let form = match weather.kind {
+                        WeatherKind::Rain => Form::Rain,
+                        WeatherKind::Snow => Form::Snow,
+                        WeatherKind::Pollen => Form::Pollen,
+                    };
+                    // The particles of the old form have to disappear first
+                    if self.form != form && self.particles.len() > 0 {
+                        self.change = true;
+                        return;
                     }
-                    biome::Biome::Debug => (),
+                    self.form = form;
+                    self.last_biome = biome;
+                    self.strength = match weather.strength(chance) {
+                        Some(WeatherStrength::Weak) => Strength::Weak,
+                        Some(WeatherStrength::Medium) => Strength::Medium,
+                        Some(WeatherStrength::Heavy) => Strength::Heavy,
+                        None => Strength::None,
+                    };
                 }
                 self.weather_time = 0.0;
             }
@@ -7,7 +7,7 @@ use GameContext;
 use std::rc::Rc;
 use super::tex_generator;
 use super::normal_converter;
-use base::world::ground::GroundMaterial;
+use base::world::ground::{MaterialRegistry, TextureParams};
 use base::math::*;
 
 pub struct ChunkRenderer {
@@ -15,6 +15,7 @@ pub struct ChunkRenderer {
     program: Program,
     /// Shadow map shader
     shadow_program: Program,
+    context: Rc<GameContext>,
     pub noise_sand: Texture2d,
     pub noise_snow: Texture2d,
     pub noise_grass: Texture2d,
@@ -33,13 +34,16 @@ pub struct ChunkRenderer {
 
 impl ChunkRenderer {
     pub fn new(context: Rc<GameContext>) -> Self {
-        // Get a tupel of a heightmap and texturemap
-        let sand = tex_generator::create_texture_maps(GroundMaterial::Sand);
-        let snow = tex_generator::create_texture_maps(GroundMaterial::Snow);
-        let grass = tex_generator::create_texture_maps(GroundMaterial::Grass);
-        let stone = tex_generator::create_texture_maps(GroundMaterial::Stone);
-        let dirt = tex_generator::create_texture_maps(GroundMaterial::Dirt);
-        let mulch = tex_generator::create_texture_maps(GroundMaterial::Mulch);
+        let materials = context.get_materials();
+
+        // Get a tupel of a heightmap and texturemap for every texture slot of
+        // the chunk shader
+        let grass = texture_maps(materials, 1);
+        let sand = texture_maps(materials, 2);
+        let snow = texture_maps(materials, 3);
+        let dirt = texture_maps(materials, 4);
+        let stone = texture_maps(materials, 5);
+        let mulch = texture_maps(materials, 7);
 
         ChunkRenderer {
             program: context.load_program("chunk_std").unwrap(),
@@ -71,10 +75,16 @@ impl ChunkRenderer {
             normal_mulch: Texture2d::new(context.get_facade(),
                                          normal_converter::convert(mulch.0, 1.0))
                 .unwrap(),
-            outline: HexagonOutline::new(context),
+            outline: HexagonOutline::new(context.clone()),
+            context: context.clone(),
         }
     }
 
+    /// Gets the registry of all ground materials.
+    pub fn materials(&self) -> &MaterialRegistry {
+        self.context.get_materials()
+    }
+
     /// Gets a reference to the shared chunk shader.
     pub fn program(&self) -> &Program {
         &self.program
@@ -85,6 +95,18 @@ impl ChunkRenderer {
     }
 }
 
+/// Creates the height and texture map for the given texture slot with the
+/// parameters of the first material using that slot.
+fn texture_maps(materials: &MaterialRegistry,
+                slot: u8)
+                -> (Vec<Vec<f32>>, Vec<Vec<(f32, f32, f32)>>) {
+    let params = materials.iter()
+        .map(|m| m.texture)
+        .find(|t| t.slot == slot)
+        .unwrap_or(TextureParams { slot: slot, ..TextureParams::default() });
+    tex_generator::create_texture_maps(&params)
+}
+
 pub struct HexagonOutline {
     program: Program,
     vbuf: VertexBuffer<OutlineVertex>,
@@ -1,5 +1,5 @@
 use base::math::*;
-use base::world::ground::GroundMaterial;
+use base::world::ground::{Material, MaterialRegistry};
 use base::world::{
     Chunk,
     CHUNK_SIZE,
@@ -14,22 +14,30 @@ use base::world::{
 use Camera;
 use DayTime;
 use glium::backend::Facade;
-use glium::draw_parameters::{BackfaceCullingMode, DepthTest};
+use glium::draw_parameters::{BackfaceCullingMode, BlendingFunction, DepthTest};
 use glium::index::PrimitiveType;
 use glium::texture::Texture2d;
 use glium::uniforms::MinifySamplerFilter;
 use glium::uniforms::SamplerWrapFunction;
-use glium::{self, DrawParameters, IndexBuffer, VertexBuffer};
+use glium::{self, DrawParameters, IndexBuffer, LinearBlendingFactor, VertexBuffer};
 use std::rc::Rc;
 use util::ToArr;
 use world::ChunkRenderer;
 
+/// The opacity of water surfaces.
+const WATER_ALPHA: f32 = 0.6;
+
 /// Graphical representation of the `base::Chunk`.
+///
+/// Solid ground and water are kept in separate buffers, because water is
+/// translucent and has to be drawn after all solid geometry.
 pub struct ChunkView {
     offset: AxialPoint,
     renderer: Rc<ChunkRenderer>,
     vertex_buf: VertexBuffer<Vertex>,
     index_buf: IndexBuffer<u32>,
+    water_vertex_buf: VertexBuffer<Vertex>,
+    water_index_buf: IndexBuffer<u32>,
 }
 
 impl ChunkView {
@@ -40,7 +48,8 @@ impl ChunkView {
                                  chunk_renderer: Rc<ChunkRenderer>,
                                  facade: &F)
                                  -> Self {
-        let (raw_buf, raw_indices) = get_vertices(chunk);
+        let (raw_buf, raw_indices) = get_vertices(chunk, chunk_renderer.materials());
+        let (water_buf, water_indices) = get_water_vertices(chunk, chunk_renderer.materials());
 
         ChunkView {
             offset: offset,
@@ -49,6 +58,10 @@ impl ChunkView {
             index_buf: IndexBuffer::new(facade,
                                       PrimitiveType::TrianglesList,
                                       &raw_indices).unwrap(),
+            water_vertex_buf: VertexBuffer::new(facade, &water_buf).unwrap(),
+            water_index_buf: IndexBuffer::new(facade,
+                                            PrimitiveType::TrianglesList,
+                                            &water_indices).unwrap(),
         }
     }
 
@@ -85,12 +98,17 @@ impl ChunkView {
 
     pub fn update<F: Facade>(&mut self, facade: &F, world: &World) {
         let chunk = world.chunk_at(ChunkIndex(self.offset)).unwrap();
-        let (vbuf, ibuf) = get_vertices(&chunk);
+        let (vbuf, ibuf) = get_vertices(&chunk, self.renderer.materials());
+        let (water_vbuf, water_ibuf) = get_water_vertices(&chunk, self.renderer.materials());
 
         self.vertex_buf = VertexBuffer::new(facade, &vbuf).unwrap();
         self.index_buf = IndexBuffer::new(facade,
                                   PrimitiveType::TrianglesList,
                                   &ibuf).unwrap();
+        self.water_vertex_buf = VertexBuffer::new(facade, &water_vbuf).unwrap();
+        self.water_index_buf = IndexBuffer::new(facade,
+                                        PrimitiveType::TrianglesList,
+                                        &water_ibuf).unwrap();
 
     }
 
@@ -101,6 +119,77 @@ impl ChunkView {
                                    depth_view_proj: &Matrix4<f32>,
                                    daytime: &DayTime,
                                    sun_dir: Vector3f) {
+        let params = DrawParameters {
+            depth: glium::Depth {
+                write: true,
+                test: DepthTest::IfLess,
+                ..Default::default()
+            },
+            backface_culling: BackfaceCullingMode::CullCounterClockwise,
+            ..Default::default()
+        };
+
+        self.draw_buffers(surface,
+                          camera,
+                          shadow_map,
+                          depth_view_proj,
+                          daytime,
+                          sun_dir,
+                          (&self.vertex_buf, &self.index_buf),
+                          &params);
+    }
+
+    /// Draws the water surfaces of this chunk. This has to be done after all
+    /// solid geometry was drawn, because water is translucent.
+    pub fn draw_water<S: glium::Surface>(&self,
+                                         surface: &mut S,
+                                         camera: &Camera,
+                                         shadow_map: &Texture2d,
+                                         depth_view_proj: &Matrix4<f32>,
+                                         daytime: &DayTime,
+                                         sun_dir: Vector3f) {
+        if self.water_index_buf.len() == 0 {
+            return;
+        }
+
+        // The surface is visible from below, too, and doesn't hide anything
+        // behind it
+        let params = DrawParameters {
+            depth: glium::Depth {
+                write: false,
+                test: DepthTest::IfLess,
+                ..Default::default()
+            },
+            blend: glium::Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::ConstantAlpha,
+                    destination: LinearBlendingFactor::OneMinusConstantAlpha,
+                },
+                constant_value: (0.0, 0.0, 0.0, WATER_ALPHA),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        self.draw_buffers(surface,
+                          camera,
+                          shadow_map,
+                          depth_view_proj,
+                          daytime,
+                          sun_dir,
+                          (&self.water_vertex_buf, &self.water_index_buf),
+                          &params);
+    }
+
+    fn draw_buffers<S: glium::Surface>(&self,
+                                       surface: &mut S,
+                                       camera: &Camera,
+                                       shadow_map: &Texture2d,
+                                       depth_view_proj: &Matrix4<f32>,
+                                       daytime: &DayTime,
+                                       sun_dir: Vector3f,
+                                       buffers: (&VertexBuffer<Vertex>, &IndexBuffer<u32>),
+                                       params: &DrawParameters) {
         let real_off = self.offset.to_real();
         let look_at2 = Vector2::new(camera.get_look_at_vector().x, camera.get_look_at_vector().y)
             .normalize();
@@ -150,22 +239,13 @@ impl ChunkView {
             normal_dirt: &self.renderer.normal_dirt,
             normal_mulch: &self.renderer.normal_mulch,
         };
-        let params = DrawParameters {
-            depth: glium::Depth {
-                write: true,
-                test: DepthTest::IfLess,
-                ..Default::default()
-            },
-            backface_culling: BackfaceCullingMode::CullCounterClockwise,
-            ..Default::default()
-        };
 
         surface.draw(
-            &self.vertex_buf,
-            &self.index_buf,
+            buffers.0,
+            buffers.1,
             self.renderer.program(),
             &uniforms,
-            &params).unwrap();
+            params).unwrap();
     }
 }
 
@@ -263,53 +343,18 @@ const CORNER_UV: &'static [[f32; 2]] = &[
 ];
 
 /// We add faces for sides to neighbors in these directions.
-const SIDE_PROPAGATION_NEIGHBORS: &'static [EdgeDir] = &[
-    EdgeDir::BottomRight,
-    EdgeDir::Right,
-    EdgeDir::TopRight,
+const SIDE_PROPAGATION_NEIGHBORS: &'static [HexDirection] = &[
+    HexDirection::BottomRight,
+    HexDirection::Right,
+    HexDirection::TopRight,
 ];
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-enum EdgeDir {
-    TopLeft,
-    TopRight,
-    Right,
-    BottomRight,
-    BottomLeft,
-    Left,
-}
-
-impl EdgeDir {
-    /// Index for lookup in the const arrays above.
-    fn idx(&self) -> usize {
-        match *self {
-            EdgeDir::TopLeft => 0,
-            EdgeDir::TopRight => 1,
-            EdgeDir::Right => 2,
-            EdgeDir::BottomRight => 3,
-            EdgeDir::BottomLeft => 4,
-            EdgeDir::Left => 5,
-        }
-    }
-
-    /// The corresponding vector for the direction.
-    fn axial_vec(&self) -> AxialVector {
-        match *self {
-            EdgeDir::TopLeft        => AxialVector::new( 0,  1),
-            EdgeDir::TopRight       => AxialVector::new( 1,  1),
-            EdgeDir::Right          => AxialVector::new( 1,  0),
-            EdgeDir::BottomRight    => AxialVector::new( 0, -1),
-            EdgeDir::BottomLeft     => AxialVector::new(-1, -1),
-            EdgeDir::Left           => AxialVector::new(-1,  0),
-        }
-    }
-}
-
 // --------------------------------------------------------------------------
 // We have a few functions to create both buffers.
 
-/// Generates a pair of vertex and index buffer which represent the given
-/// `Chunk`.
+/// Generates a pair of vertex and index buffer which represent the solid
+/// parts of the given `Chunk`. Fluid sections are left out, see
+/// `get_water_vertices`.
 ///
 /// This function (and the other helper functions) assume a few important
 /// things about the given `Chunk`:
@@ -332,7 +377,19 @@ impl EdgeDir {
 /// with the neighbor pillar under special circumstances. Another optimization
 /// is a bit more ugly: we could connect side pieces with the same position and
 /// orientation. Sadly this "creates" geometry inside our blobs of world.
-fn get_vertices(chunk: &Chunk) -> (Vec<Vertex>, Vec<u32>) {
+fn get_vertices(chunk: &Chunk, materials: &MaterialRegistry) -> (Vec<Vertex>, Vec<u32>) {
+    // Sides between ground and water have to be added as if the water
+    // wasn't there
+    let solid = Chunk::from_pillars(chunk.pillars
+        .iter()
+        .map(|pillar| {
+            let mut pillar = pillar.clone();
+            pillar.sections_mut().retain(|section| !section.ground.is_fluid());
+            pillar
+        })
+        .collect());
+    let chunk = &solid;
+
     // Make a crude guess how many vertices we will need. This assumes that the
     // chunk has at least one pillar section per pillar.
     //
@@ -344,15 +401,39 @@ fn get_vertices(chunk: &Chunk) -> (Vec<Vertex>, Vec<u32>) {
     let mut indices = Vec::with_capacity(minimal_ilen);
 
     Chunk::for_pillars_positions(|pos| {
-        add_top_and_bottom_face(pos, &chunk[pos], &mut vertices, &mut indices);
+        add_top_and_bottom_face(pos, &chunk[pos], materials, &mut vertices, &mut indices);
 
         // We only do this in one direction to handle every edge only once.
         for &dir in SIDE_PROPAGATION_NEIGHBORS {
-            connect_pillars(pos, dir, chunk, &mut vertices, &mut indices);
+            connect_pillars(pos, dir, chunk, materials, &mut vertices, &mut indices);
         }
 
         // Add sides to the outside if this pillar on the outer edge.
-        add_outer_shell(pos, &chunk[pos], &mut vertices, &mut indices);
+        add_outer_shell(pos, &chunk[pos], materials, &mut vertices, &mut indices);
+    });
+
+    (vertices, indices)
+}
+
+/// Generates a pair of vertex and index buffer which contain the surfaces
+/// of all fluid sections in the given `Chunk`.
+///
+/// Only the top faces are needed: fluid sections always rest on solid ground
+/// and their sides are hidden by the neighbouring terrain or water in almost
+/// all cases.
+fn get_water_vertices(chunk: &Chunk, materials: &MaterialRegistry) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    Chunk::for_pillars_positions(|pos| {
+        for sec in chunk[pos].sections().iter().filter(|sec| sec.ground.is_fluid()) {
+            add_face(pos,
+                     sec.top,
+                     false,
+                     materials.material(sec.ground),
+                     &mut vertices,
+                     &mut indices);
+        }
     });
 
     (vertices, indices)
@@ -363,63 +444,71 @@ fn get_vertices(chunk: &Chunk) -> (Vec<Vertex>, Vec<u32>) {
 fn add_top_and_bottom_face(
     pos: AxialPoint,
     pillar: &HexPillar,
+    materials: &MaterialRegistry,
     vertices: &mut Vec<Vertex>,
     indices: &mut Vec<u32>
 ) {
     for sec in pillar.sections() {
-        let ground = sec.ground.get_id();
+        let material = materials.material(sec.ground);
 
-        // Add top and bottom face
-        let face_props = [
-            (sec.top, [0.0, 0.0, 1.0], false),
-            (sec.bottom, [0.0, 0.0, -1.0], true)
-        ];
-
-        for &(height, normal, rev) in &face_props {
-            let prev_len = vertices.len() as u32;
-
-            // We completely skip all faces at height 0
-            if height.units() == 0 {
-                continue;
+        // We completely skip all faces at height 0
+        for &(height, rev) in &[(sec.top, false), (sec.bottom, true)] {
+            if height.units() != 0 {
+                add_face(pos, height, rev, material, vertices, indices);
             }
+        }
+    }
+}
 
-            // Add center point
-            vertices.push(Vertex {
-                position: [pos.to_real().x, pos.to_real().y, height.to_real()],
-                normal: normal,
-                radius: 0.0,
-                tex_coords: [0.5, 0.5],
-                material_color: sec.ground.get_color(),
-                ground: ground,
-            });
+/// Adds a single horizontal face at the given height. The face points
+/// downwards if `rev` is `true` and upwards otherwise.
+fn add_face(
+    pos: AxialPoint,
+    height: HeightType,
+    rev: bool,
+    material: &Material,
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>
+) {
+    let prev_len = vertices.len() as u32;
+    let ground = material.texture.slot as i32;
+    let normal = if rev { [0.0, 0.0, -1.0] } else { [0.0, 0.0, 1.0] };
+
+    // Add center point
+    vertices.push(Vertex {
+        position: [pos.to_real().x, pos.to_real().y, height.to_real()],
+        normal: normal,
+        radius: 0.0,
+        tex_coords: [0.5, 0.5],
+        material_color: material.color,
+        ground: ground,
+    });
 
-            // Iterate over all corners with position and uv coordinates
-            let iter = NORM_CORNERS.iter()
-                .map(|c| c * HEX_OUTER_RADIUS)
-                .zip(CORNER_UV).enumerate();
-            for (i, (corner, &uv)) in iter {
-                let i = i as u32;
-                let pos2d = pos.to_real() + corner;
-
-                vertices.push(Vertex {
-                    position: [pos2d.x, pos2d.y, height.to_real()],
-                    normal: normal,
-                    radius: 1.0,
-                    tex_coords: uv,
-                    material_color: sec.ground.get_color(),
-                    ground: ground,
-                });
-
-                if rev {
-                    indices.push(prev_len);
-                    indices.push(prev_len + ((i + 1) % 6) + 1);
-                    indices.push(prev_len + i + 1);
-                } else {
-                    indices.push(prev_len);
-                    indices.push(prev_len + i + 1);
-                    indices.push(prev_len + ((i + 1) % 6) + 1);
-                }
-            }
+    // Iterate over all corners with position and uv coordinates
+    let iter = NORM_CORNERS.iter()
+        .map(|c| c * HEX_OUTER_RADIUS)
+        .zip(CORNER_UV).enumerate();
+    for (i, (corner, &uv)) in iter {
+        let i = i as u32;
+        let pos2d = pos.to_real() + corner;
+
+        vertices.push(Vertex {
+            position: [pos2d.x, pos2d.y, height.to_real()],
+            normal: normal,
+            radius: 1.0,
+            tex_coords: uv,
+            material_color: material.color,
+            ground: ground,
+        });
+
+        if rev {
+            indices.push(prev_len);
+            indices.push(prev_len + ((i + 1) % 6) + 1);
+            indices.push(prev_len + i + 1);
+        } else {
+            indices.push(prev_len);
+            indices.push(prev_len + i + 1);
+            indices.push(prev_len + ((i + 1) % 6) + 1);
         }
     }
 }
@@ -429,22 +518,14 @@ fn add_top_and_bottom_face(
 fn add_outer_shell(
     pos: AxialPoint,
     pillar: &HexPillar,
+    materials: &MaterialRegistry,
     vertices: &mut Vec<Vertex>,
     indices: &mut Vec<u32>,
 ) {
-    let neighbors = [
-        EdgeDir::TopLeft,
-        EdgeDir::TopRight,
-        EdgeDir::Right,
-        EdgeDir::BottomRight,
-        EdgeDir::BottomLeft,
-        EdgeDir::Left,
-    ];
-
-    for &neighbor in &neighbors {
+    for &neighbor in &HEX_DIRECTIONS {
         // Check if this neighbor is outside of the current chunk. If yes, we
         // need to add sides, otherwise we skip it.
-        let neighbor_pos = pos + neighbor.axial_vec();
+        let neighbor_pos = pos.neighbor(neighbor);
         let is_outer =
             neighbor_pos.q >= CHUNK_SIZE.into()
             || neighbor_pos.r >= CHUNK_SIZE.into()
@@ -456,7 +537,7 @@ fn add_outer_shell(
                 add_side(
                     sec.bottom,
                     sec.top,
-                    sec.ground,
+                    materials.material(sec.ground),
                     false,
                     neighbor,
                     pos,
@@ -475,13 +556,14 @@ fn add_outer_shell(
 /// all functions in this module. Detailed description inside the function.
 fn connect_pillars(
     a_pos: AxialPoint,
-    a_to_b: EdgeDir,
+    a_to_b: HexDirection,
     chunk: &Chunk,
+    materials: &MaterialRegistry,
     vertices: &mut Vec<Vertex>,
     indices: &mut Vec<u32>,
 ) {
     // We'll call the two pillars 'a' and 'b'.
-    let b_pos = a_pos + a_to_b.axial_vec();
+    let b_pos = a_pos.neighbor(a_to_b);
 
     // If b isn't even inside this chunk, we will skip it. `add_outer_shell`
     // will repair those holes.
@@ -742,7 +824,7 @@ fn connect_pillars(
         add_side(
             lower,
             upper,
-            ab[pillar.idx()].sections()[sec_idx].ground,
+            materials.material(ab[pillar.idx()].sections()[sec_idx].ground),
             normal_to_a,
             a_to_b,
             a_pos,
@@ -753,7 +835,7 @@ fn connect_pillars(
 }
 
 /// This function adds a side with from the height `bottom` to `top` with the
-/// material `ground`. The side is added between the pillars at `offset`
+/// given material. The side is added between the pillars at `offset`
 /// (pillar 'a') and `offset + dir` (pillar 'b'). `normal_to_a` determines
 /// what direction the side is facing: when it's `false`, it's facing 'b' (in
 /// the `dir` direction), when it's `true`, it's facing 'a' (`-dir`). The
@@ -762,16 +844,16 @@ fn connect_pillars(
 fn add_side(
     bottom: HeightType,
     top: HeightType,
-    ground: GroundMaterial,
+    material: &Material,
     normal_to_a: bool,
-    dir: EdgeDir,
+    dir: HexDirection,
     offset: AxialPoint,
     vertices: &mut Vec<Vertex>,
     indices: &mut Vec<u32>,
 ) {
     let prev_len = vertices.len() as u32;
-    let (ca, cb) = EDGE_CORNERS_TO_NEIGHBOR[dir.idx()];
-    let normal = EDGE_NORMALS[dir.idx()] * if normal_to_a { -1.0 } else  { 1.0 };
+    let (ca, cb) = EDGE_CORNERS_TO_NEIGHBOR[dir.index()];
+    let normal = EDGE_NORMALS[dir.index()] * if normal_to_a { -1.0 } else  { 1.0 };
     let corner_cw = [
         (offset.to_real() + ca * HEX_OUTER_RADIUS, 0.25),
         (offset.to_real() + cb * HEX_OUTER_RADIUS, 0.75),
@@ -788,8 +870,8 @@ fn add_side(
                 normal: [normal.x, normal.y, 0.0],
                 radius: 0.0,
                 tex_coords: [u, v],
-                material_color: ground.get_color(),
-                ground: ground.get_id(),
+                material_color: material.color,
+                ground: material.texture.slot as i32,
             });
         }
     }
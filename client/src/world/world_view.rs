@@ -1,5 +1,5 @@
 use base::world::{self, Chunk, ChunkIndex};
-use base::prop::Plant;
+use base::gen::plant::registry::{PlantId, PlantList};
 use base::math::*;
 use glium::backend::Facade;
 use glium::{self, DepthTest, DrawParameters, LinearBlendingFactor};
@@ -27,13 +27,13 @@ pub struct WorldView {
 
     pub outline: HexagonOutline,
 
-    plant_views: HashMap<usize, PlantView>,
+    plant_views: HashMap<PlantId, PlantView>,
 
-    plant_list: Vec<Plant>,
+    plant_list: PlantList,
 }
 
 impl WorldView {
-    pub fn new(context: Rc<GameContext>, plant_list: Vec<Plant>) -> Self {
+    pub fn new(context: Rc<GameContext>, plant_list: PlantList) -> Self {
         let plant_renderer = Rc::new(PlantRenderer::new(context.clone()));
         let chunk_renderer = Rc::new(ChunkRenderer::new(context.clone()));
 
@@ -50,23 +50,27 @@ impl WorldView {
     pub fn refresh_chunk<F: Facade>(&mut self, chunk_pos: ChunkIndex, chunk: &Chunk, facade: &F) {
         self.chunks.insert(chunk_pos,
                            ChunkView::from_chunk(chunk,
-                                                 AxialPoint::new(chunk_pos.0.q *
-                                                                 (1 * world::CHUNK_SIZE as i32),
-                                                                 chunk_pos.0.r *
-                                                                 (1 * world::CHUNK_SIZE as i32)),
+                                                 chunk_pos.origin().0,
                                                  self.chunk_renderer.clone(),
                                                  facade));
 
         for (pillar_pos, pillar) in chunk.pillars() {
             for prop in pillar.props() {
-                let plant_index = prop.plant_index;
-                let plant = &self.plant_list[plant_index];
+                let plant = match self.plant_list.get(prop.plant) {
+                    Some(plant) => plant,
+                    None => {
+                        warn!("prop in chunk {:?} refers to unknown plant {:?}",
+                              chunk_pos,
+                              prop.plant);
+                        continue;
+                    }
+                };
                 let real_pos = pillar_pos.to_real();
 
-                let real_chunk_pos = (chunk_pos.0 * world::CHUNK_SIZE as i32).to_real();
+                let real_chunk_pos = chunk_pos.origin().0.to_real();
 
                 self.plant_views
-                    .entry(plant_index)
+                    .entry(prop.plant)
                     .or_insert(PlantView::from_plant(plant, self.plant_renderer.clone(), facade))
                     .add_instance_from_pos(chunk_pos,
                                            Point3f::new(real_chunk_pos.x + real_pos.x,
@@ -175,5 +179,16 @@ impl WorldView {
                            daytime,
                            sun_dir);
         }
+
+        // Water is translucent and is drawn back to front after everything
+        // solid
+        for chunkview in chunk_list.iter().rev() {
+            chunkview.draw_water(surface,
+                                 camera,
+                                 shadow_map,
+                                 depth_view_proj,
+                                 daytime,
+                                 sun_dir);
+        }
     }
 }
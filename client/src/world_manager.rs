@@ -1,12 +1,11 @@
-use base::world::{Chunk, ChunkProvider, World};
+use base::world::{Chunk, ChunkProvider, SaveFileProvider, World};
 use super::GameContext;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::rc::Rc;
-use std::mem::replace;
 use std::thread;
 use std::cell::{Ref, RefCell};
 use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
-use base::world::{CHUNK_SIZE, ChunkIndex};
+use base::world::{CHUNK_SIZE, ChunkIndex, PillarIndex};
 use base::math::*;
 use world::WorldView;
 use std::cell::RefMut;
@@ -14,7 +13,7 @@ use std::cell::RefMut;
 #[derive(Clone)]
 pub struct WorldManager {
     shared: Rc<RefCell<Shared>>,
-    chunk_requests: Sender<ChunkIndex>,
+    commands: Sender<WorkerCommand>,
     context: Rc<GameContext>,
 }
 
@@ -25,13 +24,21 @@ struct Shared {
     sent_requests: HashSet<ChunkIndex>,
     load_distance: f32,
     player_chunk: ChunkIndex,
+    save_file: Option<SaveFileProvider>,
 }
 
 impl WorldManager {
-    pub fn new(provider: Box<ChunkProvider>, game_context: Rc<GameContext>) -> Self {
-        // Create two channels to send chunk positions and receive chunks.
-        let (chunk_request_sender, chunk_request_recv) = channel();
+    /// Creates a world manager which loads chunks from `provider`. If a save
+    /// file is given, edited chunks are written to it when they are unloaded.
+    /// Both happen in a worker thread.
+    pub fn new(provider: Box<ChunkProvider>,
+               save_file: Option<SaveFileProvider>,
+               game_context: Rc<GameContext>)
+               -> Self {
+        // Create two channels to send commands and receive chunks.
+        let (command_sender, command_recv) = channel();
         let (chunk_sender, chunk_recv) = channel();
+        let worker_save_file = save_file.clone();
 
         let this = WorldManager {
             shared: Rc::new(RefCell::new(Shared {
@@ -42,8 +49,9 @@ impl WorldManager {
                 // TODO: load this from the config!
                 load_distance: 10.0,
                 player_chunk: ChunkIndex(AxialPoint::new(0, 0)),
+                save_file: save_file,
             })),
-            chunk_requests: chunk_request_sender,
+            commands: command_sender,
             context: game_context,
         };
 
@@ -51,7 +59,7 @@ impl WorldManager {
         // that is no problem: once the last sender is destroyed, the worker
         // thread will quit.
         thread::spawn(move || {
-            worker_thread(provider, chunk_request_recv, chunk_sender);
+            worker_thread(provider, worker_save_file, command_recv, chunk_sender);
         });
 
         this.update_player_chunk();
@@ -78,37 +86,62 @@ impl WorldManager {
             load_distance * CHUNK_SIZE as f32
         };
 
-        // Load new range
-        for qd in -radius..radius + 1 {
-            for rd in -radius..radius + 1 {
-                let chunk_pos = AxialPoint::new(player_chunk.q + qd, player_chunk.r + rd);
-                if is_chunk_in_range(chunk_pos) {
-                    let chunk_index = ChunkIndex(chunk_pos);
-
-                    if !shared.world.chunks.contains_key(&chunk_index) {
-                        if !shared.sent_requests.contains(&chunk_index) {
-                            self.chunk_requests.send(chunk_index).unwrap();
-                            shared.sent_requests.insert(chunk_index);
-                        }
+        // Load new range. We walk in a spiral around the player, so that the
+        // closest chunks are requested first.
+        for chunk_pos in spiral(player_chunk, radius) {
+            if is_chunk_in_range(chunk_pos) {
+                let chunk_index = ChunkIndex(chunk_pos);
+
+                if !shared.world.contains_chunk(chunk_index) {
+                    if !shared.sent_requests.contains(&chunk_index) {
+                        self.commands.send(WorkerCommand::Load(chunk_index)).unwrap();
+                        shared.sent_requests.insert(chunk_index);
                     }
                 }
             }
         }
 
         // Drop unneeded chunks from world
-        let chunks = replace(&mut shared.world.chunks, HashMap::new());
-        let mut new_chunks = HashMap::new();
-        for (index, chunk) in chunks {
-            let chunk_pos = index.0;
-            if is_chunk_in_range(chunk_pos) {
-                // Still in range
-                new_chunks.insert(index, chunk);
-            } else {
-                // Remove
+        let out_of_range: Vec<_> = shared.world
+            .chunks()
+            .map(|(&index, _)| index)
+            .filter(|index| !is_chunk_in_range(index.0))
+            .collect();
+        for index in out_of_range {
+            // Chunks which weren't edited can be loaded again from the
+            // save file or the generator
+            let unsaved = shared.world.is_unsaved(index);
+            if let Some(chunk) = shared.world.remove_chunk(index) {
                 shared.world_view.remove_chunk(index);
+                if unsaved && shared.save_file.is_some() {
+                    self.commands.send(WorkerCommand::Save(index, chunk)).unwrap();
+                }
             }
         }
-        shared.world.chunks = new_chunks;
+    }
+
+    /// Writes all loaded chunks which were edited to the save file (if any)
+    /// and waits until the worker thread has written all chunks that were
+    /// sent to it.
+    pub fn save_world(&self) {
+        use std::ops::DerefMut;
+
+        let mut shared_tmp = self.shared.borrow_mut();
+        let shared = shared_tmp.deref_mut();
+        if let Some(ref save_file) = shared.save_file {
+            for index in shared.world.take_unsaved_chunks() {
+                let chunk = shared.world.chunk_at(index).unwrap().clone();
+                self.commands.send(WorkerCommand::Save(index, chunk)).unwrap();
+            }
+
+            let (done_sender, done_recv) = channel();
+            self.commands.send(WorkerCommand::Sync(done_sender)).unwrap();
+            if done_recv.recv().is_err() {
+                error!("chunk providing worker thread shut down before saving");
+                return;
+            }
+            info!("saved the world to {}", save_file.path().display());
+        }
     }
 
 
@@ -121,6 +154,8 @@ impl WorldManager {
         Ref::map(self.shared.borrow(), |shared| &shared.world)
     }
 
+    /// Returns a mutable reference to the world. Changes made with the
+    /// editing methods of `World` are shown with the next `update_world()`.
     pub fn mut_world(&self) -> RefMut<World> {
         RefMut::map(self.shared.borrow_mut(), |shared| &mut shared.world)
     }
@@ -142,13 +177,11 @@ impl WorldManager {
     /// Starts to generate all chunks within `load_distance` (config parameter)
     /// around `pos`.
     fn load_world_around(&self, pos: Point2f) {
-        let axial_pos = AxialPoint::from_real(pos);
-        let chunk_pos = AxialPoint::new(axial_pos.q / CHUNK_SIZE as i32,
-                                        axial_pos.r / CHUNK_SIZE as i32);
+        let chunk_pos = PillarIndex(AxialPoint::from_real(pos)).chunk();
 
         let mut shared = self.shared.borrow_mut();
-        if shared.player_chunk.0 != chunk_pos {
-            shared.player_chunk = ChunkIndex(chunk_pos);
+        if shared.player_chunk != chunk_pos {
+            shared.player_chunk = chunk_pos;
             debug!("player moved to chunk {:?} (player at {:?})",
                    chunk_pos,
                    pos);
@@ -159,11 +192,14 @@ impl WorldManager {
     }
 
     /// Applies all queued updated to the actual world. Notably, all generated
-    /// chunks are added.
+    /// chunks are added and all chunks changed by edits are redrawn.
     pub fn update_world(&self, player_pos: Point3f) {
+        use std::ops::DerefMut;
+
         self.load_world_around(Point2f::new(player_pos.x, player_pos.y));
 
-        let mut shared = self.shared.borrow_mut();
+        let mut shared_tmp = self.shared.borrow_mut();
+        let shared = shared_tmp.deref_mut();
         let mut changed = false;
 
         loop {
@@ -192,28 +228,38 @@ impl WorldManager {
         }
 
         if changed {
-            debug!("{} chunks loaded", shared.world.chunks.len());
+            debug!("{} chunks loaded", shared.world.chunk_count());
         }
-    }
-
-    pub fn recalculate_chunk(&mut self, pos: AxialPoint) {
-        use std::ops::DerefMut;
-
-        let mut shared_tmp = self.shared.borrow_mut();
-        let shared = shared_tmp.deref_mut();
 
-        let chunk_pos = AxialPoint::new(pos.q / CHUNK_SIZE as i32, pos.r / CHUNK_SIZE as i32);
-        let index = ChunkIndex(chunk_pos);
+        for index in shared.world.take_dirty_chunks() {
+            if let Some(chunk) = shared.world.chunk_at(index) {
+                shared.world_view.refresh_chunk(index, chunk, self.context.get_facade());
+            }
+        }
+    }
+}
 
-        shared.world_view.refresh_chunk(index,
-                                        shared.world.chunk_at(index).unwrap(),
-                                        self.context.get_facade());
+fn save_chunk(save_file: &SaveFileProvider, index: ChunkIndex, chunk: &Chunk) {
+    if let Err(e) = save_file.save_chunk(index, chunk) {
+        error!("failed to save chunk {:?}: {}", index, e);
     }
 }
 
+/// A command for the worker thread. The commands are executed in the order
+/// they were sent, so a chunk which is saved and requested again right away
+/// is loaded from the save file after it was written.
+enum WorkerCommand {
+    /// Loads the chunk with the given index and sends it to the main thread.
+    Load(ChunkIndex),
+    /// Writes the chunk to the save file.
+    Save(ChunkIndex, Chunk),
+    /// Answers once all commands sent before were executed.
+    Sync(Sender<()>),
+}
 
 fn worker_thread(provider: Box<ChunkProvider>,
-                 commands: Receiver<ChunkIndex>,
+                 save_file: Option<SaveFileProvider>,
+                 commands: Receiver<WorkerCommand>,
                  chunks: Sender<(ChunkIndex, Chunk)>) {
     loop {
         let requested_chunk = match commands.recv() {
@@ -221,7 +267,18 @@ fn worker_thread(provider: Box<ChunkProvider>,
                 // The other side has hung up, so we can stop working, too.
                 break;
             }
-            Ok(index) => index,
+            Ok(WorkerCommand::Load(index)) => index,
+            Ok(WorkerCommand::Save(index, chunk)) => {
+                if let Some(ref save_file) = save_file {
+                    save_chunk(save_file, index, &chunk);
+                }
+                continue;
+            }
+            Ok(WorkerCommand::Sync(done)) => {
+                // The main thread might not wait for the answer anymore
+                let _ = done.send(());
+                continue;
+            }
         };
 
         debug!("chunk provider thread: received request to generate chunk {:?}",